@@ -190,6 +190,20 @@ fn benchmark_batch_queries(c: &mut Criterion) {
         b.iter(|| black_box(hashmap_store.check_batch(black_box(&batch_uuids), black_box(7))))
     });
 
+    // Batch of 1000 UUIDs (same mix), where VecStore's sorted merge pays off
+    let large_batch_uuids: Vec<Uuid> = (0..900)
+        .map(|i| uuids[i * 2_000])
+        .chain((0..100).map(|i| uuids[1_800_000 + i * 2_000]))
+        .collect();
+
+    group.bench_function(BenchmarkId::new("vecstore", "batch_1000"), |b| {
+        b.iter(|| black_box(vec_store.check_batch(black_box(&large_batch_uuids), black_box(7))))
+    });
+
+    group.bench_function(BenchmarkId::new("hashmap", "batch_1000"), |b| {
+        b.iter(|| black_box(hashmap_store.check_batch(black_box(&large_batch_uuids), black_box(7))))
+    });
+
     group.finish();
 }
 
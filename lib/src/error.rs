@@ -8,6 +8,12 @@ pub enum StoreError {
 
     #[error("Invalid format: {0}")]
     InvalidFormat(String),
+
+    #[error("Remote authority error: {0}")]
+    Remote(String),
+
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
 }
 
 pub type Result<T> = std::result::Result<T, StoreError>;
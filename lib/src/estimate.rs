@@ -0,0 +1,125 @@
+use crate::hash_table_bytes;
+use uuid::Uuid;
+
+/// Maximum number of visibility levels the hybrid store places in hash sets.
+const MAX_HOT_LEVELS: usize = 4;
+
+/// Minimum share of all entries a level must hold to be placed in a hash set.
+const MIN_HOT_SHARE: f64 = 0.10;
+
+/// Pick the levels the hybrid store hashes, most frequent first, given the number of
+/// entries at each level (indexed by level).
+pub(crate) fn hot_levels(counts: &[usize; 256]) -> Vec<u8> {
+    let min_count = counts.iter().sum::<usize>() as f64 * MIN_HOT_SHARE;
+    let mut levels: Vec<(u8, usize)> = (0..=u8::MAX)
+        .map(|level| (level, counts[usize::from(level)]))
+        .filter(|&(_, count)| count > 0 && count as f64 >= min_count)
+        .collect();
+
+    // Most frequent first; ties broken by lower level
+    levels.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    levels.truncate(MAX_HOT_LEVELS);
+    levels.into_iter().map(|(level, _)| level).collect()
+}
+
+/// Capacity of a hash table shrunk to fit `len` values, as `hashbrown` sizes it.
+fn table_capacity(len: usize) -> usize {
+    match len {
+        0 => 0,
+        1..=3 => 3,
+        4..=7 => 7,
+        _ => (len * 8 / 7).next_power_of_two() / 8 * 7,
+    }
+}
+
+/// Estimated heap memory of each store implementation, in bytes, for entries with the
+/// given number of UUIDs at each level (indexed by level).
+///
+/// Matches what [`Store::memory_usage`](crate::Store::memory_usage) reports once the store
+/// is built, so implementations can be compared without compiling them in. Returned in
+/// the order `hashmap`, `vec`, `hybrid`, `fullhash`.
+pub fn estimate_memory(counts: &[usize; 256]) -> [(&'static str, usize); 4] {
+    let total: usize = counts.iter().sum();
+    let entry = std::mem::size_of::<(Uuid, u8)>();
+    let set = |len| hash_table_bytes::<Uuid>(table_capacity(len));
+
+    let hot = hot_levels(counts);
+    let hot_count: usize = hot.iter().map(|&level| counts[usize::from(level)]).sum();
+    let hybrid = hot
+        .iter()
+        .map(|&level| set(counts[usize::from(level)]))
+        .sum::<usize>()
+        + (total - hot_count) * entry;
+
+    [
+        (
+            "hashmap",
+            hash_table_bytes::<(Uuid, u8)>(table_capacity(total)),
+        ),
+        ("vec", total * entry),
+        ("hybrid", hybrid),
+        (
+            "fullhash",
+            counts
+                .iter()
+                .filter(|&&count| count > 0)
+                .map(|&count| set(count))
+                .sum(),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashMapStore, Store};
+
+    fn entries(counts: &[(u8, usize)]) -> (Vec<(Uuid, u8)>, [usize; 256]) {
+        let mut by_level = [0; 256];
+        let mut entries = Vec::new();
+        for &(level, count) in counts {
+            by_level[usize::from(level)] += count;
+            entries.extend((0..count).map(|_| (Uuid::new_v4(), level)));
+        }
+        (entries, by_level)
+    }
+
+    #[test]
+    fn test_hot_levels() {
+        let (_, counts) = entries(&[(0, 50), (1, 9), (2, 20), (3, 11), (4, 10)]);
+        assert_eq!(hot_levels(&counts), vec![0, 2, 3, 4]);
+        assert!(hot_levels(&[0; 256]).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_matches_store() {
+        for counts in [&[][..], &[(0, 5)], &[(0, 9000), (3, 700), (7, 300)]] {
+            let (entries, by_level) = entries(counts);
+            let estimates = estimate_memory(&by_level);
+            let store = HashMapStore::new(entries.clone()).unwrap();
+            assert_eq!(
+                estimates[0],
+                ("hashmap", store.memory_usage()),
+                "{counts:?}"
+            );
+
+            #[cfg(feature = "bench")]
+            {
+                let stores = [
+                    crate::VecStore::new(entries.clone())
+                        .unwrap()
+                        .memory_usage(),
+                    crate::HybridAuthStore::new(entries.clone())
+                        .unwrap()
+                        .memory_usage(),
+                    crate::FullHashStore::new(entries.clone())
+                        .unwrap()
+                        .memory_usage(),
+                ];
+                for (estimate, actual) in estimates[1..].iter().zip(stores) {
+                    assert_eq!(estimate.1, actual, "{}: {counts:?}", estimate.0);
+                }
+            }
+        }
+    }
+}
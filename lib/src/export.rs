@@ -0,0 +1,70 @@
+//! Writing a store back out, so merged or patched stores can be republished.
+
+use crate::{Store, snapshot::Snapshot};
+use std::io::{self, Write};
+use uuid::Uuid;
+
+/// Entries of `store` sorted by UUID, so exports of equal stores are identical.
+fn sorted_entries(store: &impl Store) -> Vec<(Uuid, u8)> {
+    let mut entries = store.entries();
+    entries.sort_unstable();
+    entries
+}
+
+/// Write `store` as CSV with a `uuid,visibility_level` header, sorted by UUID.
+///
+/// The output loads back into an identical store.
+pub fn export_csv<W: Write>(store: &impl Store, writer: W) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    writeln!(writer, "uuid,visibility_level")?;
+    for (uuid, level) in sorted_entries(store) {
+        writeln!(writer, "{uuid},{level}")?;
+    }
+    writer.flush()
+}
+
+/// Write `store` as a binary [`Snapshot`] recording `source` as its origin.
+pub fn export_snapshot<W: Write>(
+    store: &impl Store,
+    source: &str,
+    mut writer: W,
+) -> io::Result<()> {
+    let snapshot = Snapshot::new(source.to_string(), sorted_entries(store));
+    writer.write_all(&snapshot.encode())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_store;
+
+    #[test]
+    fn test_export_csv() {
+        let store = build_store(vec![(Uuid::from_u128(2), 7), (Uuid::from_u128(1), 0)]).unwrap();
+        let mut out = Vec::new();
+        export_csv(&store, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "uuid,visibility_level\n{},0\n{},7\n",
+                Uuid::from_u128(1),
+                Uuid::from_u128(2)
+            )
+        );
+    }
+
+    #[test]
+    fn test_export_snapshot() {
+        let store = build_store(vec![(Uuid::from_u128(2), 7), (Uuid::from_u128(1), 0)]).unwrap();
+        let mut out = Vec::new();
+        export_snapshot(&store, "merged", &mut out).unwrap();
+
+        let snapshot = Snapshot::decode(&out).unwrap();
+        assert_eq!(snapshot.source, "merged");
+        assert_eq!(
+            snapshot.entries,
+            vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 7)]
+        );
+    }
+}
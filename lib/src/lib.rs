@@ -37,14 +37,32 @@
 //!
 //! - `nofx`: Use std `HashMap` instead of `FxHash` (slower but no extra dependency)
 //! - `vec`: Use `VecStore` (sorted vector with binary search)
-//! - `hybrid`: Use `HybridAuthStore` (`HashSet` for level 0 + sorted vector)
+//! - `hybrid`: Use `HybridAuthStore` (`HashSets` for the most frequent levels + sorted vector)
 //! - `fullhash`: Use `FullHashStore` (256 `HashSets`, one per level)
 //! - `bench`: Enable all stores for benchmark comparisons
 //!
+//! ## Tiered Stores
+//!
+//! [`TieredStore`] wraps a local store and a [`RemoteAuthority`], answering from
+//! memory when possible and asking the remote (with a TTL cache) for unknown UUIDs.
+//! It implements [`AsyncStore`], which every [`Store`] also implements.
+//!
+//! ## Export
+//!
+//! [`export_csv`] and [`export_snapshot`] write any store back out, sorted by UUID,
+//! as CSV or as a binary [`snapshot::Snapshot`], so merged or patched stores can be
+//! republished.
+//!
+//! ## Memory Estimates
+//!
+//! [`estimate_memory`] predicts the heap memory of every store implementation from a
+//! level distribution alone, so implementations can be compared before picking a feature.
+//!
 //! ## Thread Safety
 //!
 //! All store implementations are immutable after construction and implement `Send + Sync`,
-//! making them safe to share across threads (e.g., wrapped in `Arc`).
+//! making them safe to share across threads (e.g., wrapped in `Arc`). [`SwappableStore`]
+//! layers atomic reloads and incremental per-UUID changes on top.
 //!
 //! ## Example
 //!
@@ -113,6 +131,10 @@ pub trait Store: Send + Sync {
     #[must_use]
     fn is_visible(&self, uuid: &Uuid, mask: u8) -> bool;
 
+    /// Returns the visibility level of a UUID, or `None` if it is not stored.
+    #[must_use]
+    fn get_visibility(&self, uuid: &Uuid) -> Option<u8>;
+
     /// Check if all UUIDs in the batch are visible at the given mask.
     #[must_use]
     fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool;
@@ -128,6 +150,33 @@ pub trait Store: Send + Sync {
     /// Returns a map of visibility level to count of UUIDs at that level.
     #[must_use]
     fn visibility_distribution(&self) -> HashMap<u8, usize>;
+
+    /// Returns every (UUID, visibility level) pair in the store, in no particular order.
+    #[must_use]
+    fn entries(&self) -> Vec<(Uuid, u8)>;
+
+    /// Returns the UUIDs at `level`, in ascending order.
+    #[must_use]
+    fn uuids_at_level(&self, level: u8) -> Vec<Uuid> {
+        let mut uuids: Vec<Uuid> = self
+            .entries()
+            .into_iter()
+            .filter(|&(_, l)| l == level)
+            .map(|(uuid, _)| uuid)
+            .collect();
+        uuids.sort_unstable();
+        uuids
+    }
+
+    /// Returns an estimate of the heap memory held by the store, in bytes.
+    #[must_use]
+    fn memory_usage(&self) -> usize;
+}
+
+/// Estimated heap bytes of a hash table with room for `capacity` values of type `T`
+/// (one control byte per slot, as in `hashbrown`).
+pub(crate) fn hash_table_bytes<T>(capacity: usize) -> usize {
+    capacity * (std::mem::size_of::<T>() + 1)
 }
 
 /// Statistics about the distribution of UUIDs across visibility levels.
@@ -137,14 +186,23 @@ pub struct DistributionStats {
     pub level_0_count: usize,
     pub higher_levels_count: usize,
     pub level_0_percentage: f64,
+    /// Levels stored in hash sets, most frequent first
+    pub hot_levels: Vec<u8>,
+    /// Number of UUIDs stored at the hot levels
+    pub hot_count: usize,
 }
 
 impl std::fmt::Display for DistributionStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Total: {}, Level 0: {} ({:.1}%), Higher: {}",
-            self.total_uuids, self.level_0_count, self.level_0_percentage, self.higher_levels_count
+            "Total: {}, Level 0: {} ({:.1}%), Higher: {}, Hot levels: {:?} ({} UUIDs)",
+            self.total_uuids,
+            self.level_0_count,
+            self.level_0_percentage,
+            self.higher_levels_count,
+            self.hot_levels,
+            self.hot_count
         )
     }
 }
@@ -159,6 +217,19 @@ impl std::fmt::Display for DistributionStats {
 #[cfg(feature = "fullhash")]
 pub type ActiveStore = FullHashStore;
 
+/// Name of the [`ActiveStore`] implementation, for diagnostics.
+#[cfg(feature = "fullhash")]
+pub const ACTIVE_STORE_NAME: &str = "fullhash";
+
+#[cfg(all(feature = "hybrid", not(feature = "fullhash")))]
+pub const ACTIVE_STORE_NAME: &str = "hybrid";
+
+#[cfg(all(feature = "vec", not(feature = "hybrid"), not(feature = "fullhash")))]
+pub const ACTIVE_STORE_NAME: &str = "vec";
+
+#[cfg(not(any(feature = "vec", feature = "hybrid", feature = "fullhash")))]
+pub const ACTIVE_STORE_NAME: &str = "hashmap";
+
 #[cfg(all(feature = "hybrid", not(feature = "fullhash")))]
 pub type ActiveStore = HybridAuthStore;
 
@@ -195,6 +266,19 @@ pub fn build_store(entries: Vec<(Uuid, u8)>) -> Result<ActiveStore> {
 mod swappable;
 pub use swappable::SwappableStore;
 
+// Async store trait and remote-backed tiered store
+mod tiered;
+pub use tiered::{AsyncStore, RemoteAuthority, TieredStore};
+
+// Binary snapshot format and CSV/snapshot export
+mod export;
+pub mod snapshot;
+pub use export::{export_csv, export_snapshot};
+
+// Memory estimates of every store implementation, without building them
+mod estimate;
+pub use estimate::estimate_memory;
+
 // Bench-only store builders for benchmark comparisons
 #[cfg(feature = "bench")]
 pub fn build_hashmap_store(entries: Vec<(Uuid, u8)>) -> Result<HashMapStore> {
@@ -284,6 +368,23 @@ mod parameterized_tests {
         assert!(!store.is_visible(&uuid, 255));
     }
 
+    #[rstest]
+    #[case::hashmap(build_hashmap_store as fn(Vec<(Uuid, u8)>) -> Result<HashMapStore>)]
+    #[case::vec(build_vec_store as fn(Vec<(Uuid, u8)>) -> Result<VecStore>)]
+    #[case::hybrid(build_hybrid_store as fn(Vec<(Uuid, u8)>) -> Result<HybridAuthStore>)]
+    #[case::fullhash(build_fullhash_store as fn(Vec<(Uuid, u8)>) -> Result<FullHashStore>)]
+    fn test_get_visibility<S: Store + 'static>(#[case] builder: fn(Vec<(Uuid, u8)>) -> Result<S>) {
+        let mut entries: Vec<(Uuid, u8)> = (0..20).map(|i| (Uuid::from_u128(i), 0)).collect();
+        entries.push((Uuid::from_u128(100), 8));
+        entries.push((Uuid::from_u128(101), 200));
+        let store = make_store(entries, builder);
+
+        assert_eq!(store.get_visibility(&Uuid::from_u128(3)), Some(0));
+        assert_eq!(store.get_visibility(&Uuid::from_u128(100)), Some(8));
+        assert_eq!(store.get_visibility(&Uuid::from_u128(101)), Some(200));
+        assert_eq!(store.get_visibility(&Uuid::from_u128(999)), None);
+    }
+
     #[rstest]
     #[case::hashmap(build_hashmap_store as fn(Vec<(Uuid, u8)>) -> Result<HashMapStore>)]
     #[case::vec(build_vec_store as fn(Vec<(Uuid, u8)>) -> Result<VecStore>)]
@@ -344,6 +445,49 @@ mod parameterized_tests {
         assert_eq!(dist.get(&15), None);
     }
 
+    #[rstest]
+    #[case::hashmap(build_hashmap_store as fn(Vec<(Uuid, u8)>) -> Result<HashMapStore>)]
+    #[case::vec(build_vec_store as fn(Vec<(Uuid, u8)>) -> Result<VecStore>)]
+    #[case::hybrid(build_hybrid_store as fn(Vec<(Uuid, u8)>) -> Result<HybridAuthStore>)]
+    #[case::fullhash(build_fullhash_store as fn(Vec<(Uuid, u8)>) -> Result<FullHashStore>)]
+    fn test_entries<S: Store + 'static>(#[case] builder: fn(Vec<(Uuid, u8)>) -> Result<S>) {
+        let entries = vec![
+            (Uuid::from_u128(1), 0),
+            (Uuid::from_u128(2), 5),
+            (Uuid::from_u128(3), 0),
+            (Uuid::from_u128(4), 200),
+        ];
+        let store = make_store(entries.clone(), builder);
+
+        let mut stored = store.entries();
+        stored.sort_unstable();
+        assert_eq!(stored, entries);
+
+        assert_eq!(
+            store.uuids_at_level(0),
+            vec![Uuid::from_u128(1), Uuid::from_u128(3)]
+        );
+        assert_eq!(store.uuids_at_level(200), vec![Uuid::from_u128(4)]);
+        assert!(store.uuids_at_level(7).is_empty());
+    }
+
+    #[rstest]
+    #[case::hashmap(build_hashmap_store as fn(Vec<(Uuid, u8)>) -> Result<HashMapStore>)]
+    #[case::vec(build_vec_store as fn(Vec<(Uuid, u8)>) -> Result<VecStore>)]
+    #[case::hybrid(build_hybrid_store as fn(Vec<(Uuid, u8)>) -> Result<HybridAuthStore>)]
+    #[case::fullhash(build_fullhash_store as fn(Vec<(Uuid, u8)>) -> Result<FullHashStore>)]
+    fn test_memory_usage<S: Store + 'static>(#[case] builder: fn(Vec<(Uuid, u8)>) -> Result<S>) {
+        let entries: Vec<_> = (0..1000)
+            .map(|n| (Uuid::from_u128(n), (n % 7) as u8))
+            .collect();
+        let store = make_store(entries, builder);
+
+        // At least the UUIDs themselves, and not wildly more
+        let usage = store.memory_usage();
+        assert!(usage >= 1000 * 16, "{usage}");
+        assert!(usage <= 1000 * 64, "{usage}");
+    }
+
     #[rstest]
     #[case::hashmap(HashMapStore::new)]
     #[case::vec(VecStore::new)]
@@ -0,0 +1,177 @@
+//! Binary snapshots of store entries.
+//!
+//! A snapshot is a compact binary file:
+//!
+//! | Field | Size |
+//! |-------|------|
+//! | Magic `OCCSNAP\0` | 8 bytes |
+//! | Format version (LE) | 2 bytes |
+//! | Creation time, seconds since the Unix epoch (LE) | 8 bytes |
+//! | Source length (LE) + source, UTF-8 | 4 + n bytes |
+//! | Entry count (LE) | 8 bytes |
+//! | Entries: UUID + visibility level | 17 bytes each |
+//! | SHA-256 of everything above | 32 bytes |
+
+use crate::{Result, StoreError};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const MAGIC: &[u8; 8] = b"OCCSNAP\0";
+const VERSION: u16 = 1;
+const ENTRY_SIZE: usize = 17;
+const DIGEST_SIZE: usize = 32;
+
+/// The entries of a store, with where and when they came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Data source the entries were loaded from
+    pub source: String,
+    /// When the entries were loaded
+    pub created: SystemTime,
+    /// (UUID, visibility level) pairs
+    pub entries: Vec<(Uuid, u8)>,
+}
+
+impl Snapshot {
+    /// Create a snapshot of `entries` loaded from `source` now.
+    pub fn new(source: String, entries: Vec<(Uuid, u8)>) -> Self {
+        Self {
+            source,
+            created: SystemTime::now(),
+            entries,
+        }
+    }
+
+    /// Time elapsed since the snapshot was taken.
+    pub fn age(&self) -> Duration {
+        self.created.elapsed().unwrap_or_default()
+    }
+
+    /// Serialize the snapshot.
+    pub fn encode(&self) -> Vec<u8> {
+        let created = self
+            .created
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let source = self.source.as_bytes();
+        let source_len = u32::try_from(source.len()).expect("source name fits in u32");
+
+        let mut buf =
+            Vec::with_capacity(30 + source.len() + self.entries.len() * ENTRY_SIZE + DIGEST_SIZE);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&created.to_le_bytes());
+        buf.extend_from_slice(&source_len.to_le_bytes());
+        buf.extend_from_slice(source);
+        buf.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for (uuid, level) in &self.entries {
+            buf.extend_from_slice(uuid.as_bytes());
+            buf.push(*level);
+        }
+        let digest = Sha256::digest(&buf);
+        buf.extend_from_slice(&digest);
+        buf
+    }
+
+    /// Deserialize a snapshot, verifying its checksum.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let invalid = |msg: &str| StoreError::InvalidSnapshot(msg.to_string());
+
+        let body_len = bytes
+            .len()
+            .checked_sub(DIGEST_SIZE)
+            .ok_or_else(|| invalid("truncated snapshot"))?;
+        let (body, digest) = bytes.split_at(body_len);
+        if Sha256::digest(body).as_slice() != digest {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let mut reader = Reader(body);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a snapshot file"));
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(StoreError::InvalidSnapshot(format!(
+                "unsupported snapshot version {version}"
+            )));
+        }
+        let created = u64::from_le_bytes(reader.array()?);
+        let source_len = u32::from_le_bytes(reader.array()?) as usize;
+        let source = std::str::from_utf8(reader.take(source_len)?)
+            .map_err(|_| invalid("source is not UTF-8"))?
+            .to_string();
+        let count = usize::try_from(u64::from_le_bytes(reader.array()?))
+            .map_err(|_| invalid("entry count too large"))?;
+        if reader.0.len() != count.saturating_mul(ENTRY_SIZE) {
+            return Err(invalid("entry count does not match file size"));
+        }
+
+        let entries = reader
+            .0
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| {
+                let uuid = Uuid::from_slice(&chunk[..16]).expect("16-byte UUID");
+                (uuid, chunk[16])
+            })
+            .collect();
+
+        Ok(Self {
+            source,
+            created: SystemTime::UNIX_EPOCH + Duration::from_secs(created),
+            entries,
+        })
+    }
+}
+
+/// Cursor over the snapshot header.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(StoreError::InvalidSnapshot(
+                "truncated snapshot".to_string(),
+            ));
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("slice of length N"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            source: "https://example.com/data.csv".to_string(),
+            created: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            entries: vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 200)],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(Snapshot::decode(&snapshot().encode()).unwrap(), snapshot());
+    }
+
+    #[test]
+    fn test_corrupt_snapshot() {
+        let mut bytes = snapshot().encode();
+        assert!(Snapshot::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::decode(&bytes[..10]).is_err());
+
+        let last_entry = bytes.len() - DIGEST_SIZE - 1;
+        bytes[last_entry] ^= 1;
+        let err = Snapshot::decode(&bytes).unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{err}");
+    }
+}
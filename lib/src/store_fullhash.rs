@@ -52,8 +52,16 @@ impl FullHashStore {
     }
 
     /// Returns statistics about the store distribution.
+    ///
+    /// Every level is hashed, so all populated levels are reported as hot.
     pub fn distribution_stats(&self) -> DistributionStats {
         let level_0_count = self.by_level.get(&0).map_or(0, HashSet::len);
+        let mut hot_levels: Vec<(u8, usize)> = self
+            .by_level
+            .iter()
+            .map(|(&level, set)| (level, set.len()))
+            .collect();
+        hot_levels.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
 
         DistributionStats {
             total_uuids: self.total,
@@ -64,6 +72,8 @@ impl FullHashStore {
             } else {
                 0.0
             },
+            hot_levels: hot_levels.into_iter().map(|(level, _)| level).collect(),
+            hot_count: self.total,
         }
     }
 }
@@ -76,6 +86,14 @@ impl crate::Store for FullHashStore {
             .any(|(_, set)| set.contains(uuid))
     }
 
+    #[inline]
+    fn get_visibility(&self, uuid: &Uuid) -> Option<u8> {
+        self.by_level
+            .iter()
+            .find(|(_, set)| set.contains(uuid))
+            .map(|(&level, _)| level)
+    }
+
     fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool {
         uuids.iter().all(|uuid| self.is_visible(uuid, mask))
     }
@@ -96,6 +114,31 @@ impl crate::Store for FullHashStore {
             .map(|(&level, set)| (level, set.len()))
             .collect()
     }
+
+    fn entries(&self) -> Vec<(Uuid, u8)> {
+        let mut entries = Vec::with_capacity(self.total);
+        for (&level, set) in &self.by_level {
+            entries.extend(set.iter().map(|&uuid| (uuid, level)));
+        }
+        entries
+    }
+
+    fn uuids_at_level(&self, level: u8) -> Vec<Uuid> {
+        let mut uuids: Vec<Uuid> = self
+            .by_level
+            .get(&level)
+            .map(|set| set.iter().copied().collect())
+            .unwrap_or_default();
+        uuids.sort_unstable();
+        uuids
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.by_level
+            .values()
+            .map(|set| crate::hash_table_bytes::<Uuid>(set.capacity()))
+            .sum()
+    }
 }
 
 #[cfg(test)]
@@ -208,5 +251,7 @@ mod tests {
         assert_eq!(stats.level_0_count, 3);
         assert_eq!(stats.higher_levels_count, 1);
         assert!((stats.level_0_percentage - 75.0).abs() < 0.01);
+        assert_eq!(stats.hot_levels, vec![0, 5]);
+        assert_eq!(stats.hot_count, 4);
     }
 }
@@ -55,6 +55,11 @@ impl crate::Store for HashMapStore {
         self.map.get(uuid).is_some_and(|level| *level <= mask)
     }
 
+    #[inline]
+    fn get_visibility(&self, uuid: &Uuid) -> Option<u8> {
+        self.map.get(uuid).copied()
+    }
+
     fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool {
         uuids.iter().all(|uuid| self.is_visible(uuid, mask))
     }
@@ -77,6 +82,28 @@ impl crate::Store for HashMapStore {
                 acc
             })
     }
+
+    fn entries(&self) -> Vec<(Uuid, u8)> {
+        self.map
+            .iter()
+            .map(|(&uuid, &level)| (uuid, level))
+            .collect()
+    }
+
+    fn uuids_at_level(&self, level: u8) -> Vec<Uuid> {
+        let mut uuids: Vec<Uuid> = self
+            .map
+            .iter()
+            .filter(|&(_, &l)| l == level)
+            .map(|(&uuid, _)| uuid)
+            .collect();
+        uuids.sort_unstable();
+        uuids
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::hash_table_bytes::<(Uuid, u8)>(self.map.capacity())
+    }
 }
 
 #[cfg(test)]
@@ -3,90 +3,139 @@ use uuid::Uuid;
 
 /// Hybrid authorization store optimized for skewed distributions.
 ///
-/// Uses a `HashSet` for visibility level 0 (fast O(1) lookup) and a sorted
-/// array for higher visibility levels (O(log n) binary search).
+/// Uses a `HashSet` per "hot" visibility level (fast O(1) lookup) and a sorted
+/// array for the remaining "cold" levels (O(log n) binary search).
 ///
-/// This is optimized for workloads where 80-90% of UUIDs have visibility 0.
-/// For such distributions, this provides ~4x faster average-case performance
-/// compared to pure binary search.
+/// The split is chosen adaptively at build time: the most frequent levels
+/// (up to four, each holding at least 10% of all entries)
+/// are hashed, everything else falls back to the sorted array. For the typical
+/// workload where 80-90% of UUIDs have visibility 0, this places level 0 in a
+/// `HashSet` and provides ~4x faster average-case performance compared to pure
+/// binary search.
 ///
 /// ## When to Use
-/// - Known skewed distribution (80-90% at level 0)
+/// - Known skewed distribution (a few levels hold most UUIDs)
 /// - Need similar performance to `HashMap` but with slightly lower memory for the hot path
-/// - Want optimized early exit for mask=0 queries
+/// - Want optimized early exit for masks below every cold level
 ///
 /// ## Performance (2M UUIDs, 90% at level 0, with `FxHash`)
-/// - Level 0 lookup: ~2.5ns (90% of queries)
-/// - Higher level lookup: ~48ns (10% of queries)
+/// - Hot level lookup: ~2.5ns (90% of queries)
+/// - Cold level lookup: ~48ns (10% of queries)
 /// - Batch (100): ~780ns
-/// - Memory: ~24 bytes/UUID for level 0, ~17 for others
+/// - Memory: ~24 bytes/UUID for hot levels, ~17 for others
 #[derive(Debug, Clone)]
 pub struct HybridAuthStore {
-    /// `HashSet` for O(1) lookup of UUIDs at visibility level 0
-    level_0: HashSet<Uuid>,
+    /// `HashSet`s for O(1) lookup of UUIDs at the hot levels, most frequent first
+    hot: Vec<(u8, HashSet<Uuid>)>,
 
-    /// Sorted array for binary search of UUIDs at visibility levels 1-255
-    higher_levels: Vec<(Uuid, u8)>,
+    /// Sorted array for binary search of UUIDs at the cold levels
+    cold: Vec<(Uuid, u8)>,
+
+    /// Lowest visibility level present in `cold` (`u8::MAX` if empty)
+    min_cold_level: u8,
 }
 
 impl HybridAuthStore {
     /// Create a new `HybridAuthStore` from a vector of (UUID, visibility) pairs.
     ///
-    /// Entries at visibility level 0 go into a `HashSet`, others into a sorted array.
+    /// The level distribution is analyzed first to pick the hot levels; entries at
+    /// those levels go into `HashSet`s, others into a sorted array.
     /// Duplicates will cause an error to be returned.
     pub fn new(entries: Vec<(Uuid, u8)>) -> Result<Self, StoreError> {
-        let mut level_0: HashSet<_> = HashSet::default();
-        let mut higher_levels = Vec::new();
+        let hot_levels = Self::choose_hot_levels(&entries);
+
+        let mut hot: Vec<(u8, HashSet<Uuid>)> = hot_levels
+            .iter()
+            .map(|&level| (level, HashSet::default()))
+            .collect();
+        let mut cold = Vec::new();
 
         // Partition by visibility level
         for (uuid, level) in entries {
-            if level == 0 {
-                if !level_0.insert(uuid) {
+            if let Some((_, set)) = hot.iter_mut().find(|(l, _)| *l == level) {
+                if !set.insert(uuid) {
                     return Err(StoreError::DuplicateUuid(uuid));
                 }
             } else {
-                higher_levels.push((uuid, level));
+                cold.push((uuid, level));
             }
         }
 
-        higher_levels.sort_unstable_by_key(|(uuid, _)| *uuid);
+        cold.sort_unstable_by_key(|(uuid, _)| *uuid);
 
-        if let Some(dup) = higher_levels.windows(2).find(|w| w[0].0 == w[1].0) {
+        if let Some(dup) = cold.windows(2).find(|w| w[0].0 == w[1].0) {
             return Err(StoreError::DuplicateUuid(dup[0].0));
         }
 
-        if let Some((uuid, _)) = higher_levels
+        for (i, (_, set)) in hot.iter().enumerate() {
+            for (_, other) in &hot[i + 1..] {
+                if let Some(uuid) = set.iter().find(|uuid| other.contains(uuid)) {
+                    return Err(StoreError::DuplicateUuid(*uuid));
+                }
+            }
+        }
+
+        if let Some((uuid, _)) = cold
             .iter()
-            .find(|(uuid, _)| level_0.contains(uuid))
+            .find(|(uuid, _)| hot.iter().any(|(_, set)| set.contains(uuid)))
         {
             return Err(StoreError::DuplicateUuid(*uuid));
         }
 
-        level_0.shrink_to_fit();
-        higher_levels.shrink_to_fit();
+        for (_, set) in &mut hot {
+            set.shrink_to_fit();
+        }
+        cold.shrink_to_fit();
+
+        let min_cold_level = cold
+            .iter()
+            .map(|(_, level)| *level)
+            .min()
+            .unwrap_or(u8::MAX);
 
         Ok(Self {
-            level_0,
-            higher_levels,
+            hot,
+            cold,
+            min_cold_level,
         })
     }
 
+    /// Pick the levels that get their own `HashSet`, most frequent first.
+    fn choose_hot_levels(entries: &[(Uuid, u8)]) -> Vec<u8> {
+        let mut counts = [0usize; 256];
+        for (_, level) in entries {
+            counts[usize::from(*level)] += 1;
+        }
+        crate::estimate::hot_levels(&counts)
+    }
+
+    /// Returns the hashed levels, most frequent first.
+    pub fn hot_levels(&self) -> Vec<u8> {
+        self.hot.iter().map(|(level, _)| *level).collect()
+    }
+
     /// Returns statistics about the store distribution.
     ///
-    /// Useful for understanding if the hybrid approach is beneficial.
+    /// Useful for understanding if the hybrid approach is beneficial and
+    /// which split was chosen.
     pub fn distribution_stats(&self) -> DistributionStats {
         let total = self.len();
-        let level_0_count = self.level_0.len();
+        let level_0_count = self.hot.iter().find(|(level, _)| *level == 0).map_or_else(
+            || self.cold.iter().filter(|(_, level)| *level == 0).count(),
+            |(_, set)| set.len(),
+        );
 
         DistributionStats {
             total_uuids: total,
             level_0_count,
-            higher_levels_count: self.higher_levels.len(),
+            higher_levels_count: total - level_0_count,
             level_0_percentage: if total > 0 {
                 (level_0_count as f64 / total as f64) * 100.0
             } else {
                 0.0
             },
+            hot_levels: self.hot_levels(),
+            hot_count: self.hot.iter().map(|(_, set)| set.len()).sum(),
         }
     }
 }
@@ -94,22 +143,35 @@ impl HybridAuthStore {
 impl crate::Store for HybridAuthStore {
     #[inline]
     fn is_visible(&self, uuid: &Uuid, mask: u8) -> bool {
-        // Fast path: check level 0 first (90% probability)
-        // Since level 0 is always <= any mask (u8), we just check presence
-        if self.level_0.contains(uuid) {
-            return true;
+        // Fast path: check the hot levels first (most frequent first)
+        for (level, set) in &self.hot {
+            if set.contains(uuid) {
+                return *level <= mask;
+            }
         }
 
-        // Early exit: if mask is 0 and not in level_0, it's not visible
-        if mask == 0 {
+        // Early exit: if mask is below every cold level, it's not visible
+        if mask < self.min_cold_level {
             return false;
         }
 
-        // Slow path: binary search higher levels and compare
-        self.higher_levels
+        // Slow path: binary search cold levels and compare
+        self.cold
+            .binary_search_by_key(uuid, |(u, _)| *u)
+            .ok()
+            .is_some_and(|idx| self.cold[idx].1 <= mask)
+    }
+
+    #[inline]
+    fn get_visibility(&self, uuid: &Uuid) -> Option<u8> {
+        if let Some((level, _)) = self.hot.iter().find(|(_, set)| set.contains(uuid)) {
+            return Some(*level);
+        }
+
+        self.cold
             .binary_search_by_key(uuid, |(u, _)| *u)
             .ok()
-            .is_some_and(|idx| self.higher_levels[idx].1 <= mask)
+            .map(|idx| self.cold[idx].1)
     }
 
     fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool {
@@ -118,27 +180,59 @@ impl crate::Store for HybridAuthStore {
 
     #[inline]
     fn len(&self) -> usize {
-        self.level_0.len() + self.higher_levels.len()
+        self.hot.iter().map(|(_, set)| set.len()).sum::<usize>() + self.cold.len()
     }
 
     #[inline]
     fn is_empty(&self) -> bool {
-        self.level_0.is_empty() && self.higher_levels.is_empty()
+        self.hot.iter().all(|(_, set)| set.is_empty()) && self.cold.is_empty()
     }
 
     fn visibility_distribution(&self) -> HashMap<u8, usize> {
         let mut dist = self
-            .higher_levels
+            .cold
             .iter()
             .fold(HashMap::default(), |mut acc, (_, level)| {
                 *acc.entry(*level).or_insert(0) += 1;
                 acc
             });
-        if !self.level_0.is_empty() {
-            dist.insert(0, self.level_0.len());
+        for (level, set) in &self.hot {
+            if !set.is_empty() {
+                dist.insert(*level, set.len());
+            }
         }
         dist
     }
+
+    fn entries(&self) -> Vec<(Uuid, u8)> {
+        let mut entries = self.cold.clone();
+        for (level, set) in &self.hot {
+            entries.extend(set.iter().map(|&uuid| (uuid, *level)));
+        }
+        entries
+    }
+
+    fn uuids_at_level(&self, level: u8) -> Vec<Uuid> {
+        if let Some((_, set)) = self.hot.iter().find(|(hot, _)| *hot == level) {
+            let mut uuids: Vec<Uuid> = set.iter().copied().collect();
+            uuids.sort_unstable();
+            return uuids;
+        }
+        // The cold entries are already sorted
+        self.cold
+            .iter()
+            .filter(|&&(_, l)| l == level)
+            .map(|&(uuid, _)| uuid)
+            .collect()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.hot
+            .iter()
+            .map(|(_, set)| crate::hash_table_bytes::<Uuid>(set.capacity()))
+            .sum::<usize>()
+            + self.cold.capacity() * std::mem::size_of::<(Uuid, u8)>()
+    }
 }
 
 #[cfg(test)]
@@ -155,8 +249,48 @@ mod tests {
         ];
         let store = HybridAuthStore::new(entries).unwrap();
 
-        assert_eq!(store.level_0.len(), 2);
-        assert_eq!(store.higher_levels.len(), 2);
+        // Every level holds at least 10% of entries, level 0 is the most frequent
+        assert_eq!(store.hot_levels(), vec![0, 5, 10]);
+        assert_eq!(store.hot[0].1.len(), 2);
+        assert!(store.cold.is_empty());
+    }
+
+    #[test]
+    fn test_tail_levels_go_to_cold() {
+        // 90 at level 3, 6 at level 7, 4 spread across levels 1, 2, 4, 9
+        let mut entries: Vec<(Uuid, u8)> = (0..90).map(|i| (Uuid::from_u128(i), 3)).collect();
+        entries.extend((90..96).map(|i| (Uuid::from_u128(i), 7)));
+        entries.extend(
+            [1, 2, 4, 9]
+                .iter()
+                .zip(96..)
+                .map(|(&l, i)| (Uuid::from_u128(i), l)),
+        );
+
+        let store = HybridAuthStore::new(entries).unwrap();
+
+        assert_eq!(store.hot_levels(), vec![3]);
+        assert_eq!(store.cold.len(), 10);
+        assert_eq!(store.min_cold_level, 1);
+
+        assert!(store.is_visible(&Uuid::from_u128(0), 3));
+        assert!(!store.is_visible(&Uuid::from_u128(0), 2));
+        assert!(store.is_visible(&Uuid::from_u128(96), 1));
+        assert!(!store.is_visible(&Uuid::from_u128(96), 0));
+        assert!(store.is_visible(&Uuid::from_u128(99), 9));
+        assert!(!store.is_visible(&Uuid::from_u128(99), 8));
+    }
+
+    #[test]
+    fn test_hot_levels_capped() {
+        // Uniform across 8 levels: each has 12.5%, only MAX_HOT_LEVELS are hashed
+        let entries = (0..800)
+            .map(|i| (Uuid::from_u128(i), (i % 8) as u8))
+            .collect();
+        let store = HybridAuthStore::new(entries).unwrap();
+
+        assert_eq!(store.hot_levels(), vec![0, 1, 2, 3]);
+        assert_eq!(store.cold.len(), 400);
     }
 
     #[test]
@@ -174,6 +308,11 @@ mod tests {
         // Duplicate across level 0 and higher
         let entries = vec![(uuid, 0), (uuid, 5)];
         assert!(HybridAuthStore::new(entries).is_err());
+
+        // Duplicate across hot and cold levels
+        let mut entries: Vec<(Uuid, u8)> = (0..20).map(|i| (Uuid::from_u128(i), 0)).collect();
+        entries.push((Uuid::from_u128(3), 9));
+        assert!(HybridAuthStore::new(entries).is_err());
     }
 
     #[test]
@@ -241,6 +380,8 @@ mod tests {
         assert_eq!(stats.level_0_count, 3);
         assert_eq!(stats.higher_levels_count, 1);
         assert!((stats.level_0_percentage - 75.0).abs() < 0.01);
+        assert_eq!(stats.hot_levels, vec![0, 5]);
+        assert_eq!(stats.hot_count, 4);
     }
 
     #[test]
@@ -260,5 +401,7 @@ mod tests {
         assert_eq!(stats.level_0_count, 900);
         assert_eq!(stats.higher_levels_count, 100);
         assert!((stats.level_0_percentage - 90.0).abs() < 0.01);
+        assert_eq!(stats.hot_levels, vec![0]);
+        assert_eq!(stats.hot_count, 900);
     }
 }
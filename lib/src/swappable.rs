@@ -1,7 +1,10 @@
 //! Thread-safe store wrapper that supports runtime reloading.
 
 use crate::{ActiveStore, HashMap, Store};
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, RwLock},
+};
 use uuid::Uuid;
 
 /// Thread-safe store wrapper that supports runtime reloading.
@@ -9,6 +12,14 @@ use uuid::Uuid;
 /// Wraps an `ActiveStore` in `Arc<RwLock<>>` to allow atomic swapping
 /// of the underlying store without stopping the server.
 ///
+/// Individual UUIDs can also be changed between reloads with [`upsert`](Self::upsert)
+/// and [`remove`](Self::remove). Changes are kept in a small overlay consulted before
+/// the (immutable) store, and are discarded by the next [`swap`](Self::swap), since a
+/// full reload is the source of truth.
+///
+/// With [`set_retention`](Self::set_retention), the data of the last few generations is kept
+/// after it is replaced, so that [`at_generation`](Self::at_generation) can still read it.
+///
 /// # Performance
 ///
 /// - Read operations acquire a read lock (very cheap when uncontended)
@@ -33,24 +44,259 @@ use uuid::Uuid;
 /// ```
 #[derive(Clone)]
 pub struct SwappableStore {
-    inner: Arc<RwLock<ActiveStore>>,
+    inner: Arc<RwLock<Inner>>,
+}
+
+/// The current store plus changes applied since it was built.
+struct Inner {
+    store: Arc<ActiveStore>,
+    /// UUID -> new level (`None` = removed)
+    overlay: HashMap<Uuid, Option<u8>>,
+    /// Number of visible UUIDs, accounting for the overlay
+    len: usize,
+    /// Bumped by every swap or change
+    generation: u64,
+    /// Number of replaced generations to keep
+    retention: usize,
+    /// Replaced generations, oldest first
+    retained: VecDeque<SwappableStore>,
+    /// Sorted UUIDs of the level last listed with [`SwappableStore::uuids_after`]
+    level_index: Mutex<Option<LevelIndex>>,
+}
+
+/// The UUIDs at a level of one generation, in ascending order.
+struct LevelIndex {
+    generation: u64,
+    level: u8,
+    uuids: Arc<Vec<Uuid>>,
+}
+
+impl Inner {
+    #[inline]
+    fn get_visibility(&self, uuid: &Uuid) -> Option<u8> {
+        if self.overlay.is_empty() {
+            return self.store.get_visibility(uuid);
+        }
+        match self.overlay.get(uuid) {
+            Some(level) => *level,
+            None => self.store.get_visibility(uuid),
+        }
+    }
+
+    /// A copy of the current data, sharing the store, that later changes do not affect.
+    fn snapshot(&self) -> SwappableStore {
+        SwappableStore::from_inner(Inner {
+            store: Arc::clone(&self.store),
+            overlay: self.overlay.clone(),
+            len: self.len,
+            generation: self.generation,
+            retention: 0,
+            retained: VecDeque::new(),
+            level_index: Mutex::new(None),
+        })
+    }
+
+    /// Keep the current data, if generations are retained, before it is replaced.
+    fn retain_current(&mut self) {
+        if self.retention == 0 {
+            return;
+        }
+        if self.retained.len() == self.retention {
+            self.retained.pop_front();
+        }
+        let snapshot = self.snapshot();
+        self.retained.push_back(snapshot);
+    }
+
+    /// The UUIDs at `level`, pending changes included, in ascending order.
+    fn sorted_level(&self, level: u8) -> Vec<Uuid> {
+        let mut uuids = self.store.uuids_at_level(level);
+        if !self.overlay.is_empty() {
+            uuids.retain(|uuid| !self.overlay.contains_key(uuid));
+            uuids.extend(
+                self.overlay
+                    .iter()
+                    .filter(|&(_, &changed)| changed == Some(level))
+                    .map(|(&uuid, _)| uuid),
+            );
+            uuids.sort_unstable();
+        }
+        uuids
+    }
+
+    fn apply(&mut self, uuid: Uuid, level: Option<u8>) {
+        self.generation += 1;
+        let existed = self.get_visibility(&uuid).is_some();
+        self.overlay.insert(uuid, level);
+        match (existed, level.is_some()) {
+            (false, true) => self.len += 1,
+            (true, false) => self.len -= 1,
+            _ => {}
+        }
+    }
 }
 
 impl SwappableStore {
     /// Create a new `SwappableStore` wrapping the given store.
     pub fn new(store: ActiveStore) -> Self {
+        Self::from_inner(Inner {
+            len: store.len(),
+            store: Arc::new(store),
+            overlay: HashMap::default(),
+            generation: 0,
+            retention: 0,
+            retained: VecDeque::new(),
+            level_index: Mutex::new(None),
+        })
+    }
+
+    fn from_inner(inner: Inner) -> Self {
         Self {
-            inner: Arc::new(RwLock::new(store)),
+            inner: Arc::new(RwLock::new(inner)),
+        }
+    }
+
+    /// Keep the data of the last `generations` generations after it is replaced (0, the
+    /// default, keeps none).
+    ///
+    /// Replaced stores stay in memory while retained, and each change made with
+    /// [`upsert`](Self::upsert), [`remove`](Self::remove) or
+    /// [`apply_changes`](Self::apply_changes) copies the pending changes.
+    pub fn set_retention(&self, generations: usize) {
+        let mut guard = self.inner.write().expect("RwLock poisoned");
+        guard.retention = generations;
+        let excess = guard.retained.len().saturating_sub(generations);
+        guard.retained.drain(..excess);
+    }
+
+    /// The data as of `generation`, if it is the current generation or a retained one.
+    ///
+    /// The returned store is a copy that later swaps and changes do not affect.
+    pub fn at_generation(&self, generation: u64) -> Option<SwappableStore> {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        if generation == guard.generation {
+            return Some(guard.snapshot());
         }
+        guard
+            .retained
+            .iter()
+            .find(|retained| retained.generation() == generation)
+            .cloned()
+    }
+
+    /// Returns the generations whose data is retained, oldest first.
+    pub fn retained_generations(&self) -> Vec<u64> {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        guard
+            .retained
+            .iter()
+            .map(SwappableStore::generation)
+            .collect()
     }
 
     /// Atomically swap the underlying store with a new one.
     ///
     /// This acquires a write lock, briefly blocking all read operations.
-    /// The old store is dropped after the swap completes.
+    /// The old store and any pending changes are dropped after the swap completes, unless
+    /// generations are retained.
     pub fn swap(&self, new_store: ActiveStore) {
         let mut guard = self.inner.write().expect("RwLock poisoned");
-        *guard = new_store;
+        guard.retain_current();
+        guard.len = new_store.len();
+        guard.store = Arc::new(new_store);
+        guard.overlay = HashMap::default();
+        guard.generation += 1;
+    }
+
+    /// Swap the data of `data`, e.g. a copy returned by [`at_generation`](Self::at_generation),
+    /// back in as a new generation, its pending changes included.
+    pub fn restore(&self, data: &SwappableStore) {
+        let (store, overlay, len) = {
+            let data = data.inner.read().expect("RwLock poisoned");
+            (Arc::clone(&data.store), data.overlay.clone(), data.len)
+        };
+        let mut guard = self.inner.write().expect("RwLock poisoned");
+        guard.retain_current();
+        guard.store = store;
+        guard.overlay = overlay;
+        guard.len = len;
+        guard.generation += 1;
+    }
+
+    /// Insert or update a single UUID until the next swap.
+    pub fn upsert(&self, uuid: Uuid, level: u8) {
+        let mut guard = self.inner.write().expect("RwLock poisoned");
+        guard.retain_current();
+        guard.apply(uuid, Some(level));
+    }
+
+    /// Remove a single UUID until the next swap.
+    pub fn remove(&self, uuid: Uuid) {
+        let mut guard = self.inner.write().expect("RwLock poisoned");
+        guard.retain_current();
+        guard.apply(uuid, None);
+    }
+
+    /// Apply several changes (`None` = remove) at once until the next swap.
+    ///
+    /// Readers observe either none or all of the changes.
+    pub fn apply_changes(&self, changes: impl IntoIterator<Item = (Uuid, Option<u8>)>) {
+        let mut guard = self.inner.write().expect("RwLock poisoned");
+        guard.retain_current();
+        for (uuid, level) in changes {
+            guard.apply(uuid, level);
+        }
+    }
+
+    /// Returns the generation of the data: 0 for the initial store, then bumped by every
+    /// swap and every change, so two reads with the same generation saw the same data.
+    pub fn generation(&self) -> u64 {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        guard.generation
+    }
+
+    /// Look up the levels of several UUIDs at once, along with the generation they were
+    /// read at: all lookups see the same data.
+    pub fn get_visibilities(&self, uuids: &[Uuid]) -> (u64, Vec<Option<u8>>) {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        let levels = uuids
+            .iter()
+            .map(|uuid| guard.get_visibility(uuid))
+            .collect();
+        (guard.generation, levels)
+    }
+
+    /// Returns the number of UUIDs changed since the last swap.
+    pub fn pending_changes(&self) -> usize {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        guard.overlay.len()
+    }
+
+    /// Returns at most `limit` UUIDs at `level` greater than `after`, in ascending order.
+    ///
+    /// The sorted UUIDs of the level are kept until the next swap or change, or until
+    /// another level is listed, so that paging through a level sorts it only once.
+    pub fn uuids_after(&self, level: u8, after: Option<&Uuid>, limit: usize) -> Vec<Uuid> {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        let uuids = {
+            let mut index = guard.level_index.lock().expect("Mutex poisoned");
+            match &*index {
+                Some(index) if index.generation == guard.generation && index.level == level => {
+                    Arc::clone(&index.uuids)
+                }
+                _ => {
+                    let uuids = Arc::new(guard.sorted_level(level));
+                    *index = Some(LevelIndex {
+                        generation: guard.generation,
+                        level,
+                        uuids: Arc::clone(&uuids),
+                    });
+                    uuids
+                }
+            }
+        };
+        let start = after.map_or(0, |after| uuids.partition_point(|uuid| uuid <= after));
+        uuids[start..].iter().take(limit).copied().collect()
     }
 }
 
@@ -58,29 +304,98 @@ impl Store for SwappableStore {
     #[inline]
     fn is_visible(&self, uuid: &Uuid, mask: u8) -> bool {
         let guard = self.inner.read().expect("RwLock poisoned");
-        guard.is_visible(uuid, mask)
+        if guard.overlay.is_empty() {
+            return guard.store.is_visible(uuid, mask);
+        }
+        guard
+            .get_visibility(uuid)
+            .is_some_and(|level| level <= mask)
+    }
+
+    #[inline]
+    fn get_visibility(&self, uuid: &Uuid) -> Option<u8> {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        guard.get_visibility(uuid)
     }
 
     fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool {
         let guard = self.inner.read().expect("RwLock poisoned");
-        guard.check_batch(uuids, mask)
+        if guard.overlay.is_empty() {
+            return guard.store.check_batch(uuids, mask);
+        }
+        uuids.iter().all(|uuid| {
+            guard
+                .get_visibility(uuid)
+                .is_some_and(|level| level <= mask)
+        })
     }
 
     #[inline]
     fn len(&self) -> usize {
         let guard = self.inner.read().expect("RwLock poisoned");
-        guard.len()
+        guard.len
     }
 
     #[inline]
     fn is_empty(&self) -> bool {
-        let guard = self.inner.read().expect("RwLock poisoned");
-        guard.is_empty()
+        self.len() == 0
     }
 
     fn visibility_distribution(&self) -> HashMap<u8, usize> {
         let guard = self.inner.read().expect("RwLock poisoned");
-        guard.visibility_distribution()
+        let mut distribution = guard.store.visibility_distribution();
+        for (uuid, level) in &guard.overlay {
+            if let Some(old) = guard.store.get_visibility(uuid)
+                && let Some(count) = distribution.get_mut(&old)
+            {
+                *count -= 1;
+            }
+            if let Some(new) = level {
+                *distribution.entry(*new).or_insert(0) += 1;
+            }
+        }
+        distribution.retain(|_, count| *count > 0);
+        distribution
+    }
+
+    fn entries(&self) -> Vec<(Uuid, u8)> {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        let mut entries = guard.store.entries();
+        if !guard.overlay.is_empty() {
+            entries.retain(|(uuid, _)| !guard.overlay.contains_key(uuid));
+            entries.extend(
+                guard
+                    .overlay
+                    .iter()
+                    .filter_map(|(&uuid, level)| level.map(|level| (uuid, level))),
+            );
+        }
+        entries
+    }
+
+    fn uuids_at_level(&self, level: u8) -> Vec<Uuid> {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        guard.sorted_level(level)
+    }
+
+    fn memory_usage(&self) -> usize {
+        let guard = self.inner.read().expect("RwLock poisoned");
+        let mut usage = guard.store.memory_usage()
+            + crate::hash_table_bytes::<(Uuid, Option<u8>)>(guard.overlay.capacity());
+        if let Some(index) = &*guard.level_index.lock().expect("Mutex poisoned") {
+            usage += index.uuids.capacity() * std::mem::size_of::<Uuid>();
+        }
+        // Retained generations share their store with the next one until it is swapped
+        let mut newer = Arc::as_ptr(&guard.store);
+        for retained in guard.retained.iter().rev() {
+            let retained = retained.inner.read().expect("RwLock poisoned");
+            if Arc::as_ptr(&retained.store) != newer {
+                usage += retained.store.memory_usage();
+            }
+            usage += crate::hash_table_bytes::<(Uuid, Option<u8>)>(retained.overlay.capacity());
+            newer = Arc::as_ptr(&retained.store);
+        }
+        usage
     }
 }
 
@@ -169,4 +484,176 @@ mod tests {
         assert_eq!(store2.len(), 1);
         assert!(store2.is_visible(&Uuid::from_u128(999), 0));
     }
+
+    #[test]
+    fn test_upsert_and_remove() {
+        let store = SwappableStore::new(create_test_store());
+
+        // Update an existing UUID and add a new one
+        store.upsert(Uuid::from_u128(3), 1);
+        store.upsert(Uuid::from_u128(4), 7);
+        assert!(store.is_visible(&Uuid::from_u128(3), 5));
+        assert_eq!(store.get_visibility(&Uuid::from_u128(4)), Some(7));
+        assert_eq!(store.len(), 4);
+
+        // Remove a stored UUID and the added one
+        store.remove(Uuid::from_u128(1));
+        store.remove(Uuid::from_u128(4));
+        store.remove(Uuid::from_u128(4));
+        assert!(!store.is_visible(&Uuid::from_u128(1), 255));
+        assert!(!store.check_batch(&[Uuid::from_u128(2), Uuid::from_u128(1)], 255));
+        assert!(store.check_batch(&[Uuid::from_u128(2), Uuid::from_u128(3)], 5));
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.pending_changes(), 3);
+
+        let distribution = store.visibility_distribution();
+        assert_eq!(distribution.get(&0), None);
+        assert_eq!(distribution.get(&1), Some(&1));
+        assert_eq!(distribution.get(&5), Some(&1));
+        assert_eq!(distribution.get(&10), None);
+    }
+
+    #[test]
+    fn test_uuids_after() {
+        let store = SwappableStore::new(create_store_from_entries(
+            (1..=10)
+                .map(|n| (Uuid::from_u128(n), (n % 2) as u8))
+                .collect(),
+        ));
+        let id = Uuid::from_u128;
+        assert_eq!(store.uuids_after(1, None, 2), vec![id(1), id(3)]);
+        assert_eq!(store.uuids_after(1, Some(&id(3)), 2), vec![id(5), id(7)]);
+        // Cursors need not be stored
+        assert_eq!(store.uuids_after(1, Some(&id(6)), 10), vec![id(7), id(9)]);
+        assert!(store.uuids_after(1, Some(&id(9)), 10).is_empty());
+        assert_eq!(store.uuids_after(0, Some(&id(8)), 10), vec![id(10)]);
+
+        // Changes are listed at once
+        store.upsert(id(11), 1);
+        store.upsert(id(3), 0);
+        store.remove(id(5));
+        assert_eq!(
+            store.uuids_after(1, None, 10),
+            vec![id(1), id(7), id(9), id(11)]
+        );
+        assert_eq!(store.uuids_at_level(0)[..2], [id(2), id(3)]);
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let store = SwappableStore::new(create_test_store());
+        store.apply_changes([
+            (Uuid::from_u128(1), None),
+            (Uuid::from_u128(2), Some(9)),
+            (Uuid::from_u128(5), Some(0)),
+        ]);
+
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get_visibility(&Uuid::from_u128(1)), None);
+        assert_eq!(store.get_visibility(&Uuid::from_u128(2)), Some(9));
+        assert!(store.is_visible(&Uuid::from_u128(5), 0));
+
+        let mut entries = store.entries();
+        entries.sort_unstable();
+        assert_eq!(
+            entries,
+            vec![
+                (Uuid::from_u128(2), 9),
+                (Uuid::from_u128(3), 10),
+                (Uuid::from_u128(5), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_swap_discards_changes() {
+        let store = SwappableStore::new(create_test_store());
+        store.upsert(Uuid::from_u128(100), 0);
+        store.remove(Uuid::from_u128(1));
+
+        store.swap(create_test_store());
+
+        assert_eq!(store.pending_changes(), 0);
+        assert_eq!(store.len(), 3);
+        assert!(store.is_visible(&Uuid::from_u128(1), 0));
+        assert!(!store.is_visible(&Uuid::from_u128(100), 255));
+    }
+
+    #[test]
+    fn test_generation() {
+        let store = SwappableStore::new(create_test_store());
+        assert_eq!(store.generation(), 0);
+
+        store.upsert(Uuid::from_u128(100), 0);
+        let after_upsert = store.generation();
+        assert!(after_upsert > 0);
+
+        store.swap(create_test_store());
+        assert!(store.generation() > after_upsert);
+        assert_eq!(store.clone().generation(), store.generation());
+    }
+
+    #[test]
+    fn test_get_visibilities() {
+        let store = SwappableStore::new(create_test_store());
+        store.upsert(Uuid::from_u128(100), 7);
+
+        let (generation, levels) = store.get_visibilities(&[
+            Uuid::from_u128(2),
+            Uuid::from_u128(100),
+            Uuid::from_u128(999),
+        ]);
+        assert_eq!(generation, store.generation());
+        assert_eq!(levels, vec![Some(5), Some(7), None]);
+    }
+
+    #[test]
+    fn test_restore() {
+        let store = SwappableStore::new(create_test_store());
+        store.upsert(Uuid::from_u128(1), 9);
+        let previous = store.at_generation(1).unwrap();
+        store.swap(create_store_from_entries(vec![(Uuid::from_u128(7), 0)]));
+
+        store.restore(&previous);
+        assert_eq!(store.generation(), 3);
+        assert_eq!(store.len(), previous.len());
+        assert_eq!(store.get_visibility(&Uuid::from_u128(1)), Some(9));
+        assert_eq!(store.get_visibility(&Uuid::from_u128(7)), None);
+        store.restore(&store.clone());
+        assert_eq!(store.generation(), 4);
+    }
+
+    #[test]
+    fn test_retention() {
+        let store = SwappableStore::new(create_test_store());
+        store.swap(create_store_from_entries(vec![(Uuid::from_u128(1), 3)]));
+        // Nothing is retained by default, but the current generation is always readable
+        assert!(store.at_generation(0).is_none());
+        let current = store.at_generation(1).unwrap();
+        store.upsert(Uuid::from_u128(1), 9);
+        assert_eq!(current.get_visibility(&Uuid::from_u128(1)), Some(3));
+        assert_eq!(current.generation(), 1);
+
+        store.set_retention(2);
+        store.swap(create_test_store());
+        store.apply_changes([(Uuid::from_u128(2), None), (Uuid::from_u128(3), Some(1))]);
+        assert_eq!(store.generation(), 5);
+        assert_eq!(store.retained_generations(), vec![2, 3]);
+
+        let before_swap = store.at_generation(2).unwrap();
+        assert_eq!(before_swap.len(), 1);
+        assert_eq!(before_swap.get_visibility(&Uuid::from_u128(1)), Some(9));
+        let before_changes = store.at_generation(3).unwrap();
+        assert_eq!(before_changes.len(), 3);
+        assert!(before_changes.is_visible(&Uuid::from_u128(2), 5));
+        assert!(!store.is_visible(&Uuid::from_u128(2), 5));
+        assert!(store.at_generation(4).is_none());
+
+        // The oldest generations go first
+        store.remove(Uuid::from_u128(1));
+        assert_eq!(store.retained_generations(), vec![3, 5]);
+        store.set_retention(1);
+        assert_eq!(store.retained_generations(), vec![5]);
+        assert!(store.memory_usage() > store.at_generation(6).unwrap().memory_usage());
+    }
 }
@@ -0,0 +1,283 @@
+//! Async store abstraction and a tiered store backed by a remote authority.
+
+use crate::{HashMap, Result, Store};
+use std::{
+    future::Future,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Default maximum number of cached remote decisions.
+const DEFAULT_MAX_CACHED: usize = 100_000;
+
+/// Async counterpart of [`Store`] for stores that may need I/O to answer.
+///
+/// Every [`Store`] implements `AsyncStore` by answering immediately, so callers
+/// can be written once against `AsyncStore` and work with both local and
+/// remote-backed stores.
+pub trait AsyncStore: Send + Sync {
+    /// Check if a UUID is visible at the given visibility mask.
+    fn is_visible(&self, uuid: &Uuid, mask: u8) -> impl Future<Output = bool> + Send;
+
+    /// Check if all UUIDs in the batch are visible at the given mask.
+    fn check_batch(&self, uuids: &[Uuid], mask: u8) -> impl Future<Output = bool> + Send;
+}
+
+impl<S: Store> AsyncStore for S {
+    async fn is_visible(&self, uuid: &Uuid, mask: u8) -> bool {
+        Store::is_visible(self, uuid, mask)
+    }
+
+    async fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool {
+        Store::check_batch(self, uuids, mask)
+    }
+}
+
+/// A remote source of truth consulted for UUIDs missing from the local store.
+///
+/// Implementations typically call another occlusion instance (HTTP or gRPC).
+pub trait RemoteAuthority: Send + Sync {
+    /// Ask the remote authority whether a UUID is visible at the given mask.
+    fn is_visible(&self, uuid: &Uuid, mask: u8) -> impl Future<Output = Result<bool>> + Send;
+}
+
+/// Store that answers from a local store first and falls back to a remote authority.
+///
+/// Only UUIDs unknown to the local store are sent to the remote. Remote answers are
+/// cached per (UUID, mask) for the configured TTL, up to a maximum number of answers.
+/// Remote errors fail closed (the UUID is treated as not visible) and are not cached.
+///
+/// This allows running occlusion at the edge with only the hot subset of the
+/// dataset loaded locally.
+///
+/// # Example
+///
+/// ```ignore
+/// use occlusion::{AsyncStore, TieredStore};
+/// use std::time::Duration;
+///
+/// let tiered = TieredStore::new(local_store, remote, Duration::from_mins(1));
+/// let visible = tiered.is_visible(&uuid, 10).await;
+/// ```
+pub struct TieredStore<L, R> {
+    local: L,
+    remote: R,
+    ttl: Duration,
+    max_cached: usize,
+    cache: RwLock<HashMap<(Uuid, u8), (bool, Instant)>>,
+}
+
+impl<L: Store, R: RemoteAuthority> TieredStore<L, R> {
+    /// Create a new `TieredStore` caching remote answers for `ttl`.
+    pub fn new(local: L, remote: R, ttl: Duration) -> Self {
+        Self {
+            local,
+            remote,
+            ttl,
+            max_cached: DEFAULT_MAX_CACHED,
+            cache: RwLock::new(HashMap::default()),
+        }
+    }
+
+    /// Set the maximum number of cached remote answers (0 disables caching).
+    ///
+    /// Once the cache is full, expired answers are dropped, then arbitrary ones if needed,
+    /// until it is at most three quarters full, so that pruning stays rare.
+    #[must_use]
+    pub fn with_max_cached(mut self, max_cached: usize) -> Self {
+        self.max_cached = max_cached;
+        self
+    }
+
+    /// Returns the local store.
+    pub fn local(&self) -> &L {
+        &self.local
+    }
+
+    /// Returns the number of cached remote answers (including expired ones).
+    pub fn cached_len(&self) -> usize {
+        self.cache.read().expect("RwLock poisoned").len()
+    }
+
+    fn cached(&self, uuid: &Uuid, mask: u8) -> Option<bool> {
+        let guard = self.cache.read().expect("RwLock poisoned");
+        guard
+            .get(&(*uuid, mask))
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(visible, _)| *visible)
+    }
+
+    fn insert_cached(&self, uuid: Uuid, mask: u8, visible: bool) {
+        if self.max_cached == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut guard = self.cache.write().expect("RwLock poisoned");
+        if guard.len() >= self.max_cached && !guard.contains_key(&(uuid, mask)) {
+            let target = self.max_cached - self.max_cached.div_ceil(4);
+            guard.retain(|_, (_, expires)| *expires > now);
+            let mut evicted = guard.len().saturating_sub(target);
+            guard.retain(|_, _| {
+                let keep = evicted == 0;
+                evicted = evicted.saturating_sub(1);
+                keep
+            });
+        }
+        guard.insert((uuid, mask), (visible, now + self.ttl));
+    }
+
+    async fn lookup(&self, uuid: &Uuid, mask: u8) -> bool {
+        if let Some(level) = self.local.get_visibility(uuid) {
+            return level <= mask;
+        }
+
+        if let Some(visible) = self.cached(uuid, mask) {
+            return visible;
+        }
+
+        match self.remote.is_visible(uuid, mask).await {
+            Ok(visible) => {
+                self.insert_cached(*uuid, mask, visible);
+                visible
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl<L: Store, R: RemoteAuthority> AsyncStore for TieredStore<L, R> {
+    async fn is_visible(&self, uuid: &Uuid, mask: u8) -> bool {
+        self.lookup(uuid, mask).await
+    }
+
+    async fn check_batch(&self, uuids: &[Uuid], mask: u8) -> bool {
+        for uuid in uuids {
+            if !self.lookup(uuid, mask).await {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashMapStore, StoreError};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Remote that knows UUIDs >= 1000 at level 5 and counts calls.
+    struct MockRemote {
+        calls: AtomicUsize,
+    }
+
+    impl RemoteAuthority for MockRemote {
+        async fn is_visible(&self, uuid: &Uuid, mask: u8) -> Result<bool> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if uuid.as_u128() == 666 {
+                return Err(StoreError::Remote("unavailable".to_string()));
+            }
+            Ok(uuid.as_u128() >= 1000 && mask >= 5)
+        }
+    }
+
+    fn create_tiered(ttl: Duration) -> TieredStore<HashMapStore, MockRemote> {
+        let local =
+            HashMapStore::new(vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 10)]).unwrap();
+        let remote = MockRemote {
+            calls: AtomicUsize::new(0),
+        };
+        TieredStore::new(local, remote, ttl)
+    }
+
+    #[tokio::test]
+    async fn test_local_hits_skip_remote() {
+        let store = create_tiered(Duration::from_mins(1));
+
+        assert!(AsyncStore::is_visible(&store, &Uuid::from_u128(1), 0).await);
+        assert!(!AsyncStore::is_visible(&store, &Uuid::from_u128(2), 5).await);
+        assert_eq!(store.remote.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_remote_fallback_is_cached() {
+        let store = create_tiered(Duration::from_mins(1));
+        let uuid = Uuid::from_u128(1000);
+
+        assert!(AsyncStore::is_visible(&store, &uuid, 5).await);
+        assert!(AsyncStore::is_visible(&store, &uuid, 5).await);
+        assert_eq!(store.remote.calls.load(Ordering::SeqCst), 1);
+
+        // Different mask is a different cache entry
+        assert!(!AsyncStore::is_visible(&store, &uuid, 4).await);
+        assert_eq!(store.remote.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(store.cached_len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_refetched() {
+        let store = create_tiered(Duration::ZERO);
+        let uuid = Uuid::from_u128(1000);
+
+        assert!(AsyncStore::is_visible(&store, &uuid, 5).await);
+        assert!(AsyncStore::is_visible(&store, &uuid, 5).await);
+        assert_eq!(store.remote.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_bounded() {
+        let store = create_tiered(Duration::from_mins(1)).with_max_cached(8);
+        for n in 1000..1100 {
+            assert!(AsyncStore::is_visible(&store, &Uuid::from_u128(n), 5).await);
+            assert!(store.cached_len() <= 8);
+        }
+        // Evicted answers are fetched again
+        let calls = store.remote.calls.load(Ordering::SeqCst);
+        for n in 1000..1100 {
+            assert!(AsyncStore::is_visible(&store, &Uuid::from_u128(n), 5).await);
+        }
+        assert!(store.remote.calls.load(Ordering::SeqCst) - calls >= 92);
+
+        // Refreshing a cached answer evicts nothing
+        let store = create_tiered(Duration::ZERO).with_max_cached(2);
+        let uuid = Uuid::from_u128(1000);
+        for mask in [5, 6, 5, 6] {
+            assert!(AsyncStore::is_visible(&store, &uuid, mask).await);
+        }
+        assert_eq!(store.cached_len(), 2);
+
+        let store = create_tiered(Duration::from_mins(1)).with_max_cached(0);
+        assert!(AsyncStore::is_visible(&store, &uuid, 5).await);
+        assert_eq!(store.cached_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_remote_errors_fail_closed() {
+        let store = create_tiered(Duration::from_mins(1));
+
+        assert!(!AsyncStore::is_visible(&store, &Uuid::from_u128(666), 255).await);
+        assert_eq!(store.cached_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_check_batch() {
+        let store = create_tiered(Duration::from_mins(1));
+        let batch = [
+            Uuid::from_u128(1),
+            Uuid::from_u128(2),
+            Uuid::from_u128(1000),
+        ];
+
+        assert!(AsyncStore::check_batch(&store, &batch, 10).await);
+        assert!(!AsyncStore::check_batch(&store, &batch, 5).await);
+    }
+
+    #[tokio::test]
+    async fn test_blanket_impl_for_store() {
+        let store = HashMapStore::new(vec![(Uuid::from_u128(1), 3)]).unwrap();
+
+        assert!(AsyncStore::is_visible(&store, &Uuid::from_u128(1), 3).await);
+        assert!(!AsyncStore::check_batch(&store, &[Uuid::from_u128(1)], 2).await);
+    }
+}
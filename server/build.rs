@@ -0,0 +1,45 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+/// Generate the gRPC service for the messages in `src/grpc.rs`, which mirror
+/// `proto/occlusion.proto` (no `protoc` needed).
+#[cfg(feature = "grpc")]
+fn grpc() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::proto::{input}"))
+            .output_type(format!("crate::grpc::proto::{output}"))
+            .codec_path("tonic_prost::ProstCodec")
+            .build()
+    };
+    let service = Service::builder()
+        .name("Occlusion")
+        .package("occlusion.v1")
+        .method(method("check", "Check", "CheckRequest", "CheckResponse"))
+        .method(method(
+            "check_batch",
+            "CheckBatch",
+            "CheckBatchRequest",
+            "CheckBatchResponse",
+        ))
+        .method(method(
+            "get_level",
+            "GetLevel",
+            "GetLevelRequest",
+            "GetLevelResponse",
+        ))
+        .method(method("stats", "Stats", "StatsRequest", "StatsResponse"))
+        .build();
+
+    println!("cargo:rerun-if-changed=build.rs");
+    Builder::new()
+        .build_client(false)
+        .build_transport(false)
+        .compile(&[service]);
+}
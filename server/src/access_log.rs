@@ -0,0 +1,384 @@
+//! Access log: one JSON line per request, appended to a file rotated by size and age.
+//!
+//! Meant as a durable request trail, separate from the tracing output. Entries are queued by
+//! a fairing and written by a background thread, so requests never wait on the disk.
+
+use rocket::{
+    Data, Request, Response,
+    fairing::{Fairing, Info, Kind},
+};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Entries waiting in the queue before new ones are dropped.
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// When access log files are rotated regardless of their size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rotation {
+    /// Only by size
+    Never,
+    /// At the start of every hour (UTC)
+    Hourly,
+    /// At the start of every day (UTC)
+    #[default]
+    Daily,
+}
+
+impl Rotation {
+    /// The rotation period `time` falls in.
+    fn period(self, time: SystemTime) -> u64 {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match self {
+            Self::Never => 0,
+            Self::Hourly => secs / 3600,
+            Self::Daily => secs / 86400,
+        }
+    }
+}
+
+/// Rotation of the access log file.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessLogOptions {
+    /// Size in bytes past which the file is rotated (0 = unlimited)
+    pub max_size: u64,
+    pub rotation: Rotation,
+    /// Rotated files kept, as `<path>.1` (newest) to `<path>.<keep>`
+    pub keep: usize,
+}
+
+impl Default for AccessLogOptions {
+    fn default() -> Self {
+        Self {
+            max_size: 0,
+            rotation: Rotation::Daily,
+            keep: 7,
+        }
+    }
+}
+
+/// A request, as an access log line.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessEntry {
+    /// RFC 3339
+    pub timestamp: String,
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_us: u64,
+    /// `allow` or `deny`, for check requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision: Option<&'static str>,
+    /// Identity of the caller's credentials: JWT subject, client certificate or `admin`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+/// What the guards and handlers of a request tell the access log about it.
+#[derive(Debug, Default)]
+pub struct AccessNote {
+    decision: OnceLock<bool>,
+    caller: OnceLock<String>,
+}
+
+impl AccessNote {
+    /// The note of `request`.
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r Self {
+        request.local_cache(Self::default)
+    }
+
+    /// Record whether the request was allowed.
+    pub fn set_decision(&self, allowed: bool) {
+        let _ = self.decision.set(allowed);
+    }
+
+    /// Record the identity of the caller's credentials.
+    pub fn set_caller(&self, caller: impl Into<String>) {
+        let _ = self.caller.set(caller.into());
+    }
+
+    /// The identity of the caller's credentials, once checked.
+    pub fn caller(&self) -> Option<&str> {
+        self.caller.get().map(String::as_str)
+    }
+}
+
+/// Fairing writing an access log entry for every response.
+#[derive(Clone)]
+pub struct AccessLog {
+    sender: mpsc::Sender<AccessEntry>,
+}
+
+/// Request-local start time.
+struct Started(Instant);
+
+impl AccessLog {
+    /// Open the log at `path` and start its writer thread.
+    ///
+    /// Entries still queued when the fairing is dropped are written before the thread stops.
+    pub fn open(path: PathBuf, options: AccessLogOptions) -> io::Result<Self> {
+        let mut file = RotatingFile::open(path, options)?;
+        let (sender, mut receiver) = mpsc::channel::<AccessEntry>(QUEUE_CAPACITY);
+        std::thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || {
+                while let Some(entry) = receiver.blocking_recv() {
+                    let mut result = file.write(&entry);
+                    while let Ok(entry) = receiver.try_recv() {
+                        result = result.and_then(|()| file.write(&entry));
+                    }
+                    if let Err(e) = result.and_then(|()| file.flush()) {
+                        warn!(path = %file.path.display(), error = %e, "Failed to write access log");
+                    }
+                }
+            })?;
+        Ok(Self { sender })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access Log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| Started(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let started = request.local_cache(|| Started(Instant::now()));
+        let note = AccessNote::of(request);
+        let entry = AccessEntry {
+            timestamp: humantime::format_rfc3339_micros(SystemTime::now()).to_string(),
+            request_id: crate::request_id::of(request).to_string(),
+            method: request.method().to_string(),
+            path: request.uri().path().to_string(),
+            status: response.status().code,
+            latency_us: u64::try_from(started.0.elapsed().as_micros()).unwrap_or(u64::MAX),
+            decision: note
+                .decision
+                .get()
+                .map(|&allowed| if allowed { "allow" } else { "deny" }),
+            caller: note.caller.get().cloned(),
+            remote: request.client_ip().map(|ip| ip.to_string()),
+        };
+        if self.sender.try_send(entry).is_err() {
+            warn!("Access log queue full, dropping entry");
+        }
+    }
+}
+
+/// A file of JSON lines, rotated to numbered siblings when too large or too old.
+struct RotatingFile {
+    path: PathBuf,
+    options: AccessLogOptions,
+    file: BufWriter<File>,
+    size: u64,
+    /// Rotation period the file was started in
+    period: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, options: AccessLogOptions) -> io::Result<Self> {
+        let file = append(&path)?;
+        let metadata = file.metadata()?;
+        let started = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+        Ok(Self {
+            file: BufWriter::new(file),
+            size: metadata.len(),
+            period: options.rotation.period(started),
+            path,
+            options,
+        })
+    }
+
+    fn write(&mut self, entry: &AccessEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let len = line.len() as u64;
+        let too_large =
+            self.options.max_size > 0 && self.size > 0 && self.size + len > self.options.max_size;
+        let period = self.options.rotation.period(SystemTime::now());
+        if too_large || period != self.period {
+            self.rotate(period)?;
+        }
+        self.file.write_all(&line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    /// Shift `<path>.N` to `<path>.N+1`, dropping the oldest, move the file to `<path>.1`
+    /// and start a new one.
+    fn rotate(&mut self, period: u64) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        if self.options.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.options.keep).rev() {
+                match std::fs::rename(rotated(n), rotated(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = BufWriter::new(append(&self.path)?);
+        self.size = 0;
+        self.period = period;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> AccessEntry {
+        AccessEntry {
+            timestamp: String::new(),
+            request_id: String::new(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            status: 200,
+            latency_us: 1,
+            decision: None,
+            caller: None,
+            remote: None,
+        }
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let line_len = serde_json::to_vec(&entry("/a")).unwrap().len() as u64 + 1;
+        let options = AccessLogOptions {
+            max_size: line_len * 2,
+            rotation: Rotation::Never,
+            keep: 2,
+        };
+        let mut file = RotatingFile::open(path.clone(), options).unwrap();
+        for path in ["/a", "/b", "/c", "/d", "/e", "/f", "/g"] {
+            file.write(&entry(path)).unwrap();
+        }
+        file.flush().unwrap();
+
+        let paths = |name: &str| {
+            std::fs::read_to_string(dir.path().join(name))
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    serde_json::from_str::<serde_json::Value>(line).unwrap()["path"].clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths("access.log"), ["/g"]);
+        assert_eq!(paths("access.log.1"), ["/e", "/f"]);
+        assert_eq!(paths("access.log.2"), ["/c", "/d"]);
+        assert!(!dir.path().join("access.log.3").exists());
+    }
+
+    #[test]
+    fn test_rotate_by_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let options = AccessLogOptions {
+            rotation: Rotation::Hourly,
+            ..AccessLogOptions::default()
+        };
+        let mut file = RotatingFile::open(path.clone(), options).unwrap();
+        file.write(&entry("/a")).unwrap();
+        // As if the file had been started an hour ago
+        file.period -= 1;
+        file.write(&entry("/b")).unwrap();
+        file.flush().unwrap();
+
+        assert!(std::fs::read_to_string(&path).unwrap().contains("/b"));
+        assert!(
+            std::fs::read_to_string(dir.path().join("access.log.1"))
+                .unwrap()
+                .contains("/a")
+        );
+    }
+
+    #[test]
+    fn test_fairing() {
+        use rocket::http::ContentType;
+        use rocket::local::blocking::Client;
+        use uuid::Uuid;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let entries = vec![(Uuid::from_u128(1), 10)];
+        let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let log = AccessLog::open(path.clone(), AccessLogOptions::default()).unwrap();
+        let rocket = rocket::build()
+            .manage(store)
+            .attach(log)
+            .mount("/", rocket::routes![crate::routes::check]);
+        let client = Client::tracked(rocket).unwrap();
+        client
+            .post("/api/v1/check")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"object": "{}", "visibility_mask": 5}}"#,
+                Uuid::from_u128(1)
+            ))
+            .dispatch();
+        client.get("/nope").dispatch();
+
+        let mut lines = Vec::new();
+        for _ in 0..100 {
+            lines = std::fs::read_to_string(&path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect();
+            if lines.len() == 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["method"], "POST");
+        assert_eq!(lines[0]["path"], "/api/v1/check");
+        assert_eq!(lines[0]["status"], 200);
+        assert_eq!(lines[0]["decision"], "deny");
+        assert_eq!(lines[1]["status"], 404);
+        assert!(lines[1].get("decision").is_none());
+    }
+}
@@ -0,0 +1,507 @@
+//! Admin endpoints, guarded by the admin token (see [`Admin`]).
+
+use crate::{
+    ReloadState,
+    cache::GENERATION_HEADER,
+    canary::Canary,
+    catchers::ApiError,
+    error::LoadError,
+    events::{Events, StoreEvent},
+    format::InputFormat,
+    guards::{Admin, JsonBody, MaybeState},
+    leader::{SOURCE_MODIFIED_HEADER, SOURCE_VERSION_HEADER},
+    loader::{self, LoadOptions},
+    models::{
+        DiffRequest, DiffResponse, Entry, ReloadResponse, SampleResponse, SourceRequest,
+        SourceResponse, StoreResponse,
+    },
+    namespace::Namespaces,
+    scheduler::{NamespaceSchedulers, Scheduler, SchedulerStatus, SchedulersStatus},
+    source::{DataSource, SourceMetadata},
+    validation::{self, Diff, SourceReport, ValidationGates},
+};
+use occlusion::{Store, SwappableStore};
+use rand::seq::IteratorRandom;
+use rocket::{
+    Data, Request, Response, State,
+    data::{ByteUnit, Limits, ToByteUnit},
+    http::{ContentType, Status},
+    response::{self, Responder, status::Accepted, stream::ByteStream},
+    serde::json::Json,
+};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Rocket limit (`limits.store`) on the size of a store uploaded with [`replace_store`].
+pub const STORE_LIMIT: &str = "store";
+
+/// Upload size allowed when `limits.store` is not configured.
+pub fn default_store_limit() -> ByteUnit {
+    256.mebibytes()
+}
+
+/// Map a load failure to a response: 409 for data rejected by the validation gates,
+/// 502 for a source that could not be loaded.
+fn load_failure(e: &LoadError) -> ApiError {
+    let status = match e {
+        LoadError::ValidationError(_) => Status::Conflict,
+        _ => Status::BadGateway,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+/// The reload state, or a 503 when the data source is not reloadable.
+fn reloadable<'r>(
+    reload_state: &MaybeState<'r, Arc<ReloadState>>,
+) -> Result<&'r Arc<ReloadState>, ApiError> {
+    reload_state.0.ok_or_else(|| {
+        ApiError::new(
+            Status::ServiceUnavailable,
+            "the data source is not reloadable".to_string(),
+        )
+    })
+}
+
+/// The reload scheduler, or a 503 when scheduled reloads are disabled.
+fn scheduled<'r>(
+    scheduler: &MaybeState<'r, Arc<Scheduler>>,
+) -> Result<&'r Arc<Scheduler>, ApiError> {
+    scheduler.0.ok_or_else(|| {
+        ApiError::new(
+            Status::ServiceUnavailable,
+            "scheduled reloads are disabled".to_string(),
+        )
+    })
+}
+
+/// Reload the default data source now.
+///
+/// The reload is conditional (skipped if the source is unchanged) unless `force` is set.
+/// It goes through the same validation gates as scheduled reloads.
+#[post("/api/v1/admin/reload?<force>")]
+pub async fn reload(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    force: bool,
+) -> Result<Json<ReloadResponse>, ApiError> {
+    let state = reloadable(&reload_state)?;
+    info!(source = %state.source(), force, "Reload requested through the admin API");
+    let diff = state.reload_with_diff(store, !force).await.map_err(|e| {
+        warn!(error = %e, "Admin reload failed");
+        load_failure(&e)
+    })?;
+    Ok(Json(ReloadResponse {
+        reloaded: diff.is_some(),
+        generation: store.generation(),
+        diff,
+    }))
+}
+
+/// Preview a reload of the default data source without swapping it in.
+///
+/// The source is always fetched, checked against the validation gates and compared with the
+/// live store. The report is returned whatever the outcome, so a rejected load still shows
+/// what it would have changed.
+#[post("/api/v1/admin/reload?dry_run=true")]
+pub async fn reload_dry_run(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+) -> Result<Json<SourceReport>, ApiError> {
+    let state = reloadable(&reload_state)?;
+    let source = state.source();
+    info!(source = %source, "Dry-run reload requested through the admin API");
+    let mut report =
+        validation::validate_source(&source, &state.options(), &state.gates, Some(&**store)).await;
+    report.source = source.redacted();
+    Ok(Json(report))
+}
+
+/// Swap an empty store in for the default store and every namespace, to stop exposing
+/// anything immediately.
+///
+/// Unchanged sources are not reloaded, so the stores stay empty until a source changes
+/// or a reload is forced. A canary evaluation in progress ends without rolling back.
+#[post("/api/v1/admin/clear")]
+pub fn clear(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    namespaces: MaybeState<'_, Namespaces>,
+    events: MaybeState<'_, Events>,
+    canary: MaybeState<'_, Arc<Canary>>,
+) -> Result<Json<StoreResponse>, ApiError> {
+    let empty = || {
+        occlusion::build_store(vec![])
+            .map_err(|e| ApiError::new(Status::InternalServerError, e.to_string()))
+    };
+    let announce = |namespace: Option<&str>, store: &SwappableStore| {
+        if let Some(events) = events.0 {
+            events.send(StoreEvent::swapped(
+                namespace.map(str::to_string),
+                store,
+                None,
+            ));
+        }
+    };
+    if let Some(canary) = canary.0 {
+        canary.stop();
+    }
+    store.swap(empty()?);
+    announce(None, store);
+    for (name, namespace) in namespaces.0.into_iter().flat_map(Namespaces::iter) {
+        namespace.store.swap(empty()?);
+        announce(Some(name), &namespace.store);
+    }
+    warn!("Stores cleared through the admin API");
+    Ok(Json(StoreResponse {
+        uuid_count: 0,
+        generation: store.generation(),
+    }))
+}
+
+/// Replace the default store with the data in the request body.
+///
+/// The format comes from the `Content-Type` (then `--format`, then CSV) and the data is
+/// parsed with the source's options, may be compressed, and must pass the validation gates.
+/// Rows routed to a namespace are ignored. A canary evaluation in progress ends without
+/// rolling back.
+#[put("/api/v1/admin/store", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn replace_store(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    events: MaybeState<'_, Events>,
+    canary: MaybeState<'_, Arc<Canary>>,
+    content_type: Option<&ContentType>,
+    limits: &Limits,
+    data: Data<'_>,
+) -> Result<Json<StoreResponse>, ApiError> {
+    let limit = limits.get(STORE_LIMIT).unwrap_or_else(default_store_limit);
+    let content = data
+        .open(limit)
+        .into_bytes()
+        .await
+        .map_err(|e| ApiError::new(Status::BadRequest, e.to_string()))?;
+    if !content.is_complete() {
+        return Err(ApiError::new(
+            Status::PayloadTooLarge,
+            format!("store larger than {limit}, raise limits.{STORE_LIMIT}"),
+        ));
+    }
+
+    let (options, gates) = reload_state.0.map_or(
+        (Arc::new(LoadOptions::default()), ValidationGates::default()),
+        |state| (state.options(), state.gates),
+    );
+    let format = content_type
+        .and_then(|content_type| InputFormat::from_content_type(&content_type.to_string()))
+        .or(options.format)
+        .unwrap_or_default();
+    let loaded = loader::load_bytes(content.into_inner(), format, &options)
+        .await
+        .map_err(|e| ApiError::new(Status::UnprocessableEntity, e.to_string()))?;
+    if !loaded.namespaces.is_empty() {
+        warn!(
+            namespaces = loaded.namespaces.len(),
+            "Uploaded rows with a namespace ignored"
+        );
+    }
+    gates
+        .check(&**store, &loaded.store)
+        .map_err(|e| load_failure(&e))?;
+
+    let uuid_count = loaded.store.len();
+    let diff = events
+        .0
+        .filter(|events| events.has_subscribers())
+        .map(|_| Diff::between(&**store, &loaded.store));
+    if let Some(canary) = canary.0 {
+        canary.stop();
+    }
+    store.swap(loaded.store);
+    if let Some(events) = events.0 {
+        events.send(StoreEvent::swapped(None, store, diff));
+    }
+    warn!(uuid_count, "Store replaced through the admin API");
+    Ok(Json(StoreResponse {
+        uuid_count,
+        generation: store.generation(),
+    }))
+}
+
+/// Switch the default data source and load it now.
+///
+/// The current source and data are kept if the new source fails to load or validate.
+#[put("/api/v1/admin/source", data = "<request>")]
+pub async fn change_source(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    request: JsonBody<SourceRequest>,
+) -> Result<Json<SourceResponse>, ApiError> {
+    let state = reloadable(&reload_state)?;
+    let source = DataSource::parse(request.source.trim());
+    info!(from = %state.source(), to = %source, "Source change requested through the admin API");
+    let diff = state.repoint(store, source).await.map_err(|e| {
+        warn!(error = %e, "Source change failed");
+        load_failure(&e)
+    })?;
+    Ok(Json(SourceResponse {
+        source: state.source().redacted(),
+        generation: store.generation(),
+        diff,
+    }))
+}
+
+/// Default number of entries returned by [`sample`].
+pub const DEFAULT_SAMPLE_SIZE: usize = 50;
+
+/// Largest sample [`sample`] returns, whatever the requested size.
+pub const MAX_SAMPLE_SIZE: usize = 1000;
+
+/// Draw `n` entries of the default store at random, only among those at `level` if given,
+/// to see what the server actually loaded.
+#[get("/api/v1/admin/sample?<n>&<level>")]
+pub fn sample(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    n: Option<usize>,
+    level: Option<u8>,
+) -> Json<SampleResponse> {
+    let n = n.unwrap_or(DEFAULT_SAMPLE_SIZE).min(MAX_SAMPLE_SIZE);
+    let generation = store.generation();
+    let mut population = 0;
+    let entries = store
+        .entries()
+        .into_iter()
+        .filter(|&(_, l)| level.is_none_or(|level| l == level))
+        .inspect(|_| population += 1)
+        .map(|(object, level)| Entry { object, level })
+        .choose_multiple(&mut rand::rng(), n);
+    Json(SampleResponse {
+        generation,
+        population,
+        entries,
+    })
+}
+
+/// Chunks of an export encoded ahead of the client.
+const EXPORT_CHUNKS: usize = 16;
+
+/// Formats of [`export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum ExportFormat {
+    /// CSV with a `uuid,visibility_level` header
+    Csv,
+    /// Binary snapshot, as written to `--snapshot-dir`
+    Snapshot,
+}
+
+/// Writer passing what is written on to a response stream, in the chunks written.
+struct ChunkWriter(mpsc::Sender<Vec<u8>>);
+
+impl std::io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.blocking_send(buf.to_vec()).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "export no longer read")
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A download of the store's data.
+pub struct Export<R> {
+    format: ExportFormat,
+    generation: u64,
+    /// Metadata of the source the data was loaded from, if reloadable
+    source: Option<SourceMetadata>,
+    body: R,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Export<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let (content_type, extension) = match self.format {
+            ExportFormat::Csv => (ContentType::CSV, "csv"),
+            ExportFormat::Snapshot => (ContentType::Binary, "snapshot"),
+        };
+        let disposition = format!(
+            "attachment; filename=\"occlusion-{}.{extension}\"",
+            self.generation
+        );
+        let mut response = Response::build_from(self.body.respond_to(request)?);
+        response
+            .header(content_type)
+            .raw_header("Content-Disposition", disposition)
+            .raw_header(GENERATION_HEADER, self.generation.to_string());
+        if let Some(version) = self.source.as_ref().and_then(SourceMetadata::version) {
+            response.raw_header(SOURCE_VERSION_HEADER, version);
+        }
+        if let Some(at) = self.source.as_ref().and_then(SourceMetadata::modified_at) {
+            response.raw_header(SOURCE_MODIFIED_HEADER, at.to_string());
+        }
+        response.ok()
+    }
+}
+
+/// Stream the data of the default store, or of `namespace`'s, as it is being served.
+///
+/// The entries are copied when the export starts (sorted by UUID, so exports of the same data
+/// are identical) and streamed as they are encoded, so the export is unaffected by later swaps.
+#[get("/api/v1/admin/export?<format>&<namespace>")]
+pub fn export(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    namespaces: MaybeState<'_, Namespaces>,
+    format: Option<ExportFormat>,
+    namespace: Option<&str>,
+) -> Result<Export<ByteStream![Vec<u8>]>, ApiError> {
+    let (store, state) = match namespace {
+        Some(name) => {
+            let namespace = namespaces
+                .0
+                .and_then(|namespaces| namespaces.get(name))
+                .ok_or_else(|| {
+                    ApiError::new(Status::NotFound, format!("unknown namespace {name:?}"))
+                })?;
+            (&namespace.store, Some(&namespace.reload_state))
+        }
+        None => (&**store, reload_state.0),
+    };
+    let format = format.unwrap_or(ExportFormat::Csv);
+    let source = state.map_or_else(|| "export".to_string(), |state| state.source().redacted());
+    let generation = store.generation();
+    let metadata = state.map(|state| state.metadata.read().expect("RwLock poisoned").clone());
+    info!(
+        ?format,
+        namespace, generation, "Export requested through the admin API"
+    );
+
+    let (sender, mut receiver) = mpsc::channel(EXPORT_CHUNKS);
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || {
+        let writer = ChunkWriter(sender);
+        let result = match format {
+            ExportFormat::Csv => occlusion::export_csv(&store, writer),
+            ExportFormat::Snapshot => occlusion::export_snapshot(&store, &source, writer),
+        };
+        if let Err(e) = result {
+            warn!(error = %e, "Export interrupted");
+        }
+    });
+    Ok(Export {
+        format,
+        generation,
+        source: metadata,
+        body: ByteStream! {
+            while let Some(chunk) = receiver.recv().await {
+                yield chunk;
+            }
+        },
+    })
+}
+
+/// Default number of level shifts reported by [`diff_source`].
+pub const DEFAULT_LEVEL_SHIFTS: usize = 10;
+
+/// Compare a candidate data source with the default store, without serving it.
+///
+/// The candidate is loaded with the current source's options (sidecars at their default
+/// location follow it), but not checked against the validation gates.
+#[post("/api/v1/admin/diff", data = "<request>")]
+pub async fn diff_source(
+    _admin: Admin,
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    request: JsonBody<DiffRequest>,
+) -> Result<Json<DiffResponse>, ApiError> {
+    let source = DataSource::parse(request.source.trim());
+    let options = match reload_state.0 {
+        Some(state) => state.options().for_source(&state.source(), &source),
+        None => LoadOptions::default(),
+    };
+    info!(source = %source, "Source comparison requested through the admin API");
+    let candidate = match loader::load_with_options(&source, None, &options).await {
+        Ok(Some((candidate, _))) => candidate,
+        Ok(None) => unreachable!("unconditional load always returns data"),
+        Err(e) => {
+            warn!(error = %e, "Candidate source failed to load");
+            return Err(load_failure(&e));
+        }
+    };
+    let mut level_shifts = validation::level_shifts(&**store, &candidate);
+    level_shifts.truncate(request.top.unwrap_or(DEFAULT_LEVEL_SHIFTS));
+    Ok(Json(DiffResponse {
+        source: source.redacted(),
+        generation: store.generation(),
+        entries: candidate.len(),
+        diff: Diff::between(&**store, &candidate),
+        level_shifts,
+    }))
+}
+
+/// The scheduler of `namespace`'s own data source, or of the default source.
+fn scheduler_of<'r>(
+    scheduler: &MaybeState<'r, Arc<Scheduler>>,
+    namespaces: &MaybeState<'r, NamespaceSchedulers>,
+    namespace: Option<&str>,
+) -> Result<&'r Arc<Scheduler>, ApiError> {
+    let scheduler = scheduled(scheduler)?;
+    let Some(name) = namespace else {
+        return Ok(scheduler);
+    };
+    namespaces
+        .0
+        .and_then(|namespaces| namespaces.0.get(name))
+        .ok_or_else(|| {
+            ApiError::new(
+                Status::NotFound,
+                format!("no reload scheduler for namespace {name:?}"),
+            )
+        })
+}
+
+/// Status of the default source's reload scheduler and of each namespace's, or of
+/// `namespace`'s only.
+#[get("/api/v1/admin/scheduler?<namespace>")]
+pub fn scheduler_status(
+    _admin: Admin,
+    scheduler: MaybeState<'_, Arc<Scheduler>>,
+    namespaces: MaybeState<'_, NamespaceSchedulers>,
+    namespace: Option<&str>,
+) -> Result<Json<SchedulersStatus>, ApiError> {
+    let status = scheduler_of(&scheduler, &namespaces, namespace)?.status();
+    let namespaces = match (namespace, namespaces.0) {
+        (None, Some(namespaces)) => namespaces
+            .0
+            .iter()
+            .map(|(name, scheduler)| (name.clone(), scheduler.status()))
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    Ok(Json(SchedulersStatus { status, namespaces }))
+}
+
+/// Run the scheduled check of the default source, or of `namespace`'s, now rather than at its
+/// next due time.
+///
+/// The check runs in the background; its outcome shows up in [`scheduler_status`].
+#[post("/api/v1/admin/scheduler/run?<namespace>")]
+pub fn run_scheduler(
+    _admin: Admin,
+    scheduler: MaybeState<'_, Arc<Scheduler>>,
+    namespaces: MaybeState<'_, NamespaceSchedulers>,
+    namespace: Option<&str>,
+) -> Result<Accepted<Json<SchedulerStatus>>, ApiError> {
+    let scheduler = scheduler_of(&scheduler, &namespaces, namespace)?;
+    info!(namespace, "Scheduled check requested through the admin API");
+    scheduler.run_now();
+    Ok(Accepted(Json(scheduler.status())))
+}
@@ -0,0 +1,313 @@
+//! Load shedding: limits on the requests in flight per route class.
+//!
+//! Requests over a class's limit are turned away at once with a 503 and `Retry-After`, rather
+//! than queueing behind the ones being served, so a burst of large batches cannot build an
+//! unbounded backlog in front of cheap single checks. Probes and stats are never limited.
+//!
+//! With [`RejectStale`], data-plane requests are also turned away while the data answering
+//! them is stale, rather than answered from data that may be long out of date.
+
+use crate::{
+    ReloadState,
+    cache::note_generation,
+    catchers::{set_error_message, set_retry_after},
+    namespace::Namespaces,
+    pinning::Pin,
+    timeout::Deadline,
+};
+use rocket::{
+    Request,
+    http::Status,
+    outcome::try_outcome,
+    request::{FromRequest, Outcome},
+};
+use std::{fmt, sync::Arc};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Kinds of routes limited separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Visibility checks and lookups
+    DataPlane,
+    /// The admin API
+    Admin,
+}
+
+impl fmt::Display for RouteClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::DataPlane => "data-plane",
+            Self::Admin => "admin",
+        })
+    }
+}
+
+/// In-flight request limits, managed by Rocket when load shedding is enabled.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimits {
+    data_plane: Option<Arc<Semaphore>>,
+    admin: Option<Arc<Semaphore>>,
+    /// Seconds clients are told to wait before retrying a shed request
+    retry_after: u64,
+}
+
+impl ConcurrencyLimits {
+    /// Limits of `data_plane` and `admin` requests in flight (0 = unlimited).
+    pub fn new(data_plane: usize, admin: usize, retry_after: u64) -> Self {
+        let semaphore = |limit| (limit > 0).then(|| Arc::new(Semaphore::new(limit)));
+        Self {
+            data_plane: semaphore(data_plane),
+            admin: semaphore(admin),
+            retry_after,
+        }
+    }
+
+    fn semaphore(&self, class: RouteClass) -> Option<&Arc<Semaphore>> {
+        match class {
+            RouteClass::DataPlane => self.data_plane.as_ref(),
+            RouteClass::Admin => self.admin.as_ref(),
+        }
+    }
+
+    /// Take a slot of `class`, or `None` when its limit is reached.
+    pub fn try_take(&self, class: RouteClass) -> Option<Slot> {
+        match self.semaphore(class) {
+            Some(semaphore) => semaphore
+                .clone()
+                .try_acquire_owned()
+                .ok()
+                .map(|permit| Slot {
+                    _permit: Some(permit),
+                }),
+            None => Some(Slot { _permit: None }),
+        }
+    }
+}
+
+/// Slot taken by a request, released when dropped: for HTTP requests, after their response.
+pub struct Slot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Take a slot of `class` for `request`, or record why it is shed.
+///
+/// Always succeeds when no [`ConcurrencyLimits`] are managed.
+pub fn admit(request: &Request<'_>, class: RouteClass) -> Result<(), Status> {
+    let Some(limits) = request.rocket().state::<ConcurrencyLimits>() else {
+        return Ok(());
+    };
+    if let Some(slot) = limits.try_take(class) {
+        request.local_cache(|| slot);
+        return Ok(());
+    }
+    set_error_message(
+        request,
+        format!("too many {class} requests in flight, retry later"),
+    );
+    set_retry_after(request, limits.retry_after);
+    Err(Status::ServiceUnavailable)
+}
+
+/// Managed by Rocket to answer data-plane requests with a 503 while their data is older than
+/// its source's maximum staleness.
+#[derive(Debug, Clone, Copy)]
+pub struct RejectStale;
+
+/// Check that the data answering `request` is not stale, or record why it is.
+///
+/// Namespace-scoped routes are answered from their namespace's data, others from the default
+/// source's. Always succeeds when [`RejectStale`] is not managed.
+pub fn check_fresh(request: &Request<'_>) -> Result<(), Status> {
+    let rocket = request.rocket();
+    if rocket.state::<RejectStale>().is_none() {
+        return Ok(());
+    }
+    let namespaced = request
+        .route()
+        .is_some_and(|route| route.uri.path().starts_with("/api/v1/ns/"));
+    let state = if namespaced {
+        request
+            .routed_segment(3)
+            .and_then(|name| rocket.state::<Namespaces>()?.get(name))
+            .map(|namespace| &namespace.reload_state)
+    } else {
+        rocket.state::<Arc<ReloadState>>()
+    };
+    let Some(age) = state.and_then(|state| state.staleness()) else {
+        return Ok(());
+    };
+    set_error_message(
+        request,
+        format!("data is stale: not refreshed for {}s", age.as_secs()),
+    );
+    Err(Status::ServiceUnavailable)
+}
+
+/// Request guard admitting data-plane requests within the [`ConcurrencyLimits`].
+///
+/// Fails with 503 and `Retry-After` when the data-plane limit is reached, with 503 when the
+/// data is stale and [`RejectStale`] is managed, and with 400 when the request pins an invalid
+/// generation. Admitted requests get the
+/// [generation header](crate::cache::GenerationHeader).
+pub struct DataPlane {
+    /// When the admitted request must be done by
+    pub deadline: Deadline,
+    /// The generation the request must be answered from, if any
+    pub pin: Pin,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DataPlane {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pin = try_outcome!(Pin::from_request(request).await);
+        if let Err(status) = check_fresh(request) {
+            return Outcome::Error((status, "stale data"));
+        }
+        match admit(request, RouteClass::DataPlane) {
+            Ok(()) => {
+                note_generation(request, pin);
+                Outcome::Success(DataPlane {
+                    deadline: Deadline::of(request),
+                    pin,
+                })
+            }
+            Err(status) => Outcome::Error((status, "request shed")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{
+        http::{ContentType, Header},
+        local::blocking::Client,
+    };
+    use uuid::Uuid;
+
+    #[test]
+    fn test_shed() {
+        let limits = ConcurrencyLimits::new(1, 1, 3);
+        let entries = vec![(Uuid::from_u128(1), 0)];
+        let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let rocket = rocket::build()
+            .manage(store)
+            .manage(limits.clone())
+            .manage(crate::guards::AdminToken("secret".to_string()))
+            .register("/", crate::catchers::catchers())
+            .mount(
+                "/",
+                rocket::routes![crate::routes::check, crate::admin::clear],
+            );
+        let client = Client::tracked(rocket).unwrap();
+        let check = || {
+            client
+                .post("/api/v1/check")
+                .header(ContentType::JSON)
+                .body(format!(
+                    r#"{{"object": "{}", "visibility_mask": 5}}"#,
+                    Uuid::from_u128(1)
+                ))
+                .dispatch()
+        };
+        assert_eq!(
+            check().status(),
+            Status::Ok,
+            "slot released after each request"
+        );
+        assert_eq!(check().status(), Status::Ok);
+
+        // Fill the data-plane slot, as a request in flight would
+        let held = limits
+            .data_plane
+            .clone()
+            .unwrap()
+            .try_acquire_owned()
+            .unwrap();
+        let response = check();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("3"));
+        let body: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert_eq!(
+            body.error.message,
+            "too many data-plane requests in flight, retry later"
+        );
+
+        // The admin API is limited separately
+        let response = client
+            .post("/api/v1/admin/clear")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        drop(held);
+        assert_eq!(check().status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_reject_stale() {
+        use crate::source::{DataSource, SourceMetadata};
+        use std::time::{Duration, SystemTime};
+
+        let state = Arc::new(
+            ReloadState::new(
+                DataSource::parse("data.csv"),
+                crate::loader::LoadOptions::default(),
+                SourceMetadata::new(),
+            )
+            .with_max_staleness(Duration::from_hours(1)),
+        );
+        let entries = vec![(Uuid::from_u128(1), 0)];
+        let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let rocket = rocket::build()
+            .manage(store)
+            .manage(state.clone())
+            .manage(RejectStale)
+            .register("/", crate::catchers::catchers())
+            .mount(
+                "/",
+                rocket::routes![crate::routes::check, crate::routes::readyz],
+            );
+        let client = Client::tracked(rocket).unwrap();
+        let check = || {
+            client
+                .post("/api/v1/check")
+                .header(ContentType::JSON)
+                .body(format!(
+                    r#"{{"object": "{}", "visibility_mask": 5}}"#,
+                    Uuid::from_u128(1)
+                ))
+                .dispatch()
+        };
+
+        // Data of unknown age is not stale
+        assert_eq!(check().status(), Status::Ok);
+        *state.last_success.write().unwrap() = Some(SystemTime::now());
+        assert_eq!(check().status(), Status::Ok);
+        assert_eq!(client.get("/readyz").dispatch().status(), Status::Ok);
+
+        *state.last_success.write().unwrap() = Some(SystemTime::now() - Duration::from_mins(61));
+        let response = check();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert!(
+            body.error
+                .message
+                .starts_with("data is stale: not refreshed for 36"),
+            "{}",
+            body.error.message
+        );
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert!(
+            body.error
+                .message
+                .starts_with("not ready: data.csv not refreshed for 36"),
+            "{}",
+            body.error.message
+        );
+    }
+}
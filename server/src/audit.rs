@@ -0,0 +1,244 @@
+//! Audit log of authorization decisions.
+//!
+//! With auditing enabled, every object checked, through any endpoint, is recorded with the
+//! caller, the mask it was checked against, the outcome and the generation of the data it was
+//! decided on, so what was answered, to whom and from which dataset can be proven later.
+//! Events go to the same kinds of sinks as the [decision log](crate::decisions).
+
+use crate::{
+    access_log::AccessNote,
+    canary::Canary,
+    decisions::{DecisionLogOptions, DecisionSink, spawn_writer},
+    models::Decision,
+    request_id,
+};
+use occlusion::{Store, SwappableStore};
+use rocket::{
+    Request,
+    request::{FromRequest, Outcome},
+};
+use serde::Serialize;
+use std::{net::IpAddr, sync::Arc, time::SystemTime};
+use tokio::sync::mpsc;
+use tracing::warn;
+use uuid::Uuid;
+
+/// A decision on one object, as an audit event.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    /// RFC 3339
+    pub timestamp: String,
+    /// ID of the request the decision was made for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Identity of the caller's credentials: JWT subject or client certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Endpoint the check was made through
+    pub path: String,
+    pub object: Uuid,
+    /// Mask the object was checked against
+    pub mask: u8,
+    pub decision: Decision,
+    /// Generation of the data the decision was made on
+    pub generation: u64,
+}
+
+/// Handle to the audit log, managed by Rocket when auditing is enabled.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: mpsc::Sender<AuditEvent>,
+}
+
+impl AuditLog {
+    /// Start the background writer for `sink`.
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn spawn(sink: DecisionSink, options: DecisionLogOptions) -> Self {
+        Self {
+            sender: spawn_writer(sink, options),
+        }
+    }
+
+    /// Queue a decision; it is dropped with a warning if the queue is full.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        request_id: Option<&str>,
+        path: &str,
+        caller: Option<&str>,
+        remote: Option<IpAddr>,
+        object: Uuid,
+        mask: u8,
+        decision: Decision,
+        generation: u64,
+    ) {
+        let event = AuditEvent {
+            timestamp: humantime::format_rfc3339_micros(SystemTime::now()).to_string(),
+            request_id: request_id.map(str::to_string),
+            caller: caller.map(str::to_string),
+            remote: remote.map(|ip| ip.to_string()),
+            path: path.to_string(),
+            object,
+            mask,
+            decision,
+            generation,
+        };
+        if self.sender.try_send(event).is_err() {
+            warn!("Audit log queue full, dropping decision");
+        }
+    }
+}
+
+/// Request guard recording a request's decisions: its outcome in the access log and, with
+/// auditing enabled, each object's in the audit log.
+///
+/// With [canary evaluation](crate::canary) enabled, each decision is also offered to the
+/// canary. Otherwise, without auditing, checks go straight to the store's fastest lookups.
+pub struct Decisions<'r> {
+    log: Option<&'r AuditLog>,
+    /// Evaluation of reloaded data, for checks against the default store
+    canary: Option<&'r Arc<Canary>>,
+    note: &'r AccessNote,
+    request_id: &'r str,
+    path: &'r str,
+    remote: Option<IpAddr>,
+}
+
+impl Decisions<'_> {
+    /// Check one object, recording the decision.
+    pub fn is_visible(&self, store: &SwappableStore, object: &Uuid, mask: u8) -> bool {
+        if self.log.is_none() && self.canary.is_none() {
+            return store.is_visible(object, mask);
+        }
+        let (generation, levels) = store.get_visibilities(std::slice::from_ref(object));
+        let decision = Decision::new(levels[0], mask);
+        self.record(*object, mask, decision, generation);
+        decision == Decision::Visible
+    }
+
+    /// Check whether every object is visible, recording each decision.
+    pub fn check_batch(&self, store: &SwappableStore, objects: &[Uuid], mask: u8) -> bool {
+        if self.log.is_none() && self.canary.is_none() {
+            return store.check_batch(objects, mask);
+        }
+        let (generation, levels) = store.get_visibilities(objects);
+        let mut all_visible = true;
+        for (object, level) in objects.iter().zip(levels) {
+            let decision = Decision::new(level, mask);
+            self.record(*object, mask, decision, generation);
+            all_visible &= decision == Decision::Visible;
+        }
+        all_visible
+    }
+
+    /// Record a decision made from data of `generation`.
+    pub fn record(&self, object: Uuid, mask: u8, decision: Decision, generation: u64) {
+        if let Some(canary) = self.canary {
+            canary.observe(&object, mask);
+        }
+        if let Some(log) = self.log {
+            let caller = self.note.caller();
+            log.record(
+                Some(self.request_id),
+                self.path,
+                caller,
+                self.remote,
+                object,
+                mask,
+                decision,
+                generation,
+            );
+        }
+    }
+
+    /// Record the outcome of the whole request.
+    pub fn outcome(&self, allowed: bool) {
+        self.note.set_decision(allowed);
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Decisions<'r> {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let path = request.uri().path().as_str();
+        Outcome::Success(Decisions {
+            log: request.rocket().state::<AuditLog>(),
+            canary: request
+                .rocket()
+                .state::<Arc<Canary>>()
+                .filter(|_| !path.starts_with("/api/v1/ns/")),
+            note: AccessNote::of(request),
+            request_id: request_id::of(request),
+            path,
+            remote: request.client_ip(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::blocking::Client;
+    use std::time::Duration;
+
+    #[test]
+    fn test_audit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let options = DecisionLogOptions {
+            batch_size: 1,
+            flush_interval: Duration::from_millis(10),
+        };
+        let log = rt.block_on(async { AuditLog::spawn(DecisionSink::File(path.clone()), options) });
+
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 10)];
+        let store = SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let generation = store.generation();
+        let rocket = rocket::build()
+            .manage(store)
+            .manage(log)
+            .mount("/", rocket::routes![crate::routes::check_batch]);
+        let client = Client::tracked(rocket).unwrap();
+        client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Real-IP", "10.0.0.7"))
+            .header(Header::new("X-Request-Id", "req-1"))
+            .body(format!(
+                r#"{{"objects": ["{}", "{}", "{}"], "visibility_mask": 5}}"#,
+                Uuid::from_u128(1),
+                Uuid::from_u128(2),
+                Uuid::from_u128(3)
+            ))
+            .dispatch();
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            events = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect();
+            if events.len() == 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(events.len(), 3);
+        let decisions: Vec<_> = events.iter().map(|event| &event["decision"]).collect();
+        assert_eq!(decisions, ["visible", "denied", "unknown"]);
+        assert_eq!(events[1]["object"], Uuid::from_u128(2).to_string());
+        assert_eq!(events[1]["mask"], 5);
+        assert_eq!(events[1]["generation"], generation);
+        assert_eq!(events[1]["path"], "/api/v1/check/batch");
+        assert_eq!(events[1]["remote"], "10.0.0.7");
+        assert_eq!(events[1]["request_id"], "req-1");
+    }
+}
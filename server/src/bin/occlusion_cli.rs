@@ -0,0 +1,485 @@
+//! Command-line client for operating occlusion servers.
+
+use clap::{Parser, Subcommand};
+use reqwest::Url;
+use server::{
+    format::{CsvColumn, CsvOptions, ErrorBudget, InputFormat, UuidByteOrder, parse_delimiter},
+    ids::{IdNamespace, ObjectId},
+    levels::LevelNames,
+    loader::LoadOptions,
+    models::{
+        BatchCheckRequestV2, BatchCheckResponseV2, BatchObject, CheckRequest, Decision,
+        ErrorResponse,
+    },
+    validation::{self, FileReport},
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+};
+use uuid::Uuid;
+
+/// Command-line client for occlusion servers
+#[derive(Parser, Debug)]
+#[command(name = "occlusion-cli")]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check the visibility of objects on a running server
+    Query(QueryArgs),
+    /// Check a data file before publishing it, parsing it as the server would
+    Validate(ValidateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    /// Base URL of the server
+    #[arg(
+        long,
+        default_value = "http://127.0.0.1:8000",
+        env = "OCCLUSION_SERVER"
+    )]
+    server: Url,
+
+    /// Object to check (a UUID, or any ID with --id-namespace); repeat for several
+    #[arg(long = "uuid", value_name = "OBJECT")]
+    objects: Vec<String>,
+
+    /// File of objects to check, one per line as OBJECT or OBJECT,MASK ("-" for stdin)
+    #[arg(long, value_name = "PATH")]
+    file: Option<String>,
+
+    /// Visibility mask of the viewer (optional when the token carries one)
+    #[arg(long)]
+    mask: Option<u8>,
+
+    /// Bearer token sent with the requests, e.g. a JWT carrying the viewer's mask
+    #[arg(long, env = "OCCLUSION_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// Print each object's level along with its decision
+    #[arg(long)]
+    levels: bool,
+
+    /// Print the server's responses as JSON, one line per batch
+    #[arg(long)]
+    json: bool,
+
+    /// Objects sent per request (at most the server's --max-batch-size)
+    #[arg(long, default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// Data file to check
+    #[arg(value_name = "PATH")]
+    path: PathBuf,
+
+    /// Input format (detected from the extension if unset)
+    #[arg(long, value_enum)]
+    format: Option<InputFormat>,
+
+    /// CSV field delimiter (a single ASCII character, or `tab`)
+    #[arg(long, default_value = ",", value_parser = parse_delimiter)]
+    csv_delimiter: u8,
+
+    /// CSV input has no header row (columns must then be selected by position)
+    #[arg(long)]
+    csv_no_header: bool,
+
+    /// CSV column holding the UUID: a header name or a zero-based position
+    #[arg(long, default_value = "uuid")]
+    csv_uuid_column: CsvColumn,
+
+    /// CSV column holding the visibility level: a header name or a zero-based position
+    #[arg(long, default_value = "visibility_level")]
+    csv_level_column: CsvColumn,
+
+    /// Optional column routing each row to a namespace: a header name or a zero-based position
+    #[arg(long, default_value = "namespace")]
+    csv_namespace_column: CsvColumn,
+
+    /// JSON file mapping role names to visibility levels
+    #[arg(long, value_name = "PATH")]
+    level_names: Option<PathBuf>,
+
+    /// Byte order of UUIDs given as raw bytes
+    #[arg(long, value_enum, default_value_t)]
+    uuid_byte_order: UuidByteOrder,
+
+    /// Accept non-UUID object IDs, hashed under this namespace UUID
+    #[arg(long, value_name = "UUID")]
+    id_namespace: Option<Uuid>,
+
+    /// Malformed rows tolerated, as N rows or N% of rows (none when unset)
+    #[arg(long, value_name = "N|N%")]
+    error_budget: Option<ErrorBudget>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("create tokio runtime");
+    let result = match args.command {
+        Command::Query(args) => runtime.block_on(query(args)),
+        Command::Validate(args) => validate(&args),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Check the objects of `args`, printing a decision per object.
+///
+/// Exits with 0 when every object is visible, 2 when some are denied or unknown.
+async fn query(args: QueryArgs) -> Result<ExitCode, String> {
+    let mut entries: Vec<(String, Option<u8>)> = args
+        .objects
+        .iter()
+        .map(|object| (object.clone(), None))
+        .collect();
+    if let Some(path) = &args.file {
+        entries.extend(read_entries(path).map_err(|e| format!("{path}: {e}"))?);
+    }
+    if entries.is_empty() {
+        return Err("nothing to check: pass --uuid or --file".to_string());
+    }
+
+    let url = args
+        .server
+        .join("api/v2/check/batch")
+        .map_err(|e| format!("invalid server URL: {e}"))?;
+    let client = reqwest::Client::new();
+    let batch_size = usize::try_from(args.batch_size).unwrap_or(usize::MAX);
+    let mut all_visible = true;
+    for batch in entries.chunks(batch_size) {
+        let request = BatchCheckRequestV2 {
+            objects: batch
+                .iter()
+                .map(|(object, mask)| {
+                    let object = ObjectId::Text(object.clone());
+                    match *mask {
+                        Some(visibility_mask) => BatchObject::Masked(CheckRequest {
+                            object,
+                            visibility_mask,
+                        }),
+                        None => BatchObject::Object(object),
+                    }
+                })
+                .collect(),
+            visibility_mask: args.mask,
+            include_levels: args.levels,
+        };
+        let mut builder = client.post(url.clone()).json(&request);
+        if let Some(token) = &args.token {
+            builder = builder.bearer_auth(token);
+        }
+        let response = builder.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            let message = serde_json::from_slice::<ErrorResponse>(&body).map_or_else(
+                |_| String::from_utf8_lossy(&body).into_owned(),
+                |e| e.error.message,
+            );
+            return Err(format!("server answered {status}: {message}"));
+        }
+        let response: BatchCheckResponseV2 =
+            serde_json::from_slice(&body).map_err(|e| format!("invalid response: {e}"))?;
+        all_visible &= response.all_visible;
+
+        if args.json {
+            println!("{}", String::from_utf8_lossy(&body));
+            continue;
+        }
+        for ((object, _), result) in batch.iter().zip(&response.results) {
+            let decision = match result.decision {
+                Decision::Visible => "visible",
+                Decision::Denied => "denied",
+                Decision::Unknown => "unknown",
+            };
+            match result.level {
+                Some(level) => println!("{object}\t{decision}\t{level}"),
+                None => println!("{object}\t{decision}"),
+            }
+        }
+    }
+    Ok(if all_visible {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(2)
+    })
+}
+
+/// Parse the file of `args` and print what it holds.
+///
+/// Exits with 0 when the server would load the file, 1 otherwise.
+fn validate(args: &ValidateArgs) -> Result<ExitCode, String> {
+    let level_names = match &args.level_names {
+        Some(path) => Some(Arc::new(
+            LevelNames::read(path).map_err(|e| format!("{}: {e}", path.display()))?,
+        )),
+        None => None,
+    };
+    let options = LoadOptions {
+        format: args.format,
+        csv: CsvOptions {
+            delimiter: args.csv_delimiter,
+            has_headers: !args.csv_no_header,
+            uuid_column: args.csv_uuid_column.clone(),
+            level_column: args.csv_level_column.clone(),
+            namespace_column: args.csv_namespace_column.clone(),
+            level_names,
+            uuid_byte_order: args.uuid_byte_order,
+            id_namespace: args.id_namespace.map(IdNamespace),
+            ..CsvOptions::default()
+        },
+        error_budget: args.error_budget,
+        ..LoadOptions::default()
+    };
+
+    let report = validation::inspect_file(&args.path, &options);
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+        );
+    } else {
+        print_report(&report);
+    }
+    Ok(ExitCode::from(
+        u8::try_from(report.status.exit_code()).unwrap_or(1),
+    ))
+}
+
+fn print_report(report: &FileReport) {
+    #[allow(clippy::cast_precision_loss)]
+    let percent = |count: usize| count as f64 * 100.0 / report.rows.max(1) as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let mib = |bytes: usize| bytes as f64 / f64::from(1 << 20);
+
+    match &report.error {
+        Some(error) => println!("{} ({}): invalid: {error}", report.path, report.format),
+        None => println!("{} ({}): valid", report.path, report.format),
+    }
+    println!(
+        "rows: {} ({} malformed, {} duplicates)",
+        report.rows, report.rows_rejected, report.duplicates
+    );
+    for rejected in &report.rejected {
+        println!("  line {}: {}", rejected.line, rejected.reason);
+    }
+    for uuid in &report.duplicated {
+        println!("  duplicate: {uuid}");
+    }
+    if !report.distribution.is_empty() {
+        println!("levels:");
+        for (level, &count) in &report.distribution {
+            println!("  {level:>3}  {count:>10}  {:5.1}%", percent(count));
+        }
+    }
+    if !report.namespaces.is_empty() {
+        println!("namespaces:");
+        for (namespace, count) in &report.namespaces {
+            println!("  {namespace}  {count}");
+        }
+    }
+    if report.rows > 0 {
+        println!("estimated memory:");
+        for (algorithm, &bytes) in &report.memory {
+            println!("  {algorithm:<8}  {:>10.1} MiB", mib(bytes));
+        }
+    }
+}
+
+/// Objects listed in `path`, skipping blank lines and `#` comments.
+fn read_entries(path: &str) -> io::Result<Vec<(String, Option<u8>)>> {
+    let reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let mut entries = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = match line.split_once(',') {
+            Some((object, mask)) => {
+                let mask = mask.trim().parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: invalid mask {mask:?}", number + 1),
+                    )
+                })?;
+                (object.trim().to_string(), Some(mask))
+            }
+            None => (line.to_string(), None),
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::models::ObjectDecision;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    #[test]
+    fn test_read_entries() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "# objects to check\n\n00000000-0000-0000-0000-000000000001\n  order-7 , 5 \n"
+        )
+        .unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        assert_eq!(
+            read_entries(&path).unwrap(),
+            vec![
+                ("00000000-0000-0000-0000-000000000001".to_string(), None),
+                ("order-7".to_string(), Some(5)),
+            ]
+        );
+
+        writeln!(file, "# still fine\norder-8,256").unwrap();
+        let e = read_entries(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "line 6: invalid mask \"256\"");
+    }
+
+    /// Answer `requests` batch checks on a local port as a server holding UUID 1 at level 3
+    /// would, returning its URL and the requests received.
+    fn serve(requests: usize) -> (Url, mpsc::Receiver<BatchCheckRequestV2>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(str::to_string)
+                        })
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                };
+                let request: BatchCheckRequestV2 = serde_json::from_str(&body).unwrap();
+                let results: Vec<ObjectDecision> = request
+                    .objects
+                    .iter()
+                    .map(|object| {
+                        let (object, mask) = match object {
+                            BatchObject::Object(object) => {
+                                (object, request.visibility_mask.unwrap())
+                            }
+                            BatchObject::Masked(check) => (&check.object, check.visibility_mask),
+                        };
+                        let object = object.resolve(None).unwrap();
+                        let level = (object == Uuid::from_u128(1)).then_some(3);
+                        ObjectDecision {
+                            object,
+                            decision: Decision::new(level, mask),
+                            level: level.filter(|_| request.include_levels),
+                        }
+                    })
+                    .collect();
+                let response = serde_json::to_string(&BatchCheckResponseV2 {
+                    all_visible: results.iter().all(|r| r.decision == Decision::Visible),
+                    generation: 1,
+                    results,
+                })
+                .unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+                sender.send(request).unwrap();
+            }
+        });
+        (url.parse().unwrap(), received)
+    }
+
+    fn args(server: Url, objects: &[&str], mask: u8) -> QueryArgs {
+        QueryArgs {
+            server,
+            objects: objects.iter().map(ToString::to_string).collect(),
+            file: None,
+            mask: Some(mask),
+            token: None,
+            levels: true,
+            json: false,
+            batch_size: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let one = Uuid::from_u128(1).to_string();
+        let two = Uuid::from_u128(2).to_string();
+
+        let (url, received) = serve(1);
+        let code = query(args(url, &[&one], 3)).await.unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+        let request = received.recv().unwrap();
+        assert_eq!(request.visibility_mask, Some(3));
+        assert!(request.include_levels);
+
+        // Denied and unknown objects, sent one batch at a time
+        let (url, received) = serve(3);
+        let code = query(args(url.clone(), &[&one, &one, &two], 2))
+            .await
+            .unwrap();
+        assert_eq!(code, ExitCode::from(2));
+        assert_eq!(received.iter().map(|r| r.objects.len()).sum::<usize>(), 3);
+
+        let code = query(args(url, &[], 2)).await.unwrap_err();
+        assert_eq!(code, "nothing to check: pass --uuid or --file");
+    }
+}
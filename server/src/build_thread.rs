@@ -0,0 +1,72 @@
+//! A dedicated thread for store builds.
+//!
+//! Builds normally run on tokio's blocking pool, which they share with file reads and
+//! snapshot writes. With `--build-thread`, they run one at a time on a single named thread
+//! instead, so a long parse ties up exactly one OS thread and never competes with the
+//! request executor or the rest of the blocking pool for CPUs.
+
+use crate::error::{LoadError, Result};
+use std::{panic::AssertUnwindSafe, sync::mpsc};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Handle to the build thread; clones share the thread, which exits once every handle is
+/// dropped.
+#[derive(Debug, Clone)]
+pub struct BuildThread {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl BuildThread {
+    /// Start the build thread.
+    pub fn spawn() -> std::io::Result<Self> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("occlusion-build".to_string())
+            .spawn(move || {
+                for job in queue {
+                    job();
+                }
+            })?;
+        Ok(Self { jobs })
+    }
+
+    /// Run `build` on the build thread once the builds queued before it are done.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        build: impl FnOnce() -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let (result, receiver) = tokio::sync::oneshot::channel();
+        self.jobs
+            .send(Box::new(move || {
+                // A panicking build drops the sender, and the thread lives on
+                if let Ok(built) = std::panic::catch_unwind(AssertUnwindSafe(build)) {
+                    let _ = result.send(built);
+                }
+            }))
+            .map_err(|_| LoadError::InvalidFormat("Build thread stopped".to_string()))?;
+        receiver
+            .await
+            .map_err(|_| LoadError::InvalidFormat("Build panicked".to_string()))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run() {
+        let thread = BuildThread::spawn().unwrap();
+        let name = thread
+            .run(|| Ok(std::thread::current().name().map(str::to_string)))
+            .await
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("occlusion-build"));
+
+        let e = thread.run(|| -> Result<()> { panic!("boom") }).await;
+        assert_eq!(e.unwrap_err().to_string(), "Invalid format: Build panicked");
+        // The thread survives a panicking build
+        assert_eq!(thread.run(|| Ok(1)).await.unwrap(), 1);
+    }
+}
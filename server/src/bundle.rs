@@ -0,0 +1,140 @@
+//! OPA bundles: tarballs of `data.json` documents (and policies, which are ignored).
+//!
+//! Each `data.json` is mounted at the path of its directory, so `tenants/data.json`
+//! provides the data under `/tenants`. The UUID to level object is found by resolving
+//! a JSON pointer against the mounted documents.
+
+use crate::{
+    error::{LoadError, Result},
+    format::{CsvOptions, ParseReport, Rows, json_entry},
+};
+use std::{
+    io::Read,
+    path::{Component, Path},
+};
+
+/// File name of the data documents in a bundle.
+const DATA_FILE: &str = "data.json";
+
+/// JSON pointer of the directory a bundle file is mounted at.
+fn mount_point(path: &Path) -> String {
+    path.parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(format!("/{}", name.to_string_lossy())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Extract the entries at the configured pointer from an uncompressed bundle tarball.
+pub(crate) fn parse_bundle(
+    content: &[u8],
+    options: &CsvOptions,
+    report: &mut ParseReport,
+) -> Result<Rows> {
+    let pointer = options.bundle_pointer.as_str();
+    let mut archive = tar::Archive::new(content);
+    for file in archive.entries()? {
+        let mut file = file?;
+        let path = file.path()?.into_owned();
+        if path.file_name().is_none_or(|name| name != DATA_FILE) {
+            continue;
+        }
+        let Some(rest) = pointer.strip_prefix(&mount_point(&path)) else {
+            continue;
+        };
+        if !rest.is_empty() && !rest.starts_with('/') {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let document: serde_json::Value = serde_json::from_slice(&data)
+            .map_err(|e| LoadError::InvalidFormat(format!("{}: {e}", path.display())))?;
+        let Some(value) = document.pointer(rest) else {
+            continue;
+        };
+        let object = value.as_object().ok_or_else(|| {
+            LoadError::InvalidFormat(format!(
+                "{}: {pointer:?} is not an object of UUIDs to levels",
+                path.display()
+            ))
+        })?;
+
+        let mut rows = Rows::default();
+        for (index, (key, value)) in object.iter().enumerate() {
+            match json_entry(key, value, index + 1, options) {
+                Ok(entry) => {
+                    rows.entries.push(entry);
+                    report.parsed(rows.len());
+                }
+                Err(e) => report.reject(index + 1, e)?,
+            }
+        }
+        return Ok(rows);
+    }
+
+    Err(LoadError::InvalidFormat(format!(
+        "No {DATA_FILE} in the bundle provides {pointer:?}"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{CsvOptions, InputFormat};
+    use uuid::Uuid;
+
+    /// Build a tarball of `(path, contents)` files.
+    fn bundle(files: &[(&str, String)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_parse_bundle() {
+        let content = bundle(&[
+            (".manifest", r#"{"roots": [""]}"#.to_string()),
+            ("policy.rego", "package authz".to_string()),
+            (
+                "data.json",
+                format!(
+                    r#"{{"other": 1, "occlusion": {{"{}": 4}}}}"#,
+                    Uuid::from_u128(1)
+                ),
+            ),
+            (
+                "tenants/acme/data.json",
+                format!(r#"{{"visibility": {{"{}": 9}}}}"#, Uuid::from_u128(2)),
+            ),
+        ]);
+        let parse = |pointer: &str| {
+            let options = CsvOptions {
+                bundle_pointer: pointer.to_string(),
+                ..CsvOptions::default()
+            };
+            InputFormat::OpaBundle
+                .parse(content.clone(), &options, None, None)
+                .map(|(rows, _)| rows.entries)
+        };
+
+        assert_eq!(parse("/occlusion").unwrap(), vec![(Uuid::from_u128(1), 4)]);
+        assert_eq!(
+            parse("/tenants/acme/visibility").unwrap(),
+            vec![(Uuid::from_u128(2), 9)]
+        );
+        assert!(parse("/other").is_err());
+        let err = parse("/missing").unwrap_err();
+        assert!(err.to_string().contains("No data.json"), "{err}");
+    }
+}
@@ -0,0 +1,136 @@
+//! HTTP caching of read endpoints.
+//!
+//! Responses that only change when the data does carry an `ETag` derived from the store
+//! generation, so caches can revalidate them with `If-None-Match` and get a bodiless 304
+//! until the next reload. Data-plane responses also tell the generation they were answered
+//! from in `X-Occlusion-Generation`, so clients caching decisions can tell when to drop them.
+
+use crate::pinning::Pin;
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::{Header, Status},
+    response::{self, Responder},
+};
+use std::fmt::Display;
+
+/// Header carrying the generation of the data a data-plane response was answered from.
+pub const GENERATION_HEADER: &str = "X-Occlusion-Generation";
+
+/// Generation of the store a request is answered from, once noted.
+struct Generation(Option<u64>);
+
+/// Note the generation of the store `request` is answered from, before the lookups: like
+/// `ETag`s, the header can be older than the data, costing clients a refetch, but never newer.
+///
+/// Namespace-scoped requests are answered from their namespace's store, and requests
+/// [pinned](crate::pinning) to a generation from that generation.
+pub fn note_generation(request: &Request<'_>, pin: Pin) {
+    request.local_cache(|| {
+        let store = crate::namespaced::store_of(request);
+        Generation(store.map(|store| pin.0.unwrap_or_else(|| store.generation())))
+    });
+}
+
+/// Fairing adding `X-Occlusion-Generation` to the responses of data-plane requests.
+pub struct GenerationHeader;
+
+#[rocket::async_trait]
+impl Fairing for GenerationHeader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Generation Header",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Generation(Some(generation)) = request.local_cache(|| Generation(None)) {
+            response.set_header(Header::new(GENERATION_HEADER, generation.to_string()));
+        }
+    }
+}
+
+/// How long caches may reuse a response without revalidating it, in seconds.
+///
+/// When not managed, or 0, responses are sent with `Cache-Control: no-cache`: caches keep them
+/// but revalidate each use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheMaxAge(pub u64);
+
+/// A response with an `ETag` and `Cache-Control`, answered with 304 Not Modified when the
+/// request's `If-None-Match` lists its tag.
+pub struct Cached<R> {
+    etag: String,
+    private: bool,
+    inner: R,
+}
+
+impl<R> Cached<R> {
+    /// Tag `inner` with `version`, which must change whenever its content may.
+    pub fn new(version: impl Display, inner: R) -> Self {
+        Self {
+            etag: format!("\"{version}\""),
+            private: false,
+            inner,
+        }
+    }
+
+    /// Mark the response as specific to the caller, so shared caches do not store it.
+    #[must_use]
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Cached<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let max_age = request
+            .rocket()
+            .state::<CacheMaxAge>()
+            .map_or(0, |age| age.0);
+        let mut cache_control = match max_age {
+            0 => "no-cache".to_string(),
+            age => format!("max-age={age}"),
+        };
+        if self.private {
+            cache_control.insert_str(0, "private, ");
+        }
+
+        let not_modified = request
+            .headers()
+            .get("If-None-Match")
+            .any(|value| matches(value, &self.etag));
+        let mut response = if not_modified {
+            Response::build().status(Status::NotModified).finalize()
+        } else {
+            self.inner.respond_to(request)?
+        };
+        response.set_header(Header::new("ETag", self.etag));
+        response.set_header(Header::new("Cache-Control", cache_control));
+        Ok(response)
+    }
+}
+
+/// Whether an `If-None-Match` value lists `etag`, by weak comparison.
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("\"7\"", "\"7\""));
+        assert!(matches("\"6\", W/\"7\"", "\"7\""));
+        assert!(matches("*", "\"7\""));
+        assert!(!matches("\"6\"", "\"7\""));
+        assert!(!matches("7", "\"7\""));
+    }
+}
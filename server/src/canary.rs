@@ -0,0 +1,291 @@
+//! Canary evaluation of reloaded data on live traffic.
+//!
+//! For a while after each reload of the default data source, a sample of the decisions made
+//! through the HTTP API is made again on the data the reload replaced, and the decisions that
+//! differ are counted. When too many do, the reload is rolled back: the previous data is
+//! swapped back in. Some divergence is expected, since the data did change: the threshold
+//! should sit above the share of decisions a normal update changes.
+//!
+//! A rolled back version is not reloaded again, since the source is unchanged: the next
+//! version published is loaded as usual. Once anything else swaps the store (an admin clear or
+//! upload, or a failure action), the evaluation ends without rolling back, so that data swapped
+//! in deliberately is never replaced.
+
+use crate::models::Decision;
+use occlusion::{Store, SwappableStore};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+/// When to roll a reload back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanaryOptions {
+    /// Share of decisions made again on the previous data, from 0 to 1
+    pub sample: f64,
+    /// How long after a reload decisions are sampled
+    pub window: Duration,
+    /// Share of sampled decisions that may differ, from 0 to 1
+    pub max_divergence: f64,
+    /// Sampled decisions needed before rolling back
+    pub min_samples: u64,
+}
+
+/// The evaluation of the last reload.
+struct Trial {
+    /// The data the reload replaced
+    previous: SwappableStore,
+    /// Generation of the reloaded data
+    generation: u64,
+    started: Instant,
+    sampled: AtomicU64,
+    diverged: AtomicU64,
+}
+
+/// Evaluation of the reloads of the default store, managed by Rocket when enabled.
+pub struct Canary {
+    live: SwappableStore,
+    options: CanaryOptions,
+    trial: RwLock<Option<Trial>>,
+    /// Decisions sampled and found to differ since startup
+    sampled: AtomicU64,
+    diverged: AtomicU64,
+    rollbacks: AtomicU64,
+}
+
+/// State of the canary evaluation, as reported in store statistics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanaryStatus {
+    /// Generation under evaluation (omitted outside of an evaluation window)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evaluating: Option<u64>,
+    /// Decisions sampled and found to differ in the current window
+    pub window_sampled: u64,
+    pub window_diverged: u64,
+    /// Decisions sampled and found to differ since startup
+    pub sampled: u64,
+    pub diverged: u64,
+    /// Reloads rolled back since startup
+    pub rollbacks: u64,
+}
+
+impl Canary {
+    /// Evaluate the reloads of `live` with `options`.
+    pub fn new(live: SwappableStore, options: CanaryOptions) -> Self {
+        Self {
+            live,
+            options,
+            trial: RwLock::new(None),
+            sampled: AtomicU64::new(0),
+            diverged: AtomicU64::new(0),
+            rollbacks: AtomicU64::new(0),
+        }
+    }
+
+    /// Start evaluating the data just swapped in, against `previous`.
+    pub fn start(&self, previous: SwappableStore) {
+        let generation = self.live.generation();
+        *self.trial.write().expect("RwLock poisoned") = Some(Trial {
+            previous,
+            generation,
+            started: Instant::now(),
+            sampled: AtomicU64::new(0),
+            diverged: AtomicU64::new(0),
+        });
+    }
+
+    /// End the evaluation, if any, without rolling back: the store is being swapped by other
+    /// means than a reload.
+    pub fn stop(&self) {
+        self.trial.write().expect("RwLock poisoned").take();
+    }
+
+    /// Sample the decision on `object` under `mask`, rolling back if too many differ.
+    pub fn observe(&self, object: &Uuid, mask: u8) {
+        let rollback = {
+            let trial = self.trial.read().expect("RwLock poisoned");
+            let Some(trial) = trial.as_ref() else {
+                return;
+            };
+            // The data evaluated was swapped out since
+            if trial.generation != self.live.generation()
+                || trial.started.elapsed() > self.options.window
+                || rand::random::<f64>() >= self.options.sample
+            {
+                return;
+            }
+            let now = Decision::new(self.live.get_visibility(object), mask);
+            let before = Decision::new(trial.previous.get_visibility(object), mask);
+            let sampled = trial.sampled.fetch_add(1, Ordering::Relaxed) + 1;
+            self.sampled.fetch_add(1, Ordering::Relaxed);
+            let diverged = if now == before {
+                trial.diverged.load(Ordering::Relaxed)
+            } else {
+                self.diverged.fetch_add(1, Ordering::Relaxed);
+                trial.diverged.fetch_add(1, Ordering::Relaxed) + 1
+            };
+            self.exceeded(sampled, diverged)
+        };
+        if rollback {
+            self.rollback();
+        }
+    }
+
+    /// Whether `diverged` decisions out of `sampled` call for a rollback.
+    #[allow(clippy::cast_precision_loss)]
+    fn exceeded(&self, sampled: u64, diverged: u64) -> bool {
+        sampled >= self.options.min_samples
+            && diverged as f64 > sampled as f64 * self.options.max_divergence
+    }
+
+    /// Swap the data under evaluation out for the data it replaced, if it is still live.
+    fn rollback(&self) {
+        // Taken, so that concurrent requests exceeding the threshold roll back only once
+        let Some(trial) = self.trial.write().expect("RwLock poisoned").take() else {
+            return;
+        };
+        if self.live.generation() != trial.generation {
+            return;
+        }
+        let (sampled, diverged) = (
+            trial.sampled.load(Ordering::Relaxed),
+            trial.diverged.load(Ordering::Relaxed),
+        );
+        self.live.restore(&trial.previous);
+        self.rollbacks.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            generation = trial.generation,
+            sampled,
+            diverged,
+            restored = self.live.generation(),
+            "Reloaded data diverged from the previous data, rolled back"
+        );
+    }
+
+    pub fn status(&self) -> CanaryStatus {
+        let trial = self.trial.read().expect("RwLock poisoned");
+        let trial = trial.as_ref().filter(|trial| {
+            trial.generation == self.live.generation()
+                && trial.started.elapsed() <= self.options.window
+        });
+        CanaryStatus {
+            evaluating: trial.map(|trial| trial.generation),
+            window_sampled: trial.map_or(0, |trial| trial.sampled.load(Ordering::Relaxed)),
+            window_diverged: trial.map_or(0, |trial| trial.diverged.load(Ordering::Relaxed)),
+            sampled: self.sampled.load(Ordering::Relaxed),
+            diverged: self.diverged.load(Ordering::Relaxed),
+            rollbacks: self.rollbacks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(entries: &[(u128, u8)]) -> occlusion::ActiveStore {
+        occlusion::build_store(
+            entries
+                .iter()
+                .map(|&(uuid, level)| (Uuid::from_u128(uuid), level))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rollback() {
+        let live = SwappableStore::new(store(&[(1, 0), (2, 0), (3, 0)]));
+        let canary = Canary::new(
+            live.clone(),
+            CanaryOptions {
+                sample: 1.0,
+                window: Duration::from_mins(1),
+                max_divergence: 0.5,
+                min_samples: 4,
+            },
+        );
+        // Outside of an evaluation window, nothing is sampled
+        canary.observe(&Uuid::from_u128(1), 0);
+        assert_eq!(canary.status(), CanaryStatus::default());
+
+        let previous = live.at_generation(0).unwrap();
+        live.swap(store(&[(1, 0), (2, 9)]));
+        canary.start(previous);
+        for _ in 0..2 {
+            canary.observe(&Uuid::from_u128(1), 0);
+        }
+        canary.observe(&Uuid::from_u128(2), 0);
+        assert_eq!(canary.status().evaluating, Some(1));
+        assert_eq!(canary.status().window_diverged, 1);
+        assert_eq!(live.len(), 2, "below the minimum sample");
+
+        // 2 of 4 differ: not more than half
+        canary.observe(&Uuid::from_u128(3), 0);
+        assert_eq!(live.len(), 2);
+        canary.observe(&Uuid::from_u128(3), 0);
+        assert_eq!(live.len(), 3, "rolled back");
+        assert_eq!(live.generation(), 2);
+        assert_eq!(
+            canary.status(),
+            CanaryStatus {
+                evaluating: None,
+                window_sampled: 0,
+                window_diverged: 0,
+                sampled: 5,
+                diverged: 3,
+                rollbacks: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_swapped_during_window() {
+        let live = SwappableStore::new(store(&[(1, 0), (2, 0)]));
+        let canary = Canary::new(
+            live.clone(),
+            CanaryOptions {
+                sample: 1.0,
+                window: Duration::from_mins(1),
+                max_divergence: 0.0,
+                min_samples: 1,
+            },
+        );
+        let reload = |entries: &[(u128, u8)]| {
+            let previous = live.at_generation(live.generation()).unwrap();
+            live.swap(store(entries));
+            canary.start(previous);
+        };
+
+        // Cleared without telling the canary: the empty store is left alone
+        reload(&[(1, 9)]);
+        live.swap(store(&[]));
+        canary.observe(&Uuid::from_u128(1), 0);
+        assert!(live.is_empty());
+        assert_eq!(canary.status().evaluating, None);
+        assert_eq!(canary.status().sampled, 0);
+
+        // Stopped by an admin swap
+        reload(&[(1, 9)]);
+        canary.stop();
+        live.swap(store(&[]));
+        canary.observe(&Uuid::from_u128(1), 0);
+        assert!(live.is_empty());
+        assert_eq!(canary.status().rollbacks, 0);
+
+        // Swapped between the decision and the rollback
+        reload(&[(1, 9)]);
+        let trial = canary.trial.read().unwrap().as_ref().unwrap().generation;
+        live.swap(store(&[]));
+        assert_ne!(live.generation(), trial);
+        canary.rollback();
+        assert!(live.is_empty());
+        assert_eq!(canary.status().rollbacks, 0);
+    }
+}
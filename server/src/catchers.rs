@@ -0,0 +1,149 @@
+//! JSON error responses.
+//!
+//! Every error, whether returned by a route as an [`ApiError`] or raised by Rocket itself
+//! (unknown route, guard failure, malformed body), is answered with an [`ErrorResponse`] body
+//! so clients can parse all responses as JSON.
+
+use crate::{
+    models::{ErrorDetail, ErrorResponse},
+    request_id,
+};
+use rocket::{
+    Catcher, Request,
+    http::Status,
+    response::{self, Responder},
+    serde::json::Json,
+};
+
+/// An error answered with its status and a JSON [`ErrorResponse`] body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+    /// The limit the request exceeded, if any
+    pub limit: Option<usize>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            limit: None,
+        }
+    }
+
+    /// A 413 for a request over `limit`.
+    pub fn too_large(message: impl Into<String>, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::new(Status::PayloadTooLarge, message)
+        }
+    }
+
+    /// A 422 for a request that is well-formed but invalid.
+    pub fn unprocessable(message: impl Into<String>) -> Self {
+        Self::new(Status::UnprocessableEntity, message)
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        Self::new(status, default_message(status))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status;
+        let mut body = ErrorResponse::from(self);
+        body.error.request_id = Some(request_id::of(request).to_string());
+        (status, Json(body)).respond_to(request)
+    }
+}
+
+impl From<ApiError> for ErrorResponse {
+    fn from(e: ApiError) -> Self {
+        let mut body = body(e.status, e.message);
+        body.error.limit = e.limit;
+        body
+    }
+}
+
+/// Why a guard rejected the request, for the catcher to report.
+///
+/// Only the first message set for a request is kept.
+struct ErrorMessage(Option<String>);
+
+/// Record why a guard is about to fail the request.
+pub fn set_error_message(request: &Request<'_>, message: impl Into<String>) {
+    let message = message.into();
+    request.local_cache(|| ErrorMessage(Some(message)));
+}
+
+/// Seconds the client should wait before retrying, for the catcher to report.
+struct RetryAfter(Option<u64>);
+
+/// Record that the request should be retried after `secs`, sent as `Retry-After`.
+pub fn set_retry_after(request: &Request<'_>, secs: u64) {
+    request.local_cache(|| RetryAfter(Some(secs)));
+}
+
+fn body(status: Status, message: String) -> ErrorResponse {
+    ErrorResponse {
+        error: ErrorDetail {
+            code: status.code,
+            message,
+            limit: None,
+            request_id: None,
+        },
+    }
+}
+
+fn default_message(status: Status) -> String {
+    match status.code {
+        400 => "malformed request".to_string(),
+        413 => "request too large".to_string(),
+        422 => "invalid request: check the body and query parameters".to_string(),
+        _ => status.reason_lossy().to_lowercase(),
+    }
+}
+
+/// An error body caught from a guard, with `Retry-After` if the guard set one.
+pub struct Caught {
+    body: Json<ErrorResponse>,
+    retry_after: Option<u64>,
+}
+
+impl<'r> Responder<'r, 'static> for Caught {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.body.respond_to(request)?;
+        if let Some(secs) = self.retry_after {
+            response.set_raw_header("Retry-After", secs.to_string());
+        }
+        Ok(response)
+    }
+}
+
+/// Catcher for every error status Rocket raises itself.
+#[catch(default)]
+pub fn default_catcher(status: Status, request: &Request<'_>) -> Caught {
+    let message = match request.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(message)) => message.clone(),
+        ErrorMessage(None) if status == Status::NotFound => {
+            format!("no route for {} {}", request.method(), request.uri())
+        }
+        ErrorMessage(None) => default_message(status),
+    };
+    let mut body = body(status, message);
+    body.error.request_id = Some(request_id::of(request).to_string());
+    Caught {
+        body: Json(body),
+        retry_after: request.local_cache(|| RetryAfter(None)).0,
+    }
+}
+
+/// The catchers to register at `/`.
+pub fn catchers() -> Vec<Catcher> {
+    catchers![default_catcher]
+}
@@ -0,0 +1,237 @@
+//! Fault injection into reloads, for exercising failure handling in staging.
+//!
+//! Built with the `chaos` feature only. Each configured fault fires on a schedule, counted
+//! in reloads (scheduled, watched, gossiped and admin ones alike, of any source):
+//!
+//! - `fail`: the reload fails before fetching, as if the source were unreachable
+//! - `slow-fetch=DURATION`: the fetch starts after `DURATION`
+//! - `delay-swap=DURATION`: the validated store is swapped in after `DURATION`
+//!
+//! A schedule follows `@`: `@N%` fires on N percent of reloads at random, `@N` on every Nth
+//! reload. Without one, the fault fires on every reload. Startup loads are never affected.
+
+use crate::error::{LoadError, Result};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tracing::warn;
+
+/// What a fault does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    Fail,
+    SlowFetch(Duration),
+    DelaySwap(Duration),
+}
+
+/// Which reloads a fault fires on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// Every Nth reload
+    Every(u64),
+    /// Each reload with this probability
+    Chance(f64),
+}
+
+/// A fault and its schedule, as given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub schedule: Schedule,
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (fault, schedule) = match s.trim().split_once('@') {
+            Some((fault, schedule)) => (fault, Some(schedule)),
+            None => (s.trim(), None),
+        };
+        let duration = |value: Option<&str>| {
+            let value = value.ok_or_else(|| format!("fault {fault:?} needs a duration"))?;
+            humantime::parse_duration(value)
+                .map_err(|e| format!("invalid duration {value:?} in fault {s:?}: {e}"))
+        };
+        let (name, value) = match fault.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (fault, None),
+        };
+        let kind = match name {
+            "fail" if value.is_none() => FaultKind::Fail,
+            "slow-fetch" => FaultKind::SlowFetch(duration(value)?),
+            "delay-swap" => FaultKind::DelaySwap(duration(value)?),
+            _ => {
+                return Err(format!(
+                    "invalid fault {s:?}: expected fail, slow-fetch=DURATION or \
+                     delay-swap=DURATION"
+                ));
+            }
+        };
+        let schedule = match schedule {
+            None => Schedule::Every(1),
+            Some(schedule) => match schedule.strip_suffix('%') {
+                Some(percent) => match percent.parse::<f64>() {
+                    Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                        Schedule::Chance(percent / 100.0)
+                    }
+                    _ => return Err(format!("invalid percentage in fault {s:?}")),
+                },
+                None => match schedule.parse() {
+                    Ok(n) if n > 0 => Schedule::Every(n),
+                    _ => return Err(format!("invalid schedule in fault {s:?}")),
+                },
+            },
+        };
+        Ok(Self { kind, schedule })
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FaultKind::Fail => write!(f, "fail")?,
+            FaultKind::SlowFetch(delay) => {
+                write!(f, "slow-fetch={}", humantime::format_duration(delay))?;
+            }
+            FaultKind::DelaySwap(delay) => {
+                write!(f, "delay-swap={}", humantime::format_duration(delay))?;
+            }
+        }
+        match self.schedule {
+            Schedule::Every(1) => Ok(()),
+            Schedule::Every(n) => write!(f, "@{n}"),
+            Schedule::Chance(p) => write!(f, "@{}%", p * 100.0),
+        }
+    }
+}
+
+/// The configured faults, shared by every reload state.
+#[derive(Debug, Default)]
+pub struct Chaos {
+    faults: Vec<(Fault, AtomicU64)>,
+}
+
+impl Chaos {
+    pub fn new(faults: Vec<Fault>) -> Self {
+        Self {
+            faults: faults
+                .into_iter()
+                .map(|fault| (fault, AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Apply the faults firing before a fetch: sleep, then fail.
+    pub async fn before_fetch(&self) -> Result<()> {
+        for fault in self.firing(|kind| !matches!(kind, FaultKind::DelaySwap(_))) {
+            match fault.kind {
+                FaultKind::SlowFetch(delay) => {
+                    warn!(fault = %fault, "Chaos: slowing fetch down");
+                    tokio::time::sleep(delay).await;
+                }
+                FaultKind::Fail => {
+                    warn!(fault = %fault, "Chaos: failing reload");
+                    return Err(LoadError::HttpError(
+                        "chaos: injected reload failure".to_string(),
+                    ));
+                }
+                FaultKind::DelaySwap(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the faults firing before a swap.
+    pub async fn before_swap(&self) {
+        for fault in self.firing(|kind| matches!(kind, FaultKind::DelaySwap(_))) {
+            if let FaultKind::DelaySwap(delay) = fault.kind {
+                warn!(fault = %fault, "Chaos: delaying swap");
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+
+    /// The faults of the kinds selected by `select` that fire on this reload, slow fetches
+    /// before failures.
+    fn firing(&self, select: impl Fn(&FaultKind) -> bool) -> Vec<Fault> {
+        let mut firing: Vec<_> = self
+            .faults
+            .iter()
+            .filter(|(fault, _)| select(&fault.kind))
+            .filter(|(fault, reloads)| {
+                let n = reloads.fetch_add(1, Ordering::Relaxed) + 1;
+                match fault.schedule {
+                    Schedule::Every(every) => n % every == 0,
+                    Schedule::Chance(p) => rand::random::<f64>() < p,
+                }
+            })
+            .map(|(fault, _)| *fault)
+            .collect();
+        firing.sort_by_key(|fault| matches!(fault.kind, FaultKind::Fail));
+        firing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let fault = |s: &str| s.parse::<Fault>();
+        assert_eq!(
+            fault("fail"),
+            Ok(Fault {
+                kind: FaultKind::Fail,
+                schedule: Schedule::Every(1)
+            })
+        );
+        assert_eq!(
+            fault("slow-fetch=30s@3"),
+            Ok(Fault {
+                kind: FaultKind::SlowFetch(Duration::from_secs(30)),
+                schedule: Schedule::Every(3)
+            })
+        );
+        assert_eq!(
+            fault("delay-swap=2m@25%").unwrap().schedule,
+            Schedule::Chance(0.25)
+        );
+        for s in ["fail@25%", "slow-fetch=30s@3", "delay-swap=2m"] {
+            assert_eq!(fault(s).unwrap().to_string(), s);
+        }
+        for s in [
+            "fail=1s",
+            "slow-fetch",
+            "slow-fetch=soon",
+            "fail@0",
+            "fail@150%",
+            "boom",
+        ] {
+            assert!(fault(s).is_err(), "{s}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_schedule() {
+        let chaos = Chaos::new(vec![
+            "fail@2".parse().unwrap(),
+            "slow-fetch=1ms".parse().unwrap(),
+            "delay-swap=1ms@0%".parse().unwrap(),
+        ]);
+        assert!(chaos.before_fetch().await.is_ok());
+        let e = chaos.before_fetch().await.unwrap_err();
+        assert_eq!(e.to_string(), "HTTP error: chaos: injected reload failure");
+        assert!(chaos.before_fetch().await.is_ok());
+        assert!(
+            chaos
+                .firing(|kind| matches!(kind, FaultKind::DelaySwap(_)))
+                .is_empty()
+        );
+        assert_eq!(chaos.faults[1].1.load(Ordering::Relaxed), 3);
+    }
+}
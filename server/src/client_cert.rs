@@ -0,0 +1,190 @@
+//! Visibility mask entitlements of TLS client certificates.
+//!
+//! With client certificates required, a JSON file maps certificate identities to the highest
+//! visibility mask their holders may check with, e.g.
+//! `{"dns:billing.internal": 10, "uri:spiffe://example.org/reports": 5, "ou:Support": 3}`.
+//! Identities are the certificate's subject alternative names (`dns:`, `uri:`, `email:`)
+//! and the organizational units of its subject (`ou:`). Requests asking for a higher mask
+//! are clamped to the entitlement or rejected.
+
+use crate::error::{LoadError, Result};
+use std::{collections::HashMap, path::Path};
+
+/// Mapping of certificate identities to the highest mask they are entitled to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CertMasks(HashMap<String, u8>);
+
+impl CertMasks {
+    /// Parse a JSON object of identities to masks.
+    pub fn from_json(content: &[u8]) -> Result<Self> {
+        let masks: HashMap<String, u8> = serde_json::from_slice(content)
+            .map_err(|e| LoadError::InvalidFormat(format!("Invalid certificate mapping: {e}")))?;
+        if let Some(identity) = masks.keys().find(|identity| !is_identity(identity)) {
+            return Err(LoadError::InvalidFormat(format!(
+                "Invalid certificate mapping: {identity:?} is not a dns:, uri:, email: or ou: identity"
+            )));
+        }
+        Ok(Self(masks))
+    }
+
+    /// Read a mapping file.
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::from_json(&std::fs::read(path)?)
+    }
+
+    /// The highest mask any of `identities` is entitled to, if any is mapped.
+    pub fn max_mask<'a>(&self, identities: impl IntoIterator<Item = &'a str>) -> Option<u8> {
+        identities
+            .into_iter()
+            .filter_map(|identity| self.0.get(identity).copied())
+            .max()
+    }
+}
+
+fn is_identity(identity: &str) -> bool {
+    ["dns:", "uri:", "email:", "ou:"].iter().any(|prefix| {
+        identity
+            .strip_prefix(prefix)
+            .is_some_and(|rest| !rest.is_empty())
+    })
+}
+
+/// What to do with a request for a higher mask than the caller is entitled to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExcessMask {
+    /// Check against the entitlement instead
+    #[default]
+    Clamp,
+    /// Reject the request with 403
+    Reject,
+}
+
+/// Certificate entitlements, managed when client certificates are mapped to masks.
+#[derive(Debug, Clone)]
+pub struct ClientCertMasks {
+    pub masks: CertMasks,
+    pub excess: ExcessMask,
+}
+
+/// The identities of a client certificate, as keys of a [`CertMasks`] mapping.
+#[cfg(feature = "tls")]
+pub fn identities(cert: &rocket::mtls::Certificate<'_>) -> Vec<String> {
+    use rocket::mtls::x509::GeneralName;
+
+    let mut identities = Vec::new();
+    if let Ok(Some(names)) = cert.subject_alternative_name() {
+        for name in &names.value.general_names {
+            match name {
+                GeneralName::DNSName(name) => identities.push(format!("dns:{name}")),
+                GeneralName::URI(uri) => identities.push(format!("uri:{uri}")),
+                GeneralName::RFC822Name(email) => identities.push(format!("email:{email}")),
+                _ => {}
+            }
+        }
+    }
+    identities.extend(
+        cert.subject()
+            .iter_organizational_unit()
+            .filter_map(|unit| unit.as_str().ok())
+            .map(|unit| format!("ou:{unit}")),
+    );
+    identities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_mask() {
+        let masks = CertMasks::from_json(
+            br#"{"dns:billing.internal": 10, "ou:Billing": 5, "uri:spiffe://example.org/x": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            masks.max_mask(["dns:billing.internal", "ou:Billing"]),
+            Some(10)
+        );
+        assert_eq!(masks.max_mask(["ou:Billing", "ou:Support"]), Some(5));
+        assert_eq!(masks.max_mask(["dns:other.internal"]), None);
+        assert_eq!(masks.max_mask([]), None);
+
+        assert!(CertMasks::from_json(br#"{"billing.internal": 1}"#).is_err());
+        assert!(CertMasks::from_json(br#"{"ou:": 1}"#).is_err());
+        assert!(CertMasks::from_json(br#"{"ou:Billing": 256}"#).is_err());
+    }
+
+    /// Client certificate with SANs `DNS:billing.internal` and
+    /// `URI:spiffe://example.org/billing`, and subject `CN=billing, OU=Billing`.
+    #[cfg(feature = "tls")]
+    const CLIENT_CERT: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBtzCCAV2gAwIBAgIUXOEYY3n/uxWgTpA6Fk6R/06r1hwwCgYIKoZIzj0EAwIw
+EjEQMA4GA1UEAwwHdGVzdC1jYTAgFw0yNjEwMTYxNDUzNDlaGA8yMTI2MDkyMjE0
+NTM0OVowJDEQMA4GA1UEAwwHYmlsbGluZzEQMA4GA1UECwwHQmlsbGluZzBZMBMG
+ByqGSM49AgEGCCqGSM49AwEHA0IABHMiGnPtl273UraJ3BS9eNVqfHxe5Jaxani9
+rL9ARuCNbPM4VxZYppWulptQ27fQ9iq6HJWkfLIH5jcvmA5jDxyjfTB7MDkGA1Ud
+EQQyMDCCEGJpbGxpbmcuaW50ZXJuYWyGHHNwaWZmZTovL2V4YW1wbGUub3JnL2Jp
+bGxpbmcwHQYDVR0OBBYEFEwpfFiMLWTe+mG6ezEIyc/TMa4bMB8GA1UdIwQYMBaA
+FD8oPP384UzOF80sS+U1ilW/nOjJMAoGCCqGSM49BAMCA0gAMEUCIC06eFxJ6d+Q
+q8wobbAtQA8telW/wuGphmLFnj6qCmBxAiEAv543fBAGTHV9jKxtQMVJl6/mil6s
+nWFwwqvOta5a7jY=
+-----END CERTIFICATE-----
+";
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_entitlement() {
+        use rocket::http::{ContentType, Status};
+        use rocket::local::blocking::{Client, LocalResponse};
+        use uuid::Uuid;
+
+        fn check(client: &Client, object: u128, mask: u8, cert: bool) -> LocalResponse<'_> {
+            let mut request = client
+                .post("/api/v1/check")
+                .header(ContentType::JSON)
+                .body(format!(
+                    r#"{{"object": "{}", "visibility_mask": {mask}}}"#,
+                    Uuid::from_u128(object)
+                ));
+            if cert {
+                request = request.identity(CLIENT_CERT.as_bytes());
+            }
+            request.dispatch()
+        }
+
+        let client = |excess| {
+            let entries = vec![(Uuid::from_u128(1), 5), (Uuid::from_u128(2), 10)];
+            let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+            let masks =
+                CertMasks::from_json(br#"{"ou:Billing": 5, "uri:spiffe://example.org/x": 10}"#)
+                    .unwrap();
+            let rocket = rocket::build()
+                .manage(store)
+                .manage(ClientCertMasks { masks, excess })
+                .register("/", crate::catchers::catchers())
+                .mount("/", rocket::routes![crate::routes::check]);
+            Client::tracked(rocket).unwrap()
+        };
+
+        let clamping = client(ExcessMask::Clamp);
+        let response = check(&clamping, 2, 255, true);
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["is_visible"], false, "clamped to the OU's mask");
+        let body: serde_json::Value = check(&clamping, 1, 255, true).into_json().unwrap();
+        assert_eq!(body["is_visible"], true);
+        assert_eq!(check(&clamping, 1, 5, false).status(), Status::Unauthorized);
+
+        let rejecting = client(ExcessMask::Reject);
+        assert_eq!(check(&rejecting, 1, 5, true).status(), Status::Ok);
+        let response = check(&rejecting, 1, 6, true);
+        assert_eq!(response.status(), Status::Forbidden);
+        let error: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert!(
+            error.error.message.contains("maximum of 5"),
+            "{}",
+            error.error.message
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Google Cloud Storage (`gs://bucket/key`) and Azure Blob Storage
+//! (`az://container/key`) object fetching.
+//!
+//! Credentials are read from the environment: `GOOGLE_APPLICATION_CREDENTIALS` /
+//! `GOOGLE_SERVICE_ACCOUNT*` for GCS and `AZURE_STORAGE_ACCOUNT_NAME` plus
+//! `AZURE_STORAGE_ACCOUNT_KEY` (or a managed identity) for Azure.
+
+use crate::{
+    error::{LoadError, Result},
+    loader::FetchedObject,
+    progress::ProgressReporter,
+    source::SourceMetadata,
+};
+use futures_util::StreamExt;
+use object_store::{Attribute, GetOptions, ObjectStore, path::Path};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+/// Cloud object storage provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Provider {
+    /// Google Cloud Storage
+    #[cfg(feature = "gcs")]
+    Gcs,
+    /// Azure Blob Storage
+    #[cfg(feature = "azure")]
+    Azure,
+}
+
+impl Provider {
+    fn scheme(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gcs")]
+            Self::Gcs => "gs",
+            #[cfg(feature = "azure")]
+            Self::Azure => "az",
+        }
+    }
+
+    fn build(self, bucket: &str) -> object_store::Result<Arc<dyn ObjectStore>> {
+        Ok(match self {
+            #[cfg(feature = "gcs")]
+            Self::Gcs => Arc::new(
+                object_store::gcp::GoogleCloudStorageBuilder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            #[cfg(feature = "azure")]
+            Self::Azure => Arc::new(
+                object_store::azure::MicrosoftAzureBuilder::from_env()
+                    .with_container_name(bucket)
+                    .build()?,
+            ),
+        })
+    }
+}
+
+/// Clients per (provider, bucket).
+type StoreCache = HashMap<(Provider, String), Arc<dyn ObjectStore>>;
+
+/// Cached clients, so credentials are reused across reloads.
+static STORES: LazyLock<Mutex<StoreCache>> = LazyLock::new(Mutex::default);
+
+fn store(provider: Provider, bucket: &str) -> object_store::Result<Arc<dyn ObjectStore>> {
+    let mut stores = STORES.lock().expect("Mutex poisoned");
+    if let Some(store) = stores.get(&(provider, bucket.to_string())) {
+        return Ok(store.clone());
+    }
+    let store = provider.build(bucket)?;
+    stores.insert((provider, bucket.to_string()), store.clone());
+    Ok(store)
+}
+
+/// Download an object, returning `None` if its `ETag` matches `old_metadata`.
+pub(crate) async fn get_object(
+    provider: Provider,
+    bucket: &str,
+    key: &str,
+    old_metadata: Option<&SourceMetadata>,
+    progress: Option<&ProgressReporter>,
+) -> Result<Option<FetchedObject>> {
+    let err = |e: object_store::Error| {
+        LoadError::ObjectStoreError(format!("{}://{bucket}/{key}: {e}", provider.scheme()))
+    };
+
+    let options = GetOptions {
+        if_none_match: old_metadata.and_then(|m| m.etag.clone()),
+        ..GetOptions::default()
+    };
+    let result = match store(provider, bucket)
+        .map_err(err)?
+        .get_opts(&Path::from(key), options)
+        .await
+    {
+        Ok(result) => result,
+        Err(object_store::Error::NotModified { .. }) => return Ok(None),
+        Err(e) => return Err(err(e)),
+    };
+
+    // GCS exposes the object generation as the version; use it when there is no ETag
+    let metadata = SourceMetadata {
+        mtime: None,
+        etag: result.meta.e_tag.clone().or(result.meta.version.clone()),
+        last_modified: Some(result.meta.last_modified.to_rfc2822()),
+        origin: None,
+    };
+    let content_type = result
+        .attributes
+        .get(&Attribute::ContentType)
+        .map(|v| v.as_ref().to_string());
+
+    if let Some(progress) = progress {
+        progress.start(Some(result.meta.size));
+    }
+    let mut content = Vec::with_capacity(usize::try_from(result.meta.size).unwrap_or(0));
+    let mut stream = result.into_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(err)?;
+        content.extend_from_slice(&chunk);
+        if let Some(progress) = progress {
+            progress.add_bytes(chunk.len() as u64);
+        }
+    }
+
+    Ok(Some(FetchedObject {
+        content,
+        metadata,
+        content_type,
+    }))
+}
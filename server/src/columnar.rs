@@ -0,0 +1,338 @@
+//! Columnar input formats backed by Arrow record batches.
+//!
+//! Record batches must contain a `uuid` column (UTF-8 string or 16-byte binary)
+//! and a `visibility_level` column of any integer type in the 0-255 range.
+
+use crate::{
+    error::{LoadError, Result},
+    format::{ParseReport, UuidByteOrder, parse_uuid_text},
+};
+use arrow_array::{Array, RecordBatch, cast::AsArray, types::UInt8Type};
+use arrow_schema::DataType;
+use uuid::Uuid;
+
+/// Name of the UUID column.
+const UUID_COLUMN: &str = "uuid";
+/// Name of the visibility level column.
+const LEVEL_COLUMN: &str = "visibility_level";
+
+/// Number of rows decoded per record batch.
+#[cfg(feature = "parquet")]
+const BATCH_SIZE: usize = 65_536;
+
+/// Magic bytes at the start of an Arrow IPC file (Feather v2).
+#[cfg(feature = "arrow")]
+const ARROW_FILE_MAGIC: &[u8] = b"ARROW1";
+
+/// Append the entries of a record batch to `entries`.
+///
+/// `row_offset` is the number of rows already consumed, used for error messages.
+/// Malformed rows are passed to `report`.
+pub(crate) fn append_batch(
+    batch: &RecordBatch,
+    row_offset: usize,
+    order: UuidByteOrder,
+    entries: &mut Vec<(Uuid, u8)>,
+    report: &mut ParseReport,
+) -> Result<()> {
+    let uuids = column(batch, UUID_COLUMN)?;
+    let levels = arrow_cast::cast(column(batch, LEVEL_COLUMN)?, &DataType::UInt8)
+        .map_err(|e| LoadError::InvalidFormat(format!("Column {LEVEL_COLUMN}: {e}")))?;
+    let levels = levels.as_primitive::<UInt8Type>();
+
+    entries.reserve(batch.num_rows());
+    for row in 0..batch.num_rows() {
+        let row_num = row_offset + row + 1;
+        if levels.is_null(row) {
+            report.reject(
+                row_num,
+                LoadError::InvalidFormat(format!(
+                    "Row {row_num}: {LEVEL_COLUMN} is null or out of range"
+                )),
+            )?;
+            continue;
+        }
+        match uuid_at(uuids, row, order) {
+            Ok(uuid) => entries.push((uuid, levels.value(row))),
+            Err(e) => report.reject(
+                row_num,
+                LoadError::InvalidFormat(format!("Row {row_num}: {e}")),
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a dyn Array> {
+    batch
+        .column_by_name(name)
+        .map(AsRef::as_ref)
+        .ok_or_else(|| LoadError::InvalidFormat(format!("Missing column: {name}")))
+}
+
+fn uuid_at(
+    array: &dyn Array,
+    row: usize,
+    order: UuidByteOrder,
+) -> std::result::Result<Uuid, String> {
+    if array.is_null(row) {
+        return Err(format!("{UUID_COLUMN} is null"));
+    }
+
+    let text = |s: &str| parse_uuid_text(s.as_bytes(), order);
+    let binary = |bytes: &[u8]| Uuid::from_slice(bytes).map(|uuid| order.normalize(uuid));
+    let parsed = match array.data_type() {
+        DataType::Utf8 => text(array.as_string::<i32>().value(row)),
+        DataType::LargeUtf8 => text(array.as_string::<i64>().value(row)),
+        DataType::Utf8View => text(array.as_string_view().value(row)),
+        DataType::FixedSizeBinary(16) => binary(array.as_fixed_size_binary().value(row)),
+        DataType::Binary => binary(array.as_binary::<i32>().value(row)),
+        other => return Err(format!("unsupported {UUID_COLUMN} column type {other}")),
+    };
+    parsed.map_err(|e| e.to_string())
+}
+
+/// Parse a Parquet file, reading only the `uuid` and `visibility_level` columns.
+#[cfg(feature = "parquet")]
+pub(crate) fn parse_parquet(
+    content: Vec<u8>,
+    order: UuidByteOrder,
+    report: &mut ParseReport,
+) -> Result<Vec<(Uuid, u8)>> {
+    use parquet::arrow::{ProjectionMask, arrow_reader::ParquetRecordBatchReaderBuilder};
+
+    let parquet_err = |e: parquet::errors::ParquetError| LoadError::InvalidFormat(e.to_string());
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(content))
+        .map_err(parquet_err)?;
+
+    let schema = builder.schema().clone();
+    let indices = [UUID_COLUMN, LEVEL_COLUMN]
+        .into_iter()
+        .map(|name| {
+            schema
+                .index_of(name)
+                .map_err(|_| LoadError::InvalidFormat(format!("Missing column: {name}")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+    let num_rows = usize::try_from(builder.metadata().file_metadata().num_rows()).unwrap_or(0);
+
+    let reader = builder
+        .with_projection(mask)
+        .with_batch_size(BATCH_SIZE)
+        .build()
+        .map_err(parquet_err)?;
+
+    let mut entries = Vec::with_capacity(num_rows);
+    let mut rows = 0;
+    for batch in reader {
+        let batch = batch.map_err(|e| LoadError::InvalidFormat(e.to_string()))?;
+        append_batch(&batch, rows, order, &mut entries, report)?;
+        rows += batch.num_rows();
+        report.parsed(entries.len());
+    }
+
+    Ok(entries)
+}
+
+/// Parse Arrow IPC data, either the file format (Feather v2) or the stream format.
+///
+/// The file format is recognized by its `ARROW1` magic bytes; anything else is
+/// read as a stream, which is what services typically send in a response body.
+#[cfg(feature = "arrow")]
+pub(crate) fn parse_arrow_ipc(
+    content: Vec<u8>,
+    order: UuidByteOrder,
+    report: &mut ParseReport,
+) -> Result<Vec<(Uuid, u8)>> {
+    use arrow_ipc::reader::{FileReader, StreamReader};
+    use std::io::Cursor;
+
+    let arrow_err = |e: arrow_schema::ArrowError| LoadError::InvalidFormat(e.to_string());
+
+    let batches: Box<dyn Iterator<Item = std::result::Result<RecordBatch, _>>> =
+        if content.starts_with(ARROW_FILE_MAGIC) {
+            Box::new(FileReader::try_new(Cursor::new(content), None).map_err(arrow_err)?)
+        } else {
+            Box::new(StreamReader::try_new(Cursor::new(content), None).map_err(arrow_err)?)
+        };
+
+    let mut entries = Vec::new();
+    let mut rows = 0;
+    for batch in batches {
+        let batch = batch.map_err(arrow_err)?;
+        append_batch(&batch, rows, order, &mut entries, report)?;
+        rows += batch.num_rows();
+        report.parsed(entries.len());
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{FixedSizeBinaryArray, Int64Array, StringArray};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn string_batch(uuids: Vec<String>, levels: Vec<i64>) -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new(UUID_COLUMN, DataType::Utf8, false),
+            Field::new(LEVEL_COLUMN, DataType::Int64, false),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(StringArray::from(uuids)),
+                Arc::new(Int64Array::from(levels)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_append_string_batch() {
+        let batch = string_batch(
+            vec![
+                Uuid::from_u128(1).to_string(),
+                Uuid::from_u128(2).to_string(),
+            ],
+            vec![0, 200],
+        );
+        let mut entries = Vec::new();
+        append_batch(
+            &batch,
+            0,
+            UuidByteOrder::Rfc,
+            &mut entries,
+            &mut ParseReport::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 200)]
+        );
+    }
+
+    #[test]
+    fn test_append_binary_batch() {
+        let schema = Schema::new(vec![
+            Field::new(UUID_COLUMN, DataType::FixedSizeBinary(16), false),
+            Field::new(LEVEL_COLUMN, DataType::Int64, false),
+        ]);
+        let uuids =
+            FixedSizeBinaryArray::try_from_iter([Uuid::from_u128(7).into_bytes()].into_iter())
+                .unwrap();
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(uuids), Arc::new(Int64Array::from(vec![3]))],
+        )
+        .unwrap();
+
+        let mut entries = Vec::new();
+        append_batch(
+            &batch,
+            0,
+            UuidByteOrder::Rfc,
+            &mut entries,
+            &mut ParseReport::default(),
+        )
+        .unwrap();
+        assert_eq!(entries, vec![(Uuid::from_u128(7), 3)]);
+    }
+
+    #[test]
+    fn test_out_of_range_level() {
+        let batch = string_batch(
+            vec![
+                Uuid::from_u128(1).to_string(),
+                Uuid::from_u128(2).to_string(),
+            ],
+            vec![1, 256],
+        );
+        let err = append_batch(
+            &batch,
+            10,
+            UuidByteOrder::Rfc,
+            &mut Vec::new(),
+            &mut ParseReport::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Row 12"), "{err}");
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let schema = Schema::new(vec![Field::new(UUID_COLUMN, DataType::Utf8, false)]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(StringArray::from(vec![Uuid::nil().to_string()]))],
+        )
+        .unwrap();
+        let err = append_batch(
+            &batch,
+            0,
+            UuidByteOrder::Rfc,
+            &mut Vec::new(),
+            &mut ParseReport::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(LEVEL_COLUMN), "{err}");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parse_parquet() {
+        use parquet::arrow::ArrowWriter;
+
+        let batch = string_batch(
+            (0..100).map(|i| Uuid::from_u128(i).to_string()).collect(),
+            (0..100).map(|i| i % 8).collect(),
+        );
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let entries = parse_parquet(buf, UuidByteOrder::Rfc, &mut ParseReport::default()).unwrap();
+        assert_eq!(entries.len(), 100);
+        assert_eq!(entries[9], (Uuid::from_u128(9), 1));
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_parse_arrow_ipc() {
+        use arrow_ipc::writer::{FileWriter, StreamWriter};
+
+        let batch = string_batch(
+            (0..10).map(|i| Uuid::from_u128(i).to_string()).collect(),
+            (0..10).collect(),
+        );
+
+        let mut stream = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut stream, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.write(&batch.slice(0, 2)).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let entries =
+            parse_arrow_ipc(stream, UuidByteOrder::Rfc, &mut ParseReport::default()).unwrap();
+        assert_eq!(entries.len(), 12);
+        assert_eq!(entries[11], (Uuid::from_u128(1), 1));
+
+        let mut file = Vec::new();
+        let mut writer = FileWriter::try_new(&mut file, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let entries =
+            parse_arrow_ipc(file, UuidByteOrder::Rfc, &mut ParseReport::default()).unwrap();
+        assert_eq!(entries.len(), 10);
+        assert_eq!(entries[4], (Uuid::from_u128(4), 4));
+    }
+}
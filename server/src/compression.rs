@@ -0,0 +1,164 @@
+//! Transparent decompression of gzip and zstd data.
+//!
+//! Compression is detected from magic bytes, so it works for compressed files
+//! (`.csv.gz`, `.csv.zst`), pre-compressed objects served over HTTP, and
+//! responses sent with a `Content-Encoding` header alike.
+
+use crate::error::{LoadError, Result};
+use std::io::Read;
+
+/// Gzip magic bytes.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// Zstandard frame magic bytes.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// `Accept-Encoding` value sent on HTTP fetches.
+pub const ACCEPT_ENCODING: &str = "gzip, zstd";
+
+/// Compression applied to raw source content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Not compressed
+    None,
+    /// Gzip (including multi-member streams)
+    Gzip,
+    /// Zstandard
+    Zstd,
+}
+
+impl Compression {
+    /// Detect compression from the leading magic bytes.
+    pub fn detect(content: &[u8]) -> Self {
+        if content.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if content.starts_with(ZSTD_MAGIC) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+
+    /// Returns true if the file extension denotes a compressed file.
+    pub fn is_compressed_extension(ext: &str) -> bool {
+        matches!(
+            ext.to_ascii_lowercase().as_str(),
+            "gz" | "gzip" | "zst" | "zstd"
+        )
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Gzip => write!(f, "gzip"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// Decompress content if it is gzip or zstd compressed, otherwise return it unchanged.
+///
+/// Decompression stops with an error once the output exceeds `max_size` bytes (unlimited when
+/// `None`), so that a small, highly compressible input cannot exhaust memory.
+pub fn decompress(content: Vec<u8>, max_size: Option<u64>) -> Result<(Vec<u8>, Compression)> {
+    let compression = Compression::detect(&content);
+    let decoder: Box<dyn Read> = match compression {
+        Compression::None => return Ok((content, compression)),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(content.as_slice())),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(content.as_slice())?),
+    };
+
+    let mut decompressed = Vec::new();
+    decoder
+        .take(max_size.map_or(u64::MAX, |max_size| max_size.saturating_add(1)))
+        .read_to_end(&mut decompressed)?;
+    if let Some(max_size) = max_size
+        && decompressed.len() as u64 > max_size
+    {
+        return Err(LoadError::InvalidFormat(format!(
+            "{compression} data exceeds the {max_size} byte limit once decompressed"
+        )));
+    }
+
+    Ok((decompressed, compression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const CONTENT: &[u8] = b"uuid,visibility_level\n00000000-0000-0000-0000-000000000001,3\n";
+
+    #[test]
+    fn test_uncompressed_passthrough() {
+        let (content, compression) = decompress(CONTENT.to_vec(), None).unwrap();
+        assert_eq!(compression, Compression::None);
+        assert_eq!(content, CONTENT);
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(CONTENT).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let (content, compression) = decompress(compressed, None).unwrap();
+        assert_eq!(compression, Compression::Gzip);
+        assert_eq!(content, CONTENT);
+    }
+
+    #[test]
+    fn test_zstd() {
+        let compressed = zstd::encode_all(CONTENT, 3).unwrap();
+
+        let (content, compression) = decompress(compressed, None).unwrap();
+        assert_eq!(compression, Compression::Zstd);
+        assert_eq!(content, CONTENT);
+    }
+
+    #[test]
+    fn test_truncated_gzip_fails() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(CONTENT).unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed.truncate(compressed.len() / 2);
+
+        assert!(decompress(compressed, None).is_err());
+    }
+
+    #[test]
+    fn test_decompressed_size_limit() {
+        // 64 MiB of zeros compress to a few KiB
+        let zeros = vec![0; 64 << 20];
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&zeros).unwrap();
+        let gzip = encoder.finish().unwrap();
+        let zstd = zstd::encode_all(zeros.as_slice(), 19).unwrap();
+        assert!(gzip.len() < 1 << 20 && zstd.len() < 1 << 20);
+
+        for (bomb, name) in [(gzip, "gzip"), (zstd, "zstd")] {
+            let err = decompress(bomb.clone(), Some(1 << 20)).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Invalid format: {name} data exceeds the 1048576 byte limit once decompressed"
+                )
+            );
+            let (content, _) = decompress(bomb, Some(64 << 20)).unwrap();
+            assert_eq!(
+                content.len(),
+                64 << 20,
+                "{name}: output at the limit is accepted"
+            );
+        }
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(CONTENT).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(decompress(compressed, Some(CONTENT.len() as u64)).is_ok());
+        // Uncompressed content is not limited here, but where it is read
+        assert!(decompress(CONTENT.to_vec(), Some(1)).is_ok());
+    }
+}
@@ -0,0 +1,381 @@
+//! Decision log in OPA's format, so decisions made through the OPA-compatible endpoints can be
+//! ingested by the same tooling as OPA's own decision logs.
+//!
+//! Events are queued by the request handlers and written in batches by a background task,
+//! either appended to a file (one JSON event per line), sent to syslog (one message per event)
+//! or uploaded to an HTTP endpoint as a gzip-compressed JSON array, like OPA's decision log
+//! plugin does. The [audit log](crate::audit) shares these sinks.
+
+use crate::{models::OpaLabels, opa};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Events waiting in the queue before new ones are dropped.
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Where decision events go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecisionSink {
+    /// Appended to a file as JSON lines
+    File(PathBuf),
+    /// Uploaded to an HTTP(S) endpoint
+    Http(String),
+    /// Sent to a syslog daemon
+    Syslog(SyslogAddr),
+}
+
+/// Address of a syslog daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogAddr {
+    /// Local socket, usually `/dev/log`
+    Unix(PathBuf),
+    /// `host:port`, over UDP
+    Udp(String),
+}
+
+impl DecisionSink {
+    /// Parse a sink: an `http://` or `https://` URL, `syslog:` (the local daemon),
+    /// `syslog:///path/to/socket`, `syslog://host:port`, or else a file path.
+    pub fn parse(s: &str) -> Self {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Self::Http(s.to_string())
+        } else if let Some(addr) = s.strip_prefix("syslog:") {
+            match addr.strip_prefix("//").unwrap_or(addr) {
+                "" => Self::Syslog(SyslogAddr::Unix(PathBuf::from("/dev/log"))),
+                path if path.starts_with('/') => {
+                    Self::Syslog(SyslogAddr::Unix(PathBuf::from(path)))
+                }
+                host => Self::Syslog(SyslogAddr::Udp(host.to_string())),
+            }
+        } else {
+            Self::File(PathBuf::from(s))
+        }
+    }
+}
+
+impl fmt::Display for DecisionSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Http(url) => f.write_str(url),
+            Self::Syslog(SyslogAddr::Unix(path)) => write!(f, "syslog://{}", path.display()),
+            Self::Syslog(SyslogAddr::Udp(host)) => write!(f, "syslog://{host}"),
+        }
+    }
+}
+
+/// A decision, as an OPA decision log event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionEvent {
+    labels: OpaLabels,
+    pub decision_id: Uuid,
+    /// Data path of the decision, e.g. `occlusion/visible`
+    pub path: &'static str,
+    pub input: serde_json::Value,
+    pub result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<String>,
+    /// RFC 3339
+    pub timestamp: String,
+}
+
+/// Batching of decision events.
+#[derive(Debug, Clone, Copy)]
+pub struct DecisionLogOptions {
+    /// Events per write or upload
+    pub batch_size: usize,
+    /// Longest time an event waits for its batch to fill up
+    pub flush_interval: Duration,
+}
+
+impl Default for DecisionLogOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Handle to the decision log, managed by Rocket when decision logging is enabled.
+#[derive(Clone)]
+pub struct DecisionLog {
+    labels: OpaLabels,
+    sender: mpsc::Sender<DecisionEvent>,
+}
+
+impl DecisionLog {
+    /// Start the background writer for `sink`.
+    ///
+    /// Must be called within a Tokio runtime. Events still queued when the last handle
+    /// is dropped are written before the writer stops.
+    pub fn spawn(sink: DecisionSink, options: DecisionLogOptions) -> Self {
+        Self {
+            labels: opa::labels(),
+            sender: spawn_writer(sink, options),
+        }
+    }
+
+    /// Queue a decision; it is dropped with a warning if the queue is full.
+    pub fn record(
+        &self,
+        decision_id: Uuid,
+        path: &'static str,
+        input: &impl Serialize,
+        result: &impl Serialize,
+        requested_by: Option<SocketAddr>,
+    ) {
+        let event = DecisionEvent {
+            labels: self.labels.clone(),
+            decision_id,
+            path,
+            input: serde_json::to_value(input).unwrap_or_default(),
+            result: serde_json::to_value(result).unwrap_or_default(),
+            requested_by: requested_by.map(|addr| addr.to_string()),
+            timestamp: humantime::format_rfc3339_micros(SystemTime::now()).to_string(),
+        };
+        if self.sender.try_send(event).is_err() {
+            warn!("Decision log queue full, dropping decision");
+        }
+    }
+}
+
+/// Start a background task writing events to `sink` in batches, and return its queue.
+///
+/// Must be called within a Tokio runtime. Events still queued when every sender is dropped
+/// are written before the task stops.
+pub(crate) fn spawn_writer<T>(sink: DecisionSink, options: DecisionLogOptions) -> mpsc::Sender<T>
+where
+    T: Serialize + Clone + Send + Sync + 'static,
+{
+    let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+    tokio::spawn(write_events(sink, options, receiver));
+    sender
+}
+
+/// Write queued events in batches until every handle is dropped.
+///
+/// Failed batches are retried with the next one; the oldest events are dropped once more
+/// than [`QUEUE_CAPACITY`] are pending.
+async fn write_events<T: Serialize + Clone + Send + Sync>(
+    sink: DecisionSink,
+    options: DecisionLogOptions,
+    mut receiver: mpsc::Receiver<T>,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("Failed to build HTTP client");
+    let mut pending = VecDeque::new();
+    let mut ticker = tokio::time::interval(options.flush_interval);
+    let mut open = true;
+    while open || !pending.is_empty() {
+        // Once closed, drain what is left without waiting
+        let flush = !open
+            || tokio::select! {
+                event = receiver.recv() => {
+                    if let Some(event) = event {
+                        pending.push_back(event);
+                        pending.len() >= options.batch_size
+                    } else {
+                        open = false;
+                        true
+                    }
+                }
+                _ = ticker.tick() => true,
+            };
+        if !flush || pending.is_empty() {
+            continue;
+        }
+
+        let batch: Vec<_> = pending.iter().take(options.batch_size).cloned().collect();
+        match write_batch(&sink, &client, &batch).await {
+            Ok(()) => {
+                debug!(events = batch.len(), sink = %sink, "Wrote event batch");
+                pending.drain(..batch.len());
+            }
+            Err(e) => {
+                warn!(sink = %sink, error = %e, pending = pending.len(), "Failed to write events");
+                if !open {
+                    return;
+                }
+                if pending.len() > QUEUE_CAPACITY {
+                    let dropped = pending.len() - QUEUE_CAPACITY;
+                    pending.drain(..dropped);
+                    warn!(dropped, sink = %sink, "Event backlog full, dropping oldest events");
+                }
+            }
+        }
+    }
+}
+
+async fn write_batch<T: Serialize>(
+    sink: &DecisionSink,
+    client: &reqwest::Client,
+    batch: &[T],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match sink {
+        DecisionSink::File(path) => {
+            let mut lines = Vec::new();
+            for event in batch {
+                serde_json::to_writer(&mut lines, event)?;
+                lines.push(b'\n');
+            }
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?
+                    .write_all(&lines)
+            })
+            .await??;
+        }
+        DecisionSink::Http(url) => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            serde_json::to_writer(&mut encoder, batch)?;
+            client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header("Content-Encoding", "gzip")
+                .body(encoder.finish()?)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        DecisionSink::Syslog(addr) => {
+            // RFC 5424, facility local0, severity informational
+            let header = format!(
+                "<134>1 {} - occlusion {} - - ",
+                humantime::format_rfc3339_micros(SystemTime::now()),
+                std::process::id()
+            );
+            let messages = batch
+                .iter()
+                .map(|event| {
+                    let mut message = header.clone().into_bytes();
+                    serde_json::to_writer(&mut message, event)?;
+                    Ok(message)
+                })
+                .collect::<Result<Vec<_>, serde_json::Error>>()?;
+            let addr = addr.clone();
+            tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+                match addr {
+                    SyslogAddr::Unix(path) => {
+                        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                        for message in messages {
+                            socket.send_to(&message, &path)?;
+                        }
+                    }
+                    SyslogAddr::Udp(host) => {
+                        let target = std::net::ToSocketAddrs::to_socket_addrs(&host)?
+                            .next()
+                            .ok_or_else(|| std::io::Error::other(format!("{host} not found")))?;
+                        let local = if target.is_ipv4() {
+                            std::net::Ipv4Addr::UNSPECIFIED.into()
+                        } else {
+                            std::net::IpAddr::from(std::net::Ipv6Addr::UNSPECIFIED)
+                        };
+                        let socket = std::net::UdpSocket::bind((local, 0))?;
+                        socket.connect(target)?;
+                        for message in messages {
+                            socket.send(&message)?;
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .await??;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink() {
+        assert_eq!(
+            DecisionSink::parse("https://logs.example.com/logs"),
+            DecisionSink::Http("https://logs.example.com/logs".to_string())
+        );
+        assert_eq!(
+            DecisionSink::parse("/var/log/decisions.jsonl"),
+            DecisionSink::File(PathBuf::from("/var/log/decisions.jsonl"))
+        );
+        assert_eq!(
+            DecisionSink::parse("syslog:"),
+            DecisionSink::Syslog(SyslogAddr::Unix(PathBuf::from("/dev/log")))
+        );
+        assert_eq!(
+            DecisionSink::parse("syslog:///run/systemd/journal/syslog"),
+            DecisionSink::Syslog(SyslogAddr::Unix(PathBuf::from(
+                "/run/systemd/journal/syslog"
+            )))
+        );
+        assert_eq!(
+            DecisionSink::parse("syslog://logs.internal:514"),
+            DecisionSink::Syslog(SyslogAddr::Udp("logs.internal:514".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_file_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decisions.jsonl");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let options = DecisionLogOptions {
+                batch_size: 2,
+                flush_interval: Duration::from_hours(1),
+            };
+            let log = DecisionLog::spawn(DecisionSink::File(path.clone()), options);
+            let input = serde_json::json!({"object": "order-1", "visibility_mask": 5});
+            for _ in 0..3 {
+                log.record(Uuid::new_v4(), "occlusion/visible", &input, &true, None);
+            }
+            // The first batch is written as soon as it is full
+            for _ in 0..100 {
+                if std::fs::read_to_string(&path).is_ok_and(|s| s.lines().count() == 2) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            // and the rest when the log is dropped
+            drop(log);
+            for _ in 0..100 {
+                if std::fs::read_to_string(&path).is_ok_and(|s| s.lines().count() == 3) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["path"], "occlusion/visible");
+        assert_eq!(events[0]["input"]["object"], "order-1");
+        assert_eq!(events[0]["result"], true);
+        assert_eq!(events[0]["labels"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(events[0]["labels"]["id"], events[2]["labels"]["id"]);
+        assert_ne!(events[0]["decision_id"], events[1]["decision_id"]);
+    }
+}
@@ -0,0 +1,289 @@
+//! Incremental updates: change events and the delta fetch protocol.
+//!
+//! A change event is a JSON object; `op` defaults to `upsert`:
+//!
+//! ```json
+//! {"uuid": "550e8400-e29b-41d4-a716-446655440000", "visibility_level": 8}
+//! {"uuid": "550e8400-e29b-41d4-a716-446655440000", "op": "delete"}
+//! ```
+//!
+//! In delta mode, a reload of a URL source requests `<delta url>?since=<ETag>`. The origin
+//! answers with one of:
+//!
+//! - `200 OK`: a JSON Lines patch of change events (optionally compressed), with an `ETag`
+//!   identifying the dataset version after the patch
+//! - `304 Not Modified`: no changes since that version
+//! - anything else (e.g. `410 Gone` once the version is too old): a full reload is done
+
+use crate::{
+    ReloadState, compression,
+    error::{LoadError, Result},
+    loader::{self, LoadOptions},
+    source::{DataSource, SourceMetadata},
+};
+use occlusion::SwappableStore;
+use serde::Deserialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Default number of pending changes after which a full reload compacts the store.
+pub const DEFAULT_MAX_PENDING: usize = 1_000_000;
+
+/// Delta fetch configuration.
+#[derive(Debug, Clone)]
+pub struct DeltaOptions {
+    /// Endpoint serving patches (the source URL when `None`)
+    pub url: Option<String>,
+    /// Do a full reload once this many changes have been applied since the last one
+    pub max_pending: usize,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self {
+            url: None,
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Op {
+    #[default]
+    Upsert,
+    Delete,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeEvent {
+    uuid: Uuid,
+    #[serde(default)]
+    op: Op,
+    visibility_level: Option<u8>,
+}
+
+/// Parse a change event into a UUID and its new level (`None` = delete).
+pub(crate) fn parse_change(payload: &[u8]) -> std::result::Result<(Uuid, Option<u8>), String> {
+    let event: ChangeEvent = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+    match event.op {
+        Op::Delete => Ok((event.uuid, None)),
+        Op::Upsert => event
+            .visibility_level
+            .map(|level| (event.uuid, Some(level)))
+            .ok_or_else(|| "upsert without visibility_level".to_string()),
+    }
+}
+
+/// Parse a JSON Lines patch. Blank lines are ignored; any malformed line fails the patch.
+fn parse_patch(content: &[u8]) -> Result<Vec<(Uuid, Option<u8>)>> {
+    content
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty())
+        .map(|(i, line)| {
+            parse_change(line)
+                .map_err(|e| LoadError::InvalidFormat(format!("patch line {}: {e}", i + 1)))
+        })
+        .collect()
+}
+
+/// Result of a delta reload attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaOutcome {
+    /// A patch was applied to the store
+    Applied,
+    /// The source has not changed
+    Unchanged,
+    /// A full reload is needed; `conditional` is false when the current version must be
+    /// downloaded again anyway (to compact pending changes)
+    FullReload {
+        /// Whether the full reload may send conditional headers
+        conditional: bool,
+    },
+}
+
+/// Try to bring `store` up to date by applying a patch, updating the reload metadata.
+///
+/// Errors are logged and reported as [`DeltaOutcome::FullReload`], so the caller's
+/// regular reload (and failure handling) takes over.
+pub async fn reload(store: &SwappableStore, state: &ReloadState) -> DeltaOutcome {
+    let full = DeltaOutcome::FullReload { conditional: true };
+    let (options, source) = (state.options(), state.source());
+    let (Some(delta), DataSource::Url(source_url)) = (&options.delta, &source) else {
+        return full;
+    };
+
+    let pending = store.pending_changes();
+    if pending >= delta.max_pending {
+        info!(
+            pending,
+            "Pending changes over limit, compacting with a full reload"
+        );
+        return DeltaOutcome::FullReload { conditional: false };
+    }
+
+    let old_metadata = state.metadata.read().expect("RwLock poisoned").clone();
+    let Some(since) = old_metadata.etag.as_deref() else {
+        return full;
+    };
+
+    let url = delta.url.as_deref().unwrap_or(source_url);
+    match fetch_patch(url, since, &options).await {
+        Ok(Some((changes, etag))) => {
+            let deletes = changes.iter().filter(|(_, level)| level.is_none()).count();
+            let upserts = changes.len() - deletes;
+            store.apply_changes(changes);
+            state.announce_swap(store, None);
+            *state.metadata.write().expect("RwLock poisoned") = SourceMetadata {
+                etag: Some(etag),
+                last_modified: None,
+                ..old_metadata
+            };
+            info!(
+                upserts,
+                deletes,
+                pending = store.pending_changes(),
+                "Delta applied"
+            );
+            DeltaOutcome::Applied
+        }
+        Ok(None) => DeltaOutcome::Unchanged,
+        Err(e) => {
+            warn!(error = %e, "Delta unavailable, falling back to a full reload");
+            full
+        }
+    }
+}
+
+/// Fetch the patch since `since`, returning `None` if nothing changed.
+async fn fetch_patch(
+    url: &str,
+    since: &str,
+    options: &LoadOptions,
+) -> Result<Option<(Vec<(Uuid, Option<u8>)>, String)>> {
+    let mut url = reqwest::Url::parse(url)
+        .map_err(|e| LoadError::InvalidFormat(format!("Invalid delta URL {url}: {e}")))?;
+    url.query_pairs_mut().append_pair("since", since);
+
+    let request = loader::http_get(url.as_str(), options)
+        .header("Accept-Encoding", compression::ACCEPT_ENCODING);
+    let response = loader::send_with_retry(request, &options.retry).await?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if response.status() != reqwest::StatusCode::OK {
+        return Err(LoadError::HttpError(format!(
+            "Delta request failed with status: {}",
+            response.status()
+        )));
+    }
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string)
+        .ok_or_else(|| LoadError::HttpError("Delta response has no ETag".to_string()))?;
+
+    loader::check_response(&response, options)?;
+    let content = loader::fetch_body(response, None, options.max_size).await?;
+    let max_size = options.max_size;
+    let changes = tokio::task::spawn_blocking(move || {
+        let (content, _) = compression::decompress(content, max_size)?;
+        parse_patch(&content)
+    })
+    .await
+    .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))??;
+
+    Ok(Some((changes, etag)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::tests::{ok, serve};
+
+    #[test]
+    fn test_parse_change() {
+        let uuid = Uuid::from_u128(1);
+
+        let upsert = format!(r#"{{"uuid": "{uuid}", "visibility_level": 4}}"#);
+        assert_eq!(parse_change(upsert.as_bytes()), Ok((uuid, Some(4))));
+
+        let delete = format!(r#"{{"uuid": "{uuid}", "op": "delete"}}"#);
+        assert_eq!(parse_change(delete.as_bytes()), Ok((uuid, None)));
+
+        assert!(parse_change(b"not json").is_err());
+        let missing_level = format!(r#"{{"uuid": "{uuid}"}}"#);
+        assert!(parse_change(missing_level.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let patch = format!(
+            "{{\"uuid\": \"{}\", \"visibility_level\": 1}}\n\n{{\"uuid\": \"{}\", \"op\": \"delete\"}}\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        assert_eq!(
+            parse_patch(patch.as_bytes()).unwrap(),
+            vec![(Uuid::from_u128(1), Some(1)), (Uuid::from_u128(2), None)]
+        );
+
+        let err = parse_patch(b"\n{}\n").unwrap_err();
+        assert!(err.to_string().contains("patch line 2"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_patch() {
+        let patch = format!(r#"{{"uuid": "{}", "op": "delete"}}"#, Uuid::from_u128(1));
+        let response = ok(&patch).replacen("200 OK\r\n", "200 OK\r\nETag: \"v2\"\r\n", 1);
+        let (url, heads) = serve(vec![response]);
+
+        let store = SwappableStore::new(
+            occlusion::build_store(vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 0)]).unwrap(),
+        );
+        let state = ReloadState::new(
+            DataSource::Url(url),
+            LoadOptions {
+                delta: Some(DeltaOptions::default()),
+                ..LoadOptions::default()
+            },
+            SourceMetadata {
+                etag: Some("\"v1\"".to_string()),
+                ..SourceMetadata::default()
+            },
+        );
+
+        assert_eq!(reload(&store, &state).await, DeltaOutcome::Applied);
+        assert!(
+            heads
+                .recv()
+                .unwrap()
+                .starts_with("GET /data.csv?since=%22v1%22 ")
+        );
+        assert_eq!(occlusion::Store::len(&store), 1);
+        assert_eq!(
+            state.metadata.read().unwrap().etag.as_deref(),
+            Some("\"v2\"")
+        );
+
+        // Over the pending limit the store is compacted by a full reload
+        let state = ReloadState::new(
+            state.source(),
+            LoadOptions {
+                delta: Some(DeltaOptions {
+                    url: None,
+                    max_pending: 1,
+                }),
+                ..LoadOptions::default()
+            },
+            state.metadata.into_inner().unwrap(),
+        );
+        assert_eq!(
+            reload(&store, &state).await,
+            DeltaOutcome::FullReload { conditional: false }
+        );
+    }
+}
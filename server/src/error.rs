@@ -20,10 +20,26 @@ pub enum LoadError {
     #[error("HTTP request failed: {0}")]
     ReqwestError(#[from] reqwest::Error),
 
+    /// Object storage (S3, ...) request error
+    #[error("Object store error: {0}")]
+    ObjectStoreError(String),
+
+    /// Checksum or signature verification failed
+    #[error("Integrity check failed: {0}")]
+    IntegrityError(String),
+
     /// Invalid data format
     #[error("Invalid format: {0}")]
     InvalidFormat(String),
 
+    /// Loaded data was rejected by a validation gate
+    #[error("Validation failed: {0}")]
+    ValidationError(String),
+
+    /// Snapshot cache file is unreadable or corrupt
+    #[error("Snapshot error: {0}")]
+    SnapshotError(String),
+
     /// Store construction error
     #[error("Store error: {0}")]
     StoreError(#[from] StoreError),
@@ -31,3 +47,34 @@ pub enum LoadError {
 
 /// Type alias for loading Results
 pub type Result<T> = std::result::Result<T, LoadError>;
+
+/// Errors deriving a visibility mask from a caller's JWT.
+#[derive(Error, Debug)]
+pub enum JwtError {
+    /// No bearer token in the request
+    #[error("missing bearer token")]
+    Missing,
+
+    /// Bad signature, expired, wrong issuer or audience, or not a JWT at all
+    #[error("invalid token: {0}")]
+    Invalid(#[from] jsonwebtoken::errors::Error),
+
+    /// The token's key is not in the JWKS, even after refreshing it
+    #[error("unknown signing key {0:?}")]
+    UnknownKey(String),
+
+    /// The JWKS could not be fetched
+    #[error("failed to fetch JWKS: {0}")]
+    Jwks(String),
+
+    /// The token is valid but does not carry a usable mask
+    #[error("token has no valid {0:?} claim (expected an integer from 0 to 255)")]
+    Claim(String),
+}
+
+impl JwtError {
+    /// Whether the caller is authenticated but not entitled, as opposed to not authenticated.
+    pub fn is_forbidden(&self) -> bool {
+        matches!(self, Self::Claim(_))
+    }
+}
@@ -0,0 +1,166 @@
+//! Notifications of store changes, streamed to clients as server-sent events.
+//!
+//! Downstream caches subscribe to `GET /api/v1/events` to drop cached decisions as soon as new
+//! data is swapped in, rather than on a timer. Reload failures are announced too, so clients
+//! can tell stale data from unchanged data.
+
+use crate::validation::Diff;
+use occlusion::{Store, SwappableStore};
+use rocket::{
+    Shutdown, State,
+    response::stream::{Event, EventStream},
+};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Events kept for subscribers that fall behind before they miss some.
+const CAPACITY: usize = 64;
+
+/// A change of a store's data, or a failure to change it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoreEvent {
+    /// New data was swapped in
+    Swapped {
+        /// Namespace of the store (omitted for the default store)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+        generation: u64,
+        entry_count: usize,
+        /// Changes from the previous data, when known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        diff: Option<Diff>,
+    },
+    /// A reload failed and the current data was kept
+    ReloadFailed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+        error: String,
+        consecutive_failures: u32,
+    },
+}
+
+impl StoreEvent {
+    /// A [`Swapped`](Self::Swapped) event for the data `store` now holds.
+    pub fn swapped(namespace: Option<String>, store: &SwappableStore, diff: Option<Diff>) -> Self {
+        Self::Swapped {
+            namespace,
+            generation: store.generation(),
+            entry_count: store.len(),
+            diff,
+        }
+    }
+
+    /// The SSE event name.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Swapped { .. } => "swapped",
+            Self::ReloadFailed { .. } => "reload_failed",
+        }
+    }
+}
+
+/// Broadcaster of [`StoreEvent`]s, managed by Rocket.
+#[derive(Debug, Clone)]
+pub struct Events(broadcast::Sender<StoreEvent>);
+
+impl Default for Events {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send `event` to the current subscribers, if any.
+    pub fn send(&self, event: StoreEvent) {
+        let _ = self.0.send(event);
+    }
+
+    /// Whether anyone is listening, e.g. to skip computing what only events would report.
+    pub fn has_subscribers(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StoreEvent> {
+        self.0.subscribe()
+    }
+}
+
+/// Stream store events as they happen.
+///
+/// A subscriber too slow to keep up gets a `lagged` event with the number of events it missed,
+/// and should assume its cached decisions are stale.
+#[get("/api/v1/events")]
+pub fn events(events: &State<Events>, mut shutdown: Shutdown) -> EventStream![] {
+    let mut subscription = events.subscribe();
+    EventStream! {
+        loop {
+            let received = tokio::select! {
+                received = subscription.recv() => received,
+                () = &mut shutdown => break,
+            };
+            match received {
+                Ok(event) => yield Event::json(&event).event(event.name()),
+                Err(RecvError::Lagged(missed)) => {
+                    yield Event::json(&serde_json::json!({ "missed": missed })).event("lagged");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::asynchronous::Client;
+    use tokio::io::AsyncBufReadExt;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_events() {
+        let events = Events::new();
+        let store = SwappableStore::new(occlusion::build_store(vec![]).unwrap());
+        let rocket = rocket::build()
+            .manage(events.clone())
+            .mount("/", rocket::routes![self::events]);
+        let client = Client::tracked(rocket).await.unwrap();
+        assert!(!events.has_subscribers());
+        let response = client.get("/api/v1/events").dispatch().await;
+        assert!(events.has_subscribers());
+
+        store.swap(occlusion::build_store(vec![(Uuid::from_u128(1), 3)]).unwrap());
+        let diff = Diff {
+            added: 1,
+            ..Diff::default()
+        };
+        events.send(StoreEvent::swapped(None, &store, Some(diff)));
+        events.send(StoreEvent::ReloadFailed {
+            namespace: Some("tenant-a".to_string()),
+            error: "HTTP 503".to_string(),
+            consecutive_failures: 2,
+        });
+
+        let mut lines = tokio::io::BufReader::new(response).lines();
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if !line.is_empty() && !line.starts_with(':') {
+                received.push(line);
+            }
+        }
+        assert_eq!(received[0], "event:swapped");
+        let data: serde_json::Value =
+            serde_json::from_str(received[1].strip_prefix("data:").unwrap()).unwrap();
+        assert_eq!(data["generation"], store.generation());
+        assert_eq!(data["entry_count"], 1);
+        assert_eq!(data["diff"]["added"], 1);
+        assert!(data.get("namespace").is_none());
+        assert_eq!(received[2], "event:reload_failed");
+        assert!(received[3].contains(r#""namespace":"tenant-a""#));
+    }
+}
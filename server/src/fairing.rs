@@ -1,5 +1,6 @@
-//! Request timing fairing for logging response times.
+//! Request timing fairing for logging response times and aggregating route latencies.
 
+use crate::latency::Latencies;
 use rocket::{
     Data, Request, Response,
     fairing::{Fairing, Info, Kind},
@@ -8,7 +9,8 @@ use rocket::{
 use std::time::Instant;
 use tracing::info;
 
-/// Fairing that logs request timing information.
+/// Fairing that logs request timing information, and records the latency of routed requests
+/// when [`Latencies`] is managed.
 pub struct RequestTimer;
 
 /// Request-local state to store the start time.
@@ -35,12 +37,19 @@ impl Fairing for RequestTimer {
         let uri = request.uri().path();
         let status = response.status();
 
+        if let (Some(latencies), Some(route)) =
+            (request.rocket().state::<Latencies>(), request.route())
+        {
+            latencies.record(&format!("{} {}", route.method, route.uri), elapsed);
+        }
+
         if status == Status::NotFound && uri.as_str() == "/" {
             // Skip logging for root path 404s (common noise)
             return;
         }
 
         info!(
+            request_id = crate::request_id::of(request),
             method = %method,
             path = %uri,
             status = status.code,
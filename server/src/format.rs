@@ -0,0 +1,1416 @@
+//! Input data formats and their parsers.
+
+use crate::{
+    compression::Compression,
+    error::{LoadError, Result},
+    ids::{IdNamespace, ObjectId},
+    levels::{self, LevelNames},
+    progress::{PROGRESS_ROWS, ProgressReporter},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::Infallible, path::Path, str::FromStr, sync::Arc};
+use uuid::Uuid;
+
+/// Maximum number of rejected rows kept in a [`ParseReport`].
+pub(crate) const MAX_REJECTED_SAMPLES: usize = 20;
+
+/// Default name of the UUID column.
+const UUID_COLUMN: &str = "uuid";
+/// Default name of the visibility level column.
+const LEVEL_COLUMN: &str = "visibility_level";
+/// Default name of the optional namespace column.
+const NAMESPACE_COLUMN: &str = "namespace";
+
+/// Format of the data source contents.
+///
+/// Each format is implemented by a [`FormatParser`], which also declares the file
+/// extensions and content types it is detected from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputFormat {
+    /// Comma-separated values with a `uuid,visibility_level` header
+    #[default]
+    Csv,
+    /// Tab-separated values with a `uuid<TAB>visibility_level` header
+    Tsv,
+    /// One JSON object per line: `{"uuid": "...", "visibility_level": 3}`
+    Jsonl,
+    /// A single JSON object mapping UUIDs to levels: `{"<uuid>": 3, ...}`
+    Json,
+    /// OPA bundle: a tarball whose `data.json` holds a UUID to level object at a JSON pointer
+    OpaBundle,
+    /// Apache Parquet with `uuid` and `visibility_level` columns (requires the `parquet` feature)
+    Parquet,
+    /// Arrow IPC stream or file/Feather v2 with `uuid` and `visibility_level` columns
+    /// (requires the `arrow` feature)
+    Arrow,
+}
+
+/// Parser for one input format.
+///
+/// Adding a format takes an [`InputFormat`] variant and an implementation of this
+/// trait returned by [`InputFormat::parser`]; detection and loading pick it up from there.
+pub trait FormatParser: Sync {
+    /// Name used on the command line and in logs.
+    fn name(&self) -> &'static str;
+
+    /// File extensions (lowercase, without the dot) the format is detected from.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// MIME types (lowercase, without parameters) the format is detected from.
+    fn content_types(&self) -> &'static [&'static str];
+
+    /// Parse the raw contents, recording malformed rows in `report`.
+    ///
+    /// `csv` holds the schema options for delimited formats.
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows>;
+}
+
+impl InputFormat {
+    /// Every supported format, in declaration order.
+    fn all() -> impl Iterator<Item = Self> {
+        <Self as clap::ValueEnum>::value_variants().iter().copied()
+    }
+
+    /// The parser implementing this format.
+    pub fn parser(self) -> &'static dyn FormatParser {
+        match self {
+            Self::Csv => &Csv,
+            Self::Tsv => &Tsv,
+            Self::Jsonl => &Jsonl,
+            Self::Json => &JsonMap,
+            Self::OpaBundle => &OpaBundle,
+            Self::Parquet => &Parquet,
+            Self::Arrow => &Arrow,
+        }
+    }
+
+    /// Detect the format from a file extension (`.csv`, `.tsv`, `.jsonl`, ...).
+    ///
+    /// Compression suffixes are skipped, so `data.csv.gz` is detected as CSV.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let mut ext = path.extension()?.to_str()?;
+        if Compression::is_compressed_extension(ext) {
+            ext = Path::new(path.file_stem()?).extension()?.to_str()?;
+        }
+        let ext = ext.to_ascii_lowercase();
+        Self::all().find(|format| format.parser().extensions().contains(&ext.as_str()))
+    }
+
+    /// Detect the format from the path component of a URL.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        Self::from_path(Path::new(path))
+    }
+
+    /// Detect the format from an HTTP `Content-Type` header value.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        Self::all().find(|format| format.parser().content_types().contains(&mime.as_str()))
+    }
+
+    /// Parse the raw contents into (UUID, `visibility_level`) entries.
+    ///
+    /// Rows with a namespace (see [`Rows`]) are kept apart from the default entries.
+    ///
+    /// `csv` applies to the delimited formats. Without an error budget the first
+    /// malformed row aborts the parse; with one, malformed rows are skipped and
+    /// listed in the returned [`ParseReport`] until the budget is exceeded.
+    ///
+    /// The number of parsed rows is published to `progress` as parsing advances.
+    pub fn parse(
+        self,
+        content: Vec<u8>,
+        csv: &CsvOptions,
+        budget: Option<ErrorBudget>,
+        progress: Option<&ProgressReporter>,
+    ) -> Result<(Rows, ParseReport)> {
+        let mut report = ParseReport::new(budget, progress.cloned());
+        let rows = self.parser().parse(content, csv, &mut report)?;
+        report.accepted = rows.len();
+        if let Some(progress) = &report.progress {
+            progress.set_rows(rows.len());
+        }
+        report.check_budget()?;
+        Ok((rows, report))
+    }
+}
+
+impl std::fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.parser().name())
+    }
+}
+
+/// Delimited text using the configured [`CsvOptions`].
+struct Csv;
+
+impl FormatParser for Csv {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["csv"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &["text/csv", "application/csv"]
+    }
+
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        parse_csv(&content, csv, report)
+    }
+}
+
+/// Tab-separated text: CSV parsing with the delimiter forced to a tab.
+struct Tsv;
+
+impl FormatParser for Tsv {
+    fn name(&self) -> &'static str {
+        "tsv"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tsv", "tab"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &["text/tab-separated-values"]
+    }
+
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        let options = CsvOptions {
+            delimiter: b'\t',
+            ..csv.clone()
+        };
+        parse_csv(&content, &options, report)
+    }
+}
+
+/// JSON Lines with `uuid` and `visibility_level` fields.
+struct Jsonl;
+
+impl FormatParser for Jsonl {
+    fn name(&self) -> &'static str {
+        "jsonl"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["jsonl", "ndjson"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &[
+            "application/x-ndjson",
+            "application/jsonl",
+            "application/x-jsonlines",
+        ]
+    }
+
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        parse_jsonl(&content, csv, report)
+    }
+}
+
+/// A JSON object of UUIDs to levels, streamed without building a document tree.
+struct JsonMap;
+
+impl FormatParser for JsonMap {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &["application/json"]
+    }
+
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        parse_json_map(&content, csv, report)
+    }
+}
+
+/// OPA bundle tarball, decoded by [`crate::bundle`].
+struct OpaBundle;
+
+impl FormatParser for OpaBundle {
+    fn name(&self) -> &'static str {
+        "opa-bundle"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tar"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &["application/x-tar"]
+    }
+
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        crate::bundle::parse_bundle(&content, csv, report)
+    }
+}
+
+/// Apache Parquet, decoded by [`crate::columnar`].
+struct Parquet;
+
+impl FormatParser for Parquet {
+    fn name(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["parquet"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &["application/vnd.apache.parquet", "application/x-parquet"]
+    }
+
+    #[cfg(feature = "parquet")]
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        crate::columnar::parse_parquet(content, csv.uuid_byte_order, report).map(Rows::from)
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn parse(
+        &self,
+        _content: Vec<u8>,
+        _csv: &CsvOptions,
+        _report: &mut ParseReport,
+    ) -> Result<Rows> {
+        Err(LoadError::InvalidFormat(
+            "Parquet support requires the `parquet` feature".to_string(),
+        ))
+    }
+}
+
+/// Arrow IPC stream or file, decoded by [`crate::columnar`].
+struct Arrow;
+
+impl FormatParser for Arrow {
+    fn name(&self) -> &'static str {
+        "arrow"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["arrow", "arrows", "feather", "ipc"]
+    }
+
+    fn content_types(&self) -> &'static [&'static str] {
+        &[
+            "application/vnd.apache.arrow.stream",
+            "application/vnd.apache.arrow.file",
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn parse(&self, content: Vec<u8>, csv: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+        crate::columnar::parse_arrow_ipc(content, csv.uuid_byte_order, report).map(Rows::from)
+    }
+
+    #[cfg(not(feature = "arrow"))]
+    fn parse(
+        &self,
+        _content: Vec<u8>,
+        _csv: &CsvOptions,
+        _report: &mut ParseReport,
+    ) -> Result<Rows> {
+        Err(LoadError::InvalidFormat(
+            "Arrow IPC support requires the `arrow` feature".to_string(),
+        ))
+    }
+}
+
+/// Parsed rows, split by the optional namespace column.
+///
+/// Rows without a namespace (or with an empty one) belong to the default store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rows {
+    /// Entries for the default store
+    pub entries: Vec<(Uuid, u8)>,
+    /// Entries for each named namespace
+    pub namespaced: BTreeMap<String, Vec<(Uuid, u8)>>,
+}
+
+impl Rows {
+    /// Add an entry to `namespace`, or the default store when `None` or empty.
+    fn push(&mut self, namespace: Option<&str>, entry: (Uuid, u8)) {
+        match namespace {
+            Some(name) if !name.is_empty() => {
+                if let Some(entries) = self.namespaced.get_mut(name) {
+                    entries.push(entry);
+                } else {
+                    self.namespaced.insert(name.to_string(), vec![entry]);
+                }
+            }
+            _ => self.entries.push(entry),
+        }
+    }
+
+    /// Total number of rows across all namespaces.
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.namespaced.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Whether no rows were parsed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<Vec<(Uuid, u8)>> for Rows {
+    fn from(entries: Vec<(Uuid, u8)>) -> Self {
+        Self {
+            entries,
+            namespaced: BTreeMap::new(),
+        }
+    }
+}
+
+/// A CSV column, selected by header name or zero-based position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// Column with the given header name
+    Name(String),
+    /// Column at the given zero-based position
+    Index(usize),
+}
+
+impl CsvColumn {
+    /// Resolve the column to a position using the header row, if any.
+    fn resolve(&self, headers: Option<&csv::ByteRecord>) -> Result<usize> {
+        match self {
+            Self::Index(idx) => Ok(*idx),
+            Self::Name(name) => {
+                let headers = headers.ok_or_else(|| {
+                    LoadError::InvalidFormat(format!(
+                        "Column {name} selected by name but the CSV has no header row"
+                    ))
+                })?;
+                headers
+                    .iter()
+                    .position(|h| h == name.as_bytes())
+                    .ok_or_else(|| LoadError::InvalidFormat(format!("Missing column: {name}")))
+            }
+        }
+    }
+
+    /// Resolve an optional column: a named column absent from the header is skipped.
+    fn resolve_optional(&self, headers: Option<&csv::ByteRecord>) -> Option<usize> {
+        match self {
+            Self::Index(idx) => Some(*idx),
+            Self::Name(name) => headers?.iter().position(|h| h == name.as_bytes()),
+        }
+    }
+}
+
+/// Numeric values select a column by position, anything else by header name.
+impl FromStr for CsvColumn {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(s.parse()
+            .map_or_else(|_| Self::Name(s.to_string()), Self::Index))
+    }
+}
+
+impl std::fmt::Display for CsvColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Index(idx) => write!(f, "{idx}"),
+        }
+    }
+}
+
+/// Parse a CSV delimiter argument: a single ASCII character, or `tab`.
+pub fn parse_delimiter(s: &str) -> std::result::Result<u8, String> {
+    match s {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ => match s.as_bytes() {
+            [b] if b.is_ascii() => Ok(*b),
+            _ => Err(format!(
+                "delimiter must be a single ASCII character, got {s:?}"
+            )),
+        },
+    }
+}
+
+/// Schema of CSV input.
+///
+/// The default is a comma-separated file with a `uuid,visibility_level` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Field delimiter
+    pub delimiter: u8,
+    /// Whether the first row is a header row
+    pub has_headers: bool,
+    /// Column holding the UUID
+    pub uuid_column: CsvColumn,
+    /// Column holding the visibility level
+    pub level_column: CsvColumn,
+    /// Optional column routing each row to a namespace
+    pub namespace_column: CsvColumn,
+    /// Role names accepted in place of numeric levels (also used for the JSON formats)
+    pub level_names: Option<Arc<LevelNames>>,
+    /// Byte order of UUIDs given as raw bytes (also used for the other formats)
+    pub uuid_byte_order: UuidByteOrder,
+    /// JSON pointer to the UUID to level object in an OPA bundle's data (empty for the root)
+    pub bundle_pointer: String,
+    /// Namespace non-UUID identifiers are hashed under (rejected when unset)
+    pub id_namespace: Option<IdNamespace>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            uuid_column: CsvColumn::Name(UUID_COLUMN.to_string()),
+            level_column: CsvColumn::Name(LEVEL_COLUMN.to_string()),
+            namespace_column: CsvColumn::Name(NAMESPACE_COLUMN.to_string()),
+            level_names: None,
+            uuid_byte_order: UuidByteOrder::default(),
+            bundle_pointer: String::new(),
+            id_namespace: None,
+        }
+    }
+}
+
+/// Number of malformed rows tolerated before a load is aborted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorBudget {
+    /// At most this many rows
+    Count(usize),
+    /// At most this percentage (0-100) of all rows
+    Percent(f64),
+}
+
+impl ErrorBudget {
+    /// Whether `rejected` malformed rows out of `total` rows are within the budget.
+    pub fn allows(self, rejected: usize, total: usize) -> bool {
+        match self {
+            Self::Count(max) => rejected <= max,
+            #[allow(clippy::cast_precision_loss)]
+            Self::Percent(max) => rejected as f64 * 100.0 / total.max(1) as f64 <= max,
+        }
+    }
+}
+
+/// Parses `N` as an absolute count and `N%` as a percentage.
+impl FromStr for ErrorBudget {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f64 = percent
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid percentage {s:?}: {e}"))?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!("percentage must be between 0 and 100, got {s:?}"));
+            }
+            Ok(Self::Percent(percent))
+        } else {
+            s.trim()
+                .parse()
+                .map(Self::Count)
+                .map_err(|e| format!("invalid row count {s:?}: {e}"))
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count(count) => write!(f, "{count} rows"),
+            Self::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+/// A malformed row skipped by a lenient parse.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectedRow {
+    /// Line (or row, for columnar formats, or entry, for JSON objects) number, starting at 1
+    pub line: usize,
+    /// Why the row was rejected
+    pub reason: String,
+}
+
+/// Summary of a parse: accepted rows and the malformed rows that were skipped.
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    /// Number of rows loaded
+    pub accepted: usize,
+    /// Number of rows skipped
+    pub rejected_count: usize,
+    /// The first rejected rows (at most 20)
+    pub rejected: Vec<RejectedRow>,
+    budget: Option<ErrorBudget>,
+    progress: Option<ProgressReporter>,
+    last_progress: usize,
+}
+
+impl ParseReport {
+    fn new(budget: Option<ErrorBudget>, progress: Option<ProgressReporter>) -> Self {
+        Self {
+            budget,
+            progress,
+            ..Self::default()
+        }
+    }
+
+    /// Publish the number of rows parsed so far, at most every [`PROGRESS_ROWS`] rows.
+    pub(crate) fn parsed(&mut self, rows: usize) {
+        if let Some(progress) = &self.progress
+            && rows >= self.last_progress + PROGRESS_ROWS
+        {
+            progress.set_rows(rows);
+            self.last_progress = rows;
+        }
+    }
+
+    /// Total number of rows seen.
+    pub fn total_rows(&self) -> usize {
+        self.accepted + self.rejected_count
+    }
+
+    /// Record a malformed row.
+    ///
+    /// Returns `err` when parsing strictly, or once an absolute budget is exceeded.
+    pub(crate) fn reject(&mut self, line: usize, err: LoadError) -> Result<()> {
+        match self.budget {
+            None => return Err(err),
+            Some(ErrorBudget::Count(max)) if self.rejected_count >= max => {
+                return Err(LoadError::InvalidFormat(format!(
+                    "Error budget of {max} malformed rows exceeded at {}",
+                    reason(err)
+                )));
+            }
+            Some(_) => {}
+        }
+
+        self.rejected_count += 1;
+        if self.rejected.len() < MAX_REJECTED_SAMPLES {
+            self.rejected.push(RejectedRow {
+                line,
+                reason: reason(err),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fail if a percentage budget was exceeded (only known once all rows are read).
+    fn check_budget(&self) -> Result<()> {
+        if let Some(budget @ ErrorBudget::Percent(max)) = self.budget
+            && !budget.allows(self.rejected_count, self.total_rows())
+        {
+            return Err(LoadError::InvalidFormat(format!(
+                "Error budget of {max}% exceeded: {} of {} rows malformed",
+                self.rejected_count,
+                self.total_rows()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Error message without the error kind prefix.
+fn reason(err: LoadError) -> String {
+    match err {
+        LoadError::InvalidFormat(msg) => msg,
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Record {
+    uuid: ObjectId,
+    visibility_level: Level,
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+/// A JSON Lines level: a number, or a role name resolved through [`LevelNames`].
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Level {
+    Number(u8),
+    Name(String),
+}
+
+impl Level {
+    fn resolve(self, names: Option<&LevelNames>, line: usize) -> Result<u8> {
+        match self {
+            Self::Number(level) => Ok(level),
+            Self::Name(name) => names.and_then(|names| names.get(&name)).ok_or_else(|| {
+                LoadError::InvalidFormat(format!("Line {line}: unknown level name {name:?}"))
+            }),
+        }
+    }
+}
+
+/// Byte order of UUIDs given as raw bytes: binary columns, or 32 hex digits without dashes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum UuidByteOrder {
+    /// RFC 9562 order, as printed
+    #[default]
+    Rfc,
+    /// Microsoft GUID layout, with the first three fields little-endian
+    /// (e.g. .NET `Guid.ToByteArray()` or SQL Server `uniqueidentifier` bytes)
+    Microsoft,
+}
+
+impl UuidByteOrder {
+    /// Convert a UUID read from raw bytes to RFC order.
+    pub(crate) fn normalize(self, uuid: Uuid) -> Uuid {
+        match self {
+            Self::Rfc => uuid,
+            Self::Microsoft => Uuid::from_bytes_le(uuid.into_bytes()),
+        }
+    }
+}
+
+/// Parse a textual UUID, ignoring surrounding whitespace.
+///
+/// Accepts hyphenated, simple (32 hex digits), braced (`{...}`) and URN (`urn:uuid:...`)
+/// forms in either case. Simple UUIDs are raw byte dumps, so `order` applies to them.
+pub(crate) fn parse_uuid_text(
+    s: &[u8],
+    order: UuidByteOrder,
+) -> std::result::Result<Uuid, uuid::Error> {
+    let s = s.trim_ascii();
+    let uuid = Uuid::try_parse_ascii(s)?;
+    Ok(if s.len() == 32 {
+        order.normalize(uuid)
+    } else {
+        uuid
+    })
+}
+
+/// Parse an object identifier: a UUID, or any UTF-8 text hashed under `options.id_namespace`.
+fn parse_id(s: &[u8], options: &CsvOptions) -> std::result::Result<Uuid, uuid::Error> {
+    parse_uuid_text(s, options.uuid_byte_order).or_else(|e| {
+        match (options.id_namespace, std::str::from_utf8(s.trim_ascii())) {
+            (Some(namespace), Ok(id)) if !id.is_empty() => Ok(namespace.hash(id)),
+            _ => Err(e),
+        }
+    })
+}
+
+fn parse_csv(content: &[u8], options: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+    // Short rows are reported as missing columns when lenient
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(options.has_headers)
+        .flexible(report.budget.is_some())
+        .from_reader(content);
+
+    let headers = if options.has_headers {
+        Some(csv_reader.byte_headers()?.clone())
+    } else {
+        None
+    };
+    let uuid_idx = options.uuid_column.resolve(headers.as_ref())?;
+    let level_idx = options.level_column.resolve(headers.as_ref())?;
+    let namespace_idx = options.namespace_column.resolve_optional(headers.as_ref());
+
+    let mut rows = Rows::default();
+    let mut record = csv::ByteRecord::new();
+    while csv_reader.read_byte_record(&mut record)? {
+        let line = record.position().map_or(0, csv::Position::line);
+        let namespace = namespace_idx
+            .and_then(|idx| record.get(idx))
+            .map(|ns| std::str::from_utf8(ns.trim_ascii()));
+        let row = match namespace {
+            Some(Err(_)) => Err(LoadError::InvalidFormat(format!(
+                "Line {line}: {NAMESPACE_COLUMN} is not UTF-8"
+            ))),
+            _ => csv_row(&record, line, uuid_idx, level_idx, options),
+        };
+        match row {
+            Ok(entry) => {
+                rows.push(namespace.and_then(std::result::Result::ok), entry);
+                report.parsed(rows.len());
+            }
+            Err(e) => report.reject(usize::try_from(line).unwrap_or(usize::MAX), e)?,
+        }
+    }
+
+    Ok(rows)
+}
+
+fn csv_row(
+    record: &csv::ByteRecord,
+    line: u64,
+    uuid_idx: usize,
+    level_idx: usize,
+    options: &CsvOptions,
+) -> Result<(Uuid, u8)> {
+    let field = |idx: usize| {
+        record
+            .get(idx)
+            .ok_or_else(|| LoadError::InvalidFormat(format!("Line {line}: missing column {idx}")))
+    };
+
+    let uuid = parse_id(field(uuid_idx)?, options)
+        .map_err(|e| LoadError::InvalidFormat(format!("Line {line}: {e}")))?;
+    let level = std::str::from_utf8(field(level_idx)?)
+        .ok()
+        .and_then(|s| levels::resolve(s, options.level_names.as_deref()))
+        .ok_or_else(|| LoadError::InvalidFormat(format!("Line {line}: invalid {LEVEL_COLUMN}")))?;
+    Ok((uuid, level))
+}
+
+fn parse_jsonl(content: &[u8], options: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+    let mut rows = Rows::default();
+    let lines = content
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty());
+
+    for (line_num, line) in lines {
+        let line_num = line_num + 1;
+        let row = serde_json::from_slice::<Record>(line)
+            .map_err(|e| LoadError::InvalidFormat(format!("Line {line_num}: {e}")))
+            .and_then(|record| {
+                let uuid = parse_id(record.uuid.as_text().as_bytes(), options)
+                    .map_err(|e| LoadError::InvalidFormat(format!("Line {line_num}: {e}")))?;
+                let level = record
+                    .visibility_level
+                    .resolve(options.level_names.as_deref(), line_num)?;
+                Ok((record.namespace, (uuid, level)))
+            });
+        match row {
+            Ok((namespace, entry)) => {
+                rows.push(namespace.as_deref(), entry);
+                report.parsed(rows.len());
+            }
+            Err(e) => report.reject(line_num, e)?,
+        }
+    }
+
+    Ok(rows)
+}
+
+fn parse_json_map(content: &[u8], options: &CsvOptions, report: &mut ParseReport) -> Result<Rows> {
+    let mut deserializer = serde_json::Deserializer::from_slice(content);
+    let mut visitor = JsonMapVisitor {
+        options,
+        report,
+        error: None,
+    };
+    let parsed = serde::Deserializer::deserialize_map(&mut deserializer, &mut visitor)
+        .and_then(|rows| deserializer.end().map(|()| rows));
+    match (parsed, visitor.error) {
+        // A malformed entry beyond the error budget aborted the parse
+        (_, Some(e)) => Err(e),
+        (Ok(rows), None) => Ok(rows),
+        (Err(e), None) => Err(LoadError::InvalidFormat(e.to_string())),
+    }
+}
+
+/// Collects the entries of a JSON object one at a time.
+struct JsonMapVisitor<'a> {
+    options: &'a CsvOptions,
+    report: &'a mut ParseReport,
+    /// Error to return instead of the serde error that stopped the parse
+    error: Option<LoadError>,
+}
+
+/// Parse one `"<uuid>": level` entry of a JSON object, numbered from 1 by `index`.
+pub(crate) fn json_entry(
+    key: &str,
+    value: &serde_json::Value,
+    index: usize,
+    options: &CsvOptions,
+) -> Result<(Uuid, u8)> {
+    let uuid = parse_id(key.as_bytes(), options)
+        .map_err(|e| LoadError::InvalidFormat(format!("Entry {index}: {e}")))?;
+    let level = match value {
+        serde_json::Value::Number(n) => n.as_u64().and_then(|n| u8::try_from(n).ok()),
+        serde_json::Value::String(s) => levels::resolve(s, options.level_names.as_deref()),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        LoadError::InvalidFormat(format!("Entry {index}: invalid {LEVEL_COLUMN} {value}"))
+    })?;
+    Ok((uuid, level))
+}
+
+impl<'de> serde::de::Visitor<'de> for &mut JsonMapVisitor<'_> {
+    type Value = Rows;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an object mapping UUIDs to visibility levels")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> std::result::Result<Rows, A::Error> {
+        let mut rows = Rows::default();
+        let mut index = 0;
+        while let Some(key) = map.next_key::<String>()? {
+            // Each value is small, so buffering it keeps type errors recoverable
+            let value = map.next_value::<serde_json::Value>()?;
+            index += 1;
+            match json_entry(&key, &value, index, self.options) {
+                Ok(entry) => {
+                    rows.entries.push(entry);
+                    self.report.parsed(rows.len());
+                }
+                Err(e) => {
+                    if let Err(e) = self.report.reject(index, e) {
+                        self.error = Some(e);
+                        return Err(serde::de::Error::custom("error budget exceeded"));
+                    }
+                }
+            }
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            InputFormat::from_path(Path::new("data.csv")),
+            Some(InputFormat::Csv)
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("/tmp/data.JSONL")),
+            Some(InputFormat::Jsonl)
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("data.ndjson")),
+            Some(InputFormat::Jsonl)
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("data.jsonl.zst")),
+            Some(InputFormat::Jsonl)
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("data.CSV.GZ")),
+            Some(InputFormat::Csv)
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("export.tsv.gz")),
+            Some(InputFormat::Tsv)
+        );
+        assert_eq!(InputFormat::from_path(Path::new("data.gz")), None);
+        assert_eq!(InputFormat::from_path(Path::new("data")), None);
+    }
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(
+            InputFormat::from_url("https://example.com/export.jsonl?sig=abc"),
+            Some(InputFormat::Jsonl)
+        );
+        assert_eq!(InputFormat::from_url("https://example.com/export"), None);
+    }
+
+    #[test]
+    fn test_from_content_type() {
+        assert_eq!(
+            InputFormat::from_content_type("text/csv; charset=utf-8"),
+            Some(InputFormat::Csv)
+        );
+        assert_eq!(
+            InputFormat::from_content_type("application/x-ndjson"),
+            Some(InputFormat::Jsonl)
+        );
+        assert_eq!(
+            InputFormat::from_content_type("application/vnd.apache.arrow.stream"),
+            Some(InputFormat::Arrow)
+        );
+        assert_eq!(
+            InputFormat::from_content_type("text/tab-separated-values"),
+            Some(InputFormat::Tsv)
+        );
+        assert_eq!(
+            InputFormat::from_content_type("application/json"),
+            Some(InputFormat::Json)
+        );
+        assert_eq!(InputFormat::from_content_type("text/html"), None);
+    }
+
+    #[test]
+    fn test_parse_json_map() {
+        let content = format!(
+            "{{\"{}\": 3, \"{}\": 0}}",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let (rows, _) = InputFormat::Json
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap();
+        assert_eq!(
+            rows.entries,
+            vec![(Uuid::from_u128(1), 3), (Uuid::from_u128(2), 0)]
+        );
+
+        let content = format!(
+            "{{\"{}\": 3, \"nope\": 1, \"{}\": 300}}",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let err = InputFormat::Json
+            .parse(
+                content.clone().into_bytes(),
+                &CsvOptions::default(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Entry 2"), "{err}");
+
+        let (rows, report) = InputFormat::Json
+            .parse(
+                content.clone().into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Count(2)),
+                None,
+            )
+            .unwrap();
+        assert_eq!(rows.entries, vec![(Uuid::from_u128(1), 3)]);
+        assert_eq!(report.rejected[1].line, 3);
+
+        let err = InputFormat::Json
+            .parse(
+                content.into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Count(1)),
+                None,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("budget"), "{err}");
+
+        // Not an object, or trailing data
+        for content in ["[1, 2]", "{} {}"] {
+            assert!(
+                InputFormat::Json
+                    .parse(content.into(), &CsvOptions::default(), None, None)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_jsonl() {
+        let content = format!(
+            "{{\"uuid\": \"{}\", \"visibility_level\": 3}}\n\n{{\"uuid\": \"{}\", \"visibility_level\": 0}}\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let entries = InputFormat::Jsonl
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap()
+            .0
+            .entries;
+        assert_eq!(
+            entries,
+            vec![(Uuid::from_u128(1), 3), (Uuid::from_u128(2), 0)]
+        );
+    }
+
+    #[test]
+    fn test_parse_jsonl_reports_line() {
+        let content = format!(
+            "{{\"uuid\": \"{}\", \"visibility_level\": 3}}\n{{\"uuid\": \"nope\", \"visibility_level\": 0}}\n",
+            Uuid::from_u128(1)
+        );
+        let err = InputFormat::Jsonl
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("Line 2"), "{err}");
+
+        let err = InputFormat::Jsonl
+            .parse(
+                b"{\"uuid\": \"x\", \"visibility_level\": 256}".to_vec(),
+                &CsvOptions::default(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Line 1"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_tsv() {
+        let content = format!(
+            "uuid\tvisibility_level\n{}\t3\n{}\t0\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        // The delimiter is always a tab, whatever the CSV options say
+        let entries = InputFormat::Tsv
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap()
+            .0
+            .entries;
+        assert_eq!(
+            entries,
+            vec![(Uuid::from_u128(1), 3), (Uuid::from_u128(2), 0)]
+        );
+    }
+
+    #[test]
+    fn test_parser_names() {
+        use clap::ValueEnum;
+
+        // Names shown in logs match the command-line values
+        for format in InputFormat::value_variants() {
+            let value = format.to_possible_value().unwrap();
+            assert_eq!(value.get_name(), format.to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_csv_default() {
+        let content = format!(
+            "uuid,visibility_level\n{},3\n{},0\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let entries = InputFormat::Csv
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap()
+            .0
+            .entries;
+        assert_eq!(
+            entries,
+            vec![(Uuid::from_u128(1), 3), (Uuid::from_u128(2), 0)]
+        );
+
+        let err = InputFormat::Csv
+            .parse(
+                format!("uuid,visibility_level\n{},300\n", Uuid::nil()).into_bytes(),
+                &CsvOptions::default(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Line 2"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_csv_named_columns() {
+        let options = CsvOptions {
+            delimiter: b';',
+            uuid_column: "id".parse().unwrap(),
+            level_column: "level".parse().unwrap(),
+            ..CsvOptions::default()
+        };
+        let content = format!("name;level;id\nfoo;7;{}\n", Uuid::from_u128(9));
+        let entries = InputFormat::Csv
+            .parse(content.into_bytes(), &options, None, None)
+            .unwrap()
+            .0
+            .entries;
+        assert_eq!(entries, vec![(Uuid::from_u128(9), 7)]);
+
+        let err = InputFormat::Csv
+            .parse(b"uuid;lvl\n".to_vec(), &options, None, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("Missing column: id"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_csv_headerless() {
+        let options = CsvOptions {
+            delimiter: b'\t',
+            has_headers: false,
+            uuid_column: CsvColumn::Index(1),
+            level_column: CsvColumn::Index(0),
+            ..CsvOptions::default()
+        };
+        let content = format!("4\t{}\n", Uuid::from_u128(3));
+        let entries = InputFormat::Csv
+            .parse(content.clone().into_bytes(), &options, None, None)
+            .unwrap()
+            .0
+            .entries;
+        assert_eq!(entries, vec![(Uuid::from_u128(3), 4)]);
+
+        let named = CsvOptions {
+            has_headers: false,
+            ..CsvOptions::default()
+        };
+        assert!(
+            InputFormat::Csv
+                .parse(content.into_bytes(), &named, None, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_uuid_forms() {
+        let uuid: Uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap();
+        for form in [
+            "67e55044-10b1-426f-9247-bb680e5fe0c8",
+            "67E55044-10B1-426F-9247-BB680E5FE0C8",
+            "67e5504410b1426f9247bb680e5fe0c8",
+            "{67e55044-10b1-426f-9247-bb680e5fe0c8}",
+            "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8",
+            " 67e55044-10b1-426f-9247-bb680e5fe0c8 ",
+        ] {
+            assert_eq!(
+                parse_uuid_text(form.as_bytes(), UuidByteOrder::Rfc).unwrap(),
+                uuid,
+                "{form}"
+            );
+        }
+
+        // Only raw byte dumps are reordered
+        let guid: Uuid = "4450e567-b110-6f42-9247-bb680e5fe0c8".parse().unwrap();
+        assert_eq!(
+            parse_uuid_text(
+                b"67e5504410b1426f9247bb680e5fe0c8",
+                UuidByteOrder::Microsoft
+            )
+            .unwrap(),
+            guid
+        );
+        assert_eq!(
+            parse_uuid_text(
+                b"67e55044-10b1-426f-9247-bb680e5fe0c8",
+                UuidByteOrder::Microsoft
+            )
+            .unwrap(),
+            uuid
+        );
+
+        let options = CsvOptions {
+            uuid_byte_order: UuidByteOrder::Microsoft,
+            ..CsvOptions::default()
+        };
+        let (rows, _) = InputFormat::Csv
+            .parse(
+                b"uuid,visibility_level\n67e5504410b1426f9247bb680e5fe0c8,1\n".to_vec(),
+                &options,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(rows.entries, vec![(guid, 1)]);
+    }
+
+    #[test]
+    fn test_parse_hashed_ids() {
+        let content = format!(
+            "uuid,visibility_level\norder-1,1\n{},2\n",
+            Uuid::from_u128(1)
+        );
+        assert!(
+            InputFormat::Csv
+                .parse(
+                    content.clone().into_bytes(),
+                    &CsvOptions::default(),
+                    None,
+                    None
+                )
+                .is_err()
+        );
+
+        let namespace = IdNamespace(Uuid::NAMESPACE_OID);
+        let options = CsvOptions {
+            id_namespace: Some(namespace),
+            ..CsvOptions::default()
+        };
+        let (rows, _) = InputFormat::Csv
+            .parse(content.into_bytes(), &options, None, None)
+            .unwrap();
+        assert_eq!(
+            rows.entries,
+            vec![(namespace.hash("order-1"), 1), (Uuid::from_u128(1), 2)]
+        );
+
+        let content = b"{\"uuid\": 42, \"visibility_level\": 3}\n".to_vec();
+        let (rows, _) = InputFormat::Jsonl
+            .parse(content, &options, None, None)
+            .unwrap();
+        assert_eq!(rows.entries, vec![(namespace.hash("42"), 3)]);
+
+        let (rows, _) = InputFormat::Json
+            .parse(b"{\"order-1\": 4}".to_vec(), &options, None, None)
+            .unwrap();
+        assert_eq!(rows.entries, vec![(namespace.hash("order-1"), 4)]);
+    }
+
+    #[test]
+    fn test_parse_namespace_column() {
+        let content = format!(
+            "uuid,visibility_level,namespace\n{},1,tenant-a\n{},2,\n{},3,tenant-b\n{},4,tenant-a\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2),
+            Uuid::from_u128(3),
+            Uuid::from_u128(4)
+        );
+        let (rows, report) = InputFormat::Csv
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap();
+        assert_eq!(report.accepted, 4);
+        assert_eq!(rows.entries, vec![(Uuid::from_u128(2), 2)]);
+        assert_eq!(
+            rows.namespaced,
+            BTreeMap::from([
+                (
+                    "tenant-a".to_string(),
+                    vec![(Uuid::from_u128(1), 1), (Uuid::from_u128(4), 4)]
+                ),
+                ("tenant-b".to_string(), vec![(Uuid::from_u128(3), 3)]),
+            ])
+        );
+
+        let content = format!(
+            "{{\"uuid\": \"{}\", \"visibility_level\": 3, \"namespace\": \"tenant-a\"}}\n\
+             {{\"uuid\": \"{}\", \"visibility_level\": 0}}\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let (rows, _) = InputFormat::Jsonl
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap();
+        assert_eq!(rows.entries, vec![(Uuid::from_u128(2), 0)]);
+        assert_eq!(rows.namespaced["tenant-a"], vec![(Uuid::from_u128(1), 3)]);
+    }
+
+    #[test]
+    fn test_parse_level_names() {
+        let options = CsvOptions {
+            level_names: Some(Arc::new(
+                LevelNames::from_json(br#"{"public": 0, "internal": 5}"#).unwrap(),
+            )),
+            ..CsvOptions::default()
+        };
+        let content = format!(
+            "uuid,visibility_level\n{},internal\n{},9\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let (rows, _) = InputFormat::Csv
+            .parse(content.into_bytes(), &options, None, None)
+            .unwrap();
+        assert_eq!(
+            rows.entries,
+            vec![(Uuid::from_u128(1), 5), (Uuid::from_u128(2), 9)]
+        );
+
+        let content = format!(
+            "{{\"uuid\": \"{}\", \"visibility_level\": \"public\"}}\n",
+            Uuid::from_u128(1)
+        );
+        let (rows, _) = InputFormat::Jsonl
+            .parse(content.clone().into_bytes(), &options, None, None)
+            .unwrap();
+        assert_eq!(rows.entries, vec![(Uuid::from_u128(1), 0)]);
+
+        // Names are rejected without a mapping
+        let err = InputFormat::Jsonl
+            .parse(content.into_bytes(), &CsvOptions::default(), None, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown level name"), "{err}");
+    }
+
+    fn lenient_csv() -> String {
+        let mut lines = vec!["uuid,visibility_level".to_string()];
+        lines.extend((0..8).map(|i| format!("{},{}", Uuid::from_u128(i), i)));
+        lines.push("not-a-uuid,1".to_string());
+        lines.push(format!("{},256", Uuid::from_u128(99)));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_lenient_within_budget() {
+        let (entries, report) = InputFormat::Csv
+            .parse(
+                lenient_csv().into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Count(2)),
+                None,
+            )
+            .unwrap();
+        assert_eq!(entries.len(), 8);
+        assert_eq!(report.accepted, 8);
+        assert_eq!(report.rejected_count, 2);
+        assert_eq!(report.total_rows(), 10);
+        assert_eq!(report.rejected[0].line, 10);
+        assert_eq!(report.rejected[1].line, 11);
+        assert!(report.rejected[1].reason.contains("Line 11"));
+
+        let (_, report) = InputFormat::Csv
+            .parse(
+                lenient_csv().into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Percent(20.0)),
+                None,
+            )
+            .unwrap();
+        assert_eq!(report.rejected_count, 2);
+    }
+
+    #[test]
+    fn test_lenient_budget_exceeded() {
+        let err = InputFormat::Csv
+            .parse(
+                lenient_csv().into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Count(1)),
+                None,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Line 11"), "{err}");
+
+        let err = InputFormat::Csv
+            .parse(
+                lenient_csv().into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Percent(10.0)),
+                None,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("2 of 10"), "{err}");
+    }
+
+    #[test]
+    fn test_lenient_jsonl() {
+        let content = format!(
+            "{{\"uuid\": \"{}\", \"visibility_level\": 3}}\n{{\"uuid\": \"x\"}}\n",
+            Uuid::from_u128(1)
+        );
+        let (rows, report) = InputFormat::Jsonl
+            .parse(
+                content.into_bytes(),
+                &CsvOptions::default(),
+                Some(ErrorBudget::Count(1)),
+                None,
+            )
+            .unwrap();
+        assert_eq!(rows.entries, vec![(Uuid::from_u128(1), 3)]);
+        assert_eq!(report.rejected[0].line, 2);
+    }
+
+    #[test]
+    fn test_error_budget_from_str() {
+        assert_eq!("100".parse(), Ok(ErrorBudget::Count(100)));
+        assert_eq!("0.5%".parse(), Ok(ErrorBudget::Percent(0.5)));
+        assert!("150%".parse::<ErrorBudget>().is_err());
+        assert!("lots".parse::<ErrorBudget>().is_err());
+    }
+}
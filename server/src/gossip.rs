@@ -0,0 +1,419 @@
+//! Peer gossip: instances of a fleet compare the version of the data they serve.
+//!
+//! Every round, each peer's `/api/v1/meta` is fetched and the source version it reports
+//! (the `ETag`, Last-Modified header or modification time of its data) is compared with
+//! this instance's. Peers are a static list of URLs, names resolved every round (e.g. a
+//! Kubernetes headless service, whose addresses include this instance's own), or both.
+//! Peers are expected to load the same source: versions of different sources are not
+//! comparable.
+//!
+//! Optionally, a peer serving newer data triggers an immediate conditional reload, so that
+//! a fleet converges within a gossip round of the first instance noticing a change rather
+//! than each instance waiting for its own next scheduled check.
+
+use crate::{
+    ReloadState,
+    models::MetaResponse,
+    scheduler::{ReloadWindow, next_window},
+    source::SourceMetadata,
+};
+use occlusion::SwappableStore;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Longest time fetching a peer's version may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A name resolved to peers every round: each of its addresses, on `port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerDns {
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for PeerDns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = s
+            .rsplit_once(':')
+            .filter(|(host, _)| !host.is_empty())
+            .ok_or_else(|| format!("invalid peer name {s:?}: expected HOST:PORT"))?;
+        let port = port
+            .parse()
+            .map_err(|_| format!("invalid port in peer name {s:?}"))?;
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for PeerDns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// How the data a peer serves compares with this instance's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Convergence {
+    /// Same version
+    InSync,
+    /// A newer version
+    Ahead,
+    /// An older version, or no data
+    Behind,
+    /// A different version that cannot be ordered with ours
+    Diverged,
+    /// The peer could not be reached
+    Unreachable,
+}
+
+impl Convergence {
+    /// Compare the version `peer` reports with `ours`.
+    pub fn of(ours: &SourceMetadata, peer: &MetaResponse) -> Self {
+        let version = ours.version();
+        match (version, &peer.source_version) {
+            (ours, theirs) if ours == *theirs => Self::InSync,
+            (_, None) => Self::Behind,
+            (None, Some(_)) => Self::Ahead,
+            (Some(_), Some(_)) => match (ours.modified_at(), peer.source_modified_at) {
+                (Some(ours), Some(theirs)) if theirs > ours => Self::Ahead,
+                (Some(ours), Some(theirs)) if theirs < ours => Self::Behind,
+                _ => Self::Diverged,
+            },
+        }
+    }
+
+    /// Whether the peer may serve data this instance has not loaded yet.
+    pub fn is_newer(self) -> bool {
+        matches!(self, Self::Ahead | Self::Diverged)
+    }
+}
+
+/// What a peer reported in the last round.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStatus {
+    pub peer: String,
+    pub convergence: Convergence,
+    /// Generation of the peer's data (only meaningful to that peer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_modified_at: Option<u64>,
+    /// Why the peer could not be reached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Convergence of the fleet as seen from this instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FleetStatus {
+    /// When the last round finished (seconds since the Unix epoch; omitted before the first)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<u64>,
+    /// Version of the data this instance serves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_version: Option<String>,
+    pub peers: Vec<PeerStatus>,
+}
+
+impl FleetStatus {
+    /// Counts of the peers by convergence.
+    pub fn summary(&self) -> FleetSummary {
+        let count = |convergence| {
+            self.peers
+                .iter()
+                .filter(|peer| peer.convergence == convergence)
+                .count()
+        };
+        let in_sync = count(Convergence::InSync);
+        let unreachable = count(Convergence::Unreachable);
+        FleetSummary {
+            peers: self.peers.len(),
+            in_sync,
+            unreachable,
+            converged: self.checked_at.is_some() && in_sync + unreachable == self.peers.len(),
+        }
+    }
+}
+
+/// Fleet convergence, as reported in store statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FleetSummary {
+    pub peers: usize,
+    pub in_sync: usize,
+    pub unreachable: usize,
+    /// Whether every reachable peer serves the same version as this instance
+    pub converged: bool,
+}
+
+/// The latest fleet status, shared between the gossip task and the API.
+#[derive(Debug, Clone, Default)]
+pub struct Fleet(Arc<RwLock<FleetStatus>>);
+
+impl Fleet {
+    pub fn status(&self) -> FleetStatus {
+        self.0.read().expect("RwLock poisoned").clone()
+    }
+}
+
+/// Peers, and what to do about them.
+#[derive(Debug, Clone)]
+pub struct Gossip {
+    peers: Vec<Url>,
+    dns: Option<PeerDns>,
+    interval: Duration,
+    reload: bool,
+    windows: Vec<ReloadWindow>,
+    client: reqwest::Client,
+}
+
+impl Gossip {
+    /// Gossip with `peers` and the addresses of `dns` every `interval`.
+    pub fn new(peers: Vec<Url>, dns: Option<PeerDns>, interval: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        Self {
+            peers,
+            dns,
+            interval,
+            reload: false,
+            windows: Vec::new(),
+            client,
+        }
+    }
+
+    /// Reload as soon as a peer reports a newer version, within `windows` if any.
+    #[must_use]
+    pub fn with_reload(mut self, reload: bool, windows: Vec<ReloadWindow>) -> Self {
+        self.reload = reload;
+        self.windows = windows;
+        self
+    }
+
+    /// The peers of this round: the static ones, then those `dns` resolves to.
+    async fn resolve(&self) -> Vec<Url> {
+        let mut peers = self.peers.clone();
+        if let Some(dns) = &self.dns {
+            match tokio::net::lookup_host((dns.host.as_str(), dns.port)).await {
+                Ok(addrs) => peers
+                    .extend(addrs.filter_map(|addr| format!("http://{addr}/").parse::<Url>().ok())),
+                Err(e) => warn!(name = %dns, error = %e, "Failed to resolve peers"),
+            }
+        }
+        peers.sort();
+        peers.dedup();
+        peers
+    }
+
+    /// The status of `peer`, compared with `ours`.
+    async fn fetch(&self, peer: &Url, ours: &SourceMetadata) -> PeerStatus {
+        let result = async {
+            self.client
+                .get(peer.join("api/v1/meta")?)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<MetaResponse>()
+                .await
+                .map_err(Into::into)
+        };
+        let result: Result<MetaResponse, Box<dyn std::error::Error>> = result.await;
+        match result {
+            Ok(meta) => PeerStatus {
+                peer: peer.to_string(),
+                convergence: Convergence::of(ours, &meta),
+                generation: Some(meta.generation),
+                source_version: meta.source_version,
+                source_modified_at: meta.source_modified_at,
+                error: None,
+            },
+            Err(e) => PeerStatus {
+                peer: peer.to_string(),
+                convergence: Convergence::Unreachable,
+                generation: None,
+                source_version: None,
+                source_modified_at: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    /// Fetch every peer's version, compared with `ours`.
+    pub async fn round(&self, ours: &SourceMetadata) -> FleetStatus {
+        let peers = self.resolve().await;
+        let statuses = peers.iter().map(|peer| self.fetch(peer, ours));
+        FleetStatus {
+            checked_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|at| at.as_secs()),
+            source_version: ours.version(),
+            peers: futures_util::future::join_all(statuses).await,
+        }
+    }
+
+    /// Gossip in the background, publishing each round's status to `fleet`.
+    ///
+    /// With reloads enabled, each newer version a peer reports triggers a single
+    /// conditional reload of `store`: a peer that keeps serving a version this instance
+    /// cannot load does not cause a reload every round.
+    pub fn spawn(
+        self,
+        fleet: Fleet,
+        store: SwappableStore,
+        reload_state: Arc<ReloadState>,
+    ) -> JoinHandle<()> {
+        info!(
+            peers = self.peers.len(),
+            dns = ?self.dns.as_ref().map(ToString::to_string),
+            interval_secs = self.interval.as_secs(),
+            reload = self.reload,
+            "Gossiping data versions with peers"
+        );
+        tokio::spawn(async move {
+            let mut triggered: Option<String> = None;
+            loop {
+                let ours = reload_state
+                    .metadata
+                    .read()
+                    .expect("RwLock poisoned")
+                    .clone();
+                let status = self.round(&ours).await;
+                let newer = status
+                    .peers
+                    .iter()
+                    .filter(|peer| peer.convergence.is_newer())
+                    .find_map(|peer| Some((peer.peer.clone(), peer.source_version.clone()?)));
+                debug!(summary = ?status.summary(), "Gossip round complete");
+                // Published before reloading, for the reload to copy from an up-to-date leader
+                *fleet.0.write().expect("RwLock poisoned") = status;
+
+                if let Some((peer, version)) = newer
+                    && self.reload
+                    && triggered.as_ref() != Some(&version)
+                    && next_window(&self.windows, SystemTime::now()).is_none()
+                {
+                    info!(peer, version, "Peer serves newer data, reloading");
+                    match reload_state.reload(&store, true).await {
+                        Ok(true) => {}
+                        Ok(false) => debug!("Source unchanged after peer report"),
+                        Err(e) => {
+                            error!(error = %e, "Failed to reload store, keeping existing data");
+                        }
+                    }
+                    triggered = Some(version);
+                }
+
+                tokio::time::sleep(self.interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::tests::{ok, serve};
+
+    fn meta(version: Option<&str>, modified_at: Option<u64>) -> MetaResponse {
+        MetaResponse {
+            generation: 1,
+            loaded_at: None,
+            source: None,
+            source_version: version.map(str::to_string),
+            source_modified_at: modified_at,
+            entry_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_convergence() {
+        let ours = SourceMetadata {
+            etag: Some("a".to_string()),
+            last_modified: Some("Thu, 01 Jan 1970 00:01:40 GMT".to_string()),
+            ..SourceMetadata::new()
+        };
+        let of = |version, modified_at| Convergence::of(&ours, &meta(version, modified_at));
+
+        assert_eq!(of(Some("a"), Some(100)), Convergence::InSync);
+        assert_eq!(of(Some("b"), Some(200)), Convergence::Ahead);
+        assert_eq!(of(Some("b"), Some(50)), Convergence::Behind);
+        assert_eq!(of(Some("b"), None), Convergence::Diverged);
+        assert_eq!(of(None, None), Convergence::Behind);
+        assert_eq!(
+            Convergence::of(&SourceMetadata::new(), &meta(Some("a"), None)),
+            Convergence::Ahead
+        );
+        assert!(Convergence::Diverged.is_newer());
+        assert!(!Convergence::Unreachable.is_newer());
+
+        assert_eq!(
+            "peers.local:8080".parse(),
+            Ok(PeerDns {
+                host: "peers.local".to_string(),
+                port: 8080
+            })
+        );
+        assert!("peers.local".parse::<PeerDns>().is_err());
+        assert!(":8080".parse::<PeerDns>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_round() {
+        let body = serde_json::to_string(&meta(Some("b"), Some(200))).unwrap();
+        let (url, heads) = serve(vec![ok(&body)]);
+        let peer: Url = url.parse().unwrap();
+        let root = peer.join("/").unwrap();
+        let down: Url = "http://127.0.0.1:1/".parse().unwrap();
+        let gossip = Gossip::new(vec![root.clone(), down], None, Duration::from_secs(1));
+
+        let ours = SourceMetadata {
+            etag: Some("a".to_string()),
+            ..SourceMetadata::new()
+        };
+        let status = gossip.round(&ours).await;
+        assert!(heads.recv().unwrap().starts_with("GET /api/v1/meta"));
+        assert_eq!(status.source_version.as_deref(), Some("a"));
+        assert_eq!(status.peers.len(), 2);
+        let peer = |url: &Url| {
+            status
+                .peers
+                .iter()
+                .find(|peer| peer.peer == url.as_str())
+                .unwrap()
+        };
+        let (reachable, down) = (peer(&root), peer(&"http://127.0.0.1:1/".parse().unwrap()));
+        assert_eq!(reachable.convergence, Convergence::Diverged);
+        assert_eq!(reachable.source_version.as_deref(), Some("b"));
+        assert_eq!(down.convergence, Convergence::Unreachable);
+        assert!(down.error.is_some());
+
+        let summary = status.summary();
+        assert_eq!(
+            summary,
+            FleetSummary {
+                peers: 2,
+                in_sync: 0,
+                unreachable: 1,
+                converged: false,
+            }
+        );
+        assert!(!FleetStatus::default().summary().converged);
+    }
+}
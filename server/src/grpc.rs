@@ -0,0 +1,364 @@
+//! gRPC API, served alongside the HTTP API for callers that only speak gRPC.
+//!
+//! The interface is published in `proto/occlusion.proto`. The messages below mirror it and the
+//! service stubs are generated from them by the build script, so no `protoc` is needed.
+
+use crate::{
+    audit::AuditLog,
+    ids::{IdNamespace, ObjectId},
+    jwt::JwtVerifier,
+    models::Decision,
+};
+use occlusion::{Store, SwappableStore};
+use proto::occlusion_server::{Occlusion, OcclusionServer};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+/// Messages and service stubs of the `occlusion.v1` package.
+#[allow(clippy::pedantic)]
+pub mod proto {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CheckRequest {
+        #[prost(string, tag = "1")]
+        pub object: String,
+        #[prost(uint32, tag = "2")]
+        pub visibility_mask: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CheckResponse {
+        #[prost(string, tag = "1")]
+        pub object: String,
+        #[prost(bool, tag = "2")]
+        pub visible: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CheckBatchRequest {
+        #[prost(string, repeated, tag = "1")]
+        pub objects: Vec<String>,
+        #[prost(uint32, tag = "2")]
+        pub visibility_mask: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CheckBatchResponse {
+        #[prost(bool, tag = "1")]
+        pub all_visible: bool,
+        #[prost(bool, repeated, tag = "2")]
+        pub visible: Vec<bool>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetLevelRequest {
+        #[prost(string, tag = "1")]
+        pub object: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetLevelResponse {
+        #[prost(string, tag = "1")]
+        pub object: String,
+        #[prost(uint32, optional, tag = "2")]
+        pub level: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StatsRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StatsResponse {
+        #[prost(uint64, tag = "1")]
+        pub total_uuids: u64,
+        #[prost(map = "uint32, uint64", tag = "2")]
+        pub visibility_distribution: HashMap<u32, u64>,
+        #[prost(string, tag = "3")]
+        pub store_algorithm: String,
+        #[prost(uint64, tag = "4")]
+        pub generation: u64,
+        #[prost(uint64, tag = "5")]
+        pub memory_bytes: u64,
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/occlusion.v1.Occlusion.rs"));
+}
+
+/// The `occlusion.v1.Occlusion` service, answering from the default store.
+pub struct OcclusionService {
+    store: SwappableStore,
+    ids: Option<IdNamespace>,
+    /// Largest `CheckBatch` request (0 = unlimited)
+    max_batch_size: usize,
+    /// Verifier of the tokens that visibility masks are taken from, if enabled
+    jwt: Option<Arc<JwtVerifier>>,
+    audit: Option<AuditLog>,
+}
+
+/// Who made a call and the mask it is checked against.
+struct Caller {
+    mask: u8,
+    /// Subject of the caller's JWT
+    subject: Option<String>,
+    remote: Option<IpAddr>,
+    /// The call's `x-request-id` metadata
+    request_id: Option<String>,
+}
+
+impl OcclusionService {
+    pub fn new(store: SwappableStore, ids: Option<IdNamespace>, max_batch_size: usize) -> Self {
+        Self {
+            store,
+            ids,
+            max_batch_size,
+            jwt: None,
+            audit: None,
+        }
+    }
+
+    /// Take visibility masks from the JWT in each call's `authorization` metadata instead
+    /// of from the request messages.
+    #[must_use]
+    pub fn with_jwt(mut self, verifier: Arc<JwtVerifier>) -> Self {
+        self.jwt = Some(verifier);
+        self
+    }
+
+    /// Record every decision in the audit log.
+    #[must_use]
+    pub fn with_audit(mut self, log: AuditLog) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// The caller of a call, checked against its token's mask when JWT masks are enabled,
+    /// else against the requested one.
+    async fn caller<T>(&self, request: &Request<T>, requested: u32) -> Result<Caller, Status> {
+        let remote = request.remote_addr().map(|addr| addr.ip());
+        let request_id = request
+            .metadata()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let Some(verifier) = &self.jwt else {
+            return Ok(Caller {
+                mask: mask(requested)?,
+                subject: None,
+                remote,
+                request_id,
+            });
+        };
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+        let grant = verifier.verify(token).await.map_err(|e| {
+            if e.is_forbidden() {
+                Status::permission_denied(e.to_string())
+            } else {
+                Status::unauthenticated(e.to_string())
+            }
+        })?;
+        Ok(Caller {
+            mask: grant.mask,
+            subject: grant.subject,
+            remote,
+            request_id,
+        })
+    }
+
+    /// Check `objects` for `caller`, recording the decisions if auditing is enabled.
+    fn check_all(&self, method: &str, caller: &Caller, objects: &[Uuid]) -> Vec<bool> {
+        let (generation, levels) = self.store.get_visibilities(objects);
+        objects
+            .iter()
+            .zip(levels)
+            .map(|(&object, level)| {
+                let decision = Decision::new(level, caller.mask);
+                if let Some(audit) = &self.audit {
+                    audit.record(
+                        caller.request_id.as_deref(),
+                        method,
+                        caller.subject.as_deref(),
+                        caller.remote,
+                        object,
+                        caller.mask,
+                        decision,
+                        generation,
+                    );
+                }
+                decision == Decision::Visible
+            })
+            .collect()
+    }
+
+    fn resolve(&self, id: &str) -> Result<Uuid, Status> {
+        ObjectId::from(id)
+            .resolve(self.ids.as_ref())
+            .ok_or_else(|| {
+                Status::invalid_argument(format!("invalid object ID {id:?}: expected a UUID"))
+            })
+    }
+}
+
+fn mask(mask: u32) -> Result<u8, Status> {
+    u8::try_from(mask).map_err(|_| {
+        Status::invalid_argument(format!("invalid visibility mask {mask}: expected 0-255"))
+    })
+}
+
+#[tonic::async_trait]
+impl Occlusion for OcclusionService {
+    async fn check(
+        &self,
+        request: Request<proto::CheckRequest>,
+    ) -> Result<Response<proto::CheckResponse>, Status> {
+        let caller = self
+            .caller(&request, request.get_ref().visibility_mask)
+            .await?;
+        let object = self.resolve(&request.into_inner().object)?;
+        let visible = self.check_all("/occlusion.v1.Occlusion/Check", &caller, &[object])[0];
+        Ok(Response::new(proto::CheckResponse {
+            object: object.to_string(),
+            visible,
+        }))
+    }
+
+    async fn check_batch(
+        &self,
+        request: Request<proto::CheckBatchRequest>,
+    ) -> Result<Response<proto::CheckBatchResponse>, Status> {
+        let caller = self
+            .caller(&request, request.get_ref().visibility_mask)
+            .await?;
+        let request = request.into_inner();
+        let len = request.objects.len();
+        if self.max_batch_size != 0 && len > self.max_batch_size {
+            return Err(Status::resource_exhausted(format!(
+                "batch of {len} objects exceeds the limit of {}",
+                self.max_batch_size
+            )));
+        }
+        let objects = request
+            .objects
+            .iter()
+            .map(|id| self.resolve(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let visible = self.check_all("/occlusion.v1.Occlusion/CheckBatch", &caller, &objects);
+        Ok(Response::new(proto::CheckBatchResponse {
+            all_visible: visible.iter().all(|&visible| visible),
+            visible,
+        }))
+    }
+
+    async fn get_level(
+        &self,
+        request: Request<proto::GetLevelRequest>,
+    ) -> Result<Response<proto::GetLevelResponse>, Status> {
+        let object = self.resolve(&request.into_inner().object)?;
+        Ok(Response::new(proto::GetLevelResponse {
+            object: object.to_string(),
+            level: self.store.get_visibility(&object).map(u32::from),
+        }))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<proto::StatsRequest>,
+    ) -> Result<Response<proto::StatsResponse>, Status> {
+        Ok(Response::new(proto::StatsResponse {
+            total_uuids: self.store.len() as u64,
+            visibility_distribution: self
+                .store
+                .visibility_distribution()
+                .into_iter()
+                .map(|(level, count)| (u32::from(level), count as u64))
+                .collect(),
+            store_algorithm: occlusion::ACTIVE_STORE_NAME.to_string(),
+            generation: self.store.generation(),
+            memory_bytes: self.store.memory_usage() as u64,
+        }))
+    }
+}
+
+/// Serve the gRPC API on `addr` until the process exits.
+pub async fn serve(
+    addr: SocketAddr,
+    service: OcclusionService,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(OcclusionServer::new(service))
+        .serve(addr)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> OcclusionService {
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 10)];
+        let store = SwappableStore::new(occlusion::build_store(entries).unwrap());
+        OcclusionService::new(store, Some(IdNamespace(Uuid::NAMESPACE_OID)), 2)
+    }
+
+    #[test]
+    fn test_rpcs() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let service = service();
+        let id = |n: u128| Uuid::from_u128(n).to_string();
+
+        rt.block_on(async {
+            let check = |object: String, visibility_mask| {
+                service.check(Request::new(proto::CheckRequest {
+                    object,
+                    visibility_mask,
+                }))
+            };
+            assert!(check(id(2), 10).await.unwrap().into_inner().visible);
+            assert!(!check(id(2), 5).await.unwrap().into_inner().visible);
+            assert!(
+                !check("order-1".to_string(), 255)
+                    .await
+                    .unwrap()
+                    .into_inner()
+                    .visible
+            );
+            let e = check(id(2), 256).await.unwrap_err();
+            assert_eq!(e.code(), tonic::Code::InvalidArgument);
+
+            let batch = |objects: Vec<String>| {
+                service.check_batch(Request::new(proto::CheckBatchRequest {
+                    objects,
+                    visibility_mask: 5,
+                }))
+            };
+            let response = batch(vec![id(1), id(2)]).await.unwrap().into_inner();
+            assert!(!response.all_visible);
+            assert_eq!(response.visible, [true, false]);
+            let e = batch(vec![id(1), id(1), id(1)]).await.unwrap_err();
+            assert_eq!(e.code(), tonic::Code::ResourceExhausted);
+
+            let level =
+                |object: String| service.get_level(Request::new(proto::GetLevelRequest { object }));
+            assert_eq!(level(id(2)).await.unwrap().into_inner().level, Some(10));
+            assert_eq!(level(id(3)).await.unwrap().into_inner().level, None);
+
+            let stats = service
+                .stats(Request::new(proto::StatsRequest {}))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(stats.total_uuids, 2);
+            assert_eq!(stats.visibility_distribution[&10], 1);
+            assert_eq!(stats.store_algorithm, occlusion::ACTIVE_STORE_NAME);
+        });
+    }
+}
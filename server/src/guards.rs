@@ -0,0 +1,285 @@
+//! Request guards shared by the routes.
+
+use crate::{
+    access_log::AccessNote,
+    admission::{self, RouteClass},
+    catchers::{ApiError, set_error_message},
+    client_cert::ExcessMask,
+    error::JwtError,
+    jwt::JwtVerifier,
+    timeout::Deadline,
+};
+use ipnet::IpNet;
+use rocket::{
+    Data, Request,
+    data::{self, FromData},
+    http::Status,
+    request::{FromRequest, Outcome},
+    serde::json::{self, Json},
+};
+use serde::de::DeserializeOwned;
+use std::{io, net::IpAddr, ops::Deref, sync::Arc};
+
+/// Managed state that may be absent.
+///
+/// Unlike `Option<&State<T>>`, which Rocket's sentinels reject at launch when `T` is not
+/// managed, this guard lets optional subsystems be left out of the rocket entirely.
+pub struct MaybeState<'r, T: Send + Sync + 'static>(pub Option<&'r T>);
+
+#[rocket::async_trait]
+impl<'r, T: Send + Sync + 'static> FromRequest<'r> for MaybeState<'r, T> {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(MaybeState(request.rocket().state::<T>()))
+    }
+}
+
+/// Bearer token required by the admin endpoints.
+///
+/// When not managed, the admin endpoints are disabled.
+pub struct AdminToken(pub String);
+
+/// Networks the admin endpoints accept connections from; any when not managed.
+///
+/// Matched against the address of the connection's peer, not `X-Real-IP`, which any client
+/// can set: behind a reverse proxy, the proxy's address is the one that must be allowed.
+pub struct AdminAllowlist(pub Vec<IpNet>);
+
+impl AdminAllowlist {
+    /// Whether connections from `ip` may use the admin endpoints.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|network| network.contains(&ip))
+    }
+}
+
+/// Parse a network in CIDR notation (`10.0.0.0/8`), or a single address.
+pub fn parse_network(s: &str) -> Result<IpNet, String> {
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("invalid network {s:?}: expected CIDR notation or an IP address"))
+}
+
+/// Request guard for the admin endpoints: requires `Authorization: Bearer <token>`.
+///
+/// Fails with 401 on a missing or wrong token, with 403 if no admin token is configured or the
+/// connection is not from an [allowed network](AdminAllowlist), and with 503 when the admin
+/// [concurrency limit](crate::admission) is reached.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(AdminToken(token)) = request.rocket().state::<AdminToken>() else {
+            set_error_message(request, "admin endpoints are disabled");
+            return Outcome::Error((Status::Forbidden, "admin endpoints are disabled"));
+        };
+        if let Some(allowlist) = request.rocket().state::<AdminAllowlist>() {
+            let peer = request.remote().map(|remote| remote.ip());
+            if !peer.is_some_and(|ip| allowlist.allows(ip)) {
+                let from =
+                    peer.map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string());
+                set_error_message(
+                    request,
+                    format!("admin endpoints are not available from {from}"),
+                );
+                return Outcome::Error((Status::Forbidden, "admin client address not allowed"));
+            }
+        }
+        let presented = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+                AccessNote::of(request).set_caller("admin");
+                match admission::admit(request, RouteClass::Admin) {
+                    Ok(()) => Outcome::Success(Admin),
+                    Err(status) => Outcome::Error((status, "request shed")),
+                }
+            }
+            _ => {
+                set_error_message(request, "missing or invalid admin token");
+                Outcome::Error((Status::Unauthorized, "missing or invalid admin token"))
+            }
+        }
+    }
+}
+
+/// The visibility masks a caller may check with.
+///
+/// With JWT masks enabled (an [`Arc<JwtVerifier>`] managed), requires
+/// `Authorization: Bearer <jwt>` and replaces requested masks with the token's. Fails with 401
+/// on a missing or invalid token and with 403 on a token without a valid mask claim.
+///
+/// With client certificates mapped to masks ([`ClientCertMasks`](crate::client_cert::ClientCertMasks) managed), requires a client
+/// certificate, and clamps or rejects masks above its entitlement. Fails with 401 without a
+/// certificate and with 403 when the certificate is not mapped.
+///
+/// Otherwise, requests keep their own masks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Entitlement {
+    /// Mask from the caller's token
+    token: Option<u8>,
+    /// Highest mask the caller's certificate allows
+    limit: Option<(u8, ExcessMask)>,
+}
+
+impl Entitlement {
+    /// The mask to check a request for `requested` against.
+    pub fn mask(&self, requested: u8) -> Result<u8, ApiError> {
+        let mask = self.token.unwrap_or(requested);
+        match self.limit {
+            Some((limit, ExcessMask::Reject)) if mask > limit => Err(ApiError::new(
+                Status::Forbidden,
+                format!(
+                    "visibility mask {mask} exceeds the client certificate's maximum of {limit}"
+                ),
+            )),
+            Some((limit, _)) => Ok(mask.min(limit)),
+            None => Ok(mask),
+        }
+    }
+
+    /// Whether the caller's credentials decide the masks, making responses caller-specific.
+    pub fn is_personal(&self) -> bool {
+        self.token.is_some() || self.limit.is_some()
+    }
+
+    /// The mask to check a batch's objects without their own mask against.
+    pub fn shared_mask(&self, requested: Option<u8>) -> Result<u8, ApiError> {
+        match (self.token, requested) {
+            (Some(token), _) => self.mask(token),
+            (None, Some(requested)) => self.mask(requested),
+            (None, None) => Err(ApiError::unprocessable(
+                "visibility_mask is required for objects without their own",
+            )),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Entitlement {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let reject = |status, message: String| {
+            set_error_message(request, message);
+            Outcome::Error((status, "visibility mask entitlement rejected"))
+        };
+
+        let mut entitlement = Entitlement::default();
+        if let Some(verifier) = request.rocket().state::<Arc<JwtVerifier>>() {
+            let token = request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "));
+            let result = match token {
+                Some(token) => verifier.verify(token).await,
+                None => Err(JwtError::Missing),
+            };
+            match result {
+                Ok(grant) => {
+                    if let Some(subject) = grant.subject {
+                        AccessNote::of(request).set_caller(subject);
+                    }
+                    entitlement.token = Some(grant.mask);
+                }
+                Err(e) if e.is_forbidden() => return reject(Status::Forbidden, e.to_string()),
+                Err(e) => return reject(Status::Unauthorized, e.to_string()),
+            }
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(crate::client_cert::ClientCertMasks { masks, excess }) =
+            request
+                .rocket()
+                .state::<crate::client_cert::ClientCertMasks>()
+        {
+            let Outcome::Success(cert) = request.guard::<rocket::mtls::Certificate<'_>>().await
+            else {
+                return reject(
+                    Status::Unauthorized,
+                    "a client certificate is required".to_string(),
+                );
+            };
+            let identities = crate::client_cert::identities(&cert);
+            if let Some(identity) = identities.first() {
+                AccessNote::of(request).set_caller(identity.as_str());
+            }
+            let Some(limit) = masks.max_mask(identities.iter().map(String::as_str)) else {
+                let message = format!(
+                    "client certificate ({}) is not entitled to any visibility mask",
+                    identities.join(", ")
+                );
+                return reject(Status::Forbidden, message);
+            };
+            entitlement.limit = Some((limit, *excess));
+        }
+
+        Outcome::Success(entitlement)
+    }
+}
+
+/// JSON request body.
+///
+/// Behaves like [`Json`], but a body that cannot be read or parsed is described in the
+/// error response (e.g. which field holds an out-of-range mask) instead of a bare status.
+/// Fails with 408 when the body is not received by the request's [`Deadline`].
+pub struct JsonBody<T>(pub T);
+
+impl<T> JsonBody<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for JsonBody<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for JsonBody<T> {
+    type Error = json::Error<'r>;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let parsed = Deadline::of(request)
+            .within(Json::<T>::from_data(request, data))
+            .await;
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                set_error_message(request, e.message);
+                let timed_out = io::Error::from(io::ErrorKind::TimedOut);
+                return data::Outcome::Error((e.status, json::Error::Io(timed_out)));
+            }
+        };
+        match parsed {
+            data::Outcome::Success(Json(value)) => data::Outcome::Success(JsonBody(value)),
+            data::Outcome::Error((status, e)) => {
+                let message = match &e {
+                    json::Error::Parse(_, e) => format!("invalid request body: {e}"),
+                    json::Error::Io(_) if status == Status::PayloadTooLarge => {
+                        "request body larger than the JSON limit (--json-limit)".to_string()
+                    }
+                    json::Error::Io(e) => format!("failed to read request body: {e}"),
+                };
+                set_error_message(request, message);
+                data::Outcome::Error((status, e))
+            }
+            data::Outcome::Forward(forward) => data::Outcome::Forward(forward),
+        }
+    }
+}
+
+/// Compare secrets without leaking the position of the first difference through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,102 @@
+//! Non-UUID object identifiers, mapped to UUIDs by name-based (version 5) hashing.
+//!
+//! With an ID namespace configured, any identifier that does not parse as a UUID is replaced
+//! by `UUIDv5(namespace, id)` both when loading data and when answering queries, so clients
+//! can keep using their own IDs (`"order-1234"`, `42`) end to end.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+/// Namespace UUID that non-UUID identifiers are hashed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdNamespace(pub Uuid);
+
+impl IdNamespace {
+    /// The version 5 UUID of `id` in this namespace.
+    pub fn hash(&self, id: &str) -> Uuid {
+        Uuid::new_v5(&self.0, id.as_bytes())
+    }
+
+    /// Parse `id` as a UUID, or hash it if it is not one.
+    pub fn resolve(&self, id: &str) -> Uuid {
+        Uuid::try_parse(id.trim()).unwrap_or_else(|_| self.hash(id.trim()))
+    }
+}
+
+/// An object identifier in a request or JSON record: a string or a non-negative integer.
+///
+/// Integers are hashed through their decimal form, so `42` and `"42"` are the same object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ObjectId {
+    Text(String),
+    Number(u64),
+}
+
+impl ObjectId {
+    /// The identifier as text.
+    pub fn as_text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(s) => Cow::Borrowed(s),
+            Self::Number(n) => Cow::Owned(n.to_string()),
+        }
+    }
+
+    /// The UUID this identifier refers to.
+    ///
+    /// Without a namespace only UUIDs are accepted.
+    pub fn resolve(&self, namespace: Option<&IdNamespace>) -> Option<Uuid> {
+        match namespace {
+            Some(namespace) => Some(namespace.resolve(&self.as_text())),
+            None => match self {
+                Self::Text(s) => Uuid::try_parse(s.trim()).ok(),
+                Self::Number(_) => None,
+            },
+        }
+    }
+}
+
+impl From<Uuid> for ObjectId {
+    fn from(uuid: Uuid) -> Self {
+        Self::Text(uuid.to_string())
+    }
+}
+
+impl From<&str> for ObjectId {
+    fn from(id: &str) -> Self {
+        Self::Text(id.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let namespace = IdNamespace(Uuid::NAMESPACE_OID);
+        let uuid = Uuid::from_u128(7);
+
+        assert_eq!(ObjectId::from(uuid).resolve(None), Some(uuid));
+        assert_eq!(ObjectId::from(uuid).resolve(Some(&namespace)), Some(uuid));
+        assert_eq!(ObjectId::from("order-1").resolve(None), None);
+        assert_eq!(ObjectId::Number(42).resolve(None), None);
+
+        let hashed = namespace.hash("order-1");
+        assert_eq!(hashed.get_version_num(), 5);
+        assert_eq!(
+            ObjectId::from("order-1").resolve(Some(&namespace)),
+            Some(hashed)
+        );
+        assert_eq!(
+            ObjectId::Number(42).resolve(Some(&namespace)),
+            ObjectId::from("42").resolve(Some(&namespace))
+        );
+        assert_ne!(
+            IdNamespace(Uuid::NAMESPACE_URL).hash("order-1"),
+            hashed,
+            "hashes depend on the namespace"
+        );
+    }
+}
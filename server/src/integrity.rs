@@ -0,0 +1,131 @@
+//! Integrity checks on raw source content.
+
+use crate::{
+    error::{LoadError, Result},
+    source::DataSource,
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// Detached ed25519 signature verification settings.
+#[derive(Debug, Clone)]
+pub struct SignatureCheck {
+    /// Key the data must be signed with
+    pub public_key: VerifyingKey,
+    /// Sidecar holding the detached signature (e.g. `data.csv.sig`)
+    pub sidecar: DataSource,
+}
+
+/// Verify `content` against a `.sha256` sidecar.
+///
+/// The sidecar holds a hex-encoded SHA-256 digest, optionally followed by a
+/// file name as written by `sha256sum`.
+pub fn verify_sha256(content: &[u8], sidecar: &[u8]) -> Result<()> {
+    let expected = std::str::from_utf8(sidecar)
+        .ok()
+        .and_then(|s| s.split_whitespace().next())
+        .filter(|digest| digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| LoadError::IntegrityError("malformed SHA-256 sidecar".to_string()))?;
+
+    let actual = format!("{:x}", Sha256::digest(content));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(LoadError::IntegrityError(format!(
+            "SHA-256 mismatch: expected {}, got {actual}",
+            expected.to_ascii_lowercase()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Decode a hex or base64 encoded value of exactly `N` bytes.
+fn decode_fixed<const N: usize>(encoded: &str) -> Option<[u8; N]> {
+    let encoded = encoded.trim();
+    hex::decode(encoded)
+        .ok()
+        .or_else(|| BASE64.decode(encoded).ok())
+        .and_then(|bytes| bytes.try_into().ok())
+}
+
+/// Parse an ed25519 public key given as 32 hex or base64 encoded bytes.
+pub fn parse_public_key(encoded: &str) -> Result<VerifyingKey> {
+    let bytes = decode_fixed::<32>(encoded).ok_or_else(|| {
+        LoadError::IntegrityError("public key must be 32 hex or base64 encoded bytes".to_string())
+    })?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|e| LoadError::IntegrityError(format!("invalid public key: {e}")))
+}
+
+/// Verify `content` against a detached ed25519 signature.
+///
+/// The signature may be raw (64 bytes), hex or base64 encoded.
+pub fn verify_signature(content: &[u8], signature: &[u8], key: &VerifyingKey) -> Result<()> {
+    let bytes = std::str::from_utf8(signature)
+        .ok()
+        .and_then(decode_fixed::<64>)
+        .or_else(|| signature.try_into().ok())
+        .ok_or_else(|| LoadError::IntegrityError("malformed signature sidecar".to_string()))?;
+
+    key.verify_strict(content, &Signature::from_bytes(&bytes))
+        .map_err(|_| LoadError::IntegrityError("signature verification failed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const CONTENT: &[u8] = b"uuid,visibility_level\n";
+
+    #[test]
+    fn test_verify_sha256() {
+        let digest = format!("{:x}", Sha256::digest(CONTENT));
+
+        verify_sha256(CONTENT, digest.as_bytes()).unwrap();
+        verify_sha256(
+            CONTENT,
+            format!("{}  data.csv\n", digest.to_uppercase()).as_bytes(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_verify_sha256_mismatch() {
+        let err = verify_sha256(CONTENT, &[b'0'; 64]).unwrap_err();
+        assert!(err.to_string().contains("mismatch"), "{err}");
+    }
+
+    #[test]
+    fn test_verify_sha256_malformed_sidecar() {
+        assert!(verify_sha256(CONTENT, b"").is_err());
+        assert!(verify_sha256(CONTENT, b"not-a-digest  data.csv").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let public_key =
+            parse_public_key(&hex::encode(signing_key.verifying_key().as_bytes())).unwrap();
+        let signature = signing_key.sign(CONTENT).to_bytes();
+
+        verify_signature(CONTENT, &signature, &public_key).unwrap();
+        verify_signature(CONTENT, hex::encode(signature).as_bytes(), &public_key).unwrap();
+        verify_signature(CONTENT, BASE64.encode(signature).as_bytes(), &public_key).unwrap();
+
+        let err = verify_signature(b"tampered", &signature, &public_key).unwrap_err();
+        assert!(err.to_string().contains("signature"), "{err}");
+        assert!(verify_signature(CONTENT, b"short", &public_key).is_err());
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let key = SigningKey::from_bytes(&[7; 32]).verifying_key();
+
+        assert_eq!(
+            parse_public_key(&BASE64.encode(key.as_bytes())).unwrap(),
+            key
+        );
+        assert!(parse_public_key("abcd").is_err());
+    }
+}
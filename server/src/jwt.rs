@@ -0,0 +1,288 @@
+//! Visibility masks taken from verified JWTs instead of requests.
+//!
+//! When enabled, a request's `visibility_mask` is ignored and the mask comes from a claim of
+//! the caller's bearer token, verified against the keys of a JWKS endpoint. Tokens signed with
+//! a key missing from the cached JWKS trigger a refresh, so keys can be rotated at the issuer.
+
+use crate::error::JwtError;
+use jsonwebtoken::{DecodingKey, Validation, jwk::JwkSet};
+use serde_json::{Map, Value};
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// Shortest time between two JWKS fetches triggered by unknown keys.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How tokens are verified and where the mask is read from.
+#[derive(Debug, Clone)]
+pub struct JwtOptions {
+    /// URL of the issuer's JWKS
+    pub jwks_url: String,
+    /// Claim holding the visibility mask
+    pub claim: String,
+    /// Required `iss`, if any
+    pub issuer: Option<String>,
+    /// Required `aud`, if any
+    pub audience: Option<String>,
+}
+
+/// What a verified token grants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub mask: u8,
+    /// The token's `sub` claim
+    pub subject: Option<String>,
+}
+
+/// Verifies bearer tokens and extracts their visibility mask.
+pub struct JwtVerifier {
+    options: JwtOptions,
+    client: reqwest::Client,
+    keys: RwLock<JwkSet>,
+    /// When the JWKS was last fetched, to rate-limit refreshes
+    refreshed: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl JwtVerifier {
+    /// Create a verifier, fetching the JWKS.
+    pub async fn new(options: JwtOptions) -> Result<Self, JwtError> {
+        let verifier = Self::with_keys(options, JwkSet { keys: Vec::new() });
+        verifier.refresh(true).await?;
+        Ok(verifier)
+    }
+
+    /// Create a verifier with a known JWKS, refreshed from `options.jwks_url` only when
+    /// a token uses a key missing from it.
+    pub fn with_keys(options: JwtOptions, keys: JwkSet) -> Self {
+        Self {
+            options,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Failed to build HTTP client"),
+            keys: RwLock::new(keys),
+            refreshed: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Verify `token` and return the visibility mask it grants.
+    pub async fn mask(&self, token: &str) -> Result<u8, JwtError> {
+        self.verify(token).await.map(|grant| grant.mask)
+    }
+
+    /// Verify `token` and return what it grants.
+    pub async fn verify(&self, token: &str) -> Result<Grant, JwtError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header.kid.unwrap_or_default();
+        let key = if let Some(key) = self.key(&kid)? {
+            key
+        } else {
+            self.refresh(false).await?;
+            self.key(&kid)?.ok_or(JwtError::UnknownKey(kid))?
+        };
+
+        let mut validation = Validation::new(header.alg);
+        if let Some(issuer) = &self.options.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.options.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)?.claims;
+        let mask = claims
+            .get(&self.options.claim)
+            .and_then(Value::as_u64)
+            .and_then(|mask| u8::try_from(mask).ok())
+            .ok_or_else(|| JwtError::Claim(self.options.claim.clone()))?;
+        Ok(Grant {
+            mask,
+            subject: claims
+                .get("sub")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// The key with ID `kid` (or the only key, for tokens without one).
+    fn key(&self, kid: &str) -> Result<Option<DecodingKey>, JwtError> {
+        let keys = self.keys.read().expect("RwLock poisoned");
+        let jwk = if kid.is_empty() && keys.keys.len() == 1 {
+            keys.keys.first()
+        } else {
+            keys.find(kid)
+        };
+        Ok(jwk.map(DecodingKey::from_jwk).transpose()?)
+    }
+
+    /// Fetch the JWKS, unless it was fetched less than [`MIN_REFRESH_INTERVAL`] ago and
+    /// this is not the first fetch.
+    async fn refresh(&self, force: bool) -> Result<(), JwtError> {
+        let mut refreshed = self.refreshed.lock().await;
+        if !force && refreshed.is_some_and(|at| at.elapsed() < MIN_REFRESH_INTERVAL) {
+            return Ok(());
+        }
+        *refreshed = Some(Instant::now());
+
+        let keys: JwkSet = async {
+            self.client
+                .get(&self.options.jwks_url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+        }
+        .await
+        .map_err(|e| {
+            warn!(url = %self.options.jwks_url, error = %e, "Failed to fetch JWKS");
+            JwtError::Jwks(e.to_string())
+        })?;
+        info!(url = %self.options.jwks_url, keys = keys.keys.len(), "Fetched JWKS");
+        *self.keys.write().expect("RwLock poisoned") = keys;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    const SECRET: &[u8] = b"test secret, long enough for HS256";
+
+    fn test_verifier(claim: &str) -> JwtVerifier {
+        let k = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(SECRET);
+        let keys: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [{"kty": "oct", "kid": "key-1", "alg": "HS256", "k": k}]
+        }))
+        .unwrap();
+        let options = JwtOptions {
+            // Never fetched: refreshes fail fast
+            jwks_url: "http://127.0.0.1:1/jwks.json".to_string(),
+            claim: claim.to_string(),
+            issuer: Some("https://issuer.example.com".to_string()),
+            audience: None,
+        };
+        JwtVerifier::with_keys(options, keys)
+    }
+
+    fn token(kid: &str, claims: &Value) -> String {
+        let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let mut claims = claims.clone();
+        claims["exp"] = exp.into();
+        claims["iss"] = "https://issuer.example.com".into();
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[test]
+    fn test_mask() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let verifier = test_verifier("clearance");
+
+        rt.block_on(async {
+            let mask = verifier
+                .mask(&token("key-1", &serde_json::json!({"clearance": 10})))
+                .await;
+            assert_eq!(mask.unwrap(), 10);
+
+            let e = verifier
+                .mask(&token("key-1", &serde_json::json!({"clearance": 300})))
+                .await
+                .unwrap_err();
+            assert!(e.is_forbidden());
+            let e = verifier
+                .mask(&token("key-1", &serde_json::json!({})))
+                .await
+                .unwrap_err();
+            assert!(e.is_forbidden());
+
+            let e = verifier
+                .mask(&token("key-2", &serde_json::json!({"clearance": 10})))
+                .await
+                .unwrap_err();
+            assert!(matches!(e, JwtError::Jwks(_)), "{e}");
+
+            let mut tampered = token("key-1", &serde_json::json!({"clearance": 1}));
+            tampered.pop();
+            let e = verifier.mask(&tampered).await.unwrap_err();
+            assert!(matches!(e, JwtError::Invalid(_)), "{e}");
+        });
+    }
+
+    #[test]
+    fn test_routes_use_token_mask() {
+        use rocket::http::{ContentType, Header, Status};
+        use rocket::local::blocking::Client;
+        use std::sync::Arc;
+        use uuid::Uuid;
+
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 10)];
+        let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let rocket = rocket::build()
+            .manage(store)
+            .manage(Arc::new(test_verifier("visibility_mask")))
+            .register("/", crate::catchers::catchers())
+            .mount(
+                "/",
+                rocket::routes![crate::routes::check, crate::routes::check_batch],
+            );
+        let client = Client::tracked(rocket).unwrap();
+        let bearer = |mask: u64| {
+            let token = token("key-1", &serde_json::json!({ "visibility_mask": mask }));
+            Header::new("Authorization", format!("Bearer {token}"))
+        };
+        let body = format!(
+            r#"{{"object": "{}", "visibility_mask": 255}}"#,
+            Uuid::from_u128(2)
+        );
+        let check = |auth: Option<Header<'static>>| {
+            let mut request = client
+                .post("/api/v1/check")
+                .header(ContentType::JSON)
+                .body(&body);
+            if let Some(auth) = auth {
+                request = request.header(auth);
+            }
+            request.dispatch()
+        };
+
+        let response = check(Some(bearer(5)));
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["is_visible"], false, "the requested mask is ignored");
+        let body: serde_json::Value = check(Some(bearer(10))).into_json().unwrap();
+        assert_eq!(body["is_visible"], true);
+
+        assert_eq!(check(None).status(), Status::Unauthorized);
+        let response = check(Some(bearer(256)));
+        assert_eq!(response.status(), Status::Forbidden);
+        let error: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert!(error.error.message.contains("visibility_mask"));
+
+        let response = client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .header(bearer(5))
+            .body(format!(
+                r#"{{"objects": [{{"object": "{}", "visibility_mask": 255}}]}}"#,
+                Uuid::from_u128(2)
+            ))
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(
+            body["all_visible"], false,
+            "per-object masks are ignored too"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Kafka change-feed consumer applying incremental updates between full reloads.
+//!
+//! Each message is a JSON change event (see [`crate::delta`]). A message with a UUID key
+//! and no payload (a compaction tombstone) is also a delete.
+
+use crate::delta;
+use occlusion::SwappableStore;
+use rdkafka::{
+    ClientConfig, Message,
+    consumer::{Consumer, StreamConsumer},
+    error::KafkaError,
+};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Delay before retrying after a consumer error.
+const ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Kafka consumer configuration.
+#[derive(Debug, Clone)]
+pub struct KafkaOptions {
+    /// Comma-separated bootstrap servers
+    pub brokers: String,
+    /// Topic carrying change events
+    pub topic: String,
+    /// Consumer group ID
+    pub group_id: String,
+}
+
+/// Parse a change event into a UUID and its new level (`None` = delete).
+fn parse_event(
+    key: Option<&[u8]>,
+    payload: Option<&[u8]>,
+) -> std::result::Result<(Uuid, Option<u8>), String> {
+    let Some(payload) = payload else {
+        let key = key.ok_or("tombstone without key")?;
+        let uuid = Uuid::try_parse_ascii(key).map_err(|e| format!("tombstone key: {e}"))?;
+        return Ok((uuid, None));
+    };
+
+    delta::parse_change(payload)
+}
+
+/// Subscribe to the change-feed topic and apply events to `store` in the background.
+pub fn spawn_consumer(store: SwappableStore, options: &KafkaOptions) -> Result<(), KafkaError> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &options.brokers)
+        .set("group.id", &options.group_id)
+        .set("enable.auto.commit", "true")
+        .set("auto.offset.reset", "latest")
+        .create()?;
+    consumer.subscribe(&[&options.topic])?;
+
+    info!(
+        brokers = %options.brokers,
+        topic = %options.topic,
+        group_id = %options.group_id,
+        "Consuming change feed"
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let message = match consumer.recv().await {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!(error = %e, "Kafka consumer error");
+                    tokio::time::sleep(ERROR_BACKOFF).await;
+                    continue;
+                }
+            };
+
+            match parse_event(message.key(), message.payload()) {
+                Ok((uuid, Some(level))) => {
+                    debug!(%uuid, level, "Applying upsert");
+                    store.upsert(uuid, level);
+                }
+                Ok((uuid, None)) => {
+                    debug!(%uuid, "Applying delete");
+                    store.remove(uuid);
+                }
+                Err(e) => warn!(
+                    partition = message.partition(),
+                    offset = message.offset(),
+                    error = %e,
+                    "Skipping malformed change event"
+                ),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        let uuid = Uuid::from_u128(1);
+
+        let upsert = format!(r#"{{"uuid": "{uuid}", "visibility_level": 4}}"#);
+        assert_eq!(
+            parse_event(None, Some(upsert.as_bytes())),
+            Ok((uuid, Some(4)))
+        );
+
+        let delete = format!(r#"{{"uuid": "{uuid}", "op": "delete"}}"#);
+        assert_eq!(parse_event(None, Some(delete.as_bytes())), Ok((uuid, None)));
+
+        let tombstone = uuid.to_string();
+        assert_eq!(
+            parse_event(Some(tombstone.as_bytes()), None),
+            Ok((uuid, None))
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_event() {
+        let uuid = Uuid::from_u128(1);
+
+        assert!(parse_event(None, None).is_err());
+        assert!(parse_event(None, Some(b"not json")).is_err());
+        let missing_level = format!(r#"{{"uuid": "{uuid}"}}"#);
+        assert!(parse_event(None, Some(missing_level.as_bytes())).is_err());
+        let out_of_range = format!(r#"{{"uuid": "{uuid}", "visibility_level": 256}}"#);
+        assert!(parse_event(None, Some(out_of_range.as_bytes())).is_err());
+    }
+}
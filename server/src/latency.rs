@@ -0,0 +1,295 @@
+//! Rolling request latency percentiles per route.
+//!
+//! The [`RequestTimer`](crate::fairing::RequestTimer) records how long each routed request
+//! took into a histogram per route and minute, kept for the last 15 minutes. Buckets
+//! grow exponentially with eight per power of two, so reported percentiles are within about
+//! 6% of the measured latency. Recording takes no lock except when a route sees its first
+//! request or a minute starts.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Latencies below this many microseconds get a bucket each.
+const LINEAR: u64 = 16;
+
+/// Buckets per power of two above [`LINEAR`].
+const SUB_BUCKETS: u64 = 8;
+
+/// Buckets in a histogram, the last one holding every latency above 2^40µs.
+#[allow(clippy::cast_possible_truncation)]
+const BUCKETS: usize = (LINEAR + (40 - 4) * SUB_BUCKETS) as usize;
+
+/// Minutes kept: the longest window, plus the current minute.
+const SLOTS: usize = 15 + 1;
+
+/// Minute of a slot that was never written.
+const UNUSED: u64 = u64::MAX;
+
+/// Bucket of a latency of `micros`.
+fn bucket(micros: u64) -> usize {
+    let index = if micros < LINEAR {
+        micros
+    } else {
+        let power = u64::from(micros.ilog2());
+        let sub = (micros >> (power - 3)) & (SUB_BUCKETS - 1);
+        LINEAR + (power - 4) * SUB_BUCKETS + sub
+    };
+    usize::try_from(index).map_or(BUCKETS - 1, |index| index.min(BUCKETS - 1))
+}
+
+/// Latency reported for `bucket`, in microseconds: the middle of its range.
+fn value(bucket: usize) -> u64 {
+    let index = bucket as u64;
+    if index < LINEAR {
+        return index;
+    }
+    let power = (index - LINEAR) / SUB_BUCKETS + 4;
+    let sub = (index - LINEAR) % SUB_BUCKETS;
+    let width = 1 << (power - 3);
+    (SUB_BUCKETS + sub) * width + width / 2
+}
+
+/// Requests of one minute.
+struct Slot {
+    minute: AtomicU64,
+    counts: Box<[AtomicU64]>,
+}
+
+/// Histograms of one route.
+struct Route {
+    slots: [Slot; SLOTS],
+    /// Held while a slot is reset for a new minute
+    rotation: Mutex<()>,
+}
+
+impl Route {
+    fn new() -> Self {
+        Self {
+            slots: std::array::from_fn(|_| Slot {
+                minute: AtomicU64::new(UNUSED),
+                counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            }),
+            rotation: Mutex::new(()),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn record(&self, minute: u64, micros: u64) {
+        let slot = &self.slots[(minute % SLOTS as u64) as usize];
+        if slot.minute.load(Ordering::Acquire) != minute {
+            let _rotation = self.rotation.lock().expect("Mutex poisoned");
+            if slot.minute.load(Ordering::Acquire) != minute {
+                for count in &slot.counts {
+                    count.store(0, Ordering::Relaxed);
+                }
+                slot.minute.store(minute, Ordering::Release);
+            }
+        }
+        slot.counts[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Percentiles of the requests of the last `window` complete minutes before `minute`
+    /// and of `minute` itself.
+    fn percentiles(&self, minute: u64, window: u64) -> Percentiles {
+        let mut counts = vec![0; BUCKETS];
+        for slot in &self.slots {
+            let at = slot.minute.load(Ordering::Acquire);
+            if at != UNUSED && at <= minute && minute - at <= window {
+                for (total, count) in counts.iter_mut().zip(slot.counts.iter()) {
+                    *total += count.load(Ordering::Relaxed);
+                }
+            }
+        }
+        Percentiles::of(&counts)
+    }
+}
+
+/// Latency percentiles of a route over a window, in microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Percentiles {
+    /// Requests in the window
+    pub count: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+}
+
+impl Percentiles {
+    fn of(counts: &[u64]) -> Self {
+        let count: u64 = counts.iter().sum();
+        let quantile = |permille: u64| {
+            if count == 0 {
+                return 0;
+            }
+            let rank = (count * permille).div_ceil(1000).max(1);
+            let mut seen = 0;
+            counts
+                .iter()
+                .position(|&n| {
+                    seen += n;
+                    seen >= rank
+                })
+                .map_or(0, value)
+        };
+        Self {
+            count,
+            p50_us: quantile(500),
+            p90_us: quantile(900),
+            p99_us: quantile(990),
+            p999_us: quantile(999),
+        }
+    }
+}
+
+/// Latency percentiles of a route over the last 1, 5 and 15 minutes.
+///
+/// Each window also counts the current, incomplete minute.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteLatency {
+    #[serde(rename = "1m")]
+    pub last_1m: Percentiles,
+    #[serde(rename = "5m")]
+    pub last_5m: Percentiles,
+    #[serde(rename = "15m")]
+    pub last_15m: Percentiles,
+}
+
+/// Latencies of every route, managed by Rocket.
+#[derive(Clone)]
+pub struct Latencies {
+    started: Instant,
+    routes: Arc<RwLock<HashMap<String, Arc<Route>>>>,
+    /// Requests recorded since startup
+    recorded: Arc<AtomicU64>,
+}
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            routes: Arc::default(),
+            recorded: Arc::default(),
+        }
+    }
+}
+
+impl Latencies {
+    /// Record that a request to `route` (e.g. `POST /api/v1/check`) took `elapsed`.
+    pub fn record(&self, route: &str, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let minute = self.minute();
+        let known = self
+            .routes
+            .read()
+            .expect("RwLock poisoned")
+            .get(route)
+            .cloned();
+        let histograms = known.unwrap_or_else(|| {
+            let mut routes = self.routes.write().expect("RwLock poisoned");
+            routes
+                .entry(route.to_string())
+                .or_insert_with(|| Arc::new(Route::new()))
+                .clone()
+        });
+        histograms.record(minute, micros);
+        self.recorded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Requests recorded since startup.
+    pub fn recorded(&self) -> u64 {
+        self.recorded.load(Ordering::Relaxed)
+    }
+
+    /// Percentiles of each route with requests in the last 15 minutes, keyed by route.
+    pub fn report(&self) -> BTreeMap<String, RouteLatency> {
+        let minute = self.minute();
+        let routes = self.routes.read().expect("RwLock poisoned");
+        routes
+            .iter()
+            .map(|(name, route)| {
+                let latency = RouteLatency {
+                    last_1m: route.percentiles(minute, 1),
+                    last_5m: route.percentiles(minute, 5),
+                    last_15m: route.percentiles(minute, 15),
+                };
+                (name.clone(), latency)
+            })
+            .filter(|(_, latency)| latency.last_15m.count > 0)
+            .collect()
+    }
+
+    fn minute(&self) -> u64 {
+        self.started.elapsed().as_secs() / 60
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        for micros in [0, 15, 16, 17, 100, 1_000, 123_456, 10_000_000] {
+            let reported = value(bucket(micros));
+            assert!(
+                reported.abs_diff(micros) <= micros / 16,
+                "{micros}µs reported as {reported}µs"
+            );
+        }
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+        assert!((1..BUCKETS).all(|b| value(b) > value(b - 1)));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let route = Route::new();
+        for micros in 1..=1000 {
+            route.record(20, micros * 1000);
+        }
+        route.record(16, 5);
+        // Out of every window
+        route.record(3, 5);
+
+        let last = route.percentiles(20, 1);
+        assert_eq!(last.count, 1000);
+        for (reported, expected) in [
+            (last.p50_us, 500_000),
+            (last.p90_us, 900_000),
+            (last.p99_us, 990_000),
+            (last.p999_us, 999_000),
+        ] {
+            assert!(reported.abs_diff(expected) <= expected / 16, "{reported}");
+        }
+        assert_eq!(route.percentiles(20, 5).count, 1001);
+        assert_eq!(route.percentiles(20, 15).count, 1001);
+
+        // A new round of the ring resets the slot
+        route.record(20 + SLOTS as u64, 7);
+        assert_eq!(route.percentiles(20 + SLOTS as u64, 1).p999_us, 7);
+    }
+
+    #[test]
+    fn test_report() {
+        let latencies = Latencies::default();
+        assert!(latencies.report().is_empty());
+        latencies.record("GET /api/v1/check/<uuid>", Duration::from_micros(120));
+        latencies.record("GET /api/v1/check/<uuid>", Duration::from_micros(130));
+        let report = latencies.report();
+        let latency = report["GET /api/v1/check/<uuid>"];
+        assert_eq!(latency.last_1m.count, 2);
+        assert_eq!(latency.last_15m, latency.last_1m);
+        assert_eq!(latency.last_1m.p50_us, value(bucket(120)));
+        assert_eq!(latencies.recorded(), 2);
+
+        let json = serde_json::to_value(latency).unwrap();
+        assert_eq!(json["5m"]["p999_us"], value(bucket(130)));
+    }
+}
@@ -0,0 +1,236 @@
+//! Leader-based fetch: one instance of a fleet downloads the source, the others copy it.
+//!
+//! Among the instances that answered the last [gossip](crate::gossip) round with data, the
+//! one with the lowest URL is the leader. It loads the source as usual; every other instance
+//! reloads by downloading the leader's store as a snapshot from the admin export endpoint,
+//! so a fleet costs the origin one download per change instead of one per instance. An
+//! instance whose leader is unreachable or serves another source falls back to the origin.
+//!
+//! The election needs no coordination: instances agree on the leader as long as they see
+//! the same peers, and disagreeing only costs extra origin downloads.
+
+use crate::{
+    error::{LoadError, Result},
+    gossip::{Convergence, Fleet, FleetStatus, PeerStatus},
+    snapshot,
+    source::{DataSource, SourceMetadata},
+};
+use occlusion::ActiveStore;
+use reqwest::Url;
+use std::time::{Duration, SystemTime};
+
+/// Header of exports carrying the version of the source data (see [`SourceMetadata::version`]).
+pub const SOURCE_VERSION_HEADER: &str = "X-Occlusion-Source-Version";
+
+/// Header of exports carrying when the source data was last modified, in Unix seconds.
+pub const SOURCE_MODIFIED_HEADER: &str = "X-Occlusion-Source-Modified-At";
+
+/// Longest time connecting to the leader may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest time downloading the leader's store may take.
+const TIMEOUT: Duration = Duration::from_mins(5);
+
+/// The leader of the fleet as `own` sees it in `status`, or `None` when it is `own`.
+pub fn elect<'a>(own: &Url, status: &'a FleetStatus) -> Option<&'a PeerStatus> {
+    status
+        .peers
+        .iter()
+        .filter(|peer| peer.convergence != Convergence::Unreachable)
+        .filter(|peer| peer.source_version.is_some())
+        .min_by(|a, b| a.peer.cmp(&b.peer))
+        .filter(|leader| leader.peer.as_str() < own.as_str())
+}
+
+/// Reloads from the leader of the fleet, for the instances that are not the leader.
+#[derive(Debug)]
+pub struct PeerFetch {
+    own: Url,
+    fleet: Fleet,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl PeerFetch {
+    /// Copy the leader elected in `fleet` unless it is `own`, authenticating with the admin
+    /// `token` the fleet shares.
+    pub fn new(own: Url, fleet: Fleet, token: String) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        Self {
+            own,
+            fleet,
+            token,
+            client,
+        }
+    }
+
+    /// The peer to copy from, or `None` when this instance is the leader.
+    pub fn leader(&self) -> Option<PeerStatus> {
+        elect(&self.own, &self.fleet.status()).cloned()
+    }
+
+    /// Copy the data `leader` serves of `source`.
+    ///
+    /// Returns `None` without downloading anything when the leader reported the version
+    /// `old` describes in the last gossip round.
+    pub async fn fetch(
+        &self,
+        leader: &PeerStatus,
+        source: &DataSource,
+        old: Option<&SourceMetadata>,
+    ) -> Result<Option<(ActiveStore, SourceMetadata)>> {
+        if let Some(old) = old
+            && old.version().is_some()
+            && old.version() == leader.source_version
+        {
+            return Ok(None);
+        }
+
+        let url = Url::parse(&leader.peer)
+            .and_then(|peer| peer.join("api/v1/admin/export?format=snapshot"))
+            .map_err(|e| LoadError::HttpError(format!("invalid peer URL: {e}")))?;
+        let response = self
+            .client
+            .get(url)
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let metadata = SourceMetadata {
+            mtime: header(SOURCE_MODIFIED_HEADER)
+                .and_then(|secs| secs.parse().ok())
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            etag: header(SOURCE_VERSION_HEADER),
+            last_modified: None,
+            origin: Some(leader.peer.clone()),
+        };
+        let bytes = response.bytes().await?;
+
+        let expected = source.redacted();
+        tokio::task::spawn_blocking(move || {
+            let snapshot = snapshot::decode(&bytes)?;
+            if snapshot.source != expected {
+                return Err(LoadError::HttpError(format!(
+                    "peer serves {}, not {expected}",
+                    snapshot.source
+                )));
+            }
+            Ok(Some((occlusion::build_store(snapshot.entries)?, metadata)))
+        })
+        .await
+        .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(url: &str, convergence: Convergence, version: Option<&str>) -> PeerStatus {
+        PeerStatus {
+            peer: url.to_string(),
+            convergence,
+            generation: Some(1),
+            source_version: version.map(str::to_string),
+            source_modified_at: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_elect() {
+        let status = FleetStatus {
+            checked_at: Some(0),
+            source_version: Some("a".to_string()),
+            peers: vec![
+                peer("http://10.0.0.3:8000/", Convergence::InSync, Some("a")),
+                peer("http://10.0.0.1:8000/", Convergence::Unreachable, None),
+                peer("http://10.0.0.2:8000/", Convergence::Behind, None),
+                peer("http://10.0.0.4:8000/", Convergence::Ahead, Some("b")),
+            ],
+        };
+        let own = |url: &str| Url::parse(url).unwrap();
+
+        let leader = elect(&own("http://10.0.0.5:8000/"), &status).unwrap();
+        assert_eq!(leader.peer, "http://10.0.0.3:8000/");
+        // The leader sees itself among the peers resolved from DNS
+        assert!(elect(&own("http://10.0.0.3:8000/"), &status).is_none());
+        assert!(elect(&own("http://10.0.0.2:8000/"), &status).is_none());
+        assert!(elect(&own("http://10.0.0.5:8000/"), &FleetStatus::default()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let source = DataSource::parse("https://example.com/data.csv");
+        let leader = peer("http://127.0.0.1:1/", Convergence::InSync, Some("a"));
+        let fetch = PeerFetch::new(
+            Url::parse("http://127.0.0.2:8000/").unwrap(),
+            Fleet::default(),
+            "s3cret".to_string(),
+        );
+
+        // The version the leader reported is already loaded: no request
+        let old = SourceMetadata {
+            etag: Some("a".to_string()),
+            ..SourceMetadata::new()
+        };
+        assert!(
+            fetch
+                .fetch(&leader, &source, Some(&old))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let body = snapshot::Snapshot {
+            source: source.redacted(),
+            created: SystemTime::UNIX_EPOCH,
+            entries: vec![(uuid::Uuid::from_u128(1), 3)],
+        }
+        .encode();
+        let response = |body: &[u8]| {
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{SOURCE_VERSION_HEADER}: b\r\n\
+                 {SOURCE_MODIFIED_HEADER}: 42\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(body);
+            response
+        };
+        let (url, heads) = crate::loader::tests::serve(vec![response(&body), response(&body)]);
+        let leader = peer(&url, Convergence::Ahead, Some("b"));
+        let (store, metadata) = fetch
+            .fetch(&leader, &source, Some(&old))
+            .await
+            .unwrap()
+            .unwrap();
+        let head = heads.recv().unwrap();
+        assert!(
+            head.starts_with("GET /api/v1/admin/export?format=snapshot"),
+            "{head}"
+        );
+        assert!(head.contains("authorization: Bearer s3cret"), "{head}");
+        assert_eq!(occlusion::Store::len(&store), 1);
+        assert_eq!(metadata.version().as_deref(), Some("b"));
+        assert_eq!(metadata.modified_at(), Some(42));
+
+        let other = DataSource::parse("https://example.com/other.csv");
+        let e = fetch.fetch(&leader, &other, None).await.unwrap_err();
+        assert!(
+            e.to_string().contains("not https://example.com/other.csv"),
+            "{e}"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Symbolic visibility level names, resolved to numeric levels through a mapping file.
+
+use crate::error::{LoadError, Result};
+use std::{collections::HashMap, path::Path};
+
+/// Mapping of role names (`public`, `internal`, ...) to visibility levels.
+///
+/// Read from a JSON object such as `{"public": 0, "internal": 5, "restricted": 10}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelNames(HashMap<String, u8>);
+
+impl LevelNames {
+    /// Parse a JSON object of names to levels.
+    ///
+    /// Names must not be numeric, as numeric values in the data are always read as levels.
+    pub fn from_json(content: &[u8]) -> Result<Self> {
+        let names: HashMap<String, u8> = serde_json::from_slice(content)
+            .map_err(|e| LoadError::InvalidFormat(format!("Invalid level mapping: {e}")))?;
+        if let Some(name) = names.keys().find(|name| name.parse::<u8>().is_ok()) {
+            return Err(LoadError::InvalidFormat(format!(
+                "Invalid level mapping: name {name:?} is numeric"
+            )));
+        }
+        Ok(Self(names))
+    }
+
+    /// Read a mapping file.
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::from_json(&std::fs::read(path)?)
+    }
+
+    /// Level of the role `name`, if mapped.
+    pub fn get(&self, name: &str) -> Option<u8> {
+        self.0.get(name).copied()
+    }
+}
+
+/// Resolve a level value: a number, or a name from `names`.
+pub(crate) fn resolve(value: &str, names: Option<&LevelNames>) -> Option<u8> {
+    value
+        .parse()
+        .ok()
+        .or_else(|| names.and_then(|names| names.get(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let names = LevelNames::from_json(br#"{"public": 0, "restricted": 10}"#).unwrap();
+        assert_eq!(resolve("7", Some(&names)), Some(7));
+        assert_eq!(resolve("restricted", Some(&names)), Some(10));
+        assert_eq!(resolve("internal", Some(&names)), None);
+        assert_eq!(resolve("public", None), None);
+
+        assert!(LevelNames::from_json(br#"{"3": 4}"#).is_err());
+        assert!(LevelNames::from_json(br#"{"public": 256}"#).is_err());
+    }
+}
@@ -11,18 +11,581 @@
 #[macro_use]
 extern crate rocket;
 
+pub mod access_log;
+pub mod admin;
+pub mod admission;
+pub mod audit;
+pub mod build_thread;
+mod bundle;
+pub mod cache;
+pub mod canary;
+pub mod catchers;
+#[cfg(feature = "tls")]
+pub mod cert_reload;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod client_cert;
+#[cfg(any(feature = "gcs", feature = "azure"))]
+mod cloud;
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+mod columnar;
+pub mod compression;
+pub mod decisions;
+pub mod delta;
 pub mod error;
+pub mod events;
 pub mod fairing;
+pub mod format;
+pub mod gossip;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod guards;
+pub mod ids;
+pub mod integrity;
+pub mod jwt;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod latency;
+pub mod leader;
+pub mod levels;
 pub mod loader;
 pub mod models;
+pub mod namespace;
+pub mod namespaced;
+pub mod opa;
+pub mod pinning;
+pub mod prewarm;
+pub mod progress;
+pub mod remote;
+pub mod request_id;
+pub mod resp;
+pub mod route_groups;
 pub mod routes;
+#[cfg(feature = "s3")]
+mod s3;
+pub mod scheduler;
+pub mod snapshot;
 pub mod source;
+pub mod subjects;
+pub mod systemd;
+pub mod timeout;
+pub mod v2;
+pub mod validation;
+pub mod watch;
+pub mod webhooks;
+pub mod websocket;
 
+use canary::Canary;
+use events::{Events, StoreEvent};
+use leader::PeerFetch;
+use loader::LoadOptions;
+use models::LoadMetrics;
+use occlusion::{Store, SwappableStore};
+use snapshot::Snapshot;
 use source::{DataSource, SourceMetadata};
-use std::sync::RwLock;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use subjects::SubjectMasks;
+use tracing::{debug, info, warn};
+use validation::{Diff, ValidationGates};
 
 /// Shared state for the reload scheduler
 pub struct ReloadState {
-    pub source: DataSource,
+    /// Current source, replaced by [`repoint`](Self::repoint)
+    source: RwLock<DataSource>,
+    /// Load options for the current source
+    options: RwLock<Arc<LoadOptions>>,
     pub metadata: RwLock<SourceMetadata>,
+    /// Where to save a snapshot after each successful reload
+    pub snapshot: Option<PathBuf>,
+    /// Checks a reloaded store must pass before it is swapped in
+    pub gates: ValidationGates,
+    /// Metrics of the last successful load
+    pub last_load: RwLock<Option<LoadMetrics>>,
+    /// When the last reload succeeded, whether or not the source had changed
+    pub last_success: RwLock<Option<SystemTime>>,
+    /// Reloads failed since the last success
+    pub consecutive_failures: AtomicU32,
+    /// Whether a store has been loaded, from the source or a snapshot
+    pub loaded: AtomicBool,
+    /// Set when reloads failed too many times in a row and the store was cleared, until the
+    /// next success
+    pub locked_out: AtomicBool,
+    /// Set when reloads failed too many times in a row and stale or fallback data is served
+    /// with an alarm raised, until the next success
+    pub degraded: AtomicBool,
+    /// Longest time the data may go without a successful reload before it is stale
+    pub max_staleness: Option<Duration>,
+    /// UUIDs of the current data looked up in reloaded stores before they are swapped in
+    pub prewarm: usize,
+    /// Copies the data of the fleet's leader instead of loading the source, when not leading
+    pub peer_fetch: Option<PeerFetch>,
+    /// Evaluates reloaded stores on live traffic, rolling them back if they diverge too much
+    pub canary: Option<Arc<Canary>>,
+    /// Faults injected into reloads
+    #[cfg(feature = "chaos")]
+    pub chaos: Option<Arc<chaos::Chaos>>,
+    /// Stores fed by the namespace column of the source, keyed by namespace
+    pub routes: BTreeMap<String, SwappableStore>,
+    /// Subject masks re-read with every reload
+    pub subjects: Option<Arc<SubjectMasks>>,
+    /// Where swaps and reload failures are announced, and the namespace to announce them for
+    events: Option<(Events, Option<String>)>,
+    /// Serializes reloads triggered from different places (scheduler, file watcher, admin API)
+    reload_lock: tokio::sync::Mutex<()>,
+    /// Woken once a store is loaded after starting without one
+    on_loaded: tokio::sync::Notify,
+}
+
+impl ReloadState {
+    /// Create reload state for a source loaded with `metadata`.
+    pub fn new(source: DataSource, options: LoadOptions, metadata: SourceMetadata) -> Self {
+        Self {
+            source: RwLock::new(source),
+            options: RwLock::new(Arc::new(options)),
+            metadata: RwLock::new(metadata),
+            snapshot: None,
+            gates: ValidationGates::default(),
+            last_load: RwLock::new(None),
+            last_success: RwLock::new(None),
+            consecutive_failures: AtomicU32::new(0),
+            loaded: AtomicBool::new(true),
+            locked_out: AtomicBool::new(false),
+            degraded: AtomicBool::new(false),
+            max_staleness: None,
+            prewarm: 0,
+            peer_fetch: None,
+            canary: None,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            routes: BTreeMap::new(),
+            subjects: None,
+            events: None,
+            reload_lock: tokio::sync::Mutex::new(()),
+            on_loaded: tokio::sync::Notify::new(),
+        }
+    }
+
+    /// Save a snapshot to `path` after each successful reload.
+    #[must_use]
+    pub fn with_snapshot(mut self, path: PathBuf) -> Self {
+        self.snapshot = Some(path);
+        self
+    }
+
+    /// Validate reloaded stores with `gates` before swapping them in.
+    #[must_use]
+    pub fn with_gates(mut self, gates: ValidationGates) -> Self {
+        self.gates = gates;
+        self
+    }
+
+    /// Consider the data stale when it has not been reloaded successfully for `max`.
+    #[must_use]
+    pub fn with_max_staleness(mut self, max: Duration) -> Self {
+        self.max_staleness = Some(max);
+        self
+    }
+
+    /// Warm reloaded stores up with `lookups` UUIDs of the current data before swapping them
+    /// in (see [`prewarm`]).
+    #[must_use]
+    pub fn with_prewarm(mut self, lookups: usize) -> Self {
+        self.prewarm = lookups;
+        self
+    }
+
+    /// Reload from the leader elected by `fetch` rather than from the source, unless this
+    /// instance is the leader. Sources with a namespace column are always loaded directly.
+    #[must_use]
+    pub fn with_peer_fetch(mut self, fetch: PeerFetch) -> Self {
+        self.peer_fetch = Some(fetch);
+        self
+    }
+
+    /// Evaluate reloaded stores with `canary` after swapping them in.
+    #[must_use]
+    pub fn with_canary(mut self, canary: Arc<Canary>) -> Self {
+        self.canary = Some(canary);
+        self
+    }
+
+    /// Inject the faults of `chaos` into reloads.
+    #[cfg(feature = "chaos")]
+    #[must_use]
+    pub fn with_chaos(mut self, chaos: Arc<chaos::Chaos>) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Swap rows routed by the namespace column into `routes` on each reload.
+    ///
+    /// Namespaces are fixed when the state is created: a namespace that disappears from
+    /// the source is emptied, and a new one is ignored until restart.
+    #[must_use]
+    pub fn with_routes(mut self, routes: BTreeMap<String, SwappableStore>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Re-read `subjects` with every reload, whether or not the source changed.
+    #[must_use]
+    pub fn with_subjects(mut self, subjects: Arc<SubjectMasks>) -> Self {
+        self.subjects = Some(subjects);
+        self
+    }
+
+    /// Announce swaps and reload failures on `events`, as those of `namespace`.
+    #[must_use]
+    pub fn with_events(mut self, events: Events, namespace: Option<String>) -> Self {
+        self.events = Some((events, namespace));
+        self
+    }
+
+    /// Announce that `store` was swapped, with `diff` from the previous data if known.
+    pub fn announce_swap(&self, store: &SwappableStore, diff: Option<Diff>) {
+        if let Some((events, namespace)) = &self.events {
+            events.send(StoreEvent::swapped(namespace.clone(), store, diff));
+        }
+    }
+
+    /// The data source reloads read from.
+    pub fn source(&self) -> DataSource {
+        self.source.read().expect("RwLock poisoned").clone()
+    }
+
+    /// When data was last loaded successfully (seconds since the Unix epoch), counting
+    /// reloads that found the source unchanged.
+    pub fn last_reload_at(&self) -> Option<u64> {
+        let last_success = *self.last_success.read().expect("RwLock poisoned");
+        last_success
+            .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs())
+            .or_else(|| {
+                let last_load = self.last_load.read().expect("RwLock poisoned");
+                last_load.as_ref().map(|load| load.completed_at)
+            })
+    }
+
+    /// Record that a store has been loaded, waking [`wait_loaded`](Self::wait_loaded).
+    pub fn mark_loaded(&self) {
+        if !self.loaded.swap(true, Ordering::AcqRel) {
+            self.on_loaded.notify_waiters();
+        }
+    }
+
+    /// Wait until a store has been loaded, from the source or a snapshot.
+    pub async fn wait_loaded(&self) {
+        loop {
+            let notified = self.on_loaded.notified();
+            tokio::pin!(notified);
+            // Registered before checking, so that a load in between is not missed
+            notified.as_mut().enable();
+            if self.loaded.load(Ordering::Acquire) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Why the data is not fit to serve, if it is not: nothing loaded yet, cleared after
+    /// too many failed reloads, or stale.
+    pub fn unready_reason(&self) -> Option<String> {
+        if !self.loaded.load(Ordering::Relaxed) {
+            return Some(format!("{} not loaded yet", self.source().redacted()));
+        }
+        if self.locked_out.load(Ordering::Relaxed) {
+            return Some(format!(
+                "{} cleared after repeated reload failures",
+                self.source().redacted()
+            ));
+        }
+        self.staleness().map(|age| {
+            format!(
+                "{} not refreshed for {}s",
+                self.source().redacted(),
+                age.as_secs()
+            )
+        })
+    }
+
+    /// How long the data has gone without a successful reload, if over the maximum
+    /// staleness.
+    ///
+    /// Data whose load time is unknown (restored from a snapshot and not reloaded since) is
+    /// not considered stale.
+    pub fn staleness(&self) -> Option<Duration> {
+        let max = self.max_staleness?;
+        let at = UNIX_EPOCH + Duration::from_secs(self.last_reload_at()?);
+        let age = SystemTime::now().duration_since(at).unwrap_or_default();
+        (age > max).then_some(age)
+    }
+
+    /// Why the data served is not known to be current, if it is not: kept stale or replaced
+    /// by a fallback after too many failed reloads.
+    pub fn degraded_reason(&self) -> Option<String> {
+        self.degraded.load(Ordering::Relaxed).then(|| {
+            format!(
+                "{} serving stale data after repeated reload failures",
+                self.source().redacted()
+            )
+        })
+    }
+
+    /// Load options of the current source.
+    pub fn options(&self) -> Arc<LoadOptions> {
+        self.options.read().expect("RwLock poisoned").clone()
+    }
+
+    /// Reload the source into `store`, returning whether it was swapped.
+    ///
+    /// When `conditional`, the load is skipped if the source is unchanged since the
+    /// recorded metadata. A store rejected by the validation gates is an error and
+    /// leaves `store` (and every routed namespace) untouched.
+    pub async fn reload(&self, store: &SwappableStore, conditional: bool) -> error::Result<bool> {
+        self.reload_inner(store, conditional, false)
+            .await
+            .map(|swapped| swapped.is_some())
+    }
+
+    /// Like [`reload`](Self::reload), also comparing the swapped-in store with the previous one.
+    ///
+    /// Returns `None` when the source was unchanged. The comparison walks every entry,
+    /// so it is reserved to on-demand reloads.
+    pub async fn reload_with_diff(
+        &self,
+        store: &SwappableStore,
+        conditional: bool,
+    ) -> error::Result<Option<Diff>> {
+        self.reload_inner(store, conditional, true)
+            .await
+            .map(|swapped| swapped.map(Option::unwrap_or_default))
+    }
+
+    /// Reload, returning `Some` when swapped, holding the diff when `diff` is requested.
+    async fn reload_inner(
+        &self,
+        store: &SwappableStore,
+        conditional: bool,
+        diff: bool,
+    ) -> error::Result<Option<Option<Diff>>> {
+        let result = async {
+            let _guard = self.reload_lock.lock().await;
+            let (source, options) = (self.source(), self.options());
+            let result = self
+                .load_and_swap(store, &source, &options, conditional, diff)
+                .await;
+            if let Some(subjects) = &self.subjects {
+                subjects.reload().await;
+            }
+            result
+        }
+        .await;
+        self.record(&result);
+        result
+    }
+
+    /// Point the state at a new `source` and load it into `store` now.
+    ///
+    /// If the load fails or is rejected by the validation gates, the current source and data
+    /// are kept. Sidecars at their default location (`<source>.sha256`, `<source>.sig`) follow
+    /// the source; explicitly configured ones are kept.
+    pub async fn repoint(&self, store: &SwappableStore, source: DataSource) -> error::Result<Diff> {
+        let result = async {
+            let _guard = self.reload_lock.lock().await;
+            let old = self.source();
+            let options = Arc::new(self.options().for_source(&old, &source));
+            let diff = self
+                .load_and_swap(store, &source, &options, false, true)
+                .await?
+                .flatten()
+                .unwrap_or_default();
+            info!(from = %old, to = %source, "Data source changed");
+            *self.source.write().expect("RwLock poisoned") = source;
+            *self.options.write().expect("RwLock poisoned") = options;
+            Ok(diff)
+        }
+        .await;
+        self.record(&result);
+        result
+    }
+
+    /// Track the outcome of a reload for the stats, announcing failures.
+    fn record<T>(&self, result: &error::Result<T>) {
+        match result {
+            Ok(_) => {
+                self.consecutive_failures.store(0, Ordering::Relaxed);
+                self.mark_loaded();
+                self.locked_out.store(false, Ordering::Relaxed);
+                self.degraded.store(false, Ordering::Relaxed);
+                *self.last_success.write().expect("RwLock poisoned") = Some(SystemTime::now());
+            }
+            Err(e) => {
+                let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some((events, namespace)) = &self.events {
+                    events.send(StoreEvent::ReloadFailed {
+                        namespace: namespace.clone(),
+                        error: e.to_string(),
+                        consecutive_failures: failures,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Whether swaps should be compared with the previous data for subscribers to events.
+    fn announces_diffs(&self) -> bool {
+        self.events
+            .as_ref()
+            .is_some_and(|(events, _)| events.has_subscribers())
+    }
+
+    /// Copy the data of the fleet's leader, or `None` to load `source` directly: when
+    /// this instance leads, has no leader to copy, or copying the leader's data failed.
+    async fn fetch_from_leader(
+        &self,
+        source: &DataSource,
+        old: Option<&SourceMetadata>,
+    ) -> Option<Option<(loader::Loaded, SourceMetadata)>> {
+        let fetch = self
+            .peer_fetch
+            .as_ref()
+            .filter(|_| self.routes.is_empty())?;
+        let leader = fetch.leader()?;
+        match fetch.fetch(&leader, source, old).await {
+            Ok(fetched) => Some(fetched.map(|(store, metadata)| {
+                let loaded = loader::Loaded {
+                    store,
+                    namespaces: BTreeMap::new(),
+                };
+                (loaded, metadata)
+            })),
+            Err(e) => {
+                warn!(leader = %leader.peer, error = %e, "Failed to copy the leader's data, loading the source");
+                None
+            }
+        }
+    }
+
+    /// Load `source` and swap it in, with the reload lock held.
+    async fn load_and_swap(
+        &self,
+        store: &SwappableStore,
+        source: &DataSource,
+        options: &LoadOptions,
+        conditional: bool,
+        diff: bool,
+    ) -> error::Result<Option<Option<Diff>>> {
+        let old_metadata = self.metadata.read().expect("RwLock poisoned").clone();
+        let started = Instant::now();
+
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &self.chaos {
+            chaos.before_fetch().await?;
+        }
+        let old = conditional.then_some(&old_metadata);
+        let fetched = match self.fetch_from_leader(source, old).await {
+            Some(fetched) => fetched,
+            None => loader::load_routed(source, old, options).await?,
+        };
+        let Some((loaded, new_metadata)) = fetched else {
+            return Ok(None);
+        };
+
+        let elapsed = started.elapsed();
+        let new_store = loaded.store;
+        let mut routed = loaded.namespaces;
+        self.gates.check(store, &new_store)?;
+        for (name, route) in &self.routes {
+            if let Some(candidate) = routed.get(name) {
+                self.gates.check(route, candidate).map_err(|e| {
+                    error::LoadError::ValidationError(format!("namespace {name}: {e}"))
+                })?;
+            }
+        }
+
+        let new_store = if self.prewarm > 0 {
+            let (current, lookups) = (store.clone(), self.prewarm);
+            tokio::task::spawn_blocking(move || {
+                let started = Instant::now();
+                let found = prewarm::warm(&new_store, &current, lookups);
+                debug!(
+                    lookups,
+                    found,
+                    duration_ms = started.elapsed().as_millis(),
+                    "Warmed up reloaded store"
+                );
+                new_store
+            })
+            .await
+            .map_err(|e| error::LoadError::InvalidFormat(format!("Task join error: {e}")))?
+        } else {
+            new_store
+        };
+
+        let count = new_store.len();
+        let diff = (diff || self.announces_diffs()).then(|| Diff::between(store, &new_store));
+        let entries = self.snapshot.is_some().then(|| new_store.entries());
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &self.chaos {
+            chaos.before_swap().await;
+        }
+        let previous = self
+            .canary
+            .as_ref()
+            .and_then(|_| store.at_generation(store.generation()));
+        store.swap(new_store);
+        if let (Some(canary), Some(previous)) = (&self.canary, previous) {
+            canary.start(previous);
+        }
+        for (name, route) in &self.routes {
+            if let Some(candidate) = routed.remove(name) {
+                route.swap(candidate);
+            } else {
+                warn!(namespace = %name, "Namespace missing from the source, clearing it");
+                route.swap(occlusion::build_store(vec![])?);
+            }
+        }
+        self.announce_swap(store, diff);
+        if !routed.is_empty() {
+            let names: Vec<_> = routed.into_keys().collect();
+            warn!(
+                namespaces = ?names,
+                "New namespaces in the source are ignored until restart"
+            );
+        }
+        info!(
+            uuid_count = count,
+            origin = new_metadata.origin,
+            "Store reloaded successfully"
+        );
+        let metrics = options.load_metrics(elapsed, count, &new_metadata);
+        *self.last_load.write().expect("RwLock poisoned") = Some(metrics);
+        *self.metadata.write().expect("RwLock poisoned") = new_metadata;
+
+        if let (Some(path), Some(entries)) = (&self.snapshot, entries) {
+            save_snapshot(path.clone(), Snapshot::new(source.to_string(), entries)).await;
+        }
+        Ok(Some(diff))
+    }
+}
+
+/// Write `snapshot` to `path` off the async runtime, logging (not returning) failures.
+///
+/// A missing snapshot only matters on the next cold start, so it never fails a load.
+pub async fn save_snapshot(path: PathBuf, snapshot: Snapshot) {
+    let result =
+        tokio::task::spawn_blocking(move || snapshot::write(&snapshot, &path).map(|()| path)).await;
+    match result {
+        Ok(Ok(path)) => info!(path = %path.display(), "Saved store snapshot"),
+        Ok(Err(e)) => warn!(error = %e, "Failed to save store snapshot"),
+        Err(e) => warn!(error = %e, "Snapshot task failed"),
+    }
 }
@@ -1,66 +1,464 @@
 //! Data loading utilities for files and URLs.
 
 use crate::{
+    build_thread::BuildThread,
+    compression,
+    delta::DeltaOptions,
     error::{LoadError, Result},
-    source::{DataSource, SourceMetadata},
+    format::{CsvOptions, ErrorBudget, InputFormat, Rows},
+    integrity::{self, SignatureCheck},
+    models::LoadMetrics,
+    progress::{LoadPhase, ProgressReporter},
+    source::{DataSource, SourceAuth, SourceMetadata},
 };
 use occlusion::{ActiveStore, Store};
-use serde::Deserialize;
-use std::{path::PathBuf, sync::LazyLock, time::Duration, time::Instant};
-use tracing::info;
+use std::{collections::BTreeMap, path::PathBuf, sync::LazyLock, time::Duration, time::Instant};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 /// Default HTTP timeout in seconds.
 const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 30;
+/// Default HTTP connect timeout in seconds.
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
 
 static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
-    let timeout_secs = std::env::var("OCCLUSION_HTTP_TIMEOUT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(DEFAULT_HTTP_TIMEOUT_SECS);
-
-    reqwest::Client::builder()
-        .user_agent(concat!("occlusion/", env!("CARGO_PKG_VERSION")))
-        .timeout(Duration::from_secs(timeout_secs))
-        .connect_timeout(Duration::from_secs(10))
-        .build()
+    HttpOptions::default()
+        .build_client()
         .expect("Failed to build HTTP client")
 });
 
-#[derive(Debug, Deserialize)]
-struct CsvRecord {
-    uuid: String,
-    visibility_level: u8,
+/// Network settings for fetching HTTP(S) sources.
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    /// Total time allowed for a request, including reading the body
+    pub timeout: Duration,
+    /// Time allowed to establish a connection
+    pub connect_timeout: Duration,
+    /// Maximum time between two reads of the response body
+    pub read_timeout: Option<Duration>,
+    /// Proxy for all source requests (`http://` or `https://` URL); otherwise the
+    /// `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY` environment variables apply
+    pub proxy: Option<String>,
+    /// PEM bundle of additional CA certificates to trust
+    pub ca_bundle: Option<PathBuf>,
+    /// PEM client certificate for mTLS (may also contain the private key)
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key for `client_cert`, when stored separately
+    pub client_key: Option<PathBuf>,
 }
 
-/// Parse CSV and build store from bytes (blocking, CPU-intensive).
-fn build_from_bytes(content: impl AsRef<[u8]>) -> Result<ActiveStore> {
-    let start = Instant::now();
+/// The timeout defaults to `OCCLUSION_HTTP_TIMEOUT` seconds (30 when unset).
+impl Default for HttpOptions {
+    fn default() -> Self {
+        let timeout_secs = std::env::var("OCCLUSION_HTTP_TIMEOUT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_HTTP_TIMEOUT_SECS);
+
+        Self {
+            timeout: Duration::from_secs(timeout_secs),
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            read_timeout: None,
+            proxy: None,
+            ca_bundle: None,
+            client_cert: None,
+            client_key: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Build a client applying these settings.
+    pub fn build_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(concat!("occlusion/", env!("CARGO_PKG_VERSION")))
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout);
+        if let Some(read_timeout) = self.read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        if let Some(path) = &self.ca_bundle {
+            for cert in reqwest::Certificate::from_pem_bundle(&std::fs::read(path)?)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(path) = &self.client_cert {
+            let mut pem = std::fs::read(path)?;
+            if let Some(key) = &self.client_key {
+                pem.push(b'\n');
+                pem.extend(std::fs::read(key)?);
+            }
+            builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Retry policy for HTTP(S) source requests.
+///
+/// Connection failures, timeouts, `429` and `5xx` responses are retried with full-jitter
+/// exponential backoff. A `Retry-After` header is honored when it is within `max_delay`;
+/// a longer one ends the retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt (0 = no retries)
+    pub max_retries: u32,
+    /// Backoff ceiling of the first retry, doubled for each further retry
+    pub base_delay: Duration,
+    /// Upper bound for a single delay
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt` (0-based), or `None` if the request should
+    /// not be retried.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay);
+        let millis = u64::try_from(ceiling.as_millis()).unwrap_or(u64::MAX);
+        Some(Duration::from_millis(rand::random_range(0..=millis)))
+    }
+}
 
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(content.as_ref());
-
-    let entries: Vec<(Uuid, u8)> = csv_reader
-        .deserialize()
-        .enumerate()
-        .map(|(line_num, result)| {
-            let record: CsvRecord = result?;
-            let uuid = record
-                .uuid
-                .parse::<Uuid>()
-                .map_err(|e| LoadError::InvalidFormat(format!("Line {}: {}", line_num + 2, e)))?;
-            Ok((uuid, record.visibility_level))
+/// Parse a `Retry-After` header (delay in seconds or an HTTP date).
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(secs) = value.trim().parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// Send a GET request, retrying transient failures according to `policy`.
+///
+/// Once retries are exhausted the last response (or error) is returned as is.
+pub(crate) async fn send_with_retry(
+    request: reqwest::RequestBuilder,
+    policy: &RetryPolicy,
+) -> Result<reqwest::Response> {
+    let mut attempt = 0;
+    loop {
+        let result = request
+            .try_clone()
+            .expect("GET requests have no streaming body")
+            .send()
+            .await;
+
+        let (reason, delay) = match &result {
+            Ok(response)
+                if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || response.status().is_server_error() =>
+            {
+                (
+                    response.status().to_string(),
+                    policy.delay(attempt, retry_after(response.headers())),
+                )
+            }
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                (e.to_string(), policy.delay(attempt, None))
+            }
+            _ => return Ok(result?),
+        };
+        let Some(delay) = delay else {
+            return Ok(result?);
+        };
+
+        attempt += 1;
+        warn!(
+            attempt,
+            reason = %reason,
+            delay_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+            "Source request failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Options controlling how a data source is fetched and parsed.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Explicit input format (auto-detected from extension/content-type when `None`)
+    pub format: Option<InputFormat>,
+    /// Schema of CSV input
+    pub csv: CsvOptions,
+    /// Skip malformed rows up to this budget instead of aborting (strict when `None`)
+    pub error_budget: Option<ErrorBudget>,
+    /// Receives progress updates (bytes fetched, rows parsed, phase)
+    pub progress: Option<ProgressReporter>,
+    /// Sidecar holding the expected SHA-256 of the raw content (e.g. `data.csv.sha256`)
+    pub checksum: Option<DataSource>,
+    /// Detached ed25519 signature the raw content must verify against
+    pub signature: Option<SignatureCheck>,
+    /// Credentials for HTTP(S) sources (data and sidecars)
+    pub auth: Option<SourceAuth>,
+    /// Extra headers sent with every HTTP(S) source request
+    pub headers: reqwest::header::HeaderMap,
+    /// Client for HTTP(S) sources, built from [`HttpOptions`] (shared default when `None`)
+    pub client: Option<reqwest::Client>,
+    /// Retries of failed HTTP(S) source requests
+    pub retry: RetryPolicy,
+    /// Mirrors of a URL source, tried in order when loading from the source URL fails
+    pub fallback_urls: Vec<String>,
+    /// Fetch patches since the current version on reload instead of the full data
+    pub delta: Option<DeltaOptions>,
+    /// Maximum size in bytes of an HTTP(S) response body, and of any compressed data once
+    /// decompressed (unlimited when `None`)
+    pub max_size: Option<u64>,
+    /// Media types accepted from HTTP(S) sources (any when empty), e.g. `text/csv`
+    pub allowed_content_types: Vec<String>,
+    /// Thread store builds run on (tokio's blocking pool when `None`)
+    pub build_thread: Option<BuildThread>,
+}
+
+impl LoadOptions {
+    /// Options for loading `new` instead of `old`: sidecars at their default location next
+    /// to `old` move next to `new`.
+    #[must_use]
+    pub fn for_source(&self, old: &DataSource, new: &DataSource) -> Self {
+        let mut options = self.clone();
+        if options.checksum.as_ref() == Some(&old.sidecar(".sha256")) {
+            options.checksum = Some(new.sidecar(".sha256"));
+        }
+        if let Some(signature) = &mut options.signature
+            && signature.sidecar == old.sidecar(".sig")
+        {
+            signature.sidecar = new.sidecar(".sig");
+        }
+        options
+    }
+
+    /// Metrics of a load of `rows` rows that just completed in `elapsed`.
+    ///
+    /// Phase durations, bytes and rejected rows come from the progress reporter; without
+    /// one, only the total duration is known.
+    pub fn load_metrics(
+        &self,
+        elapsed: Duration,
+        rows: usize,
+        metadata: &SourceMetadata,
+    ) -> LoadMetrics {
+        let millis = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+        let mut metrics = LoadMetrics {
+            completed_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            duration_ms: millis(elapsed),
+            rows_parsed: rows,
+            etag: metadata.etag.clone(),
+            ..LoadMetrics::default()
+        };
+        if let Some(progress) = &self.progress {
+            let timings = progress.timings();
+            let current = progress.current();
+            let cpu = timings.decompress + timings.parse + timings.build;
+            metrics.fetch_ms = millis(elapsed.saturating_sub(cpu));
+            metrics.decompress_ms = millis(timings.decompress);
+            metrics.parse_ms = millis(timings.parse);
+            metrics.build_ms = millis(timings.build);
+            metrics.bytes_fetched = current.bytes_fetched;
+            metrics.rows_rejected = current.rows_rejected;
+        }
+        metrics
+    }
+
+    /// Options for loading `origin` in place of the source URL `url`.
+    ///
+    /// Default sidecars (`<url>.sha256`, `<url>.sig`) are fetched from the same origin as
+    /// the data; explicitly configured sidecars are kept.
+    fn for_origin(&self, url: &str, origin: &str) -> Self {
+        let relocate = |sidecar: &DataSource, suffix: &str| {
+            let source = DataSource::Url(url.to_string());
+            if sidecar.to_string() == source.sidecar(suffix).to_string() {
+                DataSource::Url(origin.to_string()).sidecar(suffix)
+            } else {
+                sidecar.clone()
+            }
+        };
+
+        let mut options = self.clone();
+        if let Some(checksum) = &mut options.checksum {
+            *checksum = relocate(checksum, ".sha256");
+        }
+        if let Some(signature) = &mut options.signature {
+            signature.sidecar = relocate(&signature.sidecar, ".sig");
+        }
+        options
+    }
+}
+
+/// Sidecar files fetched alongside the data, verified before parsing.
+#[derive(Debug, Default)]
+struct Sidecars {
+    checksum: Option<Vec<u8>>,
+    signature: Option<(ed25519_dalek::VerifyingKey, Vec<u8>)>,
+}
+
+impl Sidecars {
+    async fn fetch(options: &LoadOptions) -> Result<Self> {
+        let checksum = match &options.checksum {
+            Some(source) => Some(fetch_raw(source, options).await?),
+            None => None,
+        };
+        let signature = match &options.signature {
+            Some(check) => Some((check.public_key, fetch_raw(&check.sidecar, options).await?)),
+            None => None,
+        };
+        Ok(Self {
+            checksum,
+            signature,
         })
-        .collect::<Result<_>>()?;
+    }
+
+    fn verify(&self, content: &[u8]) -> Result<()> {
+        if let Some(checksum) = &self.checksum {
+            integrity::verify_sha256(content, checksum)?;
+        }
+        if let Some((key, signature)) = &self.signature {
+            integrity::verify_signature(content, signature, key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Start a GET request to an HTTP(S) source, applying the configured headers and credentials.
+pub(crate) fn http_get(url: &str, options: &LoadOptions) -> reqwest::RequestBuilder {
+    let client = options.client.as_ref().unwrap_or(&HTTP_CLIENT);
+    let request = client.get(url).headers(options.headers.clone());
+    match &options.auth {
+        Some(SourceAuth::Bearer(token)) => request.bearer_auth(token),
+        Some(SourceAuth::Basic { username, password }) => {
+            request.basic_auth(username, password.as_ref())
+        }
+        None => request,
+    }
+}
+
+/// Fetch the raw bytes of a (small) source such as a sidecar file.
+async fn fetch_raw(source: &DataSource, options: &LoadOptions) -> Result<Vec<u8>> {
+    match source {
+        DataSource::File(path) => {
+            let path = path.clone();
+            Ok(tokio::task::spawn_blocking(move || std::fs::read(path))
+                .await
+                .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))??)
+        }
+        DataSource::Url(url) => {
+            let response = send_with_retry(http_get(url, options), &options.retry).await?;
+            if !response.status().is_success() {
+                return Err(LoadError::HttpError(format!(
+                    "HTTP request for {url} failed with status: {}",
+                    response.status()
+                )));
+            }
+            Ok(response.bytes().await?.to_vec())
+        }
+        _ => fetch_object(source, None, None)
+            .await?
+            .map(|object| object.content)
+            .ok_or_else(|| LoadError::ObjectStoreError(format!("{source}: not found"))),
+    }
+}
+
+/// Verify, decompress, parse and build store from bytes (blocking, CPU-intensive).
+fn build_from_bytes(
+    content: Vec<u8>,
+    format: InputFormat,
+    sidecars: &Sidecars,
+    options: &LoadOptions,
+) -> Result<Loaded> {
+    sidecars.verify(&content)?;
+    let rows = parse_bytes(content, format, options)?;
+    build_rows(rows, options)
+}
+
+/// Decompress and parse bytes into entries (blocking, CPU-intensive).
+///
+/// Malformed rows skipped under the error budget are reported to the progress reporter.
+pub fn parse_bytes(content: Vec<u8>, format: InputFormat, options: &LoadOptions) -> Result<Rows> {
+    let progress = options.progress.as_ref();
+    let set_phase = |phase| {
+        if let Some(progress) = progress {
+            progress.set_phase(phase);
+        }
+    };
+
+    let start = Instant::now();
+    let compressed_bytes = content.len();
+    if compression::Compression::detect(&content) != compression::Compression::None {
+        set_phase(LoadPhase::Decompress);
+    }
+    let (content, compression) = compression::decompress(content, options.max_size)?;
+    if compression != compression::Compression::None {
+        info!(
+            compression = %compression,
+            compressed_bytes,
+            decompressed_bytes = content.len(),
+            elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+            "data decompressed"
+        );
+    }
+
+    let start = Instant::now();
+    set_phase(LoadPhase::Parse);
+    let (rows, report) = format.parse(content, &options.csv, options.error_budget, progress)?;
+
+    for rejected in &report.rejected {
+        warn!(line = rejected.line, reason = %rejected.reason, "row rejected");
+    }
+    if report.rejected_count > 0 {
+        if let Some(progress) = progress {
+            progress.add_rejected(&report);
+        }
+        warn!(
+            rejected = report.rejected_count,
+            total_rows = report.total_rows(),
+            "malformed rows skipped"
+        );
+    }
 
     info!(
-        entries = entries.len(),
+        entries = rows.len(),
+        namespaces = rows.namespaced.len(),
+        format = %format,
         elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
-        "CSV parsed"
+        "data parsed"
     );
 
+    Ok(rows)
+}
+
+/// Build the store from parsed entries (blocking, CPU-intensive).
+fn build_entries(entries: Vec<(Uuid, u8)>, options: &LoadOptions) -> Result<ActiveStore> {
     let start = Instant::now();
+    if let Some(progress) = &options.progress {
+        progress.set_phase(LoadPhase::Build);
+    }
     let store = occlusion::build_store(entries)?;
     info!(
         uuid_count = store.len(),
@@ -71,11 +469,69 @@ fn build_from_bytes(content: impl AsRef<[u8]>) -> Result<ActiveStore> {
     Ok(store)
 }
 
-/// Run blocking build on tokio's blocking threadpool.
-async fn spawn_build(content: Vec<u8>) -> Result<ActiveStore> {
-    tokio::task::spawn_blocking(move || build_from_bytes(content))
-        .await
-        .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))?
+/// Build the default store and one store per routed namespace (blocking, CPU-intensive).
+fn build_rows(rows: Rows, options: &LoadOptions) -> Result<Loaded> {
+    let store = build_entries(rows.entries, options)?;
+    let namespaces = rows
+        .namespaced
+        .into_iter()
+        .map(|(name, entries)| {
+            let store = occlusion::build_store(entries)
+                .map_err(|e| LoadError::InvalidFormat(format!("namespace {name}: {e}")))?;
+            Ok((name, store))
+        })
+        .collect::<Result<_>>()?;
+    Ok(Loaded { store, namespaces })
+}
+
+/// Decompress, parse and build stores from content supplied directly, e.g. uploaded
+/// through the admin API (no sidecars are checked).
+pub async fn load_bytes(
+    content: Vec<u8>,
+    format: InputFormat,
+    options: &LoadOptions,
+) -> Result<Loaded> {
+    let options = options.clone();
+    run_build(options.build_thread.clone(), move || {
+        let rows = parse_bytes(content, format, &options)?;
+        build_rows(rows, &options)
+    })
+    .await
+}
+
+/// Stores built from one load of a data source.
+pub struct Loaded {
+    /// Rows without a namespace
+    pub store: ActiveStore,
+    /// Rows routed by the namespace column, keyed by namespace
+    pub namespaces: BTreeMap<String, ActiveStore>,
+}
+
+/// Run a blocking build on `thread`, or on tokio's blocking threadpool without one.
+async fn run_build<T: Send + 'static>(
+    thread: Option<BuildThread>,
+    build: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    match thread {
+        Some(thread) => thread.run(build).await,
+        None => tokio::task::spawn_blocking(build)
+            .await
+            .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))?,
+    }
+}
+
+/// Run blocking build off the async executor.
+async fn spawn_build(
+    content: Vec<u8>,
+    format: InputFormat,
+    options: &LoadOptions,
+) -> Result<Loaded> {
+    let sidecars = Sidecars::fetch(options).await?;
+    let options = options.clone();
+    run_build(options.build_thread.clone(), move || {
+        build_from_bytes(content, format, &sidecars, &options)
+    })
+    .await
 }
 
 /// Load store from a `DataSource`, optionally checking if it changed.
@@ -86,17 +542,62 @@ pub async fn load(
     source: &DataSource,
     old_metadata: Option<&SourceMetadata>,
 ) -> Result<Option<(ActiveStore, SourceMetadata)>> {
-    match source {
-        DataSource::File(path) => load_file(path.clone(), old_metadata).await,
-        DataSource::Url(url) => load_url(url, old_metadata).await,
+    load_with_options(source, old_metadata, &LoadOptions::default()).await
+}
+
+/// Load store from a `DataSource` with explicit options.
+///
+/// Same change-detection semantics as [`load`]. Rows routed to a namespace by the
+/// namespace column are dropped; use [`load_routed`] to keep them.
+pub async fn load_with_options(
+    source: &DataSource,
+    old_metadata: Option<&SourceMetadata>,
+    options: &LoadOptions,
+) -> Result<Option<(ActiveStore, SourceMetadata)>> {
+    let loaded = load_routed(source, old_metadata, options).await?;
+    Ok(loaded.map(|(loaded, metadata)| {
+        if !loaded.namespaces.is_empty() {
+            warn!(
+                source = %source,
+                namespaces = loaded.namespaces.len(),
+                "rows with a namespace ignored"
+            );
+        }
+        (loaded.store, metadata)
+    }))
+}
+
+/// Load a `DataSource`, keeping rows routed to namespaces in their own stores.
+///
+/// Same change-detection semantics as [`load`].
+pub async fn load_routed(
+    source: &DataSource,
+    old_metadata: Option<&SourceMetadata>,
+    options: &LoadOptions,
+) -> Result<Option<(Loaded, SourceMetadata)>> {
+    let result = match source {
+        DataSource::File(path) if path.is_dir() => {
+            let pattern = path.join("*").to_string_lossy().into_owned();
+            load_files(pattern, old_metadata, options).await
+        }
+        DataSource::File(path) => load_file(path.clone(), old_metadata, options).await,
+        DataSource::Glob(pattern) => load_files(pattern.clone(), old_metadata, options).await,
+        DataSource::Url(url) => load_url_with_fallbacks(url, old_metadata, options).await,
+        _ => load_object(source, old_metadata, options).await,
+    };
+
+    if let Some(progress) = &options.progress {
+        progress.finish();
     }
+    result
 }
 
 /// Load store from a file, optionally checking mtime.
 async fn load_file(
     path: PathBuf,
     old_metadata: Option<&SourceMetadata>,
-) -> Result<Option<(ActiveStore, SourceMetadata)>> {
+    options: &LoadOptions,
+) -> Result<Option<(Loaded, SourceMetadata)>> {
     let new_metadata = SourceMetadata::from_file(&path)?;
 
     if let Some(old) = old_metadata
@@ -105,22 +606,161 @@ async fn load_file(
         return Ok(None);
     }
 
+    let format = options
+        .format
+        .or_else(|| InputFormat::from_path(&path))
+        .unwrap_or_default();
+
+    if let Some(progress) = &options.progress {
+        progress.start(std::fs::metadata(&path).ok().map(|m| m.len()));
+    }
     let content = tokio::task::spawn_blocking(move || std::fs::read(path))
         .await
         .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))??;
+    if let Some(progress) = &options.progress {
+        progress.add_bytes(content.len() as u64);
+    }
 
-    let store = spawn_build(content).await?;
-    Ok(Some((store, new_metadata)))
+    let loaded = spawn_build(content, format, options).await?;
+    Ok(Some((loaded, new_metadata)))
+}
+
+/// Expand a glob pattern into the sorted list of matching files, skipping hidden files.
+fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..glob::MatchOptions::new()
+    };
+    let matches = glob::glob_with(pattern, options)
+        .map_err(|e| LoadError::InvalidFormat(format!("Invalid glob pattern {pattern}: {e}")))?;
+
+    let mut paths = Vec::new();
+    for path in matches {
+        let path = path.map_err(glob::GlobError::into_error)?;
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(LoadError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No files match {pattern}"),
+        )));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Load and merge every file matching a glob pattern, skipping the load if none changed.
+///
+/// Each file's format is detected from its own extension. A UUID appearing in more than
+/// one file is rejected like any other duplicate.
+async fn load_files(
+    pattern: String,
+    old_metadata: Option<&SourceMetadata>,
+    options: &LoadOptions,
+) -> Result<Option<(Loaded, SourceMetadata)>> {
+    if options.checksum.is_some() || options.signature.is_some() {
+        return Err(LoadError::InvalidFormat(format!(
+            "{pattern}: checksum and signature verification require a single-file source"
+        )));
+    }
+
+    let (paths, new_metadata) = tokio::task::spawn_blocking(move || {
+        let paths = expand_glob(&pattern)?;
+        let metadata = SourceMetadata::from_files(&paths)?;
+        Ok::<_, LoadError>((paths, metadata))
+    })
+    .await
+    .map_err(|e| LoadError::InvalidFormat(format!("Task join error: {e}")))??;
+
+    if let Some(old) = old_metadata
+        && !old.has_changed(&new_metadata)
+    {
+        return Ok(None);
+    }
+
+    let options = options.clone();
+    let loaded = run_build(options.build_thread.clone(), move || {
+        build_from_files(&paths, &options)
+    })
+    .await?;
+    Ok(Some((loaded, new_metadata)))
+}
+
+/// Read, parse and merge files into one store (blocking, CPU-intensive).
+fn build_from_files(paths: &[PathBuf], options: &LoadOptions) -> Result<Loaded> {
+    if let Some(progress) = &options.progress {
+        let total_bytes = paths
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|m| m.len())
+            .sum();
+        progress.start(Some(total_bytes));
+    }
+
+    let start = Instant::now();
+    let mut rows = Rows::default();
+    for path in paths {
+        let format = options
+            .format
+            .or_else(|| InputFormat::from_path(path))
+            .unwrap_or_default();
+        let content = std::fs::read(path)?;
+        if let Some(progress) = &options.progress {
+            progress.set_phase(LoadPhase::Fetch);
+            progress.add_bytes(content.len() as u64);
+        }
+        let parsed = parse_bytes(content, format, options).inspect_err(|e| {
+            warn!(path = %path.display(), error = %e, "Failed to parse file");
+        })?;
+        rows.entries.extend(parsed.entries);
+        for (name, entries) in parsed.namespaced {
+            rows.namespaced.entry(name).or_default().extend(entries);
+        }
+    }
+    info!(
+        files = paths.len(),
+        entries = rows.len(),
+        elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        "files merged"
+    );
+
+    build_rows(rows, options)
+}
+
+/// Load store from a URL, trying each fallback URL in turn if it fails.
+async fn load_url_with_fallbacks(
+    url: &str,
+    old_metadata: Option<&SourceMetadata>,
+    options: &LoadOptions,
+) -> Result<Option<(Loaded, SourceMetadata)>> {
+    let mut result = load_url(url, old_metadata, options).await;
+    for fallback in &options.fallback_urls {
+        let Err(e) = &result else {
+            break;
+        };
+        warn!(error = %e, fallback = %fallback, "Source failed, trying fallback URL");
+        result = load_url(fallback, old_metadata, &options.for_origin(url, fallback)).await;
+    }
+    result
 }
 
 /// Load store from a URL, optionally with conditional headers.
+///
+/// Conditional headers are only sent when `old_metadata` came from the same URL, since
+/// mirrors need not share `ETag`s.
 async fn load_url(
     url: &str,
     old_metadata: Option<&SourceMetadata>,
-) -> Result<Option<(ActiveStore, SourceMetadata)>> {
-    let mut request = HTTP_CLIENT.get(url);
+    options: &LoadOptions,
+) -> Result<Option<(Loaded, SourceMetadata)>> {
+    let mut request =
+        http_get(url, options).header("Accept-Encoding", compression::ACCEPT_ENCODING);
 
-    if let Some(meta) = old_metadata {
+    if let Some(meta) = old_metadata
+        && meta.origin.as_deref().is_none_or(|origin| origin == url)
+    {
         if let Some(etag) = &meta.etag {
             request = request.header("If-None-Match", etag);
         }
@@ -130,7 +770,7 @@ async fn load_url(
     }
 
     let start = Instant::now();
-    let response = request.send().await?;
+    let response = send_with_retry(request, &options.retry).await?;
 
     if response.status() == reqwest::StatusCode::NOT_MODIFIED {
         return Ok(None);
@@ -155,14 +795,482 @@ async fn load_url(
             .get("last-modified")
             .and_then(|v| v.to_str().ok())
             .map(ToString::to_string),
+        origin: Some(url.to_string()),
     };
 
-    let content = response.bytes().await?.to_vec();
+    let format = options
+        .format
+        .or_else(|| InputFormat::from_url(url))
+        .or_else(|| {
+            response
+                .headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .and_then(InputFormat::from_content_type)
+        })
+        .unwrap_or_default();
+
+    check_response(&response, options)?;
+    let content = fetch_body(response, options.progress.as_ref(), options.max_size).await?;
     info!(
         elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
         "HTTP fetch completed"
     );
 
-    let store = spawn_build(content).await?;
-    Ok(Some((store, new_metadata)))
+    let loaded = spawn_build(content, format, options).await?;
+    Ok(Some((loaded, new_metadata)))
+}
+
+/// An object downloaded from cloud storage.
+pub(crate) struct FetchedObject {
+    pub content: Vec<u8>,
+    pub metadata: SourceMetadata,
+    pub content_type: Option<String>,
+}
+
+/// Download a cloud storage object, returning `None` if its `ETag` is unchanged.
+#[cfg_attr(
+    not(any(feature = "s3", feature = "gcs", feature = "azure")),
+    allow(unused_variables, clippy::unused_async)
+)]
+async fn fetch_object(
+    source: &DataSource,
+    old_metadata: Option<&SourceMetadata>,
+    progress: Option<&ProgressReporter>,
+) -> Result<Option<FetchedObject>> {
+    #[cfg(not(all(feature = "s3", feature = "gcs", feature = "azure")))]
+    let feature_required = |feature: &str| {
+        LoadError::InvalidFormat(format!(
+            "{source}: this source requires the `{feature}` feature"
+        ))
+    };
+
+    match source {
+        #[cfg(feature = "s3")]
+        DataSource::S3 { bucket, key } => {
+            crate::s3::get_object(bucket, key, old_metadata, progress).await
+        }
+        #[cfg(not(feature = "s3"))]
+        DataSource::S3 { .. } => Err(feature_required("s3")),
+        #[cfg(feature = "gcs")]
+        DataSource::Gcs { bucket, key } => {
+            crate::cloud::get_object(
+                crate::cloud::Provider::Gcs,
+                bucket,
+                key,
+                old_metadata,
+                progress,
+            )
+            .await
+        }
+        #[cfg(not(feature = "gcs"))]
+        DataSource::Gcs { .. } => Err(feature_required("gcs")),
+        #[cfg(feature = "azure")]
+        DataSource::Azure { container, key } => {
+            crate::cloud::get_object(
+                crate::cloud::Provider::Azure,
+                container,
+                key,
+                old_metadata,
+                progress,
+            )
+            .await
+        }
+        #[cfg(not(feature = "azure"))]
+        DataSource::Azure { .. } => Err(feature_required("azure")),
+        DataSource::File(_) | DataSource::Glob(_) | DataSource::Url(_) => Err(
+            LoadError::InvalidFormat(format!("{source} is not a cloud storage source")),
+        ),
+    }
+}
+
+/// Load store from a cloud storage object, skipping the download if its `ETag` is unchanged.
+async fn load_object(
+    source: &DataSource,
+    old_metadata: Option<&SourceMetadata>,
+    options: &LoadOptions,
+) -> Result<Option<(Loaded, SourceMetadata)>> {
+    let start = Instant::now();
+    let Some(object) = fetch_object(source, old_metadata, options.progress.as_ref()).await? else {
+        return Ok(None);
+    };
+    info!(
+        elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        "Object fetch completed"
+    );
+
+    let format = options
+        .format
+        .or_else(|| InputFormat::from_path(std::path::Path::new(&source.to_string())))
+        .or_else(|| {
+            object
+                .content_type
+                .as_deref()
+                .and_then(InputFormat::from_content_type)
+        })
+        .unwrap_or_default();
+
+    let loaded = spawn_build(object.content, format, options).await?;
+    Ok(Some((loaded, object.metadata)))
+}
+
+/// Reject a response whose content type is not allowed or whose declared size is too large.
+///
+/// Responses without a `Content-Type` header are accepted.
+pub(crate) fn check_response(response: &reqwest::Response, options: &LoadOptions) -> Result<()> {
+    if !options.allowed_content_types.is_empty()
+        && let Some(content_type) = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+    {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if !options
+            .allowed_content_types
+            .iter()
+            .any(|allowed| allowed.trim().eq_ignore_ascii_case(media_type))
+        {
+            return Err(LoadError::HttpError(format!(
+                "{}: content type {media_type:?} is not allowed",
+                response.url()
+            )));
+        }
+    }
+
+    if let (Some(max_size), Some(length)) = (options.max_size, response.content_length())
+        && length > max_size
+    {
+        return Err(LoadError::HttpError(format!(
+            "{}: response of {length} bytes exceeds the {max_size} byte limit",
+            response.url()
+        )));
+    }
+    Ok(())
+}
+
+/// Read a response body, reporting download progress and stopping at `max_size` bytes.
+pub(crate) async fn fetch_body(
+    mut response: reqwest::Response,
+    progress: Option<&ProgressReporter>,
+    max_size: Option<u64>,
+) -> Result<Vec<u8>> {
+    let total_bytes = response.content_length();
+    if let Some(progress) = progress {
+        progress.start(total_bytes);
+    }
+
+    let mut content = Vec::with_capacity(
+        total_bytes
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(0),
+    );
+    while let Some(chunk) = response.chunk().await? {
+        if let Some(max_size) = max_size
+            && (content.len() + chunk.len()) as u64 > max_size
+        {
+            return Err(LoadError::HttpError(format!(
+                "{}: response exceeds the {max_size} byte limit",
+                response.url()
+            )));
+        }
+        content.extend_from_slice(&chunk);
+        if let Some(progress) = progress {
+            progress.add_bytes(chunk.len() as u64);
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    #[test]
+    fn test_options_for_source() {
+        let old = DataSource::parse("https://example.com/v1/data.csv");
+        let new = DataSource::parse("https://example.com/v2/data.csv");
+        let options = LoadOptions {
+            checksum: Some(old.sidecar(".sha256")),
+            ..LoadOptions::default()
+        };
+        assert_eq!(
+            options.for_source(&old, &new).checksum,
+            Some(new.sidecar(".sha256"))
+        );
+
+        // Explicitly configured sidecars stay where they are
+        let pinned = DataSource::parse("https://example.com/checksums/data.sha256");
+        let options = LoadOptions {
+            checksum: Some(pinned.clone()),
+            ..LoadOptions::default()
+        };
+        assert_eq!(options.for_source(&old, &new).checksum, Some(pinned));
+    }
+
+    /// Serve raw HTTP responses on a local port, one per connection, returning its URL
+    /// and the head of each request.
+    pub(crate) fn serve<R: AsRef<[u8]> + Send + 'static>(
+        responses: Vec<R>,
+    ) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/data.csv", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut head = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+                stream.write_all(response.as_ref()).unwrap();
+                tx.send(head).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    /// Serve a single `200 OK` response with `body`.
+    fn serve_once(body: &'static str) -> (String, mpsc::Receiver<String>) {
+        serve(vec![ok(body)])
+    }
+
+    pub(crate) fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_url_source_auth_and_headers() {
+        let body = "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,1\n";
+        let (url, head) = serve_once(body);
+        let options = LoadOptions {
+            auth: Some(SourceAuth::Bearer("t0ken".into())),
+            headers: [crate::source::parse_header("X-Api-Key: k3y").unwrap()]
+                .into_iter()
+                .collect(),
+            ..LoadOptions::default()
+        };
+
+        let (store, _) = load_with_options(&DataSource::Url(url), None, &options)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(store.len(), 1);
+        let head = head.recv().unwrap().to_ascii_lowercase();
+        assert!(head.contains("authorization: bearer t0ken"), "{head}");
+        assert!(head.contains("x-api-key: k3y"), "{head}");
+    }
+
+    #[tokio::test]
+    async fn test_url_source_through_proxy() {
+        let body = "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,1\n";
+        let (proxy_url, head) = serve_once(body);
+        let http = HttpOptions {
+            proxy: Some(proxy_url.trim_end_matches("/data.csv").to_string()),
+            ..HttpOptions::default()
+        };
+        let options = LoadOptions {
+            client: Some(http.build_client().unwrap()),
+            ..LoadOptions::default()
+        };
+
+        let source = DataSource::parse("http://origin.invalid/data.csv");
+        let loaded = load_with_options(&source, None, &options).await.unwrap();
+        assert!(loaded.is_some());
+        let head = head.recv().unwrap();
+        assert!(
+            head.starts_with("GET http://origin.invalid/data.csv"),
+            "{head}"
+        );
+    }
+
+    #[test]
+    fn test_http_options_invalid_files() {
+        let ca = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(ca.path(), "not a certificate").unwrap();
+        let http = HttpOptions {
+            client_cert: Some(ca.path().to_path_buf()),
+            ..HttpOptions::default()
+        };
+        assert!(http.build_client().is_err());
+
+        let http = HttpOptions {
+            ca_bundle: Some("/nonexistent/ca.pem".into()),
+            ..HttpOptions::default()
+        };
+        assert!(matches!(http.build_client(), Err(LoadError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_url_source_retries() {
+        let body = "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,1\n";
+        let unavailable = "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\n\
+                           Content-Length: 0\r\nConnection: close\r\n\r\n";
+        let (url, heads) = serve(vec![unavailable.to_string(), ok(body)]);
+        let options = LoadOptions {
+            retry: RetryPolicy {
+                max_retries: 1,
+                ..RetryPolicy::default()
+            },
+            ..LoadOptions::default()
+        };
+
+        let loaded = load_with_options(&DataSource::Url(url.clone()), None, &options)
+            .await
+            .unwrap();
+        assert!(loaded.is_some());
+        assert_eq!(heads.iter().take(2).count(), 2);
+
+        // Without retries the 503 fails the load
+        let (url, _) = serve(vec![unavailable.to_string()]);
+        let options = LoadOptions {
+            retry: RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            },
+            ..LoadOptions::default()
+        };
+        let err = load_with_options(&DataSource::Url(url), None, &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("503"), "{err}");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for attempt in 0..3 {
+            let delay = policy.delay(attempt, None).unwrap();
+            assert!(delay <= Duration::from_millis(100 << attempt), "{delay:?}");
+        }
+        assert_eq!(policy.delay(3, None), None);
+
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_millis(700))),
+            Some(Duration::from_millis(700))
+        );
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(5))), None);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_url_source_fallback() {
+        let body = "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,1\n";
+        // Nothing listens on the primary port once the listener is dropped
+        let primary = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/data.csv", listener.local_addr().unwrap())
+        };
+        let (fallback, _) = serve_once(body);
+        let options = LoadOptions {
+            retry: RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            },
+            fallback_urls: vec![fallback.clone()],
+            ..LoadOptions::default()
+        };
+
+        let (store, metadata) = load_with_options(&DataSource::Url(primary), None, &options)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(metadata.origin, Some(fallback));
+    }
+
+    #[test]
+    fn test_options_for_origin() {
+        let url = "https://a.example/data.csv";
+        let options = LoadOptions {
+            checksum: Some(DataSource::parse(url).sidecar(".sha256")),
+            ..LoadOptions::default()
+        };
+        let relocated = options.for_origin(url, "https://b.example/data.csv");
+        assert_eq!(
+            relocated.checksum.unwrap().to_string(),
+            "https://b.example/data.csv.sha256"
+        );
+
+        let options = LoadOptions {
+            checksum: Some(DataSource::parse("https://keys.example/data.sha256")),
+            ..LoadOptions::default()
+        };
+        let relocated = options.for_origin(url, "https://b.example/data.csv");
+        assert_eq!(
+            relocated.checksum.unwrap().to_string(),
+            "https://keys.example/data.sha256"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_url_source_limits() {
+        let body = "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,1\n";
+        let html = "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                    Content-Length: 6\r\nConnection: close\r\n\r\n<html>";
+        let (url, _) = serve(vec![html.to_string()]);
+        let options = LoadOptions {
+            allowed_content_types: vec!["text/csv".to_string()],
+            ..LoadOptions::default()
+        };
+        let err = load_with_options(&DataSource::Url(url), None, &options)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("\"text/html\" is not allowed"),
+            "{err}"
+        );
+
+        let (url, _) = serve_once(body);
+        let options = LoadOptions {
+            max_size: Some(10),
+            ..LoadOptions::default()
+        };
+        let err = load_with_options(&DataSource::Url(url), None, &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("byte limit"), "{err}");
+
+        // The limit also applies to the data once decompressed
+        let bomb = zstd::encode_all(vec![b'\n'; 1 << 20].as_slice(), 19).unwrap();
+        let options = LoadOptions {
+            max_size: Some(64 * 1024),
+            ..LoadOptions::default()
+        };
+        assert!(bomb.len() < 64 * 1024);
+        let Err(err) = load_bytes(bomb, InputFormat::Csv, &options).await else {
+            panic!("decompressed data over the limit loaded");
+        };
+        assert!(err.to_string().contains("once decompressed"), "{err}");
+    }
 }
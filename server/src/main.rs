@@ -1,27 +1,73 @@
-#[macro_use]
-extern crate rocket;
-
 #[cfg(feature = "jemalloc")]
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 use clap::{Parser, ValueEnum};
-use occlusion::{Store, SwappableStore};
-use rocket::figment::Figment;
+use ipnet::IpNet;
+use occlusion::{ActiveStore, Store, SwappableStore, TieredStore};
+use reqwest::header::{HeaderName, HeaderValue};
+use rocket::{Build, Rocket, figment::Figment};
 use server::{
     ReloadState,
+    access_log::{AccessLog, AccessLogOptions, Rotation},
+    admission::{ConcurrencyLimits, RejectStale},
+    audit::AuditLog,
+    build_thread::BuildThread,
+    cache::{CacheMaxAge, GenerationHeader},
+    canary::{Canary, CanaryOptions},
+    catchers,
+    decisions::{DecisionLog, DecisionLogOptions, DecisionSink},
+    delta::{self, DeltaOptions, DeltaOutcome},
     error::Result,
+    events::Events,
     fairing::RequestTimer,
-    loader::load,
-    routes,
-    source::{DataSource, SourceMetadata},
+    format::{CsvColumn, CsvOptions, ErrorBudget, InputFormat, UuidByteOrder, parse_delimiter},
+    gossip::{Fleet, Gossip, PeerDns},
+    guards::{self, AdminAllowlist, AdminToken},
+    ids::IdNamespace,
+    integrity::{SignatureCheck, parse_public_key},
+    jwt::{JwtOptions, JwtVerifier},
+    latency::Latencies,
+    leader::PeerFetch,
+    levels::LevelNames,
+    loader::{HttpOptions, LoadOptions, RetryPolicy, load_routed},
+    models::LoadMetrics,
+    namespace::{self, Namespace, Namespaces},
+    progress::{ProgressReporter, log_progress},
+    remote::HttpAuthority,
+    request_id::RequestIds,
+    route_groups::RouteGroup,
+    routes::MaxBatchSize,
+    save_snapshot,
+    scheduler::{
+        Backoff, BackoffCurve, CheckResult, CronSchedule, NamespaceSchedulers, ReloadWindow,
+        Scheduler,
+    },
+    snapshot::{self, Snapshot},
+    source::{self, DataSource, SourceAuth, SourceMetadata},
+    subjects::SubjectMasks,
+    systemd::{self, SystemdNotify},
+    timeout::RequestTimeout,
+    validation::{self, Diff, ValidationGates},
+    watch,
+    webhooks::{ReloadReport, Webhooks},
+};
+#[cfg(feature = "tls")]
+use server::{
+    cert_reload::{CertFiles, CertReloader},
+    client_cert::{CertMasks, ClientCertMasks, ExcessMask},
 };
 use std::{
-    sync::{Arc, RwLock},
-    time::Duration,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
 };
-use tracing::{error, info};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use uuid::Uuid;
 
 /// Action to take when max reload failures is exceeded.
 #[derive(Debug, Clone, Copy, Default, ValueEnum)]
@@ -31,6 +77,21 @@ enum FailureAction {
     Shutdown,
     /// Clear the store (replace with empty data)
     Clear,
+    /// Load the known-good local data in --fallback-file, and flag the server as degraded
+    FallbackFile,
+    /// Keep serving the current data, and flag the server as degraded
+    KeepStaleWithAlarm,
+}
+
+impl FailureAction {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Shutdown => "shutdown",
+            Self::Clear => "clear",
+            Self::FallbackFile => "fallback-file",
+            Self::KeepStaleWithAlarm => "keep-stale-with-alarm",
+        }
+    }
 }
 
 /// High-performance authorization server for UUID visibility lookups
@@ -38,15 +99,271 @@ enum FailureAction {
 #[command(name = "occlusion")]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Path to CSV file or URL (http:// or https://)
+    /// Path to CSV file, directory or glob, URL (http:// or https://) or cloud object (s3://, gs://, az://)
     #[cfg(not(feature = "static-url"))]
     #[arg(value_name = "DATA_SOURCE", env = "OCCLUSION_DATA_SOURCE")]
     data_source: String,
 
+    /// Additional data source served under its own namespace (NAME=SOURCE, repeatable)
+    #[arg(
+        long = "data-source",
+        value_name = "NAME=SOURCE",
+        value_parser = namespace::parse_spec
+    )]
+    namespaced_sources: Vec<(String, String)>,
+
+    /// Input format of the data source (auto-detected from extension/content-type if unset)
+    #[arg(long, value_enum, env = "OCCLUSION_FORMAT")]
+    format: Option<InputFormat>,
+
+    /// CSV field delimiter (a single ASCII character, or `tab`)
+    #[arg(long, default_value = ",", value_parser = parse_delimiter, env = "OCCLUSION_CSV_DELIMITER")]
+    csv_delimiter: u8,
+
+    /// CSV input has no header row (columns must then be selected by position)
+    #[arg(long, env = "OCCLUSION_CSV_NO_HEADER")]
+    csv_no_header: bool,
+
+    /// CSV column holding the UUID: a header name or a zero-based position
+    #[arg(long, default_value = "uuid", env = "OCCLUSION_CSV_UUID_COLUMN")]
+    csv_uuid_column: CsvColumn,
+
+    /// CSV column holding the visibility level: a header name or a zero-based position
+    #[arg(
+        long,
+        default_value = "visibility_level",
+        env = "OCCLUSION_CSV_LEVEL_COLUMN"
+    )]
+    csv_level_column: CsvColumn,
+
+    /// Optional column routing each row of the default data source to a namespace:
+    /// a header name or a zero-based position
+    #[arg(
+        long,
+        default_value = "namespace",
+        env = "OCCLUSION_CSV_NAMESPACE_COLUMN"
+    )]
+    csv_namespace_column: CsvColumn,
+
+    /// JSON file mapping role names to visibility levels, e.g. `{"public": 0, "internal": 5}`,
+    /// so text and JSON data can use the names instead of numbers
+    #[arg(long, value_name = "PATH", env = "OCCLUSION_LEVEL_NAMES")]
+    level_names: Option<PathBuf>,
+
+    /// Byte order of UUIDs given as raw bytes (binary columns, or 32 hex digits without dashes)
+    #[arg(long, value_enum, default_value_t, env = "OCCLUSION_UUID_BYTE_ORDER")]
+    uuid_byte_order: UuidByteOrder,
+
+    /// JSON pointer to the UUID to level object in an OPA bundle's data, e.g. `/occlusion/levels`
+    #[arg(long, default_value = "", env = "OCCLUSION_BUNDLE_POINTER")]
+    bundle_pointer: String,
+
+    /// Accept non-UUID object IDs, mapping them to UUIDv5 hashes under this namespace UUID
+    /// in the data and in check requests alike
+    #[arg(long, value_name = "UUID", env = "OCCLUSION_ID_NAMESPACE")]
+    id_namespace: Option<Uuid>,
+
+    /// Skip malformed rows instead of failing the load, up to N rows or N% of rows
+    #[arg(long, value_name = "N|N%", env = "OCCLUSION_ERROR_BUDGET")]
+    error_budget: Option<ErrorBudget>,
+
+    /// Verify the data against a `<DATA_SOURCE>.sha256` sidecar before loading
+    #[arg(long, env = "OCCLUSION_VERIFY_CHECKSUM")]
+    verify_checksum: bool,
+
+    /// Path or URL of the SHA-256 sidecar (implies --verify-checksum)
+    #[arg(long, env = "OCCLUSION_CHECKSUM_SOURCE")]
+    checksum_source: Option<String>,
+
+    /// Refuse data whose detached ed25519 signature (`<DATA_SOURCE>.sig`) doesn't verify
+    /// against this public key (32 bytes, hex or base64)
+    #[arg(
+        long,
+        value_name = "PUBLIC_KEY",
+        env = "OCCLUSION_SIGNATURE_PUBLIC_KEY"
+    )]
+    verify_signature: Option<String>,
+
+    /// Path or URL of the detached signature
+    #[arg(long, env = "OCCLUSION_SIGNATURE_SOURCE")]
+    signature_source: Option<String>,
+
+    /// Mirror of the URL source, tried in order when the source fails (repeatable)
+    #[arg(
+        long = "fallback-url",
+        value_name = "URL",
+        env = "OCCLUSION_FALLBACK_URLS",
+        value_delimiter = ' '
+    )]
+    fallback_urls: Vec<String>,
+
+    /// On reload, fetch only the changes since the current version (URL sources)
+    #[arg(long, env = "OCCLUSION_DELTA")]
+    delta: bool,
+
+    /// Endpoint serving delta patches (defaults to the source URL; implies --delta)
+    #[arg(long, value_name = "URL", env = "OCCLUSION_DELTA_URL")]
+    delta_url: Option<String>,
+
+    /// Pending delta changes after which a full reload compacts the store
+    #[arg(long, default_value_t = delta::DEFAULT_MAX_PENDING, env = "OCCLUSION_DELTA_MAX_PENDING")]
+    delta_max_pending: usize,
+
+    /// Maximum size of a URL source response, e.g. 512M or 8G (unlimited when unset)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, env = "OCCLUSION_MAX_SOURCE_SIZE")]
+    max_source_size: Option<u64>,
+
+    /// Content types accepted from URL sources (comma-separated, any when unset)
+    #[arg(
+        long,
+        value_name = "TYPES",
+        env = "OCCLUSION_ALLOWED_CONTENT_TYPES",
+        value_delimiter = ','
+    )]
+    allowed_content_types: Vec<String>,
+
+    /// Bearer token sent with requests to URL sources
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        env = "OCCLUSION_SOURCE_AUTH_BEARER",
+        conflicts_with = "source_auth_basic"
+    )]
+    source_auth_bearer: Option<String>,
+
+    /// Basic auth credentials sent with requests to URL sources
+    #[arg(
+        long,
+        value_name = "USER:PASSWORD",
+        env = "OCCLUSION_SOURCE_AUTH_BASIC"
+    )]
+    source_auth_basic: Option<String>,
+
+    /// Extra header sent with requests to URL sources ('Name: value', repeatable)
+    #[arg(
+        long = "source-header",
+        value_name = "HEADER",
+        value_parser = source::parse_header,
+        env = "OCCLUSION_SOURCE_HEADERS",
+        value_delimiter = '\n'
+    )]
+    source_headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Total timeout in seconds for a URL source request, including the body
+    #[arg(long, default_value = "30", env = "OCCLUSION_HTTP_TIMEOUT")]
+    http_timeout: u64,
+
+    /// Connect timeout in seconds for URL source requests
+    #[arg(long, default_value = "10", env = "OCCLUSION_HTTP_CONNECT_TIMEOUT")]
+    http_connect_timeout: u64,
+
+    /// Maximum seconds between two reads of a URL source response (no limit when unset)
+    #[arg(long, env = "OCCLUSION_HTTP_READ_TIMEOUT")]
+    http_read_timeout: Option<u64>,
+
+    /// Retries of failed URL source requests (connection errors, timeouts, 429, 5xx)
+    #[arg(long, default_value = "2", env = "OCCLUSION_HTTP_RETRIES")]
+    http_retries: u32,
+
+    /// Backoff ceiling in milliseconds of the first retry (doubled per retry, jittered)
+    #[arg(
+        long,
+        default_value = "500",
+        env = "OCCLUSION_HTTP_RETRY_BASE_DELAY_MS"
+    )]
+    http_retry_base_delay_ms: u64,
+
+    /// Maximum delay in seconds before a retry, including Retry-After
+    #[arg(long, default_value = "30", env = "OCCLUSION_HTTP_RETRY_MAX_DELAY")]
+    http_retry_max_delay: u64,
+
+    /// Proxy URL for requests to URL sources (defaults to HTTP(S)_PROXY)
+    #[arg(long, value_name = "URL", env = "OCCLUSION_SOURCE_PROXY")]
+    source_proxy: Option<String>,
+
+    /// PEM bundle of additional CA certificates trusted for URL sources
+    #[arg(long, value_name = "PATH", env = "OCCLUSION_SOURCE_CA_BUNDLE")]
+    source_ca_bundle: Option<PathBuf>,
+
+    /// PEM client certificate (optionally including the key) for mTLS to URL sources
+    #[arg(long, value_name = "PATH", env = "OCCLUSION_SOURCE_CLIENT_CERT")]
+    source_client_cert: Option<PathBuf>,
+
+    /// PEM private key for --source-client-cert, if not in the same file
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "OCCLUSION_SOURCE_CLIENT_KEY",
+        requires = "source_client_cert"
+    )]
+    source_client_key: Option<PathBuf>,
+
+    /// Directory for last-good snapshots, used to start when the data source is unreachable
+    #[arg(long, value_name = "DIR", env = "OCCLUSION_SNAPSHOT_DIR")]
+    snapshot_dir: Option<PathBuf>,
+
+    /// Reject loaded data with fewer entries than this
+    #[arg(long, value_name = "COUNT", env = "OCCLUSION_MIN_ENTRIES")]
+    min_entries: Option<usize>,
+
+    /// Reject reloads adding, removing or changing more than this percentage of entries
+    #[arg(long, value_name = "PERCENT", env = "OCCLUSION_MAX_CHURN")]
+    max_churn: Option<f64>,
+
+    /// Reject reloads moving the share of level-0 entries by more than this many percentage points
+    #[arg(long, value_name = "PERCENT", env = "OCCLUSION_MAX_LEVEL0_SHIFT")]
+    max_level0_shift: Option<f64>,
+
+    /// Reject data whose store would use more heap memory than this, e.g. 2G (also checked at
+    /// startup)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, env = "OCCLUSION_MEMORY_BUDGET")]
+    memory_budget: Option<u64>,
+
+    /// Load and validate the data sources, print a JSON summary and exit
+    ///
+    /// Exits 0 if every source is valid, 1 if one fails to load and 3 if one is rejected by
+    /// a validation gate. Sources are compared against their --snapshot-dir snapshots, if any.
+    #[arg(long)]
+    validate_and_exit: bool,
+
     /// Reload interval in minutes (0 = no auto-reload)
     #[arg(long, default_value = "60", env = "OCCLUSION_RELOAD_INTERVAL")]
     reload_interval: u64,
 
+    /// Reload at the times matching this cron expression (UTC) instead of every
+    /// --reload-interval minutes, e.g. "0 */2 * * *"
+    #[arg(long, value_name = "EXPR", env = "OCCLUSION_RELOAD_CRON")]
+    reload_cron: Option<CronSchedule>,
+
+    /// Randomly shorten or lengthen the time to each reload check and retry by up to this
+    /// percentage, so that servers started together do not all hit the source at once
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value = "0",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        env = "OCCLUSION_RELOAD_JITTER"
+    )]
+    reload_jitter: u8,
+
+    /// Only reload automatically (on schedule or on file changes) inside these daily windows
+    /// (UTC), e.g. "02:00-05:00,22:00-23:00"; admin reloads are always allowed
+    #[arg(
+        long = "reload-window",
+        value_name = "HH:MM-HH:MM",
+        value_delimiter = ',',
+        env = "OCCLUSION_RELOAD_WINDOWS"
+    )]
+    reload_windows: Vec<ReloadWindow>,
+
+    /// Reload file, directory and glob sources as soon as they change on disk
+    #[arg(long, env = "OCCLUSION_WATCH")]
+    watch: bool,
+
+    /// Quiet period in milliseconds after the last file change before reloading
+    #[arg(long, default_value = "500", env = "OCCLUSION_WATCH_DEBOUNCE_MS")]
+    watch_debounce_ms: u64,
+
     /// Maximum consecutive reload failures before taking action (0 = unlimited)
     #[arg(long, default_value = "0", env = "OCCLUSION_MAX_RELOAD_FAILURES")]
     max_reload_failures: u32,
@@ -55,6 +372,451 @@ struct Args {
     #[arg(long, default_value = "shutdown", env = "OCCLUSION_ON_MAX_FAILURES")]
     on_max_failures: FailureAction,
 
+    /// Before swapping a reloaded store in, look up this many UUIDs of the data it replaces in
+    /// it, so that the first requests do not hit a cold store (0 = no warm-up)
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "0",
+        env = "OCCLUSION_PREWARM_LOOKUPS"
+    )]
+    prewarm_lookups: usize,
+
+    /// After each reload of the default data source, make this share of the decisions again on
+    /// the previous data, rolling the reload back if too many differ (0 = no evaluation)
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value = "0",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        env = "OCCLUSION_CANARY_SAMPLE"
+    )]
+    canary_sample: u8,
+
+    /// How long after a reload decisions are evaluated, e.g. 10m
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "5m",
+        value_parser = parse_duration,
+        env = "OCCLUSION_CANARY_WINDOW"
+    )]
+    canary_window: Duration,
+
+    /// Roll a reload back when more than this share of the sampled decisions differ
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value = "5",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        env = "OCCLUSION_CANARY_MAX_DIVERGENCE"
+    )]
+    canary_max_divergence: u8,
+
+    /// Decisions sampled after a reload before it can be rolled back
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "100",
+        env = "OCCLUSION_CANARY_MIN_SAMPLES"
+    )]
+    canary_min_samples: u64,
+
+    /// Retry loading a data source this many times at startup, backing off as for reloads,
+    /// before restoring a snapshot or giving up
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "0",
+        env = "OCCLUSION_STARTUP_RETRY"
+    )]
+    startup_retry: u32,
+
+    /// Start with an empty store, unready, when a data source cannot be loaded at startup, and
+    /// keep trying to load it in the background
+    #[arg(long, env = "OCCLUSION_ALLOW_EMPTY_START")]
+    allow_empty_start: bool,
+
+    /// Mark the server unready when a data source has not been reloaded successfully for this
+    /// long, e.g. 90m, 6h or 7d (a bare number is in seconds)
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        env = "OCCLUSION_MAX_STALENESS"
+    )]
+    max_staleness: Option<Duration>,
+
+    /// Also answer data-plane requests with 503 while their data is older than --max-staleness
+    #[arg(long, requires = "max_staleness", env = "OCCLUSION_REJECT_STALE")]
+    reject_stale: bool,
+
+    /// Local data file loaded by --on-max-failures fallback-file (default data source only)
+    #[arg(
+        long,
+        value_name = "PATH",
+        required_if_eq("on_max_failures", "fallback-file"),
+        env = "OCCLUSION_FALLBACK_FILE"
+    )]
+    fallback_file: Option<PathBuf>,
+
+    /// Seconds to wait before retrying after a first reload failure
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "5",
+        env = "OCCLUSION_RELOAD_BACKOFF_INITIAL"
+    )]
+    reload_backoff_initial: u64,
+
+    /// Longest wait in seconds before retrying a failing reload
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "300",
+        env = "OCCLUSION_RELOAD_BACKOFF_MAX"
+    )]
+    reload_backoff_max: u64,
+
+    /// How the wait before retrying grows with consecutive reload failures
+    #[arg(long, value_enum, default_value_t, env = "OCCLUSION_RELOAD_BACKOFF")]
+    reload_backoff: BackoffCurve,
+
+    /// Factor the wait before retrying is multiplied by after each failure (exponential backoff)
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value = "2",
+        value_parser = parse_multiplier,
+        env = "OCCLUSION_RELOAD_BACKOFF_MULTIPLIER"
+    )]
+    reload_backoff_multiplier: f64,
+
+    /// URLs the reload scheduler posts a JSON report to after each check (comma-separated)
+    #[arg(
+        long = "webhook-url",
+        value_name = "URL",
+        value_delimiter = ',',
+        env = "OCCLUSION_WEBHOOK_URLS"
+    )]
+    webhook_urls: Vec<reqwest::Url>,
+
+    /// Peers to compare data versions with (comma-separated base URLs)
+    #[arg(
+        long = "peer",
+        value_name = "URL",
+        value_delimiter = ',',
+        env = "OCCLUSION_PEERS"
+    )]
+    peers: Vec<reqwest::Url>,
+
+    /// Name resolved to peers every gossip round, e.g. a headless service
+    #[arg(long, value_name = "HOST:PORT", env = "OCCLUSION_PEER_DNS")]
+    peer_dns: Option<PeerDns>,
+
+    /// Seconds between gossip rounds
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..),
+        env = "OCCLUSION_GOSSIP_INTERVAL"
+    )]
+    gossip_interval: u64,
+
+    /// Reload the default data source as soon as a peer reports a newer version of it
+    #[arg(long, env = "OCCLUSION_GOSSIP_RELOAD")]
+    gossip_reload: bool,
+
+    /// URL peers reach this instance at, as they list it (e.g. http://10.0.0.5:8000)
+    #[arg(long, value_name = "URL", env = "OCCLUSION_ADVERTISE_URL")]
+    advertise_url: Option<reqwest::Url>,
+
+    /// Only let the elected leader of the fleet download the default data source; the other
+    /// instances copy its data (requires --advertise-url and a shared --admin-token)
+    #[arg(
+        long,
+        requires = "advertise_url",
+        requires = "admin_token",
+        env = "OCCLUSION_PEER_FETCH"
+    )]
+    peer_fetch: bool,
+
+    /// Occlusion server asked about objects missing from the default data by POST
+    /// /api/v1/check and /api/v1/check/batch, through its level endpoint
+    #[arg(long, value_name = "URL", env = "OCCLUSION_REMOTE_AUTHORITY")]
+    remote_authority: Option<reqwest::Url>,
+
+    /// Seconds the remote authority's answers are cached for
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "60",
+        requires = "remote_authority",
+        env = "OCCLUSION_REMOTE_AUTHORITY_TTL"
+    )]
+    remote_authority_ttl: u64,
+
+    /// Faults to inject into reloads: fail, slow-fetch=DURATION or delay-swap=DURATION, each
+    /// optionally followed by @N (every Nth reload) or @N% (N percent of reloads)
+    #[cfg(feature = "chaos")]
+    #[arg(
+        long,
+        value_name = "FAULT",
+        value_delimiter = ',',
+        env = "OCCLUSION_CHAOS"
+    )]
+    chaos: Vec<server::chaos::Fault>,
+
+    /// Kafka bootstrap servers for the change feed (comma-separated)
+    #[cfg(feature = "kafka")]
+    #[arg(long, env = "OCCLUSION_KAFKA_BROKERS", requires = "kafka_topic")]
+    kafka_brokers: Option<String>,
+
+    /// Kafka topic carrying upsert/delete events
+    #[cfg(feature = "kafka")]
+    #[arg(long, env = "OCCLUSION_KAFKA_TOPIC", requires = "kafka_brokers")]
+    kafka_topic: Option<String>,
+
+    /// Kafka consumer group ID
+    #[cfg(feature = "kafka")]
+    #[arg(long, default_value = "occlusion", env = "OCCLUSION_KAFKA_GROUP_ID")]
+    kafka_group_id: String,
+
+    /// Address to serve the gRPC API on, alongside HTTP (e.g. 0.0.0.0:50051); plaintext, so not
+    /// available with --client-cert-masks
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR", env = "OCCLUSION_GRPC_LISTEN")]
+    #[cfg_attr(feature = "tls", arg(conflicts_with = "client_cert_masks"))]
+    grpc_listen: Option<std::net::SocketAddr>,
+
+    /// Route groups served over HTTP; health checks and probes are always served
+    #[arg(
+        long,
+        value_enum,
+        value_name = "GROUPS",
+        value_delimiter = ',',
+        default_values_t = RouteGroup::ALL,
+        env = "OCCLUSION_ROUTE_GROUPS"
+    )]
+    route_groups: Vec<RouteGroup>,
+
+    /// Serve the Redis-protocol (RESP) API on this address (e.g. 127.0.0.1:6380); plaintext, so
+    /// not available with --client-cert-masks
+    #[arg(long, value_name = "ADDR", env = "OCCLUSION_RESP_LISTEN")]
+    #[cfg_attr(feature = "tls", arg(conflicts_with = "client_cert_masks"))]
+    resp_listen: Option<std::net::SocketAddr>,
+
+    /// File naming the data source to switch to on SIGUSR2, without restarting
+    #[arg(long, value_name = "PATH", env = "OCCLUSION_SOURCE_FILE")]
+    source_file: Option<PathBuf>,
+
+    /// Maximum number of objects in a batch check or filter request (0 = unlimited)
+    #[arg(long, default_value = "10000", env = "OCCLUSION_MAX_BATCH_SIZE")]
+    max_batch_size: usize,
+
+    /// Data-plane (check and lookup) requests served at once; more are rejected with 503
+    /// (0 = unlimited)
+    #[arg(long, default_value = "0", env = "OCCLUSION_MAX_IN_FLIGHT")]
+    max_in_flight: usize,
+
+    /// Admin API requests served at once; more are rejected with 503 (0 = unlimited)
+    #[arg(long, default_value = "0", env = "OCCLUSION_MAX_ADMIN_IN_FLIGHT")]
+    max_admin_in_flight: usize,
+
+    /// Milliseconds a data-plane request may take before it is abandoned with 503, or 408
+    /// while its body is still arriving (0 = no limit)
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value = "0",
+        env = "OCCLUSION_REQUEST_TIMEOUT_MS"
+    )]
+    request_timeout_ms: u64,
+
+    /// Seconds rejected requests are told to wait before retrying (`Retry-After`)
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "1",
+        env = "OCCLUSION_SHED_RETRY_AFTER"
+    )]
+    shed_retry_after: u64,
+
+    /// Seconds caches may reuse GET check, stats and health responses without revalidating
+    /// their `ETag` (0 = always revalidate)
+    #[arg(long, default_value = "0", env = "OCCLUSION_CACHE_MAX_AGE")]
+    cache_max_age: u64,
+
+    /// Replaced generations of the data kept in memory for requests pinned to them with
+    /// `X-Occlusion-Generation` (0 = only the current generation is served)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "0",
+        env = "OCCLUSION_RETAIN_GENERATIONS"
+    )]
+    retain_generations: usize,
+
+    /// Write an access log (one JSON line per request) to this file
+    #[arg(long, value_name = "PATH", env = "OCCLUSION_ACCESS_LOG")]
+    access_log: Option<PathBuf>,
+
+    /// Size past which the access log is rotated (0 = unlimited)
+    #[arg(long, value_name = "SIZE", default_value = "0", value_parser = parse_size, env = "OCCLUSION_ACCESS_LOG_MAX_SIZE")]
+    access_log_max_size: u64,
+
+    /// Time-based rotation of the access log
+    #[arg(long, default_value = "daily", env = "OCCLUSION_ACCESS_LOG_ROTATE")]
+    access_log_rotate: Rotation,
+
+    /// Rotated access log files to keep
+    #[arg(long, default_value = "7", env = "OCCLUSION_ACCESS_LOG_KEEP")]
+    access_log_keep: usize,
+
+    /// Log decisions of the OPA-compatible endpoints, in OPA's decision log format, to a file
+    /// (JSON lines), syslog (`syslog:`, `syslog://host:port`) or an HTTP endpoint (gzipped JSON
+    /// arrays)
+    #[arg(long, value_name = "PATH|URL", env = "OCCLUSION_DECISION_LOG")]
+    decision_log: Option<String>,
+
+    /// Decisions per decision log write or upload
+    #[arg(long, default_value = "100", env = "OCCLUSION_DECISION_LOG_BATCH_SIZE")]
+    decision_log_batch_size: usize,
+
+    /// Longest time in milliseconds a decision waits before being logged
+    #[arg(long, default_value = "1000", env = "OCCLUSION_DECISION_LOG_FLUSH_MS")]
+    decision_log_flush_ms: u64,
+
+    /// JWKS URL of a JWT issuer; when set, check endpoints ignore the requested visibility
+    /// mask and take it from a claim of the caller's bearer token
+    #[arg(long, value_name = "URL", env = "OCCLUSION_JWT_JWKS_URL")]
+    jwt_jwks_url: Option<String>,
+
+    /// JWT claim holding the caller's visibility mask
+    #[arg(
+        long,
+        default_value = "visibility_mask",
+        env = "OCCLUSION_JWT_MASK_CLAIM"
+    )]
+    jwt_mask_claim: String,
+
+    /// Required JWT issuer (`iss`)
+    #[arg(long, env = "OCCLUSION_JWT_ISSUER", requires = "jwt_jwks_url")]
+    jwt_issuer: Option<String>,
+
+    /// Required JWT audience (`aud`)
+    #[arg(long, env = "OCCLUSION_JWT_AUDIENCE", requires = "jwt_jwks_url")]
+    jwt_audience: Option<String>,
+
+    /// Address to serve HTTP on [default: 127.0.0.1]
+    #[arg(long, value_name = "ADDR", env = "OCCLUSION_BIND")]
+    bind: Option<std::net::IpAddr>,
+
+    /// Port to serve HTTP on [default: 8000]
+    #[arg(long, env = "OCCLUSION_PORT")]
+    port: Option<u16>,
+
+    /// Async worker threads serving requests [default: number of CPUs]
+    #[arg(long, env = "OCCLUSION_WORKERS")]
+    workers: Option<usize>,
+
+    /// Threads for blocking work such as store builds and file reads [default: 512]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), env = "OCCLUSION_BLOCKING_THREADS")]
+    blocking_threads: Option<u16>,
+
+    /// Build stores one at a time on a dedicated thread instead of the blocking pool
+    #[arg(long, env = "OCCLUSION_BUILD_THREAD")]
+    build_thread: bool,
+
+    /// Seconds idle HTTP connections are kept open (0 = disable keep-alive) [default: 5]
+    #[arg(long, value_name = "SECS", env = "OCCLUSION_KEEP_ALIVE")]
+    keep_alive: Option<u32>,
+
+    /// Largest JSON request body accepted, e.g. 4M [default: 1M]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, env = "OCCLUSION_JSON_LIMIT")]
+    json_limit: Option<u64>,
+
+    /// PEM certificate chain to serve HTTPS with. When it, the key or the client CA changes,
+    /// the listener restarts, refusing connections until it is bound again
+    #[cfg(feature = "tls")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "OCCLUSION_TLS_CERT",
+        requires = "tls_key"
+    )]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key to serve HTTPS with
+    #[cfg(feature = "tls")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "OCCLUSION_TLS_KEY",
+        requires = "tls_cert"
+    )]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates; when set, clients must present a certificate signed by one of them
+    #[cfg(feature = "tls")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "OCCLUSION_TLS_CLIENT_CA",
+        requires = "tls_cert"
+    )]
+    tls_client_ca: Option<PathBuf>,
+
+    /// JSON file mapping client certificate identities (`dns:`, `uri:`, `email:`, `ou:`) to the
+    /// highest visibility mask they may check with
+    #[cfg(feature = "tls")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "OCCLUSION_CLIENT_CERT_MASKS",
+        requires = "tls_client_ca"
+    )]
+    client_cert_masks: Option<PathBuf>,
+
+    /// What to do with requests for a higher mask than the client certificate allows
+    #[cfg(feature = "tls")]
+    #[arg(long, default_value = "clamp", env = "OCCLUSION_CLIENT_CERT_EXCESS")]
+    client_cert_excess: ExcessMask,
+
+    /// Record every authorization decision (caller, object, mask, outcome, data generation) to
+    /// a file (JSON lines), syslog (`syslog:`, `syslog://host:port`) or an HTTP endpoint
+    #[arg(long, value_name = "PATH|URL", env = "OCCLUSION_AUDIT_LOG")]
+    audit_log: Option<String>,
+
+    /// Decisions per audit log write or upload
+    #[arg(long, default_value = "100", env = "OCCLUSION_AUDIT_LOG_BATCH_SIZE")]
+    audit_log_batch_size: usize,
+
+    /// Longest time in milliseconds a decision waits before being written to the audit log
+    #[arg(long, default_value = "1000", env = "OCCLUSION_AUDIT_LOG_FLUSH_MS")]
+    audit_log_flush_ms: u64,
+
+    /// CSV file of `subject,visibility_mask` rows, enabling checks by subject
+    /// (`/api/v1/check_subject`); re-read at every reload of the default source
+    #[arg(long, value_name = "PATH", env = "OCCLUSION_SUBJECT_MASKS")]
+    subject_masks: Option<PathBuf>,
+
+    /// Bearer token for the admin endpoints (UUID listing); they are disabled when unset
+    #[arg(long, value_name = "TOKEN", env = "OCCLUSION_ADMIN_TOKEN")]
+    admin_token: Option<String>,
+
+    /// Networks allowed to use the admin endpoints, as CIDRs or addresses (comma-separated,
+    /// any when unset)
+    #[arg(
+        long,
+        value_name = "CIDRS",
+        value_parser = guards::parse_network,
+        env = "OCCLUSION_ADMIN_ALLOW",
+        value_delimiter = ','
+    )]
+    admin_allow: Vec<IpNet>,
+
     /// Output logs as JSON
     #[arg(long, env = "OCCLUSION_JSON_LOGS")]
     json_logs: bool,
@@ -69,41 +831,275 @@ const STATIC_DATA_SOURCE: &str = match option_env!("OCCLUSION_STATIC_URL") {
 #[cfg(all(feature = "static-url", not(debug_assertions)))]
 const STATIC_DATA_SOURCE: &str = env!("OCCLUSION_STATIC_URL");
 
+/// Parse a backoff multiplier, at least 1 so that retries never speed up.
+fn parse_multiplier(s: &str) -> std::result::Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor >= 1.0 => Ok(factor),
+        _ => Err(format!(
+            "invalid multiplier {s:?}, expected a number of at least 1"
+        )),
+    }
+}
+
+/// Parse a duration with an optional unit suffix (`s`, `m`, `h`, `d`), e.g. `90m`.
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 60 * 60),
+        Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration {s:?}, expected e.g. 3600, 90m, 6h or 7d"))
+}
+
+/// Parse a byte size with an optional binary suffix (`K`, `M`, `G`, `T`), e.g. `512M`.
+fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 30),
+        Some((i, 't' | 'T')) => (&s[..i], 40),
+        _ => (s, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size {s:?}, expected e.g. 1048576, 512M or 2G"))
+}
+
 /// Initialize tracing subscriber for structured logging
-fn init_tracing(json: bool) {
+///
+/// Logs go to stderr when `stderr` is set, keeping stdout for machine-readable output.
+fn init_tracing(json: bool, stderr: bool) {
     let env_filter =
         tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into());
+    let writer = if stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
 
     if json {
         tracing_subscriber::registry()
             .with(env_filter)
-            .with(tracing_subscriber::fmt::layer().json())
+            .with(tracing_subscriber::fmt::layer().json().with_writer(writer))
             .init();
     } else {
         tracing_subscriber::registry()
             .with(env_filter)
-            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_subscriber::fmt::layer().with_writer(writer))
             .init();
     }
 }
 
+/// Stores loaded from a data source at startup.
+struct InitialLoad {
+    store: SwappableStore,
+    /// Stores fed by the namespace column, keyed by namespace
+    routes: BTreeMap<String, SwappableStore>,
+    metadata: SourceMetadata,
+    metrics: Option<LoadMetrics>,
+}
+
+impl InitialLoad {
+    /// An empty store, served until a source that failed to load at startup can be loaded.
+    fn empty() -> Self {
+        Self {
+            store: SwappableStore::new(
+                occlusion::build_store(vec![]).expect("Failed to build empty store"),
+            ),
+            routes: BTreeMap::new(),
+            metadata: SourceMetadata::new(),
+            metrics: None,
+        }
+    }
+}
+
+/// How hard to try loading a data source at startup.
+#[derive(Debug, Clone, Copy)]
+struct StartupRetry {
+    /// Attempts after the first before giving up
+    retries: u32,
+    backoff: Backoff,
+}
+
 /// Load the store from the data source (async for URL support)
-async fn load_store(source: &DataSource) -> Result<(SwappableStore, SourceMetadata)> {
+async fn load_store(
+    source: &DataSource,
+    options: &LoadOptions,
+    gates: &ValidationGates,
+) -> Result<InitialLoad> {
     info!(source = %source, "Loading authorization store");
 
-    let (store, metadata) = load(source, None)
+    let started = Instant::now();
+    let (loaded, metadata) = load_routed(source, None, options)
         .await?
         .expect("Initial load should always return data");
+    let store = loaded.store;
+    let metrics = options.load_metrics(started.elapsed(), store.len(), &metadata);
+    gates.check_standalone(&store)?;
+    for (name, route) in &loaded.namespaces {
+        gates.check_standalone(route).map_err(|e| {
+            server::error::LoadError::ValidationError(format!("namespace {name}: {e}"))
+        })?;
+    }
 
-    info!(uuid_count = store.len(), "Store loaded successfully");
+    info!(
+        uuid_count = store.len(),
+        routed_namespaces = loaded.namespaces.len(),
+        origin = metadata.origin,
+        "Store loaded successfully"
+    );
 
-    Ok((SwappableStore::new(store), metadata))
+    Ok(InitialLoad {
+        store: SwappableStore::new(store),
+        routes: loaded
+            .namespaces
+            .into_iter()
+            .map(|(name, store)| (name, SwappableStore::new(store)))
+            .collect(),
+        metadata,
+        metrics: Some(metrics),
+    })
 }
 
-/// Initial backoff delay on failure (5 seconds).
-const INITIAL_BACKOFF_SECS: u64 = 5;
-/// Maximum backoff delay (5 minutes).
-const MAX_BACKOFF_SECS: u64 = 300;
+/// Load the store, retrying as `startup` says, saving a snapshot on success and booting from
+/// it on failure.
+///
+/// Restored stores get empty metadata, so the first reload fetches the source unconditionally,
+/// and no load metrics. Snapshots only cover the default store, not routed namespaces.
+async fn load_or_restore(
+    source: &DataSource,
+    options: &LoadOptions,
+    gates: &ValidationGates,
+    snapshot: Option<&Path>,
+    startup: StartupRetry,
+) -> Result<InitialLoad> {
+    let mut attempts = 0;
+    let error = loop {
+        match load_store(source, options, gates).await {
+            Ok(loaded) => {
+                if let Some(path) = snapshot {
+                    let snapshot = Snapshot::new(source.to_string(), loaded.store.entries());
+                    save_snapshot(path.to_path_buf(), snapshot).await;
+                }
+                return Ok(loaded);
+            }
+            Err(e) if attempts < startup.retries => {
+                attempts += 1;
+                let delay = startup.backoff.delay(attempts);
+                warn!(
+                    error = %e,
+                    attempt = attempts,
+                    retries = startup.retries,
+                    retry_in_secs = delay.as_secs(),
+                    "Failed to load data source, retrying"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => break e,
+        }
+    };
+    let Some(path) = snapshot else {
+        return Err(error);
+    };
+
+    error!(error = %error, "Failed to load data source, trying last-good snapshot");
+    let snapshot = match tokio::fs::read(path).await {
+        Ok(bytes) => snapshot::decode(&bytes),
+        Err(e) => Err(e.into()),
+    };
+    match snapshot {
+        Ok(snapshot) if snapshot.source == source.to_string() => {
+            warn!(
+                path = %path.display(),
+                created = %httpdate::fmt_http_date(snapshot.created),
+                age_secs = snapshot.age().as_secs(),
+                uuid_count = snapshot.entries.len(),
+                "Serving stale data from snapshot until the source can be reloaded"
+            );
+            let store = occlusion::build_store(snapshot.entries)?;
+            Ok(InitialLoad {
+                store: SwappableStore::new(store),
+                routes: BTreeMap::new(),
+                metadata: SourceMetadata::new(),
+                metrics: None,
+            })
+        }
+        Ok(snapshot) => {
+            warn!(
+                path = %path.display(),
+                snapshot_source = %snapshot.source,
+                "Ignoring snapshot of a different data source"
+            );
+            Err(error)
+        }
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "No usable snapshot");
+            Err(error)
+        }
+    }
+}
+
+/// Last-good snapshot of `source` at `path` as a store, to validate changes against.
+fn read_baseline(path: &Path, source: &DataSource) -> Option<ActiveStore> {
+    let snapshot = snapshot::read(path).ok()?;
+    if snapshot.source != source.to_string() {
+        return None;
+    }
+    occlusion::build_store(snapshot.entries).ok()
+}
+
+/// Validate every configured source, print a JSON summary and exit.
+async fn validate_and_exit(
+    sources: Vec<(Option<String>, DataSource, LoadOptions, Option<PathBuf>)>,
+    gates: &ValidationGates,
+) -> ! {
+    let mut exit_code = 0;
+    let mut default = None;
+    let mut namespaces = serde_json::Map::new();
+    for (namespace, source, options, snapshot) in sources {
+        let baseline = snapshot.and_then(|path| read_baseline(&path, &source));
+        let report = validation::validate_source(&source, &options, gates, baseline.as_ref()).await;
+        exit_code = exit_code.max(report.status.exit_code());
+        let report = serde_json::to_value(report).expect("report serializes");
+        match namespace {
+            Some(name) => {
+                namespaces.insert(name, report);
+            }
+            None => default = Some(report),
+        }
+    }
+
+    let summary = serde_json::json!({
+        "valid": exit_code == 0,
+        "source": default,
+        "namespaces": namespaces,
+    });
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&summary).expect("summary serializes")
+    );
+    std::process::exit(exit_code);
+}
+
+/// Quiet period after a TLS file change before the certificate is reloaded, so a renewal
+/// written file by file is picked up whole.
+#[cfg(feature = "tls")]
+const CERT_RELOAD_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Interval between load progress log lines.
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Result of recording a failure in the tracker.
 enum FailureResponse {
@@ -113,22 +1109,34 @@ enum FailureResponse {
     MaxExceeded(FailureAction),
 }
 
-/// Tracks consecutive reload failures with exponential backoff.
+/// Tracks consecutive reload failures with backoff.
 struct FailureTracker {
     consecutive: u32,
     max: u32,
     action: FailureAction,
+    backoff: Backoff,
+    /// Data loaded by [`FailureAction::FallbackFile`]
+    fallback_file: Option<PathBuf>,
 }
 
 impl FailureTracker {
-    fn new(max: u32, action: FailureAction) -> Self {
+    fn new(max: u32, action: FailureAction, backoff: Backoff) -> Self {
         Self {
             consecutive: 0,
             max,
             action,
+            backoff,
+            fallback_file: None,
         }
     }
 
+    /// Load `path` when the action is [`FailureAction::FallbackFile`]; without one, that
+    /// action keeps the stale data.
+    fn with_fallback_file(mut self, path: Option<PathBuf>) -> Self {
+        self.fallback_file = path;
+        self
+    }
+
     fn reset(&mut self) {
         self.consecutive = 0;
     }
@@ -139,10 +1147,7 @@ impl FailureTracker {
         if self.max > 0 && self.consecutive >= self.max {
             FailureResponse::MaxExceeded(self.action)
         } else {
-            // Exponential backoff: 5s, 10s, 20s, 40s, ... capped at MAX_BACKOFF_SECS
-            let backoff_secs =
-                (INITIAL_BACKOFF_SECS << (self.consecutive - 1)).min(MAX_BACKOFF_SECS);
-            FailureResponse::Backoff(Duration::from_secs(backoff_secs))
+            FailureResponse::Backoff(self.backoff.delay(self.consecutive))
         }
     }
 
@@ -151,53 +1156,207 @@ impl FailureTracker {
     }
 }
 
-/// Spawn the reload scheduler task with exponential backoff on failures.
+/// Swap the data of the local file at `path` into `store`, read with the source's options.
+///
+/// Fallback data is known-good, so it skips the validation gates. The current data is kept
+/// if the file fails to load. Returns whether the fallback data was swapped in.
+async fn load_fallback(store: &SwappableStore, reload_state: &ReloadState, path: &Path) -> bool {
+    let fallback = DataSource::File(path.to_path_buf());
+    let options = reload_state
+        .options()
+        .for_source(&reload_state.source(), &fallback);
+    match server::loader::load_with_options(&fallback, None, &options).await {
+        Ok(Some((data, _))) => {
+            error!(
+                path = %path.display(),
+                uuid_count = data.len(),
+                "Serving fallback data due to reload failures"
+            );
+            if let Some(canary) = &reload_state.canary {
+                canary.stop();
+            }
+            store.swap(data);
+            reload_state.announce_swap(store, None);
+            true
+        }
+        Ok(None) => unreachable!("unconditional loads return data"),
+        Err(e) => {
+            error!(
+                path = %path.display(),
+                error = %e,
+                "Failed to load fallback file, keeping stale data"
+            );
+            false
+        }
+    }
+}
+
+/// Keep loading a source that could not be loaded at startup, backing off between attempts,
+/// until it is loaded, here or by another reload. Reloads then follow the usual schedule.
+fn spawn_startup_loader(store: SwappableStore, reload_state: Arc<ReloadState>, backoff: Backoff) {
+    tokio::spawn(async move {
+        let mut failures = 1;
+        loop {
+            tokio::time::sleep(backoff.delay(failures)).await;
+            if reload_state.loaded.load(Ordering::Relaxed) {
+                return;
+            }
+            match reload_state.reload(&store, false).await {
+                Ok(_) => {
+                    info!(
+                        source = %reload_state.source(),
+                        uuid_count = store.len(),
+                        "Data source loaded after failing at startup"
+                    );
+                    return;
+                }
+                Err(e) => {
+                    failures += 1;
+                    warn!(
+                        source = %reload_state.source(),
+                        error = %e,
+                        consecutive_failures = failures,
+                        "Data source still failing to load, serving an empty store"
+                    );
+                }
+            }
+        }
+    });
+}
+
+/// Repoint the default data source to the location named in `path` on each SIGUSR2.
+///
+/// The current data is kept if the new source fails to load or validate.
+fn spawn_source_file_listener(
+    store: SwappableStore,
+    reload_state: Arc<ReloadState>,
+    path: PathBuf,
+) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut signals = match signal(SignalKind::user_defined2()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            error!(error = %e, "Failed to listen for SIGUSR2");
+            std::process::exit(1);
+        }
+    };
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => DataSource::parse(source.trim()),
+                Err(e) => {
+                    error!(path = %path.display(), error = %e, "Failed to read source file");
+                    continue;
+                }
+            };
+            if source == reload_state.source() {
+                info!(source = %source, "SIGUSR2: data source unchanged");
+                continue;
+            }
+            match reload_state.repoint(&store, source).await {
+                Ok(diff) => info!(
+                    added = diff.added,
+                    removed = diff.removed,
+                    changed = diff.changed,
+                    "SIGUSR2: switched data source"
+                ),
+                Err(e) => {
+                    error!(error = %e, "SIGUSR2: new data source rejected, keeping the current one")
+                }
+            }
+        }
+    });
+}
+
+/// Spawn the reload scheduler task, backing off as `failures` says when checks fail.
+///
+/// Its status is published through `scheduler`, which can also trigger a check early. The
+/// outcome of each check is posted to `webhooks`, as that of `namespace`'s source.
 fn spawn_reload_scheduler(
     store: SwappableStore,
     reload_state: Arc<ReloadState>,
-    interval_mins: u64,
-    max_failures: u32,
-    on_max_failures: FailureAction,
+    scheduler: Arc<Scheduler>,
+    mut failures: FailureTracker,
+    webhooks: Option<Webhooks>,
+    namespace: Option<String>,
 ) {
     tokio::spawn(async move {
-        let base_interval = Duration::from_secs(interval_mins * 60);
-        let mut failures = FailureTracker::new(max_failures, on_max_failures);
+        let report = |outcome| ReloadReport::new(outcome, namespace.clone(), &reload_state, &store);
+        let notify = |report| webhooks.as_ref().map(|webhooks| webhooks.notify(report));
 
         // Initial delay before first check
-        tokio::time::sleep(base_interval).await;
+        scheduler.wait_next().await;
 
         loop {
-            info!(source = %reload_state.source, "Checking for data source changes");
+            info!(source = %reload_state.source(), "Checking for data source changes");
 
-            let old_metadata = {
-                let guard = reload_state.metadata.read().expect("RwLock poisoned");
-                guard.clone()
-            };
-
-            match load(&reload_state.source, Some(&old_metadata)).await {
-                Ok(Some((new_store, new_metadata))) => {
-                    let count = new_store.len();
-                    store.swap(new_store);
-
-                    let mut guard = reload_state.metadata.write().expect("RwLock poisoned");
-                    *guard = new_metadata;
+            let mut conditional = true;
+            if reload_state.options().delta.is_some() {
+                match delta::reload(&store, &reload_state).await {
+                    DeltaOutcome::Applied => {
+                        failures.reset();
+                        scheduler.record(CheckResult::DeltaApplied, None, 0, None);
+                        notify(report(CheckResult::DeltaApplied));
+                        scheduler.wait_next().await;
+                        continue;
+                    }
+                    DeltaOutcome::Unchanged => {
+                        failures.reset();
+                        scheduler.record(CheckResult::Unchanged, None, 0, None);
+                        notify(report(CheckResult::Unchanged));
+                        info!("Source unchanged, skipping reload");
+                        scheduler.wait_next().await;
+                        continue;
+                    }
+                    DeltaOutcome::FullReload { conditional: c } => conditional = c,
+                }
+            }
 
+            // Only webhooks report what a reload changed, which takes a pass over the data
+            let result = if webhooks.is_some() {
+                reload_state.reload_with_diff(&store, conditional).await
+            } else {
+                let reloaded = reload_state.reload(&store, conditional).await;
+                reloaded.map(|swapped| swapped.then(Diff::default))
+            };
+            match result {
+                Ok(Some(diff)) => {
                     failures.reset();
-                    info!(uuid_count = count, "Store reloaded successfully");
+                    scheduler.record(CheckResult::Reloaded, None, 0, None);
+                    notify(ReloadReport {
+                        diff: Some(diff),
+                        ..report(CheckResult::Reloaded)
+                    });
                 }
                 Ok(None) => {
                     failures.reset();
+                    scheduler.record(CheckResult::Unchanged, None, 0, None);
+                    notify(report(CheckResult::Unchanged));
                     info!("Source unchanged, skipping reload");
                 }
                 Err(e) => match failures.record() {
                     FailureResponse::Backoff(backoff) => {
+                        let backoff = scheduler.jittered(backoff);
                         error!(
                             error = %e,
                             consecutive_failures = failures.count(),
                             next_retry_secs = backoff.as_secs(),
                             "Failed to reload store, keeping existing data"
                         );
-                        tokio::time::sleep(backoff).await;
+                        scheduler.record(
+                            CheckResult::Failed,
+                            Some(e.to_string()),
+                            failures.count(),
+                            Some(backoff),
+                        );
+                        notify(ReloadReport {
+                            error: Some(e.to_string()),
+                            consecutive_failures: failures.count(),
+                            next_retry_secs: Some(backoff.as_secs()),
+                            ..report(CheckResult::Failed)
+                        });
+                        scheduler.wait(backoff).await;
                         continue;
                     }
                     FailureResponse::MaxExceeded(action) => {
@@ -206,85 +1365,916 @@ fn spawn_reload_scheduler(
                             consecutive_failures = failures.count(),
                             "Max reload failures exceeded"
                         );
+                        scheduler.record(
+                            CheckResult::Failed,
+                            Some(e.to_string()),
+                            failures.count(),
+                            None,
+                        );
+                        let failed = ReloadReport {
+                            error: Some(e.to_string()),
+                            consecutive_failures: failures.count(),
+                            action: Some(action.name()),
+                            ..report(CheckResult::Failed)
+                        };
                         match action {
                             FailureAction::Shutdown => {
                                 error!("Shutting down due to reload failures");
+                                if let Some(delivery) = notify(failed) {
+                                    let _ = delivery.await;
+                                }
                                 std::process::exit(1);
                             }
                             FailureAction::Clear => {
                                 error!("Clearing store due to reload failures");
                                 let empty = occlusion::build_store(vec![])
                                     .expect("Failed to build empty store");
+                                if let Some(canary) = &reload_state.canary {
+                                    canary.stop();
+                                }
                                 store.swap(empty);
+                                reload_state.announce_swap(&store, None);
+                                reload_state.locked_out.store(true, Ordering::Relaxed);
                                 failures.reset();
+                                notify(ReloadReport {
+                                    generation: store.generation(),
+                                    entry_count: 0,
+                                    ..failed
+                                });
+                            }
+                            FailureAction::FallbackFile => {
+                                let swapped = match &failures.fallback_file {
+                                    Some(path) => load_fallback(&store, &reload_state, path).await,
+                                    None => {
+                                        warn!(
+                                            "No fallback file for this source, keeping stale data"
+                                        );
+                                        false
+                                    }
+                                };
+                                // Reported as what was done
+                                let taken = if swapped {
+                                    action
+                                } else {
+                                    FailureAction::KeepStaleWithAlarm
+                                };
+                                reload_state.degraded.store(true, Ordering::Relaxed);
+                                failures.reset();
+                                notify(ReloadReport {
+                                    generation: store.generation(),
+                                    entry_count: store.len(),
+                                    action: Some(taken.name()),
+                                    ..failed
+                                });
+                            }
+                            FailureAction::KeepStaleWithAlarm => {
+                                error!("Serving stale data due to reload failures");
+                                reload_state.degraded.store(true, Ordering::Relaxed);
+                                failures.reset();
+                                notify(failed);
                             }
                         }
                     }
                 },
             }
 
-            tokio::time::sleep(base_interval).await;
+            scheduler.wait_next().await;
         }
     });
 }
 
-#[launch]
-async fn rocket() -> _ {
+fn main() {
     let args = Args::parse();
-    init_tracing(args.json_logs);
+    init_tracing(args.json_logs, args.validate_and_exit);
+    let sockets = systemd::passed_sockets();
+    if sockets > 0 {
+        warn!(
+            sockets,
+            "Socket activation is not supported, ignoring the sockets passed by systemd: \
+             set --bind and --port instead"
+        );
+    }
+
+    // The runtime is built here rather than by `#[launch]`, which only reads Rocket's own
+    // configuration, so that `--workers` and `--blocking-threads` apply to it
+    let figment = figment(&args);
+    let config = match rocket::Config::try_from(&figment) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error = %e, "Invalid server configuration");
+            std::process::exit(1);
+        }
+    };
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("rocket-worker-thread")
+        .worker_threads(config.workers)
+        .max_blocking_threads(config.max_blocking)
+        .enable_all()
+        .build()
+        .expect("create tokio runtime");
+    #[cfg(feature = "tls")]
+    let cert_files = args
+        .tls_cert
+        .clone()
+        .zip(args.tls_key.clone())
+        .map(|(cert, key)| CertFiles {
+            cert,
+            key,
+            client_ca: args.tls_client_ca.clone(),
+        });
+    runtime.block_on(async {
+        let build = setup(args, figment).await;
+        #[cfg(feature = "tls")]
+        let reloader = cert_files.map(|files| {
+            CertReloader::spawn(files, CERT_RELOAD_DEBOUNCE).unwrap_or_else(|e| {
+                error!(error = %e, "Failed to watch TLS certificate");
+                std::process::exit(1);
+            })
+        });
+        loop {
+            let rocket = match build().ignite().await {
+                Ok(rocket) => rocket,
+                Err(e) => {
+                    error!(error = %e, "Invalid server configuration");
+                    std::process::exit(1);
+                }
+            };
+            #[cfg(feature = "tls")]
+            if let Some(reloader) = &reloader {
+                reloader.serve(rocket.shutdown());
+            }
+            if let Err(e) = rocket.launch().await {
+                error!(error = %e, "Server failed");
+                std::process::exit(1);
+            }
+            // The listener only restarts to pick up a new TLS certificate
+            #[cfg(feature = "tls")]
+            let restart = reloader
+                .as_ref()
+                .is_some_and(|reloader| reloader.take_pending());
+            #[cfg(not(feature = "tls"))]
+            let restart = false;
+            if !restart {
+                break;
+            }
+        }
+    });
+}
 
+/// Rocket configuration: `Rocket.toml` and `ROCKET_*` variables, overridden by the command line.
+fn figment(args: &Args) -> Figment {
+    let mut figment = rocket::Config::figment()
+        .merge(("cli_colors", false))
+        .merge(("ident", concat!("occlusion/", env!("CARGO_PKG_VERSION"))));
+    if let Some(bind) = args.bind {
+        figment = figment.merge(("address", bind));
+    }
+    if let Some(port) = args.port {
+        figment = figment.merge(("port", port));
+    }
+    if let Some(workers) = args.workers {
+        figment = figment.merge(("workers", workers));
+    }
+    if let Some(blocking_threads) = args.blocking_threads {
+        figment = figment.merge(("max_blocking", blocking_threads));
+    }
+    if let Some(keep_alive) = args.keep_alive {
+        figment = figment.merge(("keep_alive", keep_alive));
+    }
+    if let Some(limit) = args.json_limit {
+        figment = figment.merge(("limits.json", limit));
+    }
+    #[cfg(feature = "tls")]
+    let figment = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let tls = rocket::config::TlsConfig::from_paths(cert, key);
+            let tls = match &args.tls_client_ca {
+                Some(ca) => {
+                    tls.with_mutual(rocket::config::MutualTls::from_path(ca).mandatory(true))
+                }
+                None => tls,
+            };
+            info!(cert = %cert.display(), mutual = args.tls_client_ca.is_some(), "Serving HTTPS");
+            figment.merge(("tls", tls))
+        }
+        _ => figment,
+    };
+    figment
+}
+
+/// Load the data and start the background tasks, returning a builder of the HTTP server,
+/// called again each time the listener restarts.
+async fn setup(args: Args, figment: Figment) -> impl Fn() -> Rocket<Build> {
     #[cfg(feature = "static-url")]
     let source = DataSource::parse(STATIC_DATA_SOURCE);
     #[cfg(not(feature = "static-url"))]
     let source = DataSource::parse(&args.data_source);
 
-    let (store, metadata) = match load_store(&source).await {
+    let public_key = match args.verify_signature.as_deref().map(parse_public_key) {
+        Some(Ok(public_key)) => Some(public_key),
+        Some(Err(e)) => {
+            error!(error = %e, "Invalid signature public key");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    #[cfg(feature = "tls")]
+    let client_cert_masks = match args.client_cert_masks.as_deref().map(CertMasks::read) {
+        Some(Ok(masks)) => Some(ClientCertMasks {
+            masks,
+            excess: args.client_cert_excess,
+        }),
+        Some(Err(e)) => {
+            error!(error = %e, "Invalid client certificate masks file");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let subject_masks = match args.subject_masks.map(SubjectMasks::read) {
+        Some(Ok(masks)) => {
+            info!(subjects = masks.len(), "Loaded subject masks");
+            Some(Arc::new(masks))
+        }
+        Some(Err(e)) => {
+            error!(error = %e, "Invalid subject masks file");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let access_log = args.access_log.map(|path| {
+        let options = AccessLogOptions {
+            max_size: args.access_log_max_size,
+            rotation: args.access_log_rotate,
+            keep: args.access_log_keep,
+        };
+        match AccessLog::open(path.clone(), options) {
+            Ok(log) => log,
+            Err(e) => {
+                error!(path = %path.display(), error = %e, "Failed to open access log");
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let level_names = match args.level_names.as_deref().map(LevelNames::read) {
+        Some(Ok(names)) => Some(Arc::new(names)),
+        Some(Err(e)) => {
+            error!(error = %e, "Invalid level names file");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let http = HttpOptions {
+        timeout: Duration::from_secs(args.http_timeout),
+        connect_timeout: Duration::from_secs(args.http_connect_timeout),
+        read_timeout: args.http_read_timeout.map(Duration::from_secs),
+        proxy: args.source_proxy.clone(),
+        ca_bundle: args.source_ca_bundle.clone(),
+        client_cert: args.source_client_cert.clone(),
+        client_key: args.source_client_key.clone(),
+    };
+    let client = match http.build_client() {
+        Ok(client) => client,
+        Err(e) => {
+            error!(error = %e, "Invalid HTTP client configuration");
+            std::process::exit(1);
+        }
+    };
+    let retry = RetryPolicy {
+        max_retries: args.http_retries,
+        base_delay: Duration::from_millis(args.http_retry_base_delay_ms),
+        max_delay: Duration::from_secs(args.http_retry_max_delay),
+    };
+    let build_thread = args.build_thread.then(|| match BuildThread::spawn() {
+        Ok(thread) => thread,
+        Err(e) => {
+            error!(error = %e, "Failed to start the build thread");
+            std::process::exit(1);
+        }
+    });
+
+    // Each source gets its own progress reporter so load metrics are not mixed up.
+    // Sidecars default to `<source>.sha256` / `<source>.sig` for every source.
+    let options_for = |source: &DataSource| LoadOptions {
+        format: args.format,
+        csv: CsvOptions {
+            delimiter: args.csv_delimiter,
+            has_headers: !args.csv_no_header,
+            uuid_column: args.csv_uuid_column.clone(),
+            level_column: args.csv_level_column.clone(),
+            namespace_column: args.csv_namespace_column.clone(),
+            level_names: level_names.clone(),
+            uuid_byte_order: args.uuid_byte_order,
+            bundle_pointer: args.bundle_pointer.clone(),
+            id_namespace: args.id_namespace.map(IdNamespace),
+        },
+        error_budget: args.error_budget,
+        progress: Some({
+            let (progress, progress_rx) = ProgressReporter::channel();
+            tokio::spawn(log_progress(progress_rx, PROGRESS_LOG_INTERVAL));
+            progress
+        }),
+        checksum: args.verify_checksum.then(|| source.sidecar(".sha256")),
+        signature: public_key.map(|public_key| SignatureCheck {
+            public_key,
+            sidecar: source.sidecar(".sig"),
+        }),
+        auth: args
+            .source_auth_bearer
+            .clone()
+            .map(SourceAuth::Bearer)
+            .or_else(|| {
+                args.source_auth_basic
+                    .as_deref()
+                    .map(SourceAuth::parse_basic)
+            }),
+        headers: args.source_headers.iter().cloned().collect(),
+        client: Some(client.clone()),
+        retry,
+        fallback_urls: Vec::new(),
+        delta: None,
+        max_size: args.max_source_size,
+        allowed_content_types: args.allowed_content_types.clone(),
+        build_thread: build_thread.clone(),
+    };
+
+    let mut options = options_for(&source);
+    options.fallback_urls = args.fallback_urls.clone();
+    if args.delta || args.delta_url.is_some() {
+        if !source.is_url() {
+            error!(source = %source, "Delta reloads require a URL source");
+            std::process::exit(1);
+        }
+        options.delta = Some(DeltaOptions {
+            url: args.delta_url.clone(),
+            max_pending: args.delta_max_pending,
+        });
+    }
+    if let Some(checksum_source) = &args.checksum_source {
+        options.checksum = Some(DataSource::parse(checksum_source));
+    }
+    if let (Some(signature), Some(signature_source)) =
+        (&mut options.signature, &args.signature_source)
+    {
+        signature.sidecar = DataSource::parse(signature_source);
+    }
+
+    let snapshot_path = |namespace| {
+        args.snapshot_dir
+            .as_deref()
+            .map(|dir| snapshot::path_in(dir, namespace))
+    };
+
+    let gates = ValidationGates {
+        min_entries: args.min_entries,
+        max_churn: args.max_churn,
+        max_level0_shift: args.max_level0_shift,
+        max_memory: args
+            .memory_budget
+            .map(|budget| usize::try_from(budget).unwrap_or(usize::MAX)),
+    };
+
+    let default_snapshot = snapshot_path(None);
+    let backoff = Backoff {
+        initial: Duration::from_secs(args.reload_backoff_initial),
+        max: Duration::from_secs(args.reload_backoff_max),
+        curve: args.reload_backoff,
+        multiplier: args.reload_backoff_multiplier,
+    };
+    let startup = StartupRetry {
+        retries: args.startup_retry,
+        backoff,
+    };
+
+    if args.validate_and_exit {
+        let sources = std::iter::once((None, source.clone(), options.clone(), default_snapshot))
+            .chain(args.namespaced_sources.iter().map(|(name, spec)| {
+                let source = DataSource::parse(spec);
+                let options = options_for(&source);
+                (
+                    Some(name.clone()),
+                    source,
+                    options,
+                    snapshot_path(Some(name)),
+                )
+            }))
+            .collect();
+        validate_and_exit(sources, &gates).await;
+    }
+
+    let initial = load_or_restore(
+        &source,
+        &options,
+        &gates,
+        default_snapshot.as_deref(),
+        startup,
+    )
+    .await;
+    let empty_start = initial.is_err();
+    let InitialLoad {
+        store,
+        routes,
+        metadata,
+        metrics,
+    } = match initial {
         Ok(result) => result,
+        Err(e) if args.allow_empty_start => {
+            error!(error = %e, "Failed to load data source, starting with an empty store");
+            InitialLoad::empty()
+        }
         Err(e) => {
             error!(error = %e, "Failed to start server");
             std::process::exit(1);
         }
     };
 
-    let reload_state = Arc::new(ReloadState {
-        source: source.clone(),
-        metadata: RwLock::new(metadata),
+    #[cfg(feature = "chaos")]
+    let chaos = (!args.chaos.is_empty()).then(|| {
+        let faults: Vec<_> = args.chaos.iter().map(ToString::to_string).collect();
+        warn!(faults = %faults.join(","), "Chaos mode: injecting faults into reloads");
+        Arc::new(server::chaos::Chaos::new(args.chaos.clone()))
     });
 
-    if args.reload_interval > 0 {
+    let events = Events::new();
+    let mut reload_state = ReloadState::new(source.clone(), options, metadata)
+        .with_gates(gates)
+        .with_routes(routes.clone())
+        .with_events(events.clone(), None);
+    if let Some(path) = default_snapshot {
+        reload_state = reload_state.with_snapshot(path);
+    }
+    if let Some(subjects) = &subject_masks {
+        reload_state = reload_state.with_subjects(subjects.clone());
+    }
+    if let Some(max) = args.max_staleness {
+        reload_state = reload_state.with_max_staleness(max);
+    }
+    reload_state = reload_state.with_prewarm(args.prewarm_lookups);
+    #[cfg(feature = "chaos")]
+    if let Some(chaos) = &chaos {
+        reload_state = reload_state.with_chaos(chaos.clone());
+    }
+    let canary = (args.canary_sample > 0).then(|| {
+        let options = CanaryOptions {
+            sample: f64::from(args.canary_sample) / 100.0,
+            window: args.canary_window,
+            max_divergence: f64::from(args.canary_max_divergence) / 100.0,
+            min_samples: args.canary_min_samples,
+        };
         info!(
-            interval_mins = args.reload_interval,
-            "Starting reload scheduler"
+            sample_percent = args.canary_sample,
+            window_secs = options.window.as_secs(),
+            max_divergence_percent = args.canary_max_divergence,
+            "Evaluating reloaded data on live traffic"
         );
-        spawn_reload_scheduler(
+        Arc::new(Canary::new(store.clone(), options))
+    });
+    if let Some(canary) = &canary {
+        reload_state = reload_state.with_canary(canary.clone());
+    }
+    let fleet = (!args.peers.is_empty() || args.peer_dns.is_some()).then(Fleet::default);
+    if args.peer_fetch {
+        let (Some(fleet), Some(own), Some(token)) =
+            (&fleet, &args.advertise_url, &args.admin_token)
+        else {
+            error!("--peer-fetch requires --peer or --peer-dns");
+            std::process::exit(1);
+        };
+        if !reload_state.routes.is_empty() {
+            warn!("--peer-fetch is ignored for a source with a namespace column");
+        }
+        info!(url = %own, "Copying the data of the fleet's leader when not leading");
+        reload_state =
+            reload_state.with_peer_fetch(PeerFetch::new(own.clone(), fleet.clone(), token.clone()));
+    }
+    *reload_state.last_load.write().expect("RwLock poisoned") = metrics;
+    let reload_state = Arc::new(reload_state);
+    if empty_start {
+        reload_state.loaded.store(false, Ordering::Relaxed);
+        spawn_startup_loader(store.clone(), reload_state.clone(), backoff);
+    }
+
+    // Namespaces fed by the namespace column share the default source's reload state
+    let mut namespaces = Namespaces::new();
+    for (name, store) in routes {
+        let namespace = Namespace {
+            store,
+            reload_state: reload_state.clone(),
+        };
+        namespaces.insert(name, namespace);
+    }
+
+    for (name, spec) in &args.namespaced_sources {
+        let source = DataSource::parse(spec);
+        let options = options_for(&source);
+        info!(namespace = %name, "Loading namespace");
+        let namespace_snapshot = snapshot_path(Some(name));
+        let loaded = load_or_restore(
+            &source,
+            &options,
+            &gates,
+            namespace_snapshot.as_deref(),
+            startup,
+        )
+        .await;
+        let empty_start = loaded.is_err();
+        let loaded = match loaded {
+            Ok(result) => result,
+            Err(e) if args.allow_empty_start => {
+                error!(namespace = %name, error = %e, "Failed to load namespace, starting with an empty store");
+                InitialLoad::empty()
+            }
+            Err(e) => {
+                error!(namespace = %name, error = %e, "Failed to start server");
+                std::process::exit(1);
+            }
+        };
+        if !loaded.routes.is_empty() {
+            warn!(namespace = %name, "Ignoring namespace column in a namespace's data source");
+        }
+        let mut reload_state = ReloadState::new(source, options, loaded.metadata)
+            .with_gates(gates)
+            .with_events(events.clone(), Some(name.clone()));
+        if let Some(path) = namespace_snapshot {
+            reload_state = reload_state.with_snapshot(path);
+        }
+        if let Some(max) = args.max_staleness {
+            reload_state = reload_state.with_max_staleness(max);
+        }
+        reload_state = reload_state.with_prewarm(args.prewarm_lookups);
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &chaos {
+            reload_state = reload_state.with_chaos(chaos.clone());
+        }
+        *reload_state.last_load.write().expect("RwLock poisoned") = loaded.metrics;
+        let namespace = Namespace {
+            store: loaded.store,
+            reload_state: Arc::new(reload_state),
+        };
+        if empty_start {
+            namespace
+                .reload_state
+                .loaded
+                .store(false, Ordering::Relaxed);
+            spawn_startup_loader(
+                namespace.store.clone(),
+                namespace.reload_state.clone(),
+                backoff,
+            );
+        }
+        if namespaces.insert(name.clone(), namespace).is_some() {
+            error!(namespace = %name, "Namespace configured more than once");
+            std::process::exit(1);
+        }
+    }
+
+    if args.retain_generations > 0 {
+        info!(
+            generations = args.retain_generations,
+            "Retaining replaced generations"
+        );
+        store.set_retention(args.retain_generations);
+        for (_, namespace) in namespaces.iter() {
+            namespace.store.set_retention(args.retain_generations);
+        }
+    }
+
+    let watchdog = systemd::watchdog_interval();
+    let mut default_scheduler = None;
+    let mut namespace_schedulers = NamespaceSchedulers::default();
+    if args.reload_interval > 0 || args.reload_cron.is_some() {
+        match &args.reload_cron {
+            Some(cron) => info!(%cron, "Starting reload scheduler"),
+            None => info!(
+                interval_mins = args.reload_interval,
+                "Starting reload scheduler"
+            ),
+        }
+        if !args.reload_windows.is_empty() {
+            let windows: Vec<_> = args
+                .reload_windows
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            info!(windows = %windows.join(","), "Restricting automatic reloads to windows (UTC)");
+        }
+        let webhooks =
+            (!args.webhook_urls.is_empty()).then(|| Webhooks::new(args.webhook_urls.clone()));
+        let schedules = std::iter::once((None, &store, &reload_state)).chain(
+            namespaces
+                .iter()
+                .map(|(name, namespace)| (Some(name), &namespace.store, &namespace.reload_state))
+                .filter(|(_, _, state)| !Arc::ptr_eq(state, &reload_state)),
+        );
+        for (name, store, state) in schedules {
+            let scheduler = Scheduler::new(Duration::from_secs(args.reload_interval * 60))
+                .with_jitter(f64::from(args.reload_jitter) / 100.0)
+                .with_windows(args.reload_windows.clone());
+            let scheduler = match &args.reload_cron {
+                Some(cron) => scheduler.with_cron(cron.clone()),
+                None => scheduler,
+            };
+            let is_default = Arc::ptr_eq(state, &reload_state);
+            // The default source's reload loop keeps the systemd watchdog fed
+            let scheduler = match watchdog {
+                Some(interval) if is_default => Arc::new(scheduler.with_watchdog(interval)),
+                _ => Arc::new(scheduler),
+            };
+            match name {
+                None => default_scheduler = Some(scheduler.clone()),
+                Some(name) => {
+                    namespace_schedulers
+                        .0
+                        .insert(name.to_string(), scheduler.clone());
+                }
+            }
+            spawn_reload_scheduler(
+                store.clone(),
+                state.clone(),
+                scheduler,
+                FailureTracker::new(args.max_reload_failures, args.on_max_failures, backoff)
+                    .with_fallback_file(args.fallback_file.clone().filter(|_| is_default)),
+                webhooks.clone(),
+                name.map(str::to_string),
+            );
+        }
+    }
+
+    if let (Some(interval), None) = (watchdog, &default_scheduler) {
+        systemd::spawn_watchdog(interval);
+    }
+
+    if args.watch {
+        let local = |state: &ReloadState| {
+            let source = state.source();
+            source.is_file() || source.is_glob()
+        };
+        if !local(&reload_state) {
+            error!(source = %source, "--watch requires a file, directory or glob source");
+            std::process::exit(1);
+        }
+        let watched = std::iter::once((&store, &reload_state)).chain(
+            namespaces
+                .iter()
+                .map(|(_, namespace)| (&namespace.store, &namespace.reload_state))
+                .filter(|(_, state)| !Arc::ptr_eq(state, &reload_state) && local(state)),
+        );
+        for (store, reload_state) in watched {
+            let debounce = Duration::from_millis(args.watch_debounce_ms);
+            let windows = args.reload_windows.clone();
+            if let Err(e) =
+                watch::spawn_watcher(store.clone(), reload_state.clone(), debounce, windows)
+            {
+                error!(source = %reload_state.source(), error = %e, "Failed to watch data source");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(fleet) = &fleet {
+        Gossip::new(
+            args.peers.clone(),
+            args.peer_dns.clone(),
+            Duration::from_secs(args.gossip_interval),
+        )
+        .with_reload(args.gossip_reload, args.reload_windows.clone())
+        .spawn(fleet.clone(), store.clone(), reload_state.clone());
+    } else if args.gossip_reload {
+        warn!("--gossip-reload has no effect without --peer or --peer-dns");
+    }
+
+    if let Some(path) = args.source_file {
+        spawn_source_file_listener(store.clone(), reload_state.clone(), path);
+    }
+
+    let jwt = match args.jwt_jwks_url {
+        Some(jwks_url) => {
+            let options = JwtOptions {
+                jwks_url,
+                claim: args.jwt_mask_claim,
+                issuer: args.jwt_issuer,
+                audience: args.jwt_audience,
+            };
+            match JwtVerifier::new(options).await {
+                Ok(verifier) => Some(Arc::new(verifier)),
+                Err(e) => {
+                    error!(error = %e, "Failed to set up JWT verification");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let audit_log = args.audit_log.as_deref().map(|sink| {
+        let sink = DecisionSink::parse(sink);
+        info!(sink = %sink, "Auditing decisions");
+        let options = DecisionLogOptions {
+            batch_size: args.audit_log_batch_size.max(1),
+            flush_interval: Duration::from_millis(args.audit_log_flush_ms.max(1)),
+        };
+        AuditLog::spawn(sink, options)
+    });
+
+    #[cfg(feature = "kafka")]
+    if let (Some(brokers), Some(topic)) = (args.kafka_brokers, args.kafka_topic) {
+        let options = server::kafka::KafkaOptions {
+            brokers,
+            topic,
+            group_id: args.kafka_group_id,
+        };
+        if let Err(e) = server::kafka::spawn_consumer(store.clone(), &options) {
+            error!(error = %e, "Failed to start change feed consumer");
+            std::process::exit(1);
+        }
+    }
+
+    let concurrency_limits = (args.max_in_flight > 0 || args.max_admin_in_flight > 0).then(|| {
+        ConcurrencyLimits::new(
+            args.max_in_flight,
+            args.max_admin_in_flight,
+            args.shed_retry_after,
+        )
+    });
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc_listen {
+        let service = server::grpc::OcclusionService::new(
+            store.clone(),
+            args.id_namespace.map(IdNamespace),
+            args.max_batch_size,
+        );
+        let service = match &jwt {
+            Some(verifier) => service.with_jwt(verifier.clone()),
+            None => service,
+        };
+        let service = match &audit_log {
+            Some(log) => service.with_audit(log.clone()),
+            None => service,
+        };
+        tokio::spawn(async move {
+            info!(%addr, "Serving gRPC API");
+            if let Err(e) = server::grpc::serve(addr, service).await {
+                error!(%addr, error = %e, "gRPC server failed");
+                std::process::exit(1);
+            }
+        });
+    }
+
+    if let Some(addr) = args.resp_listen {
+        let service = server::resp::RespService::new(
             store.clone(),
-            reload_state.clone(),
-            args.reload_interval,
-            args.max_reload_failures,
-            args.on_max_failures,
+            args.id_namespace.map(IdNamespace),
+            args.max_batch_size,
         );
+        let service = match &jwt {
+            Some(verifier) => service.with_jwt(verifier.clone()),
+            None => service,
+        };
+        let service = match &audit_log {
+            Some(log) => service.with_audit(log.clone()),
+            None => service,
+        };
+        let service = match &concurrency_limits {
+            Some(limits) => service.with_limits(limits.clone()),
+            None => service,
+        };
+        let service = if args.reject_stale {
+            service.with_reject_stale(reload_state.clone())
+        } else {
+            service
+        };
+        tokio::spawn(async move {
+            info!(%addr, "Serving RESP API");
+            if let Err(e) = server::resp::serve(addr, service).await {
+                error!(%addr, error = %e, "RESP server failed");
+                std::process::exit(1);
+            }
+        });
     }
 
     info!("Starting occlusion server");
 
-    let figment = Figment::from(rocket::Config::default())
-        .merge(("cli_colors", false))
-        .merge(("ident", concat!("occlusion/", env!("CARGO_PKG_VERSION"))));
+    let decision_log = args.decision_log.as_deref().map(|sink| {
+        let sink = DecisionSink::parse(sink);
+        info!(sink = %sink, "Logging OPA decisions");
+        let options = DecisionLogOptions {
+            batch_size: args.decision_log_batch_size.max(1),
+            flush_interval: Duration::from_millis(args.decision_log_flush_ms.max(1)),
+        };
+        DecisionLog::spawn(sink, options)
+    });
+    let id_namespace = args.id_namespace.map(IdNamespace);
+    let remote = args.remote_authority.map(|url| {
+        info!(url = %url, "Asking the remote authority about unknown objects");
+        Arc::new(TieredStore::new(
+            store.clone(),
+            HttpAuthority::new(url),
+            Duration::from_secs(args.remote_authority_ttl),
+        ))
+    });
+    let request_timeout = (args.request_timeout_ms > 0)
+        .then(|| RequestTimeout(Duration::from_millis(args.request_timeout_ms)));
+    let (max_batch_size, cache_max_age) = (args.max_batch_size, args.cache_max_age);
+    let admin_token = args.admin_token;
+    let admin_allow = args.admin_allow;
+    let reject_stale = args.reject_stale;
+    let mut route_groups = args.route_groups;
+    route_groups.sort_unstable();
+    route_groups.dedup();
+    info!(?route_groups, "Serving route groups");
+    let latencies = Latencies::default();
 
-    rocket::custom(figment)
-        .attach(RequestTimer)
-        .manage(store)
-        .mount(
-            "/",
-            routes![
-                // Original API
-                routes::check,
-                routes::check_batch,
-                routes::health,
-                routes::stats,
-                // OPA-compatible API
-                routes::opa_visible,
-                routes::opa_visible_batch,
-            ],
-        )
+    move || {
+        let rocket = rocket::custom(figment.clone())
+            .attach(RequestIds)
+            .attach(GenerationHeader)
+            .attach(RequestTimer)
+            .attach(SystemdNotify)
+            .manage(latencies.clone())
+            .manage(store.clone())
+            .manage(reload_state.clone())
+            .manage(namespaces.clone())
+            .manage(events.clone())
+            .manage(MaxBatchSize(max_batch_size))
+            .manage(CacheMaxAge(cache_max_age))
+            .register("/", catchers::catchers())
+            .mount("/", RouteGroup::probes());
+        let rocket = route_groups
+            .iter()
+            .fold(rocket, |rocket, group| rocket.mount("/", group.routes()));
+        let rocket = match &default_scheduler {
+            Some(scheduler) => rocket
+                .manage(scheduler.clone())
+                .manage(namespace_schedulers.clone()),
+            None => rocket,
+        };
+        let rocket = match &fleet {
+            Some(fleet) => rocket.manage(fleet.clone()),
+            None => rocket,
+        };
+        let rocket = match &canary {
+            Some(canary) => rocket.manage(canary.clone()),
+            None => rocket,
+        };
+        let rocket = match &decision_log {
+            Some(log) => rocket.manage(log.clone()),
+            None => rocket,
+        };
+        let rocket = match id_namespace {
+            Some(namespace) => rocket.manage(namespace),
+            None => rocket,
+        };
+        let rocket = match &remote {
+            Some(remote) => rocket.manage(remote.clone()),
+            None => rocket,
+        };
+        let rocket = match &concurrency_limits {
+            Some(limits) => rocket.manage(limits.clone()),
+            None => rocket,
+        };
+        let rocket = match request_timeout {
+            Some(timeout) => rocket.manage(timeout),
+            None => rocket,
+        };
+        let rocket = if reject_stale {
+            rocket.manage(RejectStale)
+        } else {
+            rocket
+        };
+        let rocket = match &subject_masks {
+            Some(subjects) => rocket.manage(subjects.clone()),
+            None => rocket,
+        };
+        let rocket = match &jwt {
+            Some(verifier) => rocket.manage(verifier.clone()),
+            None => rocket,
+        };
+        let rocket = match &access_log {
+            Some(log) => rocket.attach(log.clone()),
+            None => rocket,
+        };
+        let rocket = match &audit_log {
+            Some(log) => rocket.manage(log.clone()),
+            None => rocket,
+        };
+        #[cfg(feature = "tls")]
+        let rocket = match &client_cert_masks {
+            Some(masks) => rocket.manage(masks.clone()),
+            None => rocket,
+        };
+        let rocket = match &admin_token {
+            Some(token) => rocket.manage(AdminToken(token.clone())),
+            None => rocket,
+        };
+        if admin_allow.is_empty() {
+            rocket
+        } else {
+            rocket.manage(AdminAllowlist(admin_allow.clone()))
+        }
+    }
 }
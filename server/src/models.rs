@@ -1,12 +1,19 @@
+use crate::{
+    canary::CanaryStatus,
+    gossip::FleetSummary,
+    ids::ObjectId,
+    latency::RouteLatency,
+    validation::{Diff, LevelShift},
+};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use uuid::Uuid;
 
 /// Request to check if a single object is visible
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CheckRequest {
-    pub object: Uuid,
+    pub object: ObjectId,
     pub visibility_mask: u8,
 }
 
@@ -20,14 +27,89 @@ pub struct CheckResponse {
 /// Request to check multiple objects at once
 #[derive(Debug, Deserialize, Serialize)]
 pub struct BatchCheckRequest {
-    pub objects: Vec<Uuid>,
-    pub visibility_mask: u8,
+    pub objects: Vec<BatchObject>,
+    /// Mask for the entries without their own (required if there are any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility_mask: Option<u8>,
+}
+
+/// An entry of a batch check
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum BatchObject {
+    /// Checked against the request's mask
+    Object(ObjectId),
+    /// Checked against its own mask, e.g. on behalf of a different viewer
+    Masked(CheckRequest),
 }
 
 /// Response for batch object visibility check
 #[derive(Debug, Deserialize, Serialize)]
 pub struct BatchCheckResponse {
     pub all_visible: bool,
+    /// Per-object results, in request order (only when some entries carry their own mask)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<CheckResponse>,
+}
+
+/// Request to check objects on behalf of a subject, against the subject's mask
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubjectCheckRequest {
+    pub subject: String,
+    pub objects: Vec<ObjectId>,
+}
+
+/// Response for a check by subject
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubjectCheckResponse {
+    pub all_visible: bool,
+    /// Per-object results, in request order
+    pub results: Vec<CheckResponse>,
+}
+
+/// Response for a level lookup
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LevelResponse {
+    pub object: Uuid,
+    /// The object's visibility level, or `null` if it is unknown
+    pub level: Option<u8>,
+}
+
+/// Request to filter a list of objects down to the visible ones
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FilterRequest {
+    pub objects: Vec<ObjectId>,
+    pub visibility_mask: u8,
+}
+
+/// The visible objects of a filter request, as given and in request order
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FilterResponse {
+    pub objects: Vec<ObjectId>,
+}
+
+/// Request to count the visible objects of a list
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CountRequest {
+    pub objects: Vec<ObjectId>,
+    pub visibility_mask: u8,
+    /// Also count the objects at each visibility level
+    #[serde(default)]
+    pub by_level: bool,
+}
+
+/// How many objects of a count request are visible
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CountResponse {
+    pub visible: usize,
+    /// Objects submitted
+    pub total: usize,
+    /// Objects at each visibility level (only with `by_level`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub levels: Option<BTreeMap<u8, usize>>,
+    /// Objects not in the store (only with `by_level`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unknown: Option<usize>,
 }
 
 /// Health check response
@@ -35,6 +117,143 @@ pub struct BatchCheckResponse {
 pub struct HealthResponse {
     pub status: Cow<'static, str>,
     pub uuid_count: usize,
+    /// UUID count per namespace (omitted when no namespaces are configured)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, usize>,
+}
+
+/// What data the default store holds
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetaResponse {
+    pub generation: u64,
+    /// When the data was loaded (seconds since the Unix epoch; omitted before the first load)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded_at: Option<u64>,
+    /// Data source, without credentials (omitted when the store is not reloadable)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Version of the source data (its `ETag`, Last-Modified header or modification time),
+    /// unlike the generation comparable between instances loading the same source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_version: Option<String>,
+    /// When the source data was last modified (seconds since the Unix epoch, when known)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_modified_at: Option<u64>,
+    pub entry_count: usize,
+}
+
+/// Liveness or readiness probe response
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProbeResponse {
+    pub status: Cow<'static, str>,
+}
+
+/// A page of UUIDs at one visibility level, in ascending order
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UuidPage {
+    pub uuids: Vec<Uuid>,
+    /// Cursor for the next page (absent on the last page)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Uuid>,
+}
+
+/// An entry of the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Entry {
+    pub object: Uuid,
+    pub level: u8,
+}
+
+/// A random sample of the store's entries
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SampleResponse {
+    /// Generation of the data sampled
+    pub generation: u64,
+    /// Number of entries the sample was drawn from (those at the requested level, if any)
+    pub population: usize,
+    pub entries: Vec<Entry>,
+}
+
+/// Body of every error response
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+/// What went wrong
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ErrorDetail {
+    /// HTTP status code
+    pub code: u16,
+    pub message: String,
+    /// The limit the request exceeded, for errors about one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// ID of the failed request, as in its `X-Request-Id` header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Response of an on-demand reload
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReloadResponse {
+    /// Whether new data was swapped in (false when the source was unchanged)
+    pub reloaded: bool,
+    /// Generation of the data after the reload
+    pub generation: u64,
+    /// Changes versus the previous data (omitted when unchanged)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Diff>,
+}
+
+/// Store after an admin clear or replace
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StoreResponse {
+    pub uuid_count: usize,
+    /// Generation of the data after the change
+    pub generation: u64,
+}
+
+/// Request to switch the default data source
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SourceRequest {
+    /// Path, glob, URL or cloud object, as on the command line
+    pub source: String,
+}
+
+/// Response of a data source switch
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SourceResponse {
+    /// The new source, without credentials
+    pub source: String,
+    /// Generation of the data loaded from it
+    pub generation: u64,
+    /// Changes versus the data of the previous source
+    pub diff: Diff,
+}
+
+/// Request to compare a candidate data source with the default store
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DiffRequest {
+    /// Path, glob, URL or cloud object, as on the command line
+    pub source: String,
+    /// Number of level shifts to report (default 10)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top: Option<usize>,
+}
+
+/// Differences between a candidate data source and the default store
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DiffResponse {
+    /// The candidate source, without credentials
+    pub source: String,
+    /// Generation of the data compared against
+    pub generation: u64,
+    /// Number of entries in the candidate
+    pub entries: usize,
+    pub diff: Diff,
+    /// The most common changes of level, most common first
+    pub level_shifts: Vec<LevelShift>,
 }
 
 /// Statistics response
@@ -42,6 +261,132 @@ pub struct HealthResponse {
 pub struct StatsResponse {
     pub total_uuids: usize,
     pub visibility_distribution: HashMap<u8, usize>,
+    /// Store implementation in use (`hashmap`, `vec`, `hybrid` or `fullhash`)
+    pub store_algorithm: Cow<'static, str>,
+    /// Generation of the data, bumped by every reload and change
+    pub generation: u64,
+    /// Estimated heap memory held by the store, in bytes
+    pub memory_bytes: usize,
+    /// Data source, without credentials (omitted when the store is not reloadable)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// When the data was last loaded or confirmed unchanged (seconds since the Unix epoch)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_reload_at: Option<u64>,
+    /// Reloads failed since the last success
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Whether stale or fallback data is served after too many failed reloads
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+    /// Details of the last successful load, including its duration (omitted before the first
+    /// one completes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_load: Option<LoadMetrics>,
+    /// Convergence of the fleet (omitted when peer gossip is disabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fleet: Option<FleetSummary>,
+    /// Canary evaluation of reloaded data (omitted when disabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<CanaryStatus>,
+    /// Request latency percentiles by route, over the last 1, 5 and 15 minutes (omitted for
+    /// namespaces)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<BTreeMap<String, RouteLatency>>,
+}
+
+/// Metrics of a completed load
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LoadMetrics {
+    /// When the load finished (seconds since the Unix epoch)
+    pub completed_at: u64,
+    /// Total duration, in milliseconds
+    pub duration_ms: u64,
+    /// Time spent fetching (downloading or reading, plus sidecars), in milliseconds
+    pub fetch_ms: u64,
+    /// Time spent decompressing, in milliseconds
+    pub decompress_ms: u64,
+    /// Time spent parsing rows, in milliseconds
+    pub parse_ms: u64,
+    /// Time spent building the store, in milliseconds
+    pub build_ms: u64,
+    /// Bytes downloaded or read (before decompression)
+    pub bytes_fetched: u64,
+    /// Rows loaded into the store
+    pub rows_parsed: usize,
+    /// Malformed rows skipped under the error budget
+    pub rows_rejected: usize,
+    /// `ETag` (or object generation) of the loaded data, if the source has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+// ============================================================================
+// API v2 Models
+// ============================================================================
+
+/// Outcome of a v2 check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Known, at or below the mask
+    Visible,
+    /// Known, but above the mask
+    Denied,
+    /// Not in the store
+    Unknown,
+}
+
+impl Decision {
+    /// The decision for an object at `level` (`None` = unknown) under `mask`.
+    pub fn new(level: Option<u8>, mask: u8) -> Self {
+        match level {
+            Some(level) if level <= mask => Self::Visible,
+            Some(_) => Self::Denied,
+            None => Self::Unknown,
+        }
+    }
+}
+
+/// Response for a single v2 check
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CheckResponseV2 {
+    pub object: Uuid,
+    pub decision: Decision,
+    /// Generation of the data the decision was made on
+    pub generation: u64,
+}
+
+/// Request to check multiple objects at once (v2)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchCheckRequestV2 {
+    pub objects: Vec<BatchObject>,
+    /// Mask for the entries without their own (required if there are any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility_mask: Option<u8>,
+    /// Return each object's level along with its decision
+    #[serde(default)]
+    pub include_levels: bool,
+}
+
+/// Decision for one object of a v2 batch
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ObjectDecision {
+    pub object: Uuid,
+    pub decision: Decision,
+    /// The object's level (only with `include_levels`, and absent for unknown objects)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+}
+
+/// Response for a v2 batch check
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchCheckResponseV2 {
+    pub all_visible: bool,
+    /// Generation of the data the decisions were made on
+    pub generation: u64,
+    /// Per-object decisions, in request order
+    pub results: Vec<ObjectDecision>,
 }
 
 // ============================================================================
@@ -58,18 +403,77 @@ pub struct OpaRequest<T> {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OpaResponse<T> {
     pub result: T,
+    /// Identifies the decision in the decision log
+    pub decision_id: Uuid,
+}
+
+/// OPA-style wrapper for results that are not decisions
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OpaResult<T> {
+    pub result: T,
+}
+
+/// Labels identifying this server instance, in OPA status reports and decision logs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpaLabels {
+    /// Random ID of this process
+    pub id: Uuid,
+    pub version: Cow<'static, str>,
+}
+
+/// OPA status report
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OpaStatus {
+    pub labels: OpaLabels,
+    /// Status per data source, as OPA reports bundles: `default` and each namespace
+    pub bundles: BTreeMap<String, OpaBundleStatus>,
+    /// `bundle` (data loading) and, when enabled, `decision_logs`
+    pub plugins: BTreeMap<String, OpaPluginStatus>,
+}
+
+/// Status of a data source, as an OPA bundle status
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OpaBundleStatus {
+    pub name: String,
+    /// `ETag` of the loaded data, or else the store generation
+    pub active_revision: String,
+    /// When data was last loaded successfully (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_successful_activation: Option<String>,
+    /// Set while reloads are failing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// State of an OPA plugin: `OK` or `ERROR`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OpaPluginStatus {
+    pub state: Cow<'static, str>,
+}
+
+/// Read-only `occlusion` data document
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OcclusionDocument {
+    pub total_uuids: usize,
+    pub visibility_distribution: BTreeMap<u8, usize>,
+    pub generation: u64,
+    /// UUID count per namespace
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, usize>,
 }
 
 /// Input for OPA visible check
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OpaVisibleInput {
-    pub object: Uuid,
+    pub object: ObjectId,
     pub visibility_mask: u8,
 }
 
 /// Input for OPA batch visible check
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OpaBatchVisibleInput {
-    pub objects: Vec<Uuid>,
+    pub objects: Vec<ObjectId>,
     pub visibility_mask: u8,
 }
@@ -0,0 +1,91 @@
+//! Named data sources, each loaded into its own store.
+
+use crate::ReloadState;
+use occlusion::SwappableStore;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// A data source loaded into its own store and reloaded independently.
+#[derive(Clone)]
+pub struct Namespace {
+    /// The namespace's store
+    pub store: SwappableStore,
+    /// Source, options and metadata used to reload the store
+    pub reload_state: Arc<ReloadState>,
+}
+
+/// Registry of the namespaces configured with `--data-source NAME=SOURCE`.
+#[derive(Clone, Default)]
+pub struct Namespaces(BTreeMap<String, Namespace>);
+
+impl Namespaces {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a namespace, returning the previous one with the same name.
+    pub fn insert(&mut self, name: String, namespace: Namespace) -> Option<Namespace> {
+        self.0.insert(name, namespace)
+    }
+
+    /// Look up a namespace by name.
+    pub fn get(&self, name: &str) -> Option<&Namespace> {
+        self.0.get(name)
+    }
+
+    /// Iterate over namespaces in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Namespace)> {
+        self.0
+            .iter()
+            .map(|(name, namespace)| (name.as_str(), namespace))
+    }
+
+    /// Returns the number of namespaces.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if no namespaces are configured.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Parse a `NAME=SOURCE` argument.
+///
+/// Names may contain ASCII letters, digits, `-` and `_`, so they are safe to use in URLs.
+pub fn parse_spec(s: &str) -> Result<(String, String), String> {
+    let (name, source) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=SOURCE, got {s:?}"))?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "namespace name must be non-empty and contain only letters, digits, '-' or '_', got {name:?}"
+        ));
+    }
+    if source.is_empty() {
+        return Err(format!("missing source for namespace {name:?}"));
+    }
+    Ok((name.to_string(), source.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            parse_spec("tenant-a=https://example.com/a.csv?x=1"),
+            Ok(("tenant-a".into(), "https://example.com/a.csv?x=1".into()))
+        );
+        assert!(parse_spec("/data/a.csv").is_err());
+        assert!(parse_spec("=a.csv").is_err());
+        assert!(parse_spec("a/b=a.csv").is_err());
+        assert!(parse_spec("a=").is_err());
+    }
+}
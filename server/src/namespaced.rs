@@ -0,0 +1,247 @@
+//! Namespace-scoped API: the v1 check, level and stats routes under
+//! `/api/v1/ns/<namespace>/`, answered from that namespace's store.
+//!
+//! Requests behave exactly like their unscoped counterparts, except that unknown namespaces
+//! are answered with a 404.
+
+use crate::{
+    admission::DataPlane,
+    audit::Decisions,
+    cache::Cached,
+    catchers::ApiError,
+    guards::{Entitlement, JsonBody, MaybeState},
+    ids::IdNamespace,
+    models::{
+        BatchCheckRequest, BatchCheckResponse, CheckRequest, CheckResponse, LevelResponse,
+        StatsResponse,
+    },
+    namespace::{Namespace, Namespaces},
+    routes::{self, MaxBatchSize},
+};
+use occlusion::SwappableStore;
+use rocket::{Request, State, http::Status, serde::json::Json};
+
+/// The store `request` is answered from: its namespace's for namespace-scoped routes (if the
+/// namespace exists), the default store otherwise.
+pub(crate) fn store_of<'r>(request: &'r Request<'_>) -> Option<&'r SwappableStore> {
+    let rocket = request.rocket();
+    match request.uri().path().as_str().strip_prefix("/api/v1/ns/") {
+        Some(rest) => {
+            let name = rest.split('/').next().unwrap_or_default();
+            let namespace = rocket.state::<Namespaces>()?.get(name)?;
+            Some(&namespace.store)
+        }
+        None => rocket.state::<SwappableStore>(),
+    }
+}
+
+/// Look up a namespace, failing with a 404 naming it if it is not configured.
+pub(crate) fn lookup<'r>(
+    namespaces: &'r Namespaces,
+    name: &str,
+) -> Result<&'r Namespace, ApiError> {
+    namespaces
+        .get(name)
+        .ok_or_else(|| ApiError::new(Status::NotFound, format!("unknown namespace {name:?}")))
+}
+
+/// Check if a single object of a namespace is visible, like [`routes::check`].
+///
+/// Namespaces are not backed by the remote authority.
+#[post("/api/v1/ns/<namespace>/check", data = "<request>")]
+pub async fn check(
+    plane: DataPlane,
+    namespaces: &State<Namespaces>,
+    namespace: &str,
+    ids: MaybeState<'_, IdNamespace>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<CheckRequest>,
+) -> Result<Json<CheckResponse>, ApiError> {
+    let store = &lookup(namespaces, namespace)?.store;
+    routes::check(
+        plane,
+        store.into(),
+        ids,
+        MaybeState(None),
+        entitlement,
+        decisions,
+        request,
+    )
+    .await
+}
+
+/// Check a single object of a namespace given in the URL, like [`routes::check_get`].
+#[get("/api/v1/ns/<namespace>/check/<object>?<mask>")]
+#[allow(clippy::too_many_arguments)]
+pub fn check_get(
+    plane: DataPlane,
+    namespaces: &State<Namespaces>,
+    namespace: &str,
+    ids: MaybeState<'_, IdNamespace>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    object: &str,
+    mask: u8,
+) -> Result<Cached<Json<CheckResponse>>, ApiError> {
+    let store = &lookup(namespaces, namespace)?.store;
+    routes::check_get(
+        plane,
+        store.into(),
+        ids,
+        entitlement,
+        decisions,
+        object,
+        mask,
+    )
+}
+
+/// Check multiple objects of a namespace, like [`routes::check_batch`].
+///
+/// Namespaces are not backed by the remote authority.
+#[post("/api/v1/ns/<namespace>/check/batch", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn check_batch(
+    plane: DataPlane,
+    namespaces: &State<Namespaces>,
+    namespace: &str,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<BatchCheckRequest>,
+) -> Result<Json<BatchCheckResponse>, ApiError> {
+    let store = &lookup(namespaces, namespace)?.store;
+    routes::check_batch(
+        plane,
+        store.into(),
+        ids,
+        max,
+        MaybeState(None),
+        entitlement,
+        decisions,
+        request,
+    )
+    .await
+}
+
+/// Look up the visibility level of an object of a namespace, like [`routes::level`].
+#[get("/api/v1/ns/<namespace>/level/<object>")]
+pub fn level(
+    plane: DataPlane,
+    namespaces: &State<Namespaces>,
+    namespace: &str,
+    ids: MaybeState<'_, IdNamespace>,
+    object: &str,
+) -> Result<(Status, Json<LevelResponse>), ApiError> {
+    let store = &lookup(namespaces, namespace)?.store;
+    routes::level(plane, store.into(), ids, object)
+}
+
+/// Get statistics about a namespace's store and its last load, like [`routes::stats`].
+#[get("/api/v1/ns/<namespace>/stats")]
+pub fn stats(
+    namespaces: &State<Namespaces>,
+    namespace: &str,
+) -> Result<Cached<Json<StatsResponse>>, ApiError> {
+    let namespace = lookup(namespaces, namespace)?;
+    Ok(routes::stats(
+        (&namespace.store).into(),
+        MaybeState(Some(&namespace.reload_state)),
+        MaybeState(None),
+        MaybeState(None),
+        MaybeState(None),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ReloadState,
+        loader::LoadOptions,
+        source::{DataSource, SourceMetadata},
+    };
+    use rocket::{http::ContentType, local::blocking::Client};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_namespaced() {
+        let store = |level| {
+            let entries = vec![(Uuid::from_u128(1), level)];
+            SwappableStore::new(occlusion::build_store(entries).unwrap())
+        };
+        let reload_state = Arc::new(ReloadState::new(
+            DataSource::parse("tenant-a.csv"),
+            LoadOptions::default(),
+            SourceMetadata::default(),
+        ));
+        let mut namespaces = Namespaces::new();
+        namespaces.insert(
+            "tenant-a".to_string(),
+            Namespace {
+                store: store(10),
+                reload_state,
+            },
+        );
+        let rocket = rocket::build()
+            .manage(store(0))
+            .manage(namespaces)
+            .register("/", crate::catchers::catchers())
+            .mount(
+                "/",
+                rocket::routes![routes::check, check, check_get, check_batch, level, stats],
+            );
+        let client = Client::tracked(rocket).unwrap();
+        let object = Uuid::from_u128(1);
+        let check = |path: &str| {
+            let response = client
+                .post(path.to_string())
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"object": "{object}", "visibility_mask": 5}}"#))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<CheckResponse>().unwrap().is_visible
+        };
+
+        assert!(check("/api/v1/check"));
+        assert!(!check("/api/v1/ns/tenant-a/check"));
+
+        let response = client
+            .get(format!("/api/v1/ns/tenant-a/check/{object}?mask=15"))
+            .dispatch();
+        let body: CheckResponse = response.into_json().unwrap();
+        assert!(body.is_visible);
+
+        let response = client
+            .post("/api/v1/ns/tenant-a/check/batch")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"objects": ["{object}"], "visibility_mask": 5}}"#
+            ))
+            .dispatch();
+        let body: BatchCheckResponse = response.into_json().unwrap();
+        assert!(!body.all_visible);
+
+        let response = client
+            .get(format!("/api/v1/ns/tenant-a/level/{object}"))
+            .dispatch();
+        let body: LevelResponse = response.into_json().unwrap();
+        assert_eq!(body.level, Some(10));
+
+        let response = client.get("/api/v1/ns/tenant-a/stats").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: StatsResponse = response.into_json().unwrap();
+        assert_eq!(body.total_uuids, 1);
+
+        let response = client
+            .get(format!("/api/v1/ns/tenant-b/level/{object}"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let body: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert_eq!(body.error.message, r#"unknown namespace "tenant-b""#);
+        let response = client.get("/api/v1/ns/tenant-b/stats").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
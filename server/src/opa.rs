@@ -0,0 +1,201 @@
+//! OPA management API compatibility: the status API and the `occlusion` data document,
+//! so tooling that monitors OPA agents can monitor occlusion the same way.
+//!
+//! The decision endpoints are in [`routes`](crate::routes).
+
+use crate::{
+    ReloadState,
+    decisions::DecisionLog,
+    guards::MaybeState,
+    models::{
+        OcclusionDocument, OpaBundleStatus, OpaLabels, OpaPluginStatus, OpaResponse, OpaResult,
+        OpaStatus,
+    },
+    namespace::Namespaces,
+    routes::decide,
+};
+use occlusion::{Store, SwappableStore};
+use rocket::{State, serde::json::Json};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, OnceLock, atomic::Ordering},
+    time::{Duration, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// Labels of this process: a random ID, fixed for its lifetime, and the server version.
+pub fn labels() -> OpaLabels {
+    static ID: OnceLock<Uuid> = OnceLock::new();
+    OpaLabels {
+        id: *ID.get_or_init(Uuid::new_v4),
+        version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+    }
+}
+
+fn bundle_status(name: &str, store: &SwappableStore, state: &ReloadState) -> OpaBundleStatus {
+    let etag = state
+        .last_load
+        .read()
+        .expect("RwLock poisoned")
+        .as_ref()
+        .and_then(|load| load.etag.clone());
+    let failures = state.consecutive_failures.load(Ordering::Relaxed);
+    OpaBundleStatus {
+        name: name.to_string(),
+        active_revision: etag.unwrap_or_else(|| store.generation().to_string()),
+        last_successful_activation: state.last_reload_at().map(|at| {
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(at)).to_string()
+        }),
+        code: (failures > 0).then(|| "bundle_error".to_string()),
+        message: (failures > 0)
+            .then(|| format!("{failures} reload(s) failed since the last success")),
+    }
+}
+
+fn plugin(ok: bool) -> OpaPluginStatus {
+    OpaPluginStatus {
+        state: Cow::Borrowed(if ok { "OK" } else { "ERROR" }),
+    }
+}
+
+/// OPA status API: the state of every data source, reported as bundles.
+#[get("/v1/status")]
+pub fn status(
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    namespaces: MaybeState<'_, Namespaces>,
+    log: MaybeState<'_, DecisionLog>,
+) -> Json<OpaResult<OpaStatus>> {
+    let default = reload_state.0.map(|state| ("default", &**store, &**state));
+    let bundles: BTreeMap<_, _> = default
+        .into_iter()
+        .chain(
+            namespaces
+                .0
+                .into_iter()
+                .flat_map(Namespaces::iter)
+                .map(|(name, namespace)| (name, &namespace.store, &*namespace.reload_state)),
+        )
+        .map(|(name, store, state)| (name.to_string(), bundle_status(name, store, state)))
+        .collect();
+
+    let mut plugins = BTreeMap::new();
+    plugins.insert(
+        "bundle".to_string(),
+        plugin(bundles.values().all(|bundle| bundle.code.is_none())),
+    );
+    if log.0.is_some() {
+        plugins.insert("decision_logs".to_string(), plugin(true));
+    }
+    Json(OpaResult {
+        result: OpaStatus {
+            labels: labels(),
+            bundles,
+            plugins,
+        },
+    })
+}
+
+/// The read-only `occlusion` document: what is loaded, without the data itself.
+#[get("/v1/data/occlusion")]
+pub fn document(
+    store: &State<SwappableStore>,
+    namespaces: MaybeState<'_, Namespaces>,
+    log: MaybeState<'_, DecisionLog>,
+    remote: Option<SocketAddr>,
+) -> Json<OpaResponse<OcclusionDocument>> {
+    let document = OcclusionDocument {
+        total_uuids: store.len(),
+        visibility_distribution: store.visibility_distribution().into_iter().collect(),
+        generation: store.generation(),
+        namespaces: namespaces
+            .0
+            .into_iter()
+            .flat_map(Namespaces::iter)
+            .map(|(name, namespace)| (name.to_string(), namespace.store.len()))
+            .collect(),
+    };
+    decide("occlusion", &(), document, &log, remote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        loader::LoadOptions,
+        models::HealthResponse,
+        namespace::Namespace,
+        source::{DataSource, SourceMetadata},
+    };
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use std::time::SystemTime;
+
+    fn reload_state() -> Arc<ReloadState> {
+        Arc::new(ReloadState::new(
+            DataSource::parse("data.csv"),
+            LoadOptions::default(),
+            SourceMetadata::default(),
+        ))
+    }
+
+    #[test]
+    fn test_status_and_document() {
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)];
+        let store = SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let state = reload_state();
+        *state.last_success.write().unwrap() = Some(SystemTime::now());
+        let failing = reload_state();
+        failing.consecutive_failures.store(2, Ordering::Relaxed);
+        let mut namespaces = Namespaces::new();
+        namespaces.insert(
+            "tenant-a".to_string(),
+            Namespace {
+                store: SwappableStore::new(occlusion::build_store(vec![]).unwrap()),
+                reload_state: failing.clone(),
+            },
+        );
+
+        let rocket = rocket::build()
+            .manage(store)
+            .manage(state)
+            .manage(namespaces)
+            .mount("/", routes![status, document, crate::routes::health]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+
+        let response = client.get("/v1/status").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let status: OpaResult<OpaStatus> = response.into_json().unwrap();
+        let status = status.result;
+        assert_eq!(status.labels.id, labels().id);
+        let default = &status.bundles["default"];
+        assert_eq!(default.active_revision, "0");
+        assert!(default.last_successful_activation.is_some());
+        assert!(default.code.is_none());
+        let tenant = &status.bundles["tenant-a"];
+        assert_eq!(tenant.code.as_deref(), Some("bundle_error"));
+        assert_eq!(status.plugins["bundle"].state, "ERROR");
+        assert!(!status.plugins.contains_key("decision_logs"));
+
+        let response = client.get("/v1/data/occlusion").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let document: OpaResponse<OcclusionDocument> = response.into_json().unwrap();
+        assert_eq!(document.result.total_uuids, 2);
+        assert_eq!(document.result.visibility_distribution[&5], 1);
+        assert_eq!(document.result.namespaces["tenant-a"], 0);
+
+        // OPA health checks
+        let response = client.get("/health?bundles").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let health: HealthResponse = response.into_json().unwrap();
+        assert_eq!(health.uuid_count, 2);
+        let response = client.get("/health?plugins").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+
+        failing.consecutive_failures.store(0, Ordering::Relaxed);
+        let response = client.get("/health?bundles&plugins").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
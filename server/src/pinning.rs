@@ -0,0 +1,101 @@
+//! Generation pinning: several requests answered from the same data.
+//!
+//! Clients paging through a listing or splitting a large batch over several requests send
+//! the generation of their first response back in `X-Occlusion-Generation`, and the later
+//! requests are answered from that generation's data even if a reload happened meanwhile.
+//! The current generation is always available, earlier ones only while the store retains
+//! them (`--retain-generations`). Requests pinned to a generation that is not available are
+//! rejected with a 409, so that the client starts over rather than mix decisions made before
+//! and after a reload.
+
+use crate::{
+    cache::GENERATION_HEADER,
+    catchers::{ApiError, set_error_message},
+};
+use occlusion::SwappableStore;
+use rocket::{
+    Request,
+    http::Status,
+    request::{FromRequest, Outcome},
+};
+use std::borrow::Cow;
+
+/// The generation a request asks to be answered from, if any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pin(pub Option<u64>);
+
+impl Pin {
+    /// The pin of `request`, or why its generation header is invalid.
+    pub fn of(request: &Request<'_>) -> Result<Self, String> {
+        match request.headers().get_one(GENERATION_HEADER) {
+            None => Ok(Self(None)),
+            Some(value) => value.trim().parse().map(|n| Self(Some(n))).map_err(|_| {
+                format!("invalid {GENERATION_HEADER} header {value:?}: expected a generation")
+            }),
+        }
+    }
+
+    /// The data to answer from: `live`, or a copy of the pinned generation's data.
+    ///
+    /// Fails with a 409 when the pinned generation is neither current nor retained.
+    pub fn store(self, live: &SwappableStore) -> Result<Cow<'_, SwappableStore>, ApiError> {
+        let Some(generation) = self.0 else {
+            return Ok(Cow::Borrowed(live));
+        };
+        live.at_generation(generation)
+            .map(Cow::Owned)
+            .ok_or_else(|| {
+                ApiError::new(
+                    Status::Conflict,
+                    format!(
+                        "generation {generation} is not available (current generation is {})",
+                        live.generation()
+                    ),
+                )
+            })
+    }
+}
+
+/// Fails with a 400 when the generation header is not a generation number.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Pin {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match Self::of(request) {
+            Ok(pin) => Outcome::Success(pin),
+            Err(message) => {
+                set_error_message(request, message);
+                Outcome::Error((Status::BadRequest, "invalid generation header"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use occlusion::Store;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_pin() {
+        let store = SwappableStore::new(occlusion::build_store(vec![]).unwrap());
+        store.set_retention(1);
+        store.upsert(Uuid::from_u128(1), 3);
+        store.upsert(Uuid::from_u128(1), 5);
+
+        assert!(matches!(Pin(None).store(&store), Ok(Cow::Borrowed(_))));
+        let pinned = Pin(Some(1)).store(&store).unwrap();
+        assert_eq!(pinned.generation(), 1);
+        assert_eq!(pinned.get_visibility(&Uuid::from_u128(1)), Some(3));
+        assert_eq!(Pin(Some(2)).store(&store).unwrap().generation(), 2);
+
+        let e = Pin(Some(0)).store(&store).err().unwrap();
+        assert_eq!(e.status, Status::Conflict);
+        assert_eq!(
+            e.message,
+            "generation 0 is not available (current generation is 2)"
+        );
+    }
+}
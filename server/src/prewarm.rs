@@ -0,0 +1,42 @@
+//! Warm-up of reloaded stores before they are swapped in.
+//!
+//! A freshly built store is cold: its hash buckets are not in the CPU caches, and with a large
+//! store its pages may not even be faulted in yet, so the first requests after a swap see
+//! latency spikes. Looking up a sample of the UUIDs the current store serves, which are the
+//! ones clients ask about, brings the parts of the new store they hit into the caches before
+//! any request does.
+
+use occlusion::{ActiveStore, Store, SwappableStore};
+use rand::seq::IteratorRandom;
+
+/// Look up `lookups` UUIDs sampled from `current` in `new`, returning how many were found.
+pub fn warm(new: &ActiveStore, current: &SwappableStore, lookups: usize) -> usize {
+    let sample = current
+        .entries()
+        .into_iter()
+        .map(|(uuid, _)| uuid)
+        .choose_multiple(&mut rand::rng(), lookups);
+    sample
+        .iter()
+        .filter(|uuid| std::hint::black_box(new.get_visibility(uuid)).is_some())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_warm() {
+        let entries: Vec<_> = (0..100).map(|n| (Uuid::from_u128(n), 1)).collect();
+        let current = SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let new: Vec<_> = (50..150).map(|n| (Uuid::from_u128(n), 2)).collect();
+        let new = occlusion::build_store(new).unwrap();
+
+        assert_eq!(warm(&new, &current, 0), 0);
+        // Every UUID of the current store is looked up, half of them are still there
+        assert_eq!(warm(&new, &current, 1000), 50);
+        assert!(warm(&new, &current, 10) <= 10);
+    }
+}
@@ -0,0 +1,232 @@
+//! Progress reporting for long-running loads.
+
+use crate::format::{MAX_REJECTED_SAMPLES, ParseReport, RejectedRow};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tracing::info;
+
+/// Number of parsed rows between progress updates.
+pub(crate) const PROGRESS_ROWS: usize = 100_000;
+
+/// Stage of a load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadPhase {
+    /// No load in progress
+    #[default]
+    Idle,
+    /// Reading the file or downloading the response body
+    Fetch,
+    /// Decompressing gzip/zstd content
+    Decompress,
+    /// Parsing rows
+    Parse,
+    /// Building the store
+    Build,
+}
+
+impl std::fmt::Display for LoadPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Idle => write!(f, "idle"),
+            Self::Fetch => write!(f, "fetch"),
+            Self::Decompress => write!(f, "decompress"),
+            Self::Parse => write!(f, "parse"),
+            Self::Build => write!(f, "build"),
+        }
+    }
+}
+
+/// Snapshot of the current load.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Current stage
+    pub phase: LoadPhase,
+    /// Bytes read or downloaded so far
+    pub bytes_fetched: u64,
+    /// Total size, when known (file size or `Content-Length`)
+    pub total_bytes: Option<u64>,
+    /// Rows parsed so far (updated every 100,000 rows)
+    pub rows_parsed: usize,
+    /// Malformed rows skipped so far
+    pub rows_rejected: usize,
+    /// The first malformed rows skipped (at most 20)
+    pub rejected: Vec<RejectedRow>,
+}
+
+/// Time spent in the CPU-bound stages of the most recent load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Decompressing gzip/zstd content
+    pub decompress: Duration,
+    /// Parsing rows
+    pub parse: Duration,
+    /// Building the store
+    pub build: Duration,
+}
+
+/// Tracks how long each phase of a load takes.
+#[derive(Debug)]
+struct PhaseTimer {
+    phase: LoadPhase,
+    since: Instant,
+    timings: PhaseTimings,
+}
+
+impl PhaseTimer {
+    /// Close the current phase and enter `phase`.
+    fn enter(&mut self, phase: LoadPhase) {
+        let now = Instant::now();
+        let elapsed = now - self.since;
+        match self.phase {
+            LoadPhase::Decompress => self.timings.decompress += elapsed,
+            LoadPhase::Parse => self.timings.parse += elapsed,
+            LoadPhase::Build => self.timings.build += elapsed,
+            LoadPhase::Idle | LoadPhase::Fetch => {}
+        }
+        self.phase = phase;
+        self.since = now;
+    }
+}
+
+/// Publishes [`LoadProgress`] updates to a watch channel.
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    tx: Arc<watch::Sender<LoadProgress>>,
+    timer: Arc<Mutex<PhaseTimer>>,
+}
+
+impl ProgressReporter {
+    /// Create a reporter and the receiver observing it.
+    pub fn channel() -> (Self, watch::Receiver<LoadProgress>) {
+        let (tx, rx) = watch::channel(LoadProgress::default());
+        let timer = PhaseTimer {
+            phase: LoadPhase::Idle,
+            since: Instant::now(),
+            timings: PhaseTimings::default(),
+        };
+        let reporter = Self {
+            tx: Arc::new(tx),
+            timer: Arc::new(Mutex::new(timer)),
+        };
+        (reporter, rx)
+    }
+
+    /// Current progress (or that of the last load, once finished).
+    pub fn current(&self) -> LoadProgress {
+        self.tx.borrow().clone()
+    }
+
+    /// Time spent in each phase of the current (or last) load.
+    pub fn timings(&self) -> PhaseTimings {
+        self.timer.lock().expect("Mutex poisoned").timings
+    }
+
+    fn enter(&self, phase: LoadPhase) {
+        self.timer.lock().expect("Mutex poisoned").enter(phase);
+    }
+
+    /// Reset progress for a new fetch.
+    pub(crate) fn start(&self, total_bytes: Option<u64>) {
+        {
+            let mut timer = self.timer.lock().expect("Mutex poisoned");
+            timer.enter(LoadPhase::Fetch);
+            timer.timings = PhaseTimings::default();
+        }
+        self.tx.send_replace(LoadProgress {
+            phase: LoadPhase::Fetch,
+            total_bytes,
+            ..LoadProgress::default()
+        });
+    }
+
+    pub(crate) fn set_phase(&self, phase: LoadPhase) {
+        self.enter(phase);
+        self.tx.send_modify(|p| p.phase = phase);
+    }
+
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.tx.send_modify(|p| p.bytes_fetched += bytes);
+    }
+
+    pub(crate) fn set_rows(&self, rows: usize) {
+        self.tx.send_modify(|p| p.rows_parsed = rows);
+    }
+
+    /// Record the malformed rows skipped by a parse.
+    pub(crate) fn add_rejected(&self, report: &ParseReport) {
+        self.tx.send_modify(|p| {
+            p.rows_rejected += report.rejected_count;
+            let room = MAX_REJECTED_SAMPLES.saturating_sub(p.rejected.len());
+            p.rejected
+                .extend(report.rejected.iter().take(room).cloned());
+        });
+    }
+
+    /// Mark the load as finished (or failed).
+    pub(crate) fn finish(&self) {
+        self.set_phase(LoadPhase::Idle);
+    }
+}
+
+/// Log load progress every `interval` while a load is running.
+///
+/// Runs until the reporter is dropped.
+pub async fn log_progress(mut rx: watch::Receiver<LoadProgress>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        match rx.has_changed() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => return,
+        }
+
+        let progress = rx.borrow_and_update().clone();
+        if progress.phase == LoadPhase::Idle {
+            continue;
+        }
+        info!(
+            phase = %progress.phase,
+            bytes_fetched = progress.bytes_fetched,
+            total_bytes = progress.total_bytes,
+            rows_parsed = progress.rows_parsed,
+            "load progress"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reporter_updates() {
+        let (reporter, rx) = ProgressReporter::channel();
+
+        reporter.start(Some(100));
+        reporter.add_bytes(60);
+        reporter.add_bytes(40);
+        reporter.set_phase(LoadPhase::Parse);
+        reporter.set_rows(PROGRESS_ROWS);
+
+        assert_eq!(
+            *rx.borrow(),
+            LoadProgress {
+                phase: LoadPhase::Parse,
+                bytes_fetched: 100,
+                total_bytes: Some(100),
+                rows_parsed: PROGRESS_ROWS,
+                ..LoadProgress::default()
+            }
+        );
+
+        reporter.start(None);
+        assert_eq!(rx.borrow().bytes_fetched, 0);
+        assert_eq!(rx.borrow().phase, LoadPhase::Fetch);
+    }
+}
@@ -0,0 +1,130 @@
+//! HTTP remote authority for tiered stores.
+
+use crate::models::LevelResponse;
+use occlusion::{RemoteAuthority, StoreError, SwappableStore, TieredStore};
+use reqwest::{StatusCode, Url};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longest time a remote lookup may take before the object is treated as not visible.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The live store, backed by a remote authority for the objects it lacks.
+///
+/// Managed by Rocket when `--remote-authority` is set.
+pub type Remote = TieredStore<SwappableStore, HttpAuthority>;
+
+/// Remote authority backed by another occlusion server's `/api/v1/level/<object>` endpoint.
+///
+/// Used with [`occlusion::TieredStore`] so an edge instance holding only a subset
+/// of the data can defer unknown UUIDs to a central instance. Objects the remote does
+/// not know either (404) are not visible.
+#[derive(Debug, Clone)]
+pub struct HttpAuthority {
+    client: reqwest::Client,
+    base_url: Url,
+}
+
+impl HttpAuthority {
+    /// Create a new `HttpAuthority` for the server at `base_url` (e.g. `http://central:8000`).
+    pub fn new(base_url: Url) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        Self { client, base_url }
+    }
+
+    fn level_url(&self, uuid: &Uuid) -> occlusion::Result<Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|()| StoreError::Remote(format!("invalid remote URL {}", self.base_url)))?
+            .pop_if_empty()
+            .extend(["api", "v1", "level", &uuid.to_string()]);
+        Ok(url)
+    }
+}
+
+impl RemoteAuthority for HttpAuthority {
+    async fn is_visible(&self, uuid: &Uuid, mask: u8) -> occlusion::Result<bool> {
+        let response = self
+            .client
+            .get(self.level_url(uuid)?)
+            .send()
+            .await
+            .map_err(|e| StoreError::Remote(e.to_string()))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        let body: LevelResponse = response
+            .error_for_status()
+            .map_err(|e| StoreError::Remote(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| StoreError::Remote(e.to_string()))?;
+
+        Ok(body.level.is_some_and(|level| level <= mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::tests::serve;
+
+    fn response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_is_visible() {
+        let uuid = Uuid::from_u128(1);
+        let known = format!(r#"{{"object":"{uuid}","level":3}}"#);
+        let unknown = format!(r#"{{"object":"{uuid}","level":null}}"#);
+        let (url, heads) = serve(vec![
+            response("200 OK", &known),
+            response("200 OK", &known),
+            response("404 Not Found", &unknown),
+            response("503 Service Unavailable", ""),
+        ]);
+        let base = url.trim_end_matches("data.csv").parse().unwrap();
+        let remote = HttpAuthority::new(base);
+
+        assert!(remote.is_visible(&uuid, 3).await.unwrap());
+        assert!(
+            heads
+                .recv()
+                .unwrap()
+                .starts_with(&format!("GET /api/v1/level/{uuid} "))
+        );
+        assert!(!remote.is_visible(&uuid, 2).await.unwrap());
+        // Unknown to the remote too
+        assert!(!remote.is_visible(&uuid, 255).await.unwrap());
+        assert!(matches!(
+            remote.is_visible(&uuid, 255).await,
+            Err(StoreError::Remote(_))
+        ));
+    }
+
+    #[test]
+    fn test_level_url() {
+        let uuid = Uuid::from_u128(1);
+        for base in ["http://central:8000", "http://central:8000/"] {
+            let remote = HttpAuthority::new(base.parse().unwrap());
+            assert_eq!(
+                remote.level_url(&uuid).unwrap().as_str(),
+                format!("http://central:8000/api/v1/level/{uuid}")
+            );
+        }
+        let remote = HttpAuthority::new("http://central:8000/occlusion/".parse().unwrap());
+        assert_eq!(
+            remote.level_url(&uuid).unwrap().as_str(),
+            format!("http://central:8000/occlusion/api/v1/level/{uuid}")
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Request IDs, for correlating occlusion's logs with its callers'.
+//!
+//! A request's ID is taken from its `X-Request-Id` header, or generated if it has none, and
+//! echoed in the response's. It is included in error bodies, the request log line, and the
+//! access and audit logs.
+
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+};
+use uuid::Uuid;
+
+/// Header carrying request IDs.
+pub const HEADER: &str = "X-Request-Id";
+
+/// Longest request ID accepted from a client; longer ones are replaced.
+const MAX_LEN: usize = 200;
+
+/// A request's ID, cached for the request.
+struct RequestId(String);
+
+/// The ID of `request`: its `X-Request-Id`, if usable, or a generated UUID.
+pub fn of<'r>(request: &'r Request<'_>) -> &'r str {
+    &request
+        .local_cache(|| {
+            let id = request
+                .headers()
+                .get_one(HEADER)
+                .filter(|id| is_valid(id))
+                .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+            RequestId(id)
+        })
+        .0
+}
+
+/// Whether a client-supplied ID is safe to log and echo: visible ASCII, of reasonable length.
+fn is_valid(id: &str) -> bool {
+    (1..=MAX_LEN).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Fairing echoing each request's ID in the `X-Request-Id` response header.
+pub struct RequestIds;
+
+#[rocket::async_trait]
+impl Fairing for RequestIds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request IDs",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_header(Header::new(HEADER, of(request).to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::ContentType;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn test_request_id() {
+        let entries = vec![(Uuid::from_u128(1), 10)];
+        let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let rocket = rocket::build()
+            .manage(store)
+            .attach(RequestIds)
+            .mount("/", rocket::routes![crate::routes::check]);
+        let client = Client::tracked(rocket).unwrap();
+        let check = |body: &str, id: Option<&str>| {
+            let mut request = client.post("/api/v1/check").header(ContentType::JSON);
+            if let Some(id) = id {
+                request = request.header(Header::new(HEADER, id.to_string()));
+            }
+            let response = request.body(body).dispatch();
+            let id = response.headers().get_one(HEADER).unwrap().to_string();
+            (id, response.into_json::<serde_json::Value>().unwrap())
+        };
+        let object = Uuid::from_u128(1);
+
+        let (id, _) = check(
+            &format!(r#"{{"object": "{object}", "visibility_mask": 5}}"#),
+            Some("req-1"),
+        );
+        assert_eq!(id, "req-1");
+
+        let (id, _) = check(
+            &format!(r#"{{"object": "{object}", "visibility_mask": 5}}"#),
+            Some("not valid"),
+        );
+        assert!(Uuid::parse_str(&id).is_ok());
+
+        let (id, body) = check(r#"{"object": "nope", "visibility_mask": 5}"#, None);
+        assert!(Uuid::parse_str(&id).is_ok());
+        assert_eq!(body["error"]["request_id"], id);
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid("req-1234"));
+        assert!(is_valid(&Uuid::new_v4().to_string()));
+        assert!(!is_valid(""));
+        assert!(!is_valid("with space"));
+        assert!(!is_valid("line\nbreak"));
+        assert!(!is_valid(&"x".repeat(MAX_LEN + 1)));
+    }
+}
@@ -0,0 +1,624 @@
+//! Redis protocol (RESP2) listener, for callers that want the least overhead per check.
+//!
+//! Any Redis client library can connect to the address given with `--resp-listen` and send:
+//!
+//! - `GET <object>`: the object's level as a bulk string, nil if unknown
+//! - `MGET <object> [object ...]`: the levels of several objects
+//! - `OCC.CHECK <object> <mask>`: 1 if the object is visible under `mask`, 0 otherwise
+//! - `AUTH [username] <jwt>`, `PING [message]` and `QUIT`
+//!
+//! Commands may be pipelined: replies are only flushed once every command already received is
+//! answered. Commands are answered from the default store. When JWT visibility masks are
+//! enabled, connections must `AUTH` with a token first, and `OCC.CHECK` uses its mask instead
+//! of the one given, as over gRPC. Lookups count against the data-plane `--max-in-flight` limit
+//! and, with `--reject-stale`, are refused while the default store's data is stale.
+
+use crate::{
+    ReloadState,
+    admission::{ConcurrencyLimits, RouteClass, Slot},
+    audit::AuditLog,
+    ids::{IdNamespace, ObjectId},
+    jwt::{Grant, JwtVerifier},
+    models::Decision,
+};
+use occlusion::{Store, SwappableStore};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Longest line (inline command or RESP header) accepted.
+const MAX_LINE: u64 = 64 * 1024;
+
+/// Longest argument accepted: object IDs and tokens are much shorter.
+const MAX_ARGUMENT: usize = 16 * 1024;
+
+/// Most arguments of a command when batches are unlimited (`--max-batch-size 0`).
+const MAX_ARGUMENTS: usize = 1 << 20;
+
+/// Arguments allocated for ahead of reading them: larger commands grow as they arrive, rather
+/// than reserving memory for whatever count the client claims.
+const PREALLOCATED_ARGUMENTS: usize = 1024;
+
+/// A reply to a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    /// A bulk string, or nil
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn error(message: impl std::fmt::Display) -> Self {
+        Self::Error(format!("ERR {message}"))
+    }
+
+    fn level(level: Option<u8>) -> Self {
+        Self::Bulk(level.map(|level| level.to_string()))
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Simple(text) => out.extend_from_slice(format!("+{text}\r\n").as_bytes()),
+            // Line breaks would end the error early
+            Self::Error(message) => out.extend_from_slice(
+                format!("-{}\r\n", message.replace(['\r', '\n'], " ")).as_bytes(),
+            ),
+            Self::Integer(n) => out.extend_from_slice(format!(":{n}\r\n").as_bytes()),
+            Self::Bulk(None) => out.extend_from_slice(b"$-1\r\n"),
+            Self::Bulk(Some(text)) => {
+                out.extend_from_slice(format!("${}\r\n{text}\r\n", text.len()).as_bytes());
+            }
+            Self::Array(replies) => {
+                out.extend_from_slice(format!("*{}\r\n", replies.len()).as_bytes());
+                for reply in replies {
+                    reply.write(out);
+                }
+            }
+        }
+    }
+}
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Read a line into `line`, without its line break. Returns `false` at the end of the stream.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+) -> io::Result<bool> {
+    line.clear();
+    let read = reader.take(MAX_LINE).read_until(b'\n', line).await?;
+    if read == 0 {
+        return Ok(false);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(protocol_error("line too long or truncated"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(true)
+}
+
+/// Parse the number following the type byte of a RESP header.
+fn header_number(line: &[u8], kind: u8) -> io::Result<i64> {
+    match line.split_first() {
+        Some((&first, rest)) if first == kind => std::str::from_utf8(rest)
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| {
+                protocol_error(format!(
+                    "invalid length {:?}",
+                    String::from_utf8_lossy(rest)
+                ))
+            }),
+        _ => Err(protocol_error(format!(
+            "expected '{}', got {:?}",
+            char::from(kind),
+            String::from_utf8_lossy(line)
+        ))),
+    }
+}
+
+/// Read the next command: an array of bulk strings, or an inline command (space-separated
+/// words on a line). Returns `None` at the end of the stream.
+///
+/// Commands of more than `max_arguments` arguments are a protocol error, so that oversized
+/// batches are rejected before they are read.
+async fn read_command<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_arguments: usize,
+) -> io::Result<Option<Vec<String>>> {
+    let too_many = |count: usize| {
+        protocol_error(format!(
+            "command of {count} arguments exceeds the limit of {max_arguments}"
+        ))
+    };
+    let mut line = Vec::new();
+    loop {
+        if !read_line(reader, &mut line).await? {
+            return Ok(None);
+        }
+        if line.first() == Some(&b'*') {
+            break;
+        }
+        let words: Vec<String> = String::from_utf8_lossy(&line)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        if words.len() > max_arguments {
+            return Err(too_many(words.len()));
+        }
+        // Empty lines are skipped, as by Redis
+        if !words.is_empty() {
+            return Ok(Some(words));
+        }
+    }
+
+    let count = usize::try_from(header_number(&line, b'*')?).unwrap_or(0);
+    if count > max_arguments {
+        return Err(too_many(count));
+    }
+    let mut arguments = Vec::with_capacity(count.min(PREALLOCATED_ARGUMENTS));
+    for _ in 0..count {
+        if !read_line(reader, &mut line).await? {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let len = usize::try_from(header_number(&line, b'$')?)
+            .map_err(|_| protocol_error("invalid bulk length"))?;
+        if len > MAX_ARGUMENT {
+            return Err(protocol_error(format!(
+                "argument of {len} bytes exceeds the limit of {MAX_ARGUMENT}"
+            )));
+        }
+        let mut argument = vec![0; len + 2];
+        reader.read_exact(&mut argument).await?;
+        if !argument.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string not followed by CRLF"));
+        }
+        argument.truncate(len);
+        arguments.push(String::from_utf8_lossy(&argument).into_owned());
+    }
+    Ok(Some(arguments))
+}
+
+/// State of one client connection.
+struct Connection {
+    remote: Option<IpAddr>,
+    /// What the connection authenticated as, when JWT masks are enabled
+    grant: Option<Grant>,
+}
+
+/// The RESP service, answering from the default store.
+pub struct RespService {
+    store: SwappableStore,
+    ids: Option<IdNamespace>,
+    /// Most objects in one `MGET` (0 = unlimited)
+    max_batch_size: usize,
+    /// Verifier of the tokens connections authenticate with, if enabled
+    jwt: Option<Arc<JwtVerifier>>,
+    audit: Option<AuditLog>,
+    /// Limits shared with the HTTP data plane, if enabled
+    limits: Option<ConcurrencyLimits>,
+    /// State of the default store, when lookups are refused while its data is stale
+    reject_stale: Option<Arc<ReloadState>>,
+}
+
+impl RespService {
+    pub fn new(store: SwappableStore, ids: Option<IdNamespace>, max_batch_size: usize) -> Self {
+        Self {
+            store,
+            ids,
+            max_batch_size,
+            jwt: None,
+            audit: None,
+            limits: None,
+            reject_stale: None,
+        }
+    }
+
+    /// Require connections to `AUTH` with a JWT, and check objects against its mask.
+    #[must_use]
+    pub fn with_jwt(mut self, verifier: Arc<JwtVerifier>) -> Self {
+        self.jwt = Some(verifier);
+        self
+    }
+
+    /// Record every `OCC.CHECK` decision in the audit log.
+    #[must_use]
+    pub fn with_audit(mut self, log: AuditLog) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Count lookups against the data-plane limit of `limits`.
+    #[must_use]
+    pub fn with_limits(mut self, limits: ConcurrencyLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Refuse lookups while the data loaded by `state` is stale.
+    #[must_use]
+    pub fn with_reject_stale(mut self, state: Arc<ReloadState>) -> Self {
+        self.reject_stale = Some(state);
+        self
+    }
+
+    /// Take a data-plane slot for a lookup, or the error to answer it with while the data is
+    /// stale or the limit is reached.
+    fn admit(&self) -> Result<Option<Slot>, Reply> {
+        if let Some(age) = self
+            .reject_stale
+            .as_ref()
+            .and_then(|state| state.staleness())
+        {
+            return Err(Reply::error(format!(
+                "data is stale: not refreshed for {}s",
+                age.as_secs()
+            )));
+        }
+        let Some(limits) = &self.limits else {
+            return Ok(None);
+        };
+        limits
+            .try_take(RouteClass::DataPlane)
+            .map(Some)
+            .ok_or_else(|| {
+                Reply::error(format!(
+                    "too many {} requests in flight, retry later",
+                    RouteClass::DataPlane
+                ))
+            })
+    }
+
+    fn resolve(&self, id: &str) -> Result<Uuid, Reply> {
+        ObjectId::from(id)
+            .resolve(self.ids.as_ref())
+            .ok_or_else(|| Reply::error(format!("invalid object ID {id:?}: expected a UUID")))
+    }
+
+    /// Answer one command, or return `None` to close the connection after `QUIT`.
+    async fn execute(&self, connection: &mut Connection, arguments: &[String]) -> Option<Reply> {
+        let Some((name, arguments)) = arguments.split_first() else {
+            return Some(Reply::error("empty command"));
+        };
+        let name = name.to_ascii_uppercase();
+        let wrong_arity = || {
+            Reply::error(format!(
+                "wrong number of arguments for '{}' command",
+                name.to_lowercase()
+            ))
+        };
+        let authenticated = self.jwt.is_none() || connection.grant.is_some();
+        // Held until the lookup is answered
+        let _slot = if authenticated && matches!(name.as_str(), "GET" | "MGET" | "OCC.CHECK") {
+            match self.admit() {
+                Ok(slot) => slot,
+                Err(reply) => return Some(reply),
+            }
+        } else {
+            None
+        };
+        let reply = match (name.as_str(), arguments) {
+            ("QUIT", _) => return None,
+            ("PING", []) => Reply::Simple("PONG"),
+            ("PING", [message]) => Reply::Bulk(Some(message.clone())),
+            ("AUTH", [token] | [_, token]) => self.auth(connection, token).await,
+            ("PING" | "AUTH", _) => wrong_arity(),
+            _ if !authenticated => Reply::Error("NOAUTH Authentication required.".to_string()),
+            ("GET", [object]) => match self.resolve(object) {
+                Ok(object) => Reply::level(self.store.get_visibility(&object)),
+                Err(e) => e,
+            },
+            ("MGET", objects) if !objects.is_empty() => self.mget(objects),
+            ("OCC.CHECK", [object, mask]) => self.check(connection, object, mask),
+            ("GET" | "MGET" | "OCC.CHECK", _) => wrong_arity(),
+            _ => Reply::error(format!("unknown command '{}'", name.to_lowercase())),
+        };
+        Some(reply)
+    }
+
+    async fn auth(&self, connection: &mut Connection, token: &str) -> Reply {
+        let Some(verifier) = &self.jwt else {
+            return Reply::error(
+                "AUTH called without any password configured for the default user",
+            );
+        };
+        match verifier.verify(token).await {
+            Ok(grant) => {
+                connection.grant = Some(grant);
+                Reply::Simple("OK")
+            }
+            Err(e) => {
+                connection.grant = None;
+                Reply::Error(format!("WRONGPASS {e}"))
+            }
+        }
+    }
+
+    fn mget(&self, objects: &[String]) -> Reply {
+        let objects = match objects
+            .iter()
+            .map(|id| self.resolve(id))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(objects) => objects,
+            Err(e) => return e,
+        };
+        let (_, levels) = self.store.get_visibilities(&objects);
+        Reply::Array(levels.into_iter().map(Reply::level).collect())
+    }
+
+    fn check(&self, connection: &Connection, object: &str, mask: &str) -> Reply {
+        let object = match self.resolve(object) {
+            Ok(object) => object,
+            Err(e) => return e,
+        };
+        let Ok(mask) = mask.parse::<u8>() else {
+            return Reply::error(format!("invalid visibility mask {mask:?}: expected 0-255"));
+        };
+        let mask = connection.grant.as_ref().map_or(mask, |grant| grant.mask);
+        let (generation, levels) = self.store.get_visibilities(&[object]);
+        let decision = Decision::new(levels[0], mask);
+        if let Some(audit) = &self.audit {
+            let subject = connection
+                .grant
+                .as_ref()
+                .and_then(|grant| grant.subject.as_deref());
+            audit.record(
+                None,
+                "OCC.CHECK",
+                subject,
+                connection.remote,
+                object,
+                mask,
+                decision,
+                generation,
+            );
+        }
+        Reply::Integer(i64::from(decision == Decision::Visible))
+    }
+
+    /// Answer the commands of one connection until it is closed.
+    pub async fn handle<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+        remote: Option<IpAddr>,
+    ) -> io::Result<()> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = tokio::io::BufReader::new(reader);
+        let mut connection = Connection {
+            remote,
+            grant: None,
+        };
+        // The command name, plus as many objects as a batch may hold
+        let max_arguments = match self.max_batch_size {
+            0 => MAX_ARGUMENTS,
+            max => max.saturating_add(1),
+        };
+        let mut out = Vec::new();
+        loop {
+            let command = match read_command(&mut reader, max_arguments).await {
+                Ok(Some(command)) => command,
+                // Clients may hang up in the middle of a command
+                Ok(None) => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    Reply::error(format!("Protocol error: {e}")).write(&mut out);
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let Some(reply) = self.execute(&mut connection, &command).await else {
+                Reply::Simple("OK").write(&mut out);
+                break;
+            };
+            reply.write(&mut out);
+            // Pipelined commands are answered together
+            if reader.buffer().is_empty() {
+                writer.write_all(&out).await?;
+                out.clear();
+            }
+        }
+        writer.write_all(&out).await?;
+        writer.shutdown().await
+    }
+}
+
+/// Serve the RESP API on `addr` until the process exits.
+pub async fn serve(addr: SocketAddr, service: RespService) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    let service = Arc::new(service);
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let _ = stream.set_nodelay(true);
+        let service = service.clone();
+        tokio::spawn(async move {
+            match service.handle(stream, Some(remote.ip())).await {
+                Ok(()) => debug!(%remote, "RESP connection closed"),
+                Err(e) => warn!(%remote, error = %e, "RESP connection failed"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    fn service() -> RespService {
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 10)];
+        let store = SwappableStore::new(occlusion::build_store(entries).unwrap());
+        RespService::new(store, None, 2)
+    }
+
+    /// Send `input` on a connection and return everything answered.
+    async fn exchange(service: &RespService, input: &[u8]) -> String {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (mut client_reader, mut client_writer) = tokio::io::split(client);
+        client_writer.write_all(input).await.unwrap();
+        client_writer.shutdown().await.unwrap();
+        service.handle(server, None).await.unwrap();
+        let mut output = String::new();
+        client_reader.read_to_string(&mut output).await.unwrap();
+        output
+    }
+
+    fn command(arguments: &[&str]) -> String {
+        let mut command = format!("*{}\r\n", arguments.len());
+        for argument in arguments {
+            let _ = write!(command, "${}\r\n{argument}\r\n", argument.len());
+        }
+        command
+    }
+
+    #[tokio::test]
+    async fn test_commands() {
+        let service = service();
+        let id = |n: u128| Uuid::from_u128(n).to_string();
+
+        let input = [
+            command(&["GET", &id(2)]),
+            command(&["get", &id(3)]),
+            command(&["MGET", &id(1), &id(3)]),
+            command(&["OCC.CHECK", &id(2), "10"]),
+            command(&["occ.check", &id(2), "5"]),
+            command(&["OCC.CHECK", &id(2)]),
+            command(&["OCC.CHECK", &id(2), "256"]),
+            command(&["GET", "order-1"]),
+            command(&["FLUSHALL"]),
+            // Inline commands, as typed in a terminal
+            format!("PING\r\n\r\nGET {}\n", id(1)),
+            // Batches over the limit end the connection
+            command(&["MGET", &id(1), &id(2), &id(3)]),
+            command(&["PING"]),
+        ]
+        .concat();
+        let expected = [
+            "$2\r\n10\r\n",
+            "$-1\r\n",
+            "*2\r\n$1\r\n0\r\n$-1\r\n",
+            ":1\r\n",
+            ":0\r\n",
+            "-ERR wrong number of arguments for 'occ.check' command\r\n",
+            "-ERR invalid visibility mask \"256\": expected 0-255\r\n",
+            "-ERR invalid object ID \"order-1\": expected a UUID\r\n",
+            "-ERR unknown command 'flushall'\r\n",
+            "+PONG\r\n$1\r\n0\r\n",
+            "-ERR Protocol error: command of 4 arguments exceeds the limit of 3\r\n",
+        ]
+        .concat();
+        assert_eq!(exchange(&service, input.as_bytes()).await, expected);
+
+        // Commands after QUIT are not answered
+        let input = format!("{}QUIT\r\nPING\r\n", command(&["PING", "hi"]));
+        assert_eq!(
+            exchange(&service, input.as_bytes()).await,
+            "$2\r\nhi\r\n+OK\r\n"
+        );
+        assert_eq!(
+            exchange(&service, &command(&["AUTH", "token"]).into_bytes()).await,
+            "-ERR AUTH called without any password configured for the default user\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admission() {
+        use crate::source::{DataSource, SourceMetadata};
+        use std::time::{Duration, SystemTime};
+
+        let state = Arc::new(
+            ReloadState::new(
+                DataSource::parse("data.csv"),
+                crate::loader::LoadOptions::default(),
+                SourceMetadata::new(),
+            )
+            .with_max_staleness(Duration::from_hours(1)),
+        );
+        let limits = ConcurrencyLimits::new(1, 0, 1);
+        let service = service()
+            .with_limits(limits.clone())
+            .with_reject_stale(state.clone());
+        let get = command(&["GET", &Uuid::from_u128(2).to_string()]);
+        assert_eq!(exchange(&service, get.as_bytes()).await, "$2\r\n10\r\n");
+
+        // The slot is shared with HTTP requests in flight
+        let held = limits.try_take(RouteClass::DataPlane).unwrap();
+        assert_eq!(
+            exchange(&service, get.as_bytes()).await,
+            "-ERR too many data-plane requests in flight, retry later\r\n"
+        );
+        drop(held);
+
+        *state.last_success.write().unwrap() = Some(SystemTime::now() - Duration::from_mins(61));
+        let input = format!("{get}PING\r\n");
+        assert!(
+            exchange(&service, input.as_bytes())
+                .await
+                .starts_with("-ERR data is stale: not refreshed for 36"),
+        );
+        assert!(
+            exchange(&service, input.as_bytes())
+                .await
+                .ends_with("\r\n+PONG\r\n"),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protocol_errors() {
+        let service = &service();
+        let answered =
+            |input: &'static str| async move { exchange(service, input.as_bytes()).await };
+
+        assert_eq!(
+            answered("*1\r\n$4\r\nPINGxx").await,
+            "-ERR Protocol error: bulk string not followed by CRLF\r\n"
+        );
+        assert_eq!(
+            answered("*2\r\n:4\r\n").await,
+            "-ERR Protocol error: expected '$', got \":4\"\r\n"
+        );
+        // The command name and at most 2 objects
+        assert_eq!(
+            answered("*4\r\n").await,
+            "-ERR Protocol error: command of 4 arguments exceeds the limit of 3\r\n"
+        );
+        assert_eq!(
+            answered("*1\r\n$99999\r\n").await,
+            format!(
+                "-ERR Protocol error: argument of 99999 bytes exceeds the limit of {MAX_ARGUMENT}\r\n"
+            )
+        );
+        let unlimited = RespService::new(service.store.clone(), None, 0);
+        assert_eq!(
+            exchange(&unlimited, b"*100000000000\r\n").await,
+            format!(
+                "-ERR Protocol error: command of 100000000000 arguments exceeds the limit of {MAX_ARGUMENTS}\r\n"
+            )
+        );
+        // Claimed counts are not allocated for up front
+        assert!(
+            exchange(&unlimited, format!("*{MAX_ARGUMENTS}\r\n").as_bytes())
+                .await
+                .is_empty()
+        );
+        assert!(
+            answered("*1\r\n$4\r\nPI").await.is_empty(),
+            "truncated commands close the connection"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Families of HTTP routes that can be enabled separately.
+//!
+//! Deployments only mount the groups they use (`--route-groups`), so that e.g. an OPA-only
+//! deployment does not expose the native API at all. Health checks and probes are always
+//! mounted. The gRPC and Redis-protocol APIs have their own listeners, off unless configured.
+
+use crate::{admin, events, namespaced, opa, routes, v2, websocket};
+use rocket::Route;
+
+/// A family of routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum RouteGroup {
+    /// Checks and lookups of the v1 and v2 APIs, including namespace-scoped ones
+    Native,
+    /// The OPA-compatible API
+    Opa,
+    /// The admin API, and listing UUIDs by level
+    Admin,
+    /// Store statistics and data generation
+    Stats,
+    /// Store events, as server-sent events or over WebSocket
+    Events,
+}
+
+impl RouteGroup {
+    /// Every group, enabled by default.
+    pub const ALL: [Self; 5] = [
+        Self::Native,
+        Self::Opa,
+        Self::Admin,
+        Self::Stats,
+        Self::Events,
+    ];
+
+    /// The routes of the group.
+    pub fn routes(self) -> Vec<Route> {
+        match self {
+            Self::Native => rocket::routes![
+                // Original API
+                routes::check,
+                routes::check_get,
+                routes::check_batch,
+                routes::check_stream,
+                routes::check_subject,
+                routes::filter,
+                routes::count,
+                routes::level,
+                // API v2
+                v2::check,
+                v2::check_get,
+                v2::check_batch,
+                // Namespace-scoped API
+                namespaced::check,
+                namespaced::check_get,
+                namespaced::check_batch,
+                namespaced::level,
+            ],
+            Self::Opa => rocket::routes![
+                routes::opa_visible,
+                routes::opa_visible_batch,
+                opa::status,
+                opa::document,
+            ],
+            Self::Admin => rocket::routes![
+                routes::list_uuids,
+                admin::reload,
+                admin::reload_dry_run,
+                admin::diff_source,
+                admin::sample,
+                admin::export,
+                admin::clear,
+                admin::replace_store,
+                admin::change_source,
+                admin::scheduler_status,
+                admin::run_scheduler,
+            ],
+            Self::Stats => rocket::routes![
+                routes::stats,
+                routes::meta,
+                routes::fleet,
+                namespaced::stats
+            ],
+            Self::Events => rocket::routes![events::events, websocket::events_ws],
+        }
+    }
+
+    /// Routes mounted whatever the groups.
+    pub fn probes() -> Vec<Route> {
+        rocket::routes![routes::health, routes::livez, routes::readyz]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_groups_are_disjoint() {
+        let mut seen = HashSet::new();
+        for route in RouteGroup::ALL
+            .into_iter()
+            .flat_map(RouteGroup::routes)
+            .chain(RouteGroup::probes())
+        {
+            let key = (route.method, route.uri.to_string());
+            assert!(seen.insert(key.clone()), "{key:?} is in several groups");
+        }
+
+        let opa: Vec<_> = RouteGroup::Opa
+            .routes()
+            .iter()
+            .map(|route| route.uri.to_string())
+            .collect();
+        assert!(opa.iter().all(|uri| uri.starts_with("/v1/")), "{opa:?}");
+        assert!(
+            RouteGroup::Admin.routes().iter().all(|route| route
+                .uri
+                .path()
+                .starts_with("/api/v1/admin")
+                || route.uri.path() == "/api/v1/uuids")
+        );
+    }
+}
@@ -1,45 +1,673 @@
 use crate::models::{
-    BatchCheckRequest, BatchCheckResponse, CheckRequest, CheckResponse, HealthResponse,
-    OpaBatchVisibleInput, OpaRequest, OpaResponse, OpaVisibleInput, StatsResponse,
+    BatchCheckRequest, BatchCheckResponse, BatchObject, CheckRequest, CheckResponse, CountRequest,
+    CountResponse, Decision, ErrorResponse, FilterRequest, FilterResponse, HealthResponse,
+    LevelResponse, MetaResponse, OpaBatchVisibleInput, OpaRequest, OpaResponse, OpaVisibleInput,
+    ProbeResponse, StatsResponse, SubjectCheckRequest, SubjectCheckResponse, UuidPage,
 };
-use occlusion::{Store, SwappableStore};
-use rocket::{State, serde::json::Json};
+use crate::{
+    ReloadState,
+    admission::DataPlane,
+    audit::Decisions,
+    cache::Cached,
+    canary::Canary,
+    catchers::ApiError,
+    decisions::DecisionLog,
+    gossip::{Fleet, FleetStatus},
+    guards::{Admin, Entitlement, JsonBody, MaybeState},
+    ids::{IdNamespace, ObjectId},
+    latency::Latencies,
+    namespace::Namespaces,
+    pinning::Pin,
+    remote::Remote,
+    source::SourceMetadata,
+    subjects::SubjectMasks,
+};
+use occlusion::{AsyncStore, Store, SwappableStore};
+use rocket::{
+    Data, State,
+    data::{ByteUnit, Limits},
+    http::{ContentType, Status},
+    response::stream::TextStream,
+    serde::json::Json,
+};
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, atomic::Ordering},
+};
+use tokio::io::AsyncBufReadExt;
+use uuid::Uuid;
+
+/// Resolve a request's object ID, hashing non-UUID IDs when an ID namespace is configured.
+///
+/// Unresolvable IDs are rejected with a 422 naming the offending ID.
+pub(crate) fn resolve(id: &ObjectId, ids: &MaybeState<'_, IdNamespace>) -> Result<Uuid, ApiError> {
+    id.resolve(ids.0).ok_or_else(|| {
+        ApiError::unprocessable(format!(
+            "invalid object ID {:?}: expected a UUID",
+            id.as_text()
+        ))
+    })
+}
+
+fn resolve_all(
+    objects: &[ObjectId],
+    ids: &MaybeState<'_, IdNamespace>,
+) -> Result<Vec<Uuid>, ApiError> {
+    objects.iter().map(|id| resolve(id, ids)).collect()
+}
+
+/// Default for [`MaxBatchSize`].
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 10_000;
+
+/// Largest number of objects accepted in one batch check or filter request (0 = unlimited).
+///
+/// [`DEFAULT_MAX_BATCH_SIZE`] applies when not managed.
+pub struct MaxBatchSize(pub usize);
+
+/// Reject batches over the configured [`MaxBatchSize`] with a 413.
+pub(crate) fn check_batch_size(
+    len: usize,
+    max: &MaybeState<'_, MaxBatchSize>,
+) -> Result<(), ApiError> {
+    let max = max.0.map_or(DEFAULT_MAX_BATCH_SIZE, |max| max.0);
+    if max != 0 && len > max {
+        return Err(ApiError::too_large(
+            format!("batch of {len} objects exceeds the limit of {max}"),
+            max,
+        ));
+    }
+    Ok(())
+}
+
+/// The [`Remote`] authority to defer objects missing from the data to, if any.
+///
+/// Requests pinned to a generation are answered from its data alone.
+fn unpinned<'a>(remote: &MaybeState<'a, Arc<Remote>>, pin: Pin) -> Option<&'a Remote> {
+    remote.0.filter(|_| pin.0.is_none()).map(Arc::as_ref)
+}
+
+/// Decide whether an object is visible under `mask`, asking `remote` about objects
+/// missing from `store`, and record the decision.
+///
+/// Objects the remote authority grants are recorded as visible, all others as unknown.
+async fn remote_is_visible(
+    decisions: &Decisions<'_>,
+    store: &SwappableStore,
+    remote: Option<&Remote>,
+    object: &Uuid,
+    mask: u8,
+) -> bool {
+    match remote {
+        Some(remote) if store.get_visibility(object).is_none() => {
+            let is_visible = AsyncStore::is_visible(remote, object, mask).await;
+            let decision = if is_visible {
+                Decision::Visible
+            } else {
+                Decision::Unknown
+            };
+            decisions.record(*object, mask, decision, store.generation());
+            is_visible
+        }
+        _ => decisions.is_visible(store, object, mask),
+    }
+}
 
 /// Check if a single object is visible under the given visibility mask.
+///
+/// With a [`Remote`] authority, objects missing from the data are checked against it.
 #[post("/api/v1/check", data = "<request>")]
-pub fn check(store: &State<SwappableStore>, request: Json<CheckRequest>) -> Json<CheckResponse> {
-    let is_visible = store.is_visible(&request.object, request.visibility_mask);
-    Json(CheckResponse {
-        object: request.object,
-        is_visible,
-    })
+pub async fn check(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    remote: MaybeState<'_, Arc<Remote>>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<CheckRequest>,
+) -> Result<Json<CheckResponse>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    let object = resolve(&request.object, &ids)?;
+    let mask = entitlement.mask(request.visibility_mask)?;
+    let remote = unpinned(&remote, plane.pin);
+    let is_visible = remote_is_visible(&decisions, store, remote, &object, mask).await;
+    decisions.outcome(is_visible);
+    Ok(Json(CheckResponse { object, is_visible }))
+}
+
+/// Check a single object given in the URL, for callers that cannot send a body
+/// (curl, probes, `auth_request`) and for caching keyed on the URL. Responses carry an
+/// `ETag` of the store generation.
+#[get("/api/v1/check/<object>?<mask>")]
+pub fn check_get(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    object: &str,
+    mask: u8,
+) -> Result<Cached<Json<CheckResponse>>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    // Read before the lookup: a concurrent reload can make the tag older than the data,
+    // costing a refetch, but never newer
+    let generation = store.generation();
+    let object = resolve(&ObjectId::from(object), &ids)?;
+    let is_visible = decisions.is_visible(store, &object, entitlement.mask(mask)?);
+    decisions.outcome(is_visible);
+    Ok(
+        Cached::new(generation, Json(CheckResponse { object, is_visible }))
+            .private(entitlement.is_personal()),
+    )
 }
 
-/// Check multiple objects against the same visibility mask.
+/// Check multiple objects, against the request's visibility mask or each against its own.
+///
+/// Per-object results are only returned when some entries carry their own mask.
+/// The caller's [`Entitlement`] applies to all of them. With a [`Remote`] authority,
+/// objects missing from the data are checked against it.
 #[post("/api/v1/check/batch", data = "<request>")]
-pub fn check_batch(
+#[allow(clippy::too_many_arguments)]
+pub async fn check_batch(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    remote: MaybeState<'_, Arc<Remote>>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<BatchCheckRequest>,
+) -> Result<Json<BatchCheckResponse>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    check_batch_size(request.objects.len(), &max)?;
+    let shared_mask = || entitlement.shared_mask(request.visibility_mask);
+    let plain: Option<Vec<&ObjectId>> = request
+        .objects
+        .iter()
+        .map(|entry| match entry {
+            BatchObject::Object(id) => Some(id),
+            BatchObject::Masked(_) => None,
+        })
+        .collect();
+    if let Some(plain) = plain {
+        let objects = plain
+            .into_iter()
+            .map(|id| resolve(id, &ids))
+            .collect::<Result<Vec<_>, _>>()?;
+        plane.deadline.check()?;
+        let mask = shared_mask()?;
+        let all_visible = match unpinned(&remote, plane.pin) {
+            Some(remote) => {
+                let mut all_visible = true;
+                for object in &objects {
+                    all_visible &=
+                        remote_is_visible(&decisions, store, Some(remote), object, mask).await;
+                }
+                all_visible
+            }
+            None => decisions.check_batch(store, &objects, mask),
+        };
+        decisions.outcome(all_visible);
+        return Ok(Json(BatchCheckResponse {
+            all_visible,
+            results: Vec::new(),
+        }));
+    }
+
+    let remote = unpinned(&remote, plane.pin);
+    let mut results = Vec::with_capacity(request.objects.len());
+    for (i, entry) in request.objects.iter().enumerate() {
+        plane.deadline.check_every(i)?;
+        let (object, mask) = match entry {
+            BatchObject::Object(id) => (resolve(id, &ids)?, shared_mask()?),
+            BatchObject::Masked(check) => (
+                resolve(&check.object, &ids)?,
+                entitlement.mask(check.visibility_mask)?,
+            ),
+        };
+        let is_visible = remote_is_visible(&decisions, store, remote, &object, mask).await;
+        results.push(CheckResponse { object, is_visible });
+    }
+    let all_visible = results.iter().all(|result| result.is_visible);
+    decisions.outcome(all_visible);
+    Ok(Json(BatchCheckResponse {
+        all_visible,
+        results,
+    }))
+}
+
+/// Check objects on behalf of a subject, against the mask the subject masks file gives it.
+///
+/// Unknown subjects are answered with a 404, as is every request when no subject masks are
+/// configured. The caller's [`Entitlement`] applies to the subject's mask.
+#[post("/api/v1/check_subject", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
+pub fn check_subject(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    subjects: MaybeState<'_, Arc<SubjectMasks>>,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<SubjectCheckRequest>,
+) -> Result<Json<SubjectCheckResponse>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    let Some(subjects) = subjects.0 else {
+        return Err(ApiError::new(
+            Status::NotFound,
+            "checks by subject are disabled (--subject-masks)",
+        ));
+    };
+    let Some(mask) = subjects.get(&request.subject) else {
+        return Err(ApiError::new(
+            Status::NotFound,
+            format!("unknown subject {:?}", request.subject),
+        ));
+    };
+    check_batch_size(request.objects.len(), &max)?;
+    let mask = entitlement.mask(mask)?;
+    let objects = resolve_all(&request.objects, &ids)?;
+    let results = objects
+        .into_iter()
+        .enumerate()
+        .map(|(i, object)| {
+            plane.deadline.check_every(i)?;
+            Ok(CheckResponse {
+                object,
+                is_visible: decisions.is_visible(store, &object, mask),
+            })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+    let all_visible = results.iter().all(|result| result.is_visible);
+    decisions.outcome(all_visible);
+    Ok(Json(SubjectCheckResponse {
+        all_visible,
+        results,
+    }))
+}
+
+/// Rocket limit (`limits.stream`) on the body of [`check_stream`]; unlimited when not set.
+pub const STREAM_LIMIT: &str = "stream";
+
+/// Check a stream of objects: newline-delimited [`CheckRequest`]s in, one [`CheckResponse`]
+/// line out per request line, written as soon as it is computed.
+///
+/// Memory use does not depend on the number of lines, so there is no batch size limit.
+/// A line that cannot be checked gets an error line in its place and the stream goes on.
+#[post("/api/v1/check/stream", data = "<data>")]
+pub fn check_stream<'r>(
+    plane: DataPlane,
+    store: &'r State<SwappableStore>,
+    ids: MaybeState<'r, IdNamespace>,
+    entitlement: Entitlement,
+    decisions: Decisions<'r>,
+    limits: &Limits,
+    data: Data<'r>,
+) -> Result<(ContentType, TextStream![String + 'r]), ApiError> {
+    let store = plane.pin.store(store)?;
+    let limit = limits.get(STREAM_LIMIT).unwrap_or(ByteUnit::max_value());
+    let mut lines = tokio::io::BufReader::new(data.open(limit)).lines();
+    let ndjson = ContentType::new("application", "x-ndjson");
+    let deadline = plane.deadline;
+    let stream = TextStream! {
+        let mut number = 0;
+        loop {
+            let line = match deadline.within(lines.next_line()).await {
+                Ok(Ok(Some(line))) => line,
+                Ok(Ok(None)) => break,
+                Ok(Err(e)) => {
+                    let message = format!("failed to read request body: {e}");
+                    let e = ApiError::new(Status::BadRequest, message);
+                    yield json_line(&ErrorResponse::from(e));
+                    break;
+                }
+                Err(e) => {
+                    yield json_line(&ErrorResponse::from(e));
+                    break;
+                }
+            };
+            number += 1;
+            if let Err(e) = deadline.check_every(number) {
+                yield json_line(&ErrorResponse::from(e));
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let checked = serde_json::from_str::<CheckRequest>(&line)
+                .map_err(|e| ApiError::unprocessable(e.to_string()))
+                .and_then(|request| {
+                    let object = resolve(&request.object, &ids)?;
+                    let is_visible = decisions.is_visible(&store, &object, entitlement.mask(request.visibility_mask)?);
+                    Ok(CheckResponse { object, is_visible })
+                });
+            yield match checked {
+                Ok(response) => json_line(&response),
+                Err(mut e) => {
+                    e.message = format!("line {number}: {}", e.message);
+                    json_line(&ErrorResponse::from(e))
+                }
+            };
+        }
+    };
+    Ok((ndjson, stream))
+}
+
+fn json_line(value: &impl serde::Serialize) -> String {
+    let mut line = serde_json::to_string(value).expect("response serializes");
+    line.push('\n');
+    line
+}
+
+/// Look up an object's visibility level.
+///
+/// Unknown objects are answered with a `null` level and a 404 status.
+#[get("/api/v1/level/<object>")]
+pub fn level(
+    plane: DataPlane,
     store: &State<SwappableStore>,
-    request: Json<BatchCheckRequest>,
-) -> Json<BatchCheckResponse> {
-    let all_visible = store.check_batch(&request.objects, request.visibility_mask);
-    Json(BatchCheckResponse { all_visible })
+    ids: MaybeState<'_, IdNamespace>,
+    object: &str,
+) -> Result<(Status, Json<LevelResponse>), ApiError> {
+    let store = &plane.pin.store(store)?;
+    let object = resolve(&ObjectId::from(object), &ids)?;
+    let level = store.get_visibility(&object);
+    let status = if level.is_some() {
+        Status::Ok
+    } else {
+        Status::NotFound
+    };
+    Ok((status, Json(LevelResponse { object, level })))
+}
+
+/// Default number of UUIDs per page of [`list_uuids`].
+pub const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// Largest page [`list_uuids`] returns, whatever the requested limit.
+pub const MAX_PAGE_SIZE: usize = 10_000;
+
+/// List the UUIDs at a visibility level, with keyset pagination.
+///
+/// `cursor` is the `next_cursor` of the previous page: each page holds the `limit` smallest UUIDs
+/// greater than it, so pages stay consistent while the store changes between requests.
+#[get("/api/v1/uuids?<level>&<cursor>&<limit>")]
+pub fn list_uuids(
+    _admin: Admin,
+    pin: Pin,
+    store: &State<SwappableStore>,
+    level: u8,
+    cursor: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Json<UuidPage>, ApiError> {
+    let store = &pin.store(store)?;
+    let cursor = cursor
+        .map(Uuid::try_parse)
+        .transpose()
+        .map_err(|e| ApiError::unprocessable(format!("invalid cursor: {e}")))?;
+    let limit = match limit {
+        Some(0) => return Err(ApiError::unprocessable("limit must be positive")),
+        Some(limit) => limit.min(MAX_PAGE_SIZE),
+        None => DEFAULT_PAGE_SIZE,
+    };
+
+    let mut uuids = store.uuids_after(level, cursor.as_ref(), limit + 1);
+    let more = uuids.len() > limit;
+    uuids.truncate(limit);
+    let next_cursor = if more { uuids.last().copied() } else { None };
+    Ok(Json(UuidPage { uuids, next_cursor }))
+}
+
+/// Return the visible subset of a list of objects, preserving order.
+#[post("/api/v1/filter", data = "<request>")]
+pub fn filter(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<FilterRequest>,
+) -> Result<Json<FilterResponse>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    check_batch_size(request.objects.len(), &max)?;
+    let FilterRequest {
+        objects,
+        visibility_mask,
+    } = request.into_inner();
+    let visibility_mask = entitlement.mask(visibility_mask)?;
+    let mut visible = Vec::with_capacity(objects.len());
+    for (i, id) in objects.into_iter().enumerate() {
+        plane.deadline.check_every(i)?;
+        if decisions.is_visible(store, &resolve(&id, &ids)?, visibility_mask) {
+            visible.push(id);
+        }
+    }
+    Ok(Json(FilterResponse { objects: visible }))
+}
+
+/// Count how many of a list of objects are visible, optionally with the number of objects at
+/// each visibility level, for callers that only need totals (pagination, "N hidden" badges).
+#[post("/api/v1/count", data = "<request>")]
+pub fn count(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<CountRequest>,
+) -> Result<Json<CountResponse>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    check_batch_size(request.objects.len(), &max)?;
+    let mask = entitlement.mask(request.visibility_mask)?;
+    let objects = resolve_all(&request.objects, &ids)?;
+    plane.deadline.check()?;
+    let (generation, levels) = store.get_visibilities(&objects);
+    let mut visible = 0;
+    let mut by_level = BTreeMap::new();
+    let mut unknown = 0;
+    for (object, level) in objects.iter().zip(&levels) {
+        let decision = Decision::new(*level, mask);
+        decisions.record(*object, mask, decision, generation);
+        visible += usize::from(decision == Decision::Visible);
+        match level {
+            Some(level) => *by_level.entry(*level).or_default() += 1,
+            None => unknown += 1,
+        }
+    }
+    Ok(Json(CountResponse {
+        visible,
+        total: objects.len(),
+        levels: request.by_level.then_some(by_level),
+        unknown: request.by_level.then_some(unknown),
+    }))
 }
 
 /// Health check endpoint.
-#[get("/health")]
-pub fn health(store: &State<SwappableStore>) -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: std::borrow::Cow::Borrowed("ok"),
+///
+/// The status is `degraded` while a source serves stale data after repeated reload failures.
+/// Accepts OPA's health check parameters: with `plugins`, a source whose last reload failed
+/// makes the check fail with a 500. `bundles` is ignored, since the server only starts
+/// serving once every source is loaded.
+#[get("/health?<plugins>")]
+pub fn health(
+    store: &State<SwappableStore>,
+    namespaces: MaybeState<'_, Namespaces>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    plugins: bool,
+) -> Result<Cached<Json<HealthResponse>>, ApiError> {
+    if plugins {
+        let failing = reload_states(&reload_state, &namespaces)
+            .iter()
+            .filter(|state| state.consecutive_failures.load(Ordering::Relaxed) > 0)
+            .count();
+        if failing > 0 {
+            return Err(ApiError::new(
+                Status::InternalServerError,
+                format!("{failing} data source(s) failing to reload"),
+            ));
+        }
+    }
+    let degraded = reload_states(&reload_state, &namespaces)
+        .iter()
+        .any(|state| state.degraded_reason().is_some());
+    let mut version = std::iter::once(store.generation())
+        .chain(
+            namespaces
+                .0
+                .into_iter()
+                .flat_map(Namespaces::iter)
+                .map(|(_, namespace)| namespace.store.generation()),
+        )
+        .map(|generation| generation.to_string())
+        .collect::<Vec<_>>()
+        .join("-");
+    if degraded {
+        version.push_str("-degraded");
+    }
+    let response = HealthResponse {
+        status: Cow::Borrowed(if degraded { "degraded" } else { "ok" }),
         uuid_count: store.len(),
+        namespaces: namespaces
+            .0
+            .into_iter()
+            .flat_map(Namespaces::iter)
+            .map(|(name, namespace)| (name.to_string(), namespace.store.len()))
+            .collect(),
+    };
+    Ok(Cached::new(version, Json(response)))
+}
+
+/// Liveness probe: answers as long as the process is serving requests.
+#[get("/livez")]
+pub fn livez() -> Json<ProbeResponse> {
+    Json(ProbeResponse {
+        status: Cow::Borrowed("ok"),
     })
 }
 
-/// Get statistics about the store.
+/// Readiness probe: fails with a 503 until every data source has been loaded, and while one
+/// is cleared after repeated reload failures.
+///
+/// A source serving stale data after repeated reload failures keeps the server ready, with a
+/// `degraded` status.
+#[get("/readyz")]
+pub fn readyz(
+    namespaces: MaybeState<'_, Namespaces>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+) -> Result<Json<ProbeResponse>, ApiError> {
+    let states = reload_states(&reload_state, &namespaces);
+    let reasons: Vec<String> = states
+        .iter()
+        .filter_map(|state| state.unready_reason())
+        .collect();
+    if !reasons.is_empty() {
+        return Err(ApiError::new(
+            Status::ServiceUnavailable,
+            format!("not ready: {}", reasons.join("; ")),
+        ));
+    }
+    let degraded = states.iter().any(|state| state.degraded_reason().is_some());
+    Ok(Json(ProbeResponse {
+        status: Cow::Borrowed(if degraded { "degraded" } else { "ready" }),
+    }))
+}
+
+/// The reload states of the default source and of every namespace, each once.
+fn reload_states<'a>(
+    reload_state: &MaybeState<'a, Arc<ReloadState>>,
+    namespaces: &MaybeState<'a, Namespaces>,
+) -> Vec<&'a Arc<ReloadState>> {
+    let mut states: Vec<&Arc<ReloadState>> = reload_state.0.into_iter().collect();
+    for (_, namespace) in namespaces.0.into_iter().flat_map(Namespaces::iter) {
+        if !states
+            .iter()
+            .any(|state| Arc::ptr_eq(state, &namespace.reload_state))
+        {
+            states.push(&namespace.reload_state);
+        }
+    }
+    states
+}
+
+/// Get statistics about the store and its last load.
 #[get("/api/v1/stats")]
-pub fn stats(store: &State<SwappableStore>) -> Json<StatsResponse> {
-    Json(StatsResponse {
+pub fn stats(
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+    fleet: MaybeState<'_, Fleet>,
+    canary: MaybeState<'_, Arc<Canary>>,
+    latencies: MaybeState<'_, Latencies>,
+) -> Cached<Json<StatsResponse>> {
+    let generation = store.generation();
+    let state = reload_state.0;
+    let last_load =
+        state.and_then(|state| state.last_load.read().expect("RwLock poisoned").clone());
+    let response = StatsResponse {
         total_uuids: store.len(),
         visibility_distribution: store.visibility_distribution().into_iter().collect(),
+        store_algorithm: Cow::Borrowed(occlusion::ACTIVE_STORE_NAME),
+        generation,
+        memory_bytes: store.memory_usage(),
+        source: state.map(|state| state.source().redacted()),
+        last_reload_at: state.and_then(|state| state.last_reload_at()),
+        consecutive_failures: state.map_or(0, |state| {
+            state.consecutive_failures.load(Ordering::Relaxed)
+        }),
+        degraded: state.is_some_and(|state| state.degraded.load(Ordering::Relaxed)),
+        last_load,
+        fleet: fleet.0.map(|fleet| fleet.status().summary()),
+        canary: canary.0.map(|canary| canary.status()),
+        latency: latencies.0.map(Latencies::report),
+    };
+    let version = format!(
+        "{generation}-{}-{}-{}",
+        response.last_reload_at.unwrap_or_default(),
+        response.consecutive_failures,
+        latencies.0.map_or(0, Latencies::recorded)
+    );
+    Cached::new(version, Json(response))
+}
+
+/// Describe the data of the default store: a cheap way for clients caching decisions to
+/// notice the dataset changed.
+#[get("/api/v1/meta")]
+pub fn meta(
+    store: &State<SwappableStore>,
+    reload_state: MaybeState<'_, Arc<ReloadState>>,
+) -> Cached<Json<MetaResponse>> {
+    let generation = store.generation();
+    let state = reload_state.0;
+    let loaded_at = state.and_then(|state| {
+        let last_load = state.last_load.read().expect("RwLock poisoned");
+        last_load.as_ref().map(|load| load.completed_at)
+    });
+    let metadata = state.map(|state| state.metadata.read().expect("RwLock poisoned").clone());
+    let response = MetaResponse {
+        generation,
+        loaded_at,
+        source: state.map(|state| state.source().redacted()),
+        source_version: metadata.as_ref().and_then(SourceMetadata::version),
+        source_modified_at: metadata.as_ref().and_then(SourceMetadata::modified_at),
+        entry_count: store.len(),
+    };
+    Cached::new(
+        format!("{generation}-{}", loaded_at.unwrap_or_default()),
+        Json(response),
+    )
+}
+
+/// Versions of the data served by the peers of this instance, as of the last gossip round.
+#[get("/api/v1/fleet")]
+pub fn fleet(fleet: MaybeState<'_, Fleet>) -> Result<Json<FleetStatus>, ApiError> {
+    fleet.0.map(|fleet| Json(fleet.status())).ok_or_else(|| {
+        ApiError::new(
+            Status::ServiceUnavailable,
+            "peer gossip is disabled".to_string(),
+        )
     })
 }
 
@@ -47,32 +675,93 @@ pub fn stats(store: &State<SwappableStore>) -> Json<StatsResponse> {
 // OPA-Compatible Endpoints
 // ============================================================================
 
+/// Answer an OPA-compatible request under a new decision ID, logging the decision if
+/// decision logging is enabled.
+pub(crate) fn decide<I: Serialize, T: Serialize>(
+    path: &'static str,
+    input: &I,
+    result: T,
+    log: &MaybeState<'_, DecisionLog>,
+    remote: Option<SocketAddr>,
+) -> Json<OpaResponse<T>> {
+    let decision_id = Uuid::new_v4();
+    if let Some(log) = log.0 {
+        log.record(decision_id, path, input, &result, remote);
+    }
+    Json(OpaResponse {
+        result,
+        decision_id,
+    })
+}
+
 /// OPA-compatible visibility check.
 #[post("/v1/data/occlusion/visible", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
 pub fn opa_visible(
+    plane: DataPlane,
     store: &State<SwappableStore>,
-    request: Json<OpaRequest<OpaVisibleInput>>,
-) -> Json<OpaResponse<bool>> {
-    let is_visible = store.is_visible(&request.input.object, request.input.visibility_mask);
-    Json(OpaResponse { result: is_visible })
+    ids: MaybeState<'_, IdNamespace>,
+    log: MaybeState<'_, DecisionLog>,
+    remote: Option<SocketAddr>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<OpaRequest<OpaVisibleInput>>,
+) -> Result<Json<OpaResponse<bool>>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    let object = resolve(&request.input.object, &ids)?;
+    let is_visible = decisions.is_visible(
+        store,
+        &object,
+        entitlement.mask(request.input.visibility_mask)?,
+    );
+    decisions.outcome(is_visible);
+    Ok(decide(
+        "occlusion/visible",
+        &request.input,
+        is_visible,
+        &log,
+        remote,
+    ))
 }
 
 /// OPA-compatible batch visibility check.
 #[post("/v1/data/occlusion/visible_batch", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
 pub fn opa_visible_batch(
+    plane: DataPlane,
     store: &State<SwappableStore>,
-    request: Json<OpaRequest<OpaBatchVisibleInput>>,
-) -> Json<OpaResponse<bool>> {
-    let all_visible = store.check_batch(&request.input.objects, request.input.visibility_mask);
-    Json(OpaResponse {
-        result: all_visible,
-    })
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    log: MaybeState<'_, DecisionLog>,
+    remote: Option<SocketAddr>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<OpaRequest<OpaBatchVisibleInput>>,
+) -> Result<Json<OpaResponse<bool>>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    check_batch_size(request.input.objects.len(), &max)?;
+    let objects = resolve_all(&request.input.objects, &ids)?;
+    plane.deadline.check()?;
+    let all_visible = decisions.check_batch(
+        store,
+        &objects,
+        entitlement.mask(request.input.visibility_mask)?,
+    );
+    decisions.outcome(all_visible);
+    Ok(decide(
+        "occlusion/visible_batch",
+        &request.input,
+        all_visible,
+        &log,
+        remote,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rocket::http::{ContentType, Status};
+    use crate::guards::AdminToken;
+    use rocket::http::{ContentType, Header, Status};
     use rocket::local::blocking::Client;
     use uuid::Uuid;
 
@@ -100,17 +789,28 @@ mod tests {
         let store = TestStore::new(entries).unwrap();
         let swappable = SwappableStore::new(store);
 
-        let rocket = rocket::build().manage(swappable).mount(
-            "/",
-            routes![
-                check,
-                check_batch,
-                health,
-                stats,
-                opa_visible,
-                opa_visible_batch,
-            ],
-        );
+        let rocket = rocket::build()
+            .manage(swappable)
+            .register("/", crate::catchers::catchers())
+            .mount(
+                "/",
+                routes![
+                    check,
+                    check_get,
+                    check_batch,
+                    check_stream,
+                    filter,
+                    count,
+                    level,
+                    list_uuids,
+                    health,
+                    livez,
+                    readyz,
+                    stats,
+                    opa_visible,
+                    opa_visible_batch,
+                ],
+            );
 
         Client::tracked(rocket).expect("valid rocket instance")
     }
@@ -134,6 +834,121 @@ mod tests {
         assert_eq!(body.uuid_count, 4);
     }
 
+    #[test]
+    fn test_probes() {
+        let client = create_test_client();
+        let response = client.get("/livez").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: ProbeResponse = response.into_json().unwrap();
+        assert_eq!(body.status, "ready");
+
+        let state = Arc::new(ReloadState::new(
+            crate::source::DataSource::parse("data.csv"),
+            crate::loader::LoadOptions::default(),
+            crate::source::SourceMetadata::new(),
+        ));
+        let rocket = rocket::build()
+            .manage(state.clone())
+            .register("/", crate::catchers::catchers())
+            .mount("/", routes![livez, readyz]);
+        let client = Client::tracked(rocket).unwrap();
+        state.loaded.store(false, Ordering::Relaxed);
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: ErrorResponse = response.into_json().unwrap();
+        assert_eq!(body.error.message, "not ready: data.csv not loaded yet");
+        assert_eq!(client.get("/livez").dispatch().status(), Status::Ok);
+
+        state.loaded.store(true, Ordering::Relaxed);
+        state.degraded.store(true, Ordering::Relaxed);
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: ProbeResponse = response.into_json().unwrap();
+        assert_eq!(body.status, "degraded");
+
+        state.locked_out.store(true, Ordering::Relaxed);
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+    }
+
+    #[test]
+    fn test_health_plugins_counts_shared_sources_once() {
+        use crate::namespace::Namespace;
+
+        let store = SwappableStore::new(TestStore::new(vec![(Uuid::from_u128(1), 0)]).unwrap());
+        let state = Arc::new(ReloadState::new(
+            crate::source::DataSource::parse("data.csv"),
+            crate::loader::LoadOptions::default(),
+            crate::source::SourceMetadata::new(),
+        ));
+        // Namespaces routed from the default source's namespace column share its state
+        let mut namespaces = Namespaces::new();
+        for name in ["a", "b"] {
+            let namespace = Namespace {
+                store: store.clone(),
+                reload_state: state.clone(),
+            };
+            namespaces.insert(name.to_string(), namespace);
+        }
+        let rocket = rocket::build()
+            .manage(store)
+            .manage(state.clone())
+            .manage(namespaces)
+            .register("/", crate::catchers::catchers())
+            .mount("/", routes![health]);
+        let client = Client::tracked(rocket).unwrap();
+        assert_eq!(
+            client.get("/health?plugins").dispatch().status(),
+            Status::Ok
+        );
+
+        state.consecutive_failures.store(2, Ordering::Relaxed);
+        let response = client.get("/health?plugins").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        let body: ErrorResponse = response.into_json().unwrap();
+        assert_eq!(body.error.message, "1 data source(s) failing to reload");
+    }
+
+    #[test]
+    fn test_meta_and_generation_header() {
+        use crate::cache::{GENERATION_HEADER, GenerationHeader};
+
+        let store = SwappableStore::new(TestStore::new(vec![(Uuid::from_u128(1), 0)]).unwrap());
+        let state = Arc::new(ReloadState::new(
+            crate::source::DataSource::parse("data.csv"),
+            crate::loader::LoadOptions::default(),
+            crate::source::SourceMetadata::new(),
+        ));
+        let rocket = rocket::build()
+            .manage(store.clone())
+            .manage(state)
+            .attach(GenerationHeader)
+            .mount("/", routes![check_get, meta, health]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client.get("/api/v1/meta").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.headers().get_one(GENERATION_HEADER).is_none());
+        let body: MetaResponse = response.into_json().unwrap();
+        assert_eq!(body.generation, store.generation());
+        assert_eq!(body.entry_count, 1);
+        assert_eq!(body.source.as_deref(), Some("data.csv"));
+
+        store.swap(TestStore::new(vec![]).unwrap());
+        let response = client
+            .get(format!("/api/v1/check/{}?mask=5", uuid_str(1)))
+            .dispatch();
+        let generation = store.generation().to_string();
+        assert_eq!(
+            response.headers().get_one(GENERATION_HEADER),
+            Some(generation.as_str())
+        );
+        let response = client.get("/health").dispatch();
+        assert!(response.headers().get_one(GENERATION_HEADER).is_none());
+    }
+
     #[test]
     fn test_check_visible() {
         let client = create_test_client();
@@ -168,6 +983,38 @@ mod tests {
         assert!(!body.is_visible); // Level 15 > mask 10
     }
 
+    #[test]
+    fn test_check_get() {
+        let client = create_test_client();
+
+        let response = client
+            .get(format!("/api/v1/check/{}?mask=10", uuid_str(2)))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: CheckResponse = response.into_json().unwrap();
+        assert_eq!(body.object, Uuid::from_u128(2));
+        assert!(body.is_visible);
+
+        let response = client
+            .get(format!("/api/v1/check/{}?mask=10", uuid_str(4)))
+            .dispatch();
+        let body: CheckResponse = response.into_json().unwrap();
+        assert!(!body.is_visible);
+
+        let response = client.get("/api/v1/check/nope?mask=10").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // The mask is required and must fit a level
+        let response = client
+            .get(format!("/api/v1/check/{}?mask=256", uuid_str(2)))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client
+            .get(format!("/api/v1/check/{}", uuid_str(2)))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
     #[test]
     fn test_check_batch() {
         let client = create_test_client();
@@ -203,6 +1050,255 @@ mod tests {
         assert!(body.all_visible);
     }
 
+    #[test]
+    fn test_check_batch_per_object_masks() {
+        let client = create_test_client();
+
+        let response = client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"objects": [
+                    {{"object": "{}", "visibility_mask": 5}},
+                    {{"object": "{}", "visibility_mask": 10}},
+                    "{}"
+                ], "visibility_mask": 15}}"#,
+                uuid_str(2),
+                uuid_str(4),
+                uuid_str(4)
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: BatchCheckResponse = response.into_json().unwrap();
+        assert!(!body.all_visible);
+        let results: Vec<_> = body
+            .results
+            .iter()
+            .map(|result| (result.object, result.is_visible))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                (Uuid::from_u128(2), true),
+                (Uuid::from_u128(4), false),
+                (Uuid::from_u128(4), true),
+            ]
+        );
+
+        // Plain entries need the shared mask
+        let response = client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"objects": [{{"object": "{}", "visibility_mask": 5}}, "{}"]}}"#,
+                uuid_str(2),
+                uuid_str(1)
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn test_filter() {
+        let client = create_test_client();
+        let response = client
+            .post("/api/v1/filter")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"objects": ["{}", "{}", "{}", "{}", "{}"], "visibility_mask": 10}}"#,
+                uuid_str(3),
+                uuid_str(4),
+                uuid_str(999),
+                uuid_str(1),
+                uuid_str(2)
+            ))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let body: FilterResponse = response.into_json().unwrap();
+        assert_eq!(
+            body.objects,
+            vec![
+                Uuid::from_u128(3).into(),
+                Uuid::from_u128(1).into(),
+                Uuid::from_u128(2).into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let client = create_test_client();
+        let count = |by_level: bool| {
+            let response = client
+                .post("/api/v1/count")
+                .header(ContentType::JSON)
+                .body(format!(
+                    r#"{{"objects": ["{}", "{}", "{}", "{}"], "visibility_mask": 5, "by_level": {by_level}}}"#,
+                    uuid_str(1),
+                    uuid_str(2),
+                    uuid_str(3),
+                    uuid_str(999)
+                ))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<CountResponse>().unwrap()
+        };
+
+        let body = count(false);
+        assert_eq!((body.visible, body.total), (2, 4));
+        assert!(body.levels.is_none() && body.unknown.is_none());
+
+        let body = count(true);
+        assert_eq!(body.visible, 2);
+        assert_eq!(
+            body.levels.unwrap().into_iter().collect::<Vec<_>>(),
+            [(0, 1), (5, 1), (10, 1)]
+        );
+        assert_eq!(body.unknown, Some(1));
+    }
+
+    #[test]
+    fn test_check_subject() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "subject,visibility_mask\nalice,10\nbob,3\n").unwrap();
+        let subjects = Arc::new(SubjectMasks::read(file.path().to_path_buf()).unwrap());
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)];
+        let rocket = rocket::build()
+            .manage(SwappableStore::new(TestStore::new(entries).unwrap()))
+            .manage(subjects)
+            .register("/", crate::catchers::catchers())
+            .mount("/", routes![check_subject]);
+        let client = Client::tracked(rocket).unwrap();
+        let check = |subject: &str| {
+            client
+                .post("/api/v1/check_subject")
+                .header(ContentType::JSON)
+                .body(format!(
+                    r#"{{"subject": "{subject}", "objects": ["{}", "{}"]}}"#,
+                    uuid_str(1),
+                    uuid_str(2)
+                ))
+                .dispatch()
+        };
+
+        let body: SubjectCheckResponse = check("alice").into_json().unwrap();
+        assert!(body.all_visible);
+        let body: SubjectCheckResponse = check("bob").into_json().unwrap();
+        assert!(!body.all_visible);
+        let visible: Vec<_> = body.results.iter().map(|r| r.is_visible).collect();
+        assert_eq!(visible, [true, false]);
+
+        let response = check("carol");
+        assert_eq!(response.status(), Status::NotFound);
+        let body: ErrorResponse = response.into_json().unwrap();
+        assert_eq!(body.error.message, r#"unknown subject "carol""#);
+    }
+
+    #[test]
+    fn test_level() {
+        let client = create_test_client();
+
+        let response = client
+            .get(format!("/api/v1/level/{}", uuid_str(3)))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: LevelResponse = response.into_json().unwrap();
+        assert_eq!(body.object, Uuid::from_u128(3));
+        assert_eq!(body.level, Some(10));
+
+        let response = client
+            .get(format!("/api/v1/level/{}", uuid_str(999)))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let body: LevelResponse = response.into_json().unwrap();
+        assert_eq!(body.level, None);
+    }
+
+    #[test]
+    fn test_list_uuids() {
+        let entries: Vec<_> = (1..=25)
+            .map(|n| (Uuid::from_u128(n), u8::from(n % 5 == 0)))
+            .collect();
+        let rocket = rocket::build()
+            .manage(SwappableStore::new(TestStore::new(entries).unwrap()))
+            .manage(AdminToken("secret".to_string()))
+            .mount("/", routes![list_uuids]);
+        let client = Client::tracked(rocket).unwrap();
+        let auth = Header::new("Authorization", "Bearer secret");
+
+        let response = client.get("/api/v1/uuids?level=0").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get("/api/v1/uuids?level=0")
+            .header(Header::new("Authorization", "Bearer nope"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let mut listed = Vec::new();
+        let mut url = "/api/v1/uuids?level=0&limit=8".to_string();
+        loop {
+            let response = client.get(url.clone()).header(auth.clone()).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let page: UuidPage = response.into_json().unwrap();
+            assert!(page.uuids.len() <= 8);
+            listed.extend(page.uuids);
+            match page.next_cursor {
+                Some(cursor) => url = format!("/api/v1/uuids?level=0&limit=8&cursor={cursor}"),
+                None => break,
+            }
+        }
+        let expected: Vec<_> = (1..=25)
+            .filter(|n| n % 5 != 0)
+            .map(Uuid::from_u128)
+            .collect();
+        assert_eq!(listed, expected);
+
+        let response = client
+            .get("/api/v1/uuids?level=0&limit=0")
+            .header(auth)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn test_admin_disabled() {
+        let client = create_test_client();
+        let response = client
+            .get("/api/v1/uuids?level=0")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn test_admin_allowlist() {
+        use crate::guards::{AdminAllowlist, parse_network};
+
+        let networks = ["10.0.0.0/8", "2001:db8::1"].map(|s| parse_network(s).unwrap());
+        let rocket = rocket::build()
+            .manage(SwappableStore::new(TestStore::new(vec![]).unwrap()))
+            .manage(AdminToken("secret".to_string()))
+            .manage(AdminAllowlist(networks.to_vec()))
+            .mount("/", routes![list_uuids]);
+        let client = Client::tracked(rocket).unwrap();
+        let list = |remote: &str| {
+            client
+                .get("/api/v1/uuids?level=0")
+                .header(Header::new("Authorization", "Bearer secret"))
+                .header(Header::new("X-Real-IP", "10.0.0.1"))
+                .remote(remote.parse().unwrap())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(list("10.1.2.3:5000"), Status::Ok);
+        assert_eq!(list("[::ffff:10.1.2.3]:5000"), Status::Ok);
+        assert_eq!(list("[2001:db8::1]:5000"), Status::Ok);
+        assert_eq!(list("192.168.0.1:5000"), Status::Forbidden);
+        assert_eq!(list("[2001:db8::2]:5000"), Status::Forbidden);
+        assert!(parse_network("10.0.0.0/33").is_err());
+    }
+
     #[test]
     fn test_stats() {
         let client = create_test_client();
@@ -215,6 +1311,59 @@ mod tests {
         assert_eq!(body.visibility_distribution.get(&5), Some(&1));
         assert_eq!(body.visibility_distribution.get(&10), Some(&1));
         assert_eq!(body.visibility_distribution.get(&15), Some(&1));
+        assert_eq!(body.store_algorithm, occlusion::ACTIVE_STORE_NAME);
+        assert_eq!(body.generation, 0);
+        assert!(body.memory_bytes >= 4 * 16);
+        // Not reloadable
+        assert_eq!(body.source, None);
+        assert_eq!(body.consecutive_failures, 0);
+        assert_eq!(body.latency, None);
+    }
+
+    #[test]
+    fn test_stats_latency() {
+        let store = SwappableStore::new(TestStore::new(vec![(Uuid::from_u128(1), 0)]).unwrap());
+        let rocket = rocket::build()
+            .attach(crate::fairing::RequestTimer)
+            .manage(store)
+            .manage(Latencies::default())
+            .mount("/", routes![check_get, stats]);
+        let client = Client::tracked(rocket).unwrap();
+        for _ in 0..3 {
+            let uri = format!("/api/v1/check/{}?mask=0", uuid_str(1));
+            assert_eq!(client.get(uri).dispatch().status(), Status::Ok);
+        }
+        // Unrouted requests are not recorded
+        client.get("/nowhere").dispatch();
+
+        let response = client.get("/api/v1/stats").dispatch();
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+        let latency = response
+            .into_json::<StatsResponse>()
+            .unwrap()
+            .latency
+            .unwrap();
+        assert_eq!(
+            latency.keys().collect::<Vec<_>>(),
+            ["GET /api/v1/check/<object>?<mask>"]
+        );
+        assert_eq!(
+            latency["GET /api/v1/check/<object>?<mask>"].last_1m.count,
+            3
+        );
+
+        // The stats request itself changed the latencies
+        let response = client
+            .get("/api/v1/stats")
+            .header(Header::new("If-None-Match", etag))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let latency = response
+            .into_json::<StatsResponse>()
+            .unwrap()
+            .latency
+            .unwrap();
+        assert_eq!(latency["GET /api/v1/stats"].last_15m.count, 1);
     }
 
     // ========================================================================
@@ -306,4 +1455,289 @@ mod tests {
         let body: OpaResponse<bool> = response.into_json().unwrap();
         assert!(body.result);
     }
+
+    #[test]
+    fn test_check_hashed_ids() {
+        // Without an ID namespace, only UUIDs are accepted
+        let client = create_test_client();
+        let response = client
+            .post("/api/v1/check")
+            .header(ContentType::JSON)
+            .body(r#"{"object": "order-1", "visibility_mask": 10}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let namespace = IdNamespace(Uuid::NAMESPACE_OID);
+        let store = TestStore::new(vec![
+            (namespace.hash("order-1"), 5),
+            (namespace.hash("42"), 15),
+        ])
+        .unwrap();
+        let rocket = rocket::build()
+            .manage(SwappableStore::new(store))
+            .manage(namespace)
+            .mount("/", routes![check, check_batch, opa_visible]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client
+            .post("/api/v1/check")
+            .header(ContentType::JSON)
+            .body(r#"{"object": "order-1", "visibility_mask": 10}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: CheckResponse = response.into_json().unwrap();
+        assert_eq!(body.object, namespace.hash("order-1"));
+        assert!(body.is_visible);
+
+        // Integers hash like their decimal form
+        let response = client
+            .post("/v1/data/occlusion/visible")
+            .header(ContentType::JSON)
+            .body(r#"{"input": {"object": 42, "visibility_mask": 15}}"#)
+            .dispatch();
+        let body: OpaResponse<bool> = response.into_json().unwrap();
+        assert!(body.result);
+
+        let response = client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .body(r#"{"objects": ["order-1", 42], "visibility_mask": 10}"#)
+            .dispatch();
+        let body: BatchCheckResponse = response.into_json().unwrap();
+        assert!(!body.all_visible);
+    }
+
+    #[test]
+    fn test_error_bodies() {
+        use crate::models::ErrorResponse;
+
+        let client = create_test_client();
+        let error = |response: rocket::local::blocking::LocalResponse<'_>, status: Status| {
+            assert_eq!(response.status(), status);
+            assert_eq!(response.content_type(), Some(ContentType::JSON));
+            let body: ErrorResponse = response.into_json().unwrap();
+            assert_eq!(body.error.code, status.code);
+            body.error.message
+        };
+
+        let message = error(client.get("/api/v1/nope").dispatch(), Status::NotFound);
+        assert_eq!(message, "no route for GET /api/v1/nope");
+
+        let check = |body: &str| {
+            client
+                .post("/api/v1/check")
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+        };
+        let message = error(
+            check(r#"{"object": "order-1", "visibility_mask": 5}"#),
+            Status::UnprocessableEntity,
+        );
+        assert_eq!(message, r#"invalid object ID "order-1": expected a UUID"#);
+
+        let body = format!(r#"{{"object": "{}", "visibility_mask": 300}}"#, uuid_str(1));
+        let message = error(check(&body), Status::UnprocessableEntity);
+        assert!(
+            message.contains("invalid value: integer `300`"),
+            "{message}"
+        );
+
+        let message = error(check("{"), Status::BadRequest);
+        assert!(message.starts_with("invalid request body"), "{message}");
+
+        let message = error(
+            client.get("/api/v1/uuids?level=0").dispatch(),
+            Status::Forbidden,
+        );
+        assert_eq!(message, "admin endpoints are disabled");
+    }
+
+    #[test]
+    fn test_max_batch_size() {
+        use crate::models::ErrorResponse;
+
+        let store = TestStore::new(vec![(Uuid::from_u128(1), 0)]).unwrap();
+        let rocket = rocket::build()
+            .manage(SwappableStore::new(store))
+            .manage(MaxBatchSize(2))
+            .register("/", crate::catchers::catchers())
+            .mount("/", routes![check_batch, filter, opa_visible_batch]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+
+        let objects = |n: u128| (1..=n).map(uuid_str).collect::<Vec<_>>();
+        let requests = |n: u128| {
+            [
+                (
+                    "/api/v1/check/batch",
+                    serde_json::json!({ "objects": objects(n), "visibility_mask": 0 }),
+                ),
+                (
+                    "/api/v1/filter",
+                    serde_json::json!({ "objects": objects(n), "visibility_mask": 0 }),
+                ),
+                (
+                    "/v1/data/occlusion/visible_batch",
+                    serde_json::json!({ "input": { "objects": objects(n), "visibility_mask": 0 } }),
+                ),
+            ]
+        };
+
+        for (uri, body) in requests(2) {
+            let response = client
+                .post(uri)
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{uri}");
+        }
+        for (uri, body) in requests(3) {
+            let response = client
+                .post(uri)
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch();
+            assert_eq!(response.status(), Status::PayloadTooLarge, "{uri}");
+            let body: ErrorResponse = response.into_json().unwrap();
+            assert_eq!(body.error.limit, Some(2));
+            assert_eq!(
+                body.error.message,
+                "batch of 3 objects exceeds the limit of 2"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_stream() {
+        let client = create_test_client();
+        let line =
+            |id: &str, mask: u8| format!(r#"{{"object": "{id}", "visibility_mask": {mask}}}"#);
+        let body = [
+            line(&uuid_str(2), 5),
+            String::new(),
+            line(&uuid_str(3), 5),
+            line("order-1", 5),
+            "not json".to_string(),
+            line(&uuid_str(3), 10),
+        ]
+        .join("\n");
+        let response = client.post("/api/v1/check/stream").body(body).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "x-ndjson"))
+        );
+
+        let body = response.into_string().unwrap();
+        let lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["is_visible"], true);
+        assert_eq!(lines[1]["is_visible"], false);
+        assert_eq!(lines[2]["error"]["code"], 422);
+        assert_eq!(
+            lines[2]["error"]["message"],
+            r#"line 4: invalid object ID "order-1": expected a UUID"#
+        );
+        let message = lines[3]["error"]["message"].as_str().unwrap();
+        assert!(message.starts_with("line 5: "), "{message}");
+        assert_eq!(lines[4]["object"], uuid_str(3));
+        assert_eq!(lines[4]["is_visible"], true);
+    }
+
+    #[test]
+    fn test_opa_decision_log() {
+        use crate::decisions::{DecisionLogOptions, DecisionSink};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decisions.jsonl");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let options = DecisionLogOptions {
+            batch_size: 1,
+            ..DecisionLogOptions::default()
+        };
+        let log =
+            rt.block_on(async { DecisionLog::spawn(DecisionSink::File(path.clone()), options) });
+
+        let store = TestStore::new(vec![(Uuid::from_u128(1), 0)]).unwrap();
+        let rocket = rocket::build()
+            .manage(SwappableStore::new(store))
+            .manage(log)
+            .mount("/", routes![opa_visible]);
+        let client = Client::tracked(rocket).unwrap();
+        let response = client
+            .post("/v1/data/occlusion/visible")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"input": {{"object": "{}", "visibility_mask": 0}}}}"#,
+                uuid_str(1)
+            ))
+            .dispatch();
+        let body: OpaResponse<bool> = response.into_json().unwrap();
+        assert!(body.result);
+        assert_eq!(body.decision_id.get_version_num(), 4);
+
+        let mut content = String::new();
+        for _ in 0..200 {
+            content = std::fs::read_to_string(&path).unwrap_or_default();
+            if !content.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let event: serde_json::Value = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(event["decision_id"], body.decision_id.to_string());
+        assert_eq!(event["path"], "occlusion/visible");
+        assert_eq!(event["input"]["visibility_mask"], 0);
+        assert_eq!(event["result"], true);
+    }
+
+    #[test]
+    fn test_etag() {
+        let client = create_test_client();
+        let url = format!("/api/v1/check/{}?mask=10", uuid_str(2));
+
+        let response = client.get(url.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("no-cache")
+        );
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+        let revalidate = |url: String, etag: String| {
+            client
+                .get(url)
+                .header(Header::new("If-None-Match", etag))
+                .dispatch()
+        };
+        let response = revalidate(url.clone(), etag.clone());
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+        assert!(response.into_string().is_none());
+
+        for url in ["/api/v1/stats", "/health"] {
+            let etag = client
+                .get(url)
+                .dispatch()
+                .headers()
+                .get_one("ETag")
+                .unwrap()
+                .to_string();
+            assert_eq!(
+                revalidate(url.to_string(), etag).status(),
+                Status::NotModified,
+                "{url}"
+            );
+        }
+
+        // A reload changes the tag
+        let store = client.rocket().state::<SwappableStore>().unwrap();
+        store.upsert(Uuid::from_u128(9), 1);
+        let response = revalidate(url.clone(), etag.clone());
+        assert_eq!(response.status(), Status::Ok);
+        assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    }
 }
@@ -0,0 +1,86 @@
+//! Amazon S3 object fetching (`s3://bucket/key` sources).
+//!
+//! Credentials and region come from the standard AWS provider chain
+//! (environment, shared config/credentials files, web identity, IMDS, ...).
+
+use crate::{
+    error::{LoadError, Result},
+    loader::FetchedObject,
+    progress::ProgressReporter,
+    source::SourceMetadata,
+};
+use aws_sdk_s3::{error::DisplayErrorContext, primitives::DateTimeFormat};
+use tokio::sync::OnceCell;
+
+static S3_CLIENT: OnceCell<aws_sdk_s3::Client> = OnceCell::const_new();
+
+async fn client() -> &'static aws_sdk_s3::Client {
+    S3_CLIENT
+        .get_or_init(|| async {
+            let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+            aws_sdk_s3::Client::new(&config)
+        })
+        .await
+}
+
+/// Download an object, returning `None` if its `ETag` matches `old_metadata`.
+pub(crate) async fn get_object(
+    bucket: &str,
+    key: &str,
+    old_metadata: Option<&SourceMetadata>,
+    progress: Option<&ProgressReporter>,
+) -> Result<Option<FetchedObject>> {
+    let request = client()
+        .await
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .set_if_none_match(old_metadata.and_then(|m| m.etag.clone()));
+
+    let output = match request.send().await {
+        Ok(output) => output,
+        Err(e) if e.raw_response().map(|r| r.status().as_u16()) == Some(304) => return Ok(None),
+        Err(e) => {
+            return Err(LoadError::ObjectStoreError(format!(
+                "s3://{bucket}/{key}: {}",
+                DisplayErrorContext(e)
+            )));
+        }
+    };
+
+    let metadata = SourceMetadata {
+        mtime: None,
+        etag: output.e_tag().map(ToString::to_string),
+        last_modified: output
+            .last_modified()
+            .and_then(|t| t.fmt(DateTimeFormat::HttpDate).ok()),
+        origin: None,
+    };
+    let content_type = output.content_type().map(ToString::to_string);
+    let total_bytes = output.content_length().and_then(|n| u64::try_from(n).ok());
+
+    if let Some(progress) = progress {
+        progress.start(total_bytes);
+    }
+    let mut body = output.body;
+    let mut content = Vec::with_capacity(
+        total_bytes
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(0),
+    );
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| {
+            LoadError::ObjectStoreError(format!("s3://{bucket}/{key}: {}", DisplayErrorContext(e)))
+        })?;
+        content.extend_from_slice(&chunk);
+        if let Some(progress) = progress {
+            progress.add_bytes(chunk.len() as u64);
+        }
+    }
+
+    Ok(Some(FetchedObject {
+        content,
+        metadata,
+        content_type,
+    }))
+}
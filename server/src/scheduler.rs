@@ -0,0 +1,577 @@
+//! Status of the reload scheduler, shared between its task and the admin API.
+
+use crate::systemd;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Notify;
+
+/// Outcome of a scheduled check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckResult {
+    /// New data was swapped in
+    Reloaded,
+    /// A delta patch was applied
+    DeltaApplied,
+    /// The source had not changed
+    Unchanged,
+    /// The reload failed; the current data was kept
+    Failed,
+}
+
+/// How the delay before retrying grows with consecutive failures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackoffCurve {
+    /// Multiply the delay by the multiplier after each failure
+    #[default]
+    Exponential,
+    /// Add the initial delay after each failure
+    Linear,
+}
+
+/// Delays before retrying after consecutive failures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// Delay after the first failure
+    pub initial: Duration,
+    /// Longest delay
+    pub max: Duration,
+    /// How the delay grows
+    pub curve: BackoffCurve,
+    /// Growth factor of an exponential curve
+    pub multiplier: f64,
+}
+
+/// 5s, 10s, 20s, 40s, ... up to 5 minutes.
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(5),
+            max: Duration::from_mins(5),
+            curve: BackoffCurve::Exponential,
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before retrying after `failures` consecutive failures.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let failures = failures.max(1);
+        let factor = match self.curve {
+            BackoffCurve::Exponential => self
+                .multiplier
+                .powi(i32::try_from(failures - 1).unwrap_or(i32::MAX)),
+            BackoffCurve::Linear => f64::from(failures),
+        };
+        Duration::try_from_secs_f64(self.initial.as_secs_f64() * factor)
+            .unwrap_or(Duration::MAX)
+            .min(self.max)
+    }
+}
+
+/// A cron expression checks are run at, in UTC.
+///
+/// Expressions have five fields (minute, hour, day of month, month, day of week), or six with
+/// leading seconds.
+#[derive(Clone)]
+pub struct CronSchedule(croner::Cron);
+
+impl CronSchedule {
+    /// The first time matching the expression after `after`, if any.
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let after = chrono::DateTime::<chrono::Utc>::from(after);
+        let next = self.0.find_next_occurrence(&after, false).ok()?;
+        Some(next.into())
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        croner::Cron::new(expression)
+            .with_seconds_optional()
+            .parse()
+            .map(Self)
+            .map_err(|e| format!("invalid cron expression {expression:?}: {e}"))
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl fmt::Debug for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CronSchedule({:?})", self.0.as_str())
+    }
+}
+
+/// A daily time range (UTC) automatic reloads are restricted to, e.g. `02:00-05:00`.
+///
+/// A window ending before it starts spans midnight (`22:00-02:00`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReloadWindow {
+    /// Start, in seconds since midnight
+    start: u32,
+    /// End (excluded), in seconds since midnight
+    end: u32,
+}
+
+const DAY_SECS: u32 = 24 * 60 * 60;
+
+impl ReloadWindow {
+    fn contains(self, time_of_day: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&time_of_day)
+        } else {
+            time_of_day >= self.start || time_of_day < self.end
+        }
+    }
+
+    fn len(self) -> Duration {
+        Duration::from_secs(u64::from((self.end + DAY_SECS - self.start) % DAY_SECS))
+    }
+}
+
+impl FromStr for ReloadWindow {
+    type Err = String;
+
+    fn from_str(window: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid reload window {window:?}: expected HH:MM-HH:MM");
+        let time = |s: &str| {
+            let (hours, minutes) = s.trim().split_once(':').ok_or_else(invalid)?;
+            let hours: u32 = hours.parse().map_err(|_| invalid())?;
+            let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+            if minutes >= 60 || hours * 60 + minutes > 24 * 60 {
+                return Err(invalid());
+            }
+            Ok((hours * 60 + minutes) * 60 % DAY_SECS)
+        };
+        let (start, end) = window.split_once('-').ok_or_else(invalid)?;
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            return Err(format!("invalid reload window {window:?}: it is empty"));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for ReloadWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = |secs: u32| (secs / 3600, secs / 60 % 60);
+        let (start, end) = (minutes(self.start), minutes(self.end));
+        write!(f, "{:02}:{:02}-{:02}:{:02}", start.0, start.1, end.0, end.1)
+    }
+}
+
+/// When the first of `windows` to open after `at` opens, and how long it stays open.
+///
+/// `None` when `at` is inside a window, or when there are no windows, i.e. no restriction.
+pub fn next_window(windows: &[ReloadWindow], at: SystemTime) -> Option<(SystemTime, Duration)> {
+    let secs = unix_secs(at);
+    let time_of_day = u32::try_from(secs % u64::from(DAY_SECS)).unwrap_or_default();
+    if windows.is_empty() || windows.iter().any(|window| window.contains(time_of_day)) {
+        return None;
+    }
+    windows
+        .iter()
+        .map(|window| {
+            let wait = (window.start + DAY_SECS - time_of_day) % DAY_SECS;
+            (
+                UNIX_EPOCH + Duration::from_secs(secs + u64::from(wait)),
+                window.len(),
+            )
+        })
+        .min_by_key(|&(opens, _)| opens)
+}
+
+/// Snapshot of the scheduler's state (times in seconds since the Unix epoch).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulerStatus {
+    /// Regular interval between checks, in seconds (0 with a cron schedule)
+    pub interval_secs: u64,
+    /// Cron expression checks are run at, replacing the interval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// Daily windows (UTC) checks are restricted to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<String>,
+    /// When the next check is due
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_check_at: Option<u64>,
+    /// When the last check finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_check_at: Option<u64>,
+    /// Outcome of the last check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_result: Option<CheckResult>,
+    /// Error of the last check, if it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Checks failed in a row
+    pub consecutive_failures: u32,
+    /// Delay before the next retry while failing, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff_secs: Option<u64>,
+}
+
+/// Status of the default source's scheduler, with those of the namespaces with a data source
+/// of their own.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulersStatus {
+    #[serde(flatten)]
+    pub status: SchedulerStatus,
+    /// Status of each namespace's scheduler
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, SchedulerStatus>,
+}
+
+/// Schedulers of the namespaces with a data source of their own, keyed by namespace.
+///
+/// Each runs independently of the default source's and of the others: a failing source backs
+/// off and takes its failure action on its own.
+#[derive(Clone, Default)]
+pub struct NamespaceSchedulers(pub BTreeMap<String, Arc<Scheduler>>);
+
+/// Handle on a running reload scheduler.
+pub struct Scheduler {
+    status: RwLock<SchedulerStatus>,
+    trigger: Notify,
+    /// How often to ping the systemd watchdog while waiting
+    watchdog: Option<Duration>,
+    cron: Option<CronSchedule>,
+    /// Largest random change of a delay, as a fraction of it
+    jitter: f64,
+    windows: Vec<ReloadWindow>,
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// A random duration between 0 and `max`, to the millisecond.
+fn random_up_to(max: Duration) -> Duration {
+    let max = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rand::random_range(0..=max))
+}
+
+impl Scheduler {
+    /// Create a handle for a scheduler checking every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            status: RwLock::new(SchedulerStatus {
+                interval_secs: interval.as_secs(),
+                ..SchedulerStatus::default()
+            }),
+            trigger: Notify::new(),
+            watchdog: None,
+            cron: None,
+            jitter: 0.0,
+            windows: Vec::new(),
+        }
+    }
+
+    /// Only run checks inside `windows` (none: at any time); checks falling outside them are
+    /// deferred until the next one opens. Checks triggered with [`run_now`](Self::run_now) run
+    /// at any time.
+    #[must_use]
+    pub fn with_windows(mut self, windows: Vec<ReloadWindow>) -> Self {
+        self.status.get_mut().expect("RwLock poisoned").windows =
+            windows.iter().map(ToString::to_string).collect();
+        self.windows = windows;
+        self
+    }
+
+    /// `delay` from `now`, pushed back to when the next window opens if it ends outside them.
+    ///
+    /// Deferred checks are spread over the start of the window by the jitter.
+    fn deferred(&self, now: SystemTime, delay: Duration) -> Duration {
+        let Some((opens, len)) = next_window(&self.windows, now + delay) else {
+            return delay;
+        };
+        opens.duration_since(now).unwrap_or_default() + random_up_to(len.mul_f64(self.jitter))
+    }
+
+    /// Randomly lengthen or shorten delays by up to `fraction` of them (clamped to 0-1), so that
+    /// a fleet of servers does not hit the source at the same time.
+    ///
+    /// Checks on a cron schedule are only delayed, by up to `fraction` of the time between two
+    /// matches, so that they never run before the data is published.
+    #[must_use]
+    pub fn with_jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// `delay`, randomly lengthened or shortened by up to the jitter.
+    pub fn jittered(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let max = delay.mul_f64(self.jitter);
+        delay.saturating_sub(max) + random_up_to(max * 2)
+    }
+
+    /// Run checks at the times matching `cron` instead of at a regular interval.
+    #[must_use]
+    pub fn with_cron(mut self, cron: CronSchedule) -> Self {
+        let status = self.status.get_mut().expect("RwLock poisoned");
+        status.interval_secs = 0;
+        status.cron = Some(cron.to_string());
+        self.cron = Some(cron);
+        self
+    }
+
+    /// Ping the systemd watchdog every `interval` while waiting between checks.
+    #[must_use]
+    pub fn with_watchdog(mut self, interval: Duration) -> Self {
+        self.watchdog = Some(interval);
+        self
+    }
+
+    /// Current status.
+    pub fn status(&self) -> SchedulerStatus {
+        self.status.read().expect("RwLock poisoned").clone()
+    }
+
+    /// Run a check now instead of at the next scheduled time.
+    ///
+    /// A request made while a check is running triggers another one right after it.
+    pub fn run_now(&self) {
+        self.trigger.notify_one();
+    }
+
+    /// Delay from now until the next regular check, with jitter.
+    ///
+    /// A cron expression that never matches again (e.g. on February 30th) is retried yearly.
+    pub fn next_delay(&self) -> Duration {
+        let Some(cron) = &self.cron else {
+            return self.jittered(Duration::from_secs(self.status().interval_secs));
+        };
+        let now = SystemTime::now();
+        let Some(next) = cron.next_after(now) else {
+            return Duration::from_hours(365 * 24);
+        };
+        let delay = next.duration_since(now).unwrap_or_default();
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let period = cron
+            .next_after(next)
+            .and_then(|after| after.duration_since(next).ok())
+            .unwrap_or_default();
+        delay + random_up_to(period.mul_f64(self.jitter))
+    }
+
+    /// Wait until the next regular check, or less if [`run_now`](Self::run_now) is called.
+    pub async fn wait_next(&self) {
+        self.wait(self.next_delay()).await;
+    }
+
+    /// Wait `delay` until the next check, or less if [`run_now`](Self::run_now) is called.
+    ///
+    /// The wait is longer if the check would fall outside the reload windows.
+    pub async fn wait(&self, delay: Duration) {
+        let delay = self.deferred(SystemTime::now(), delay);
+        self.status.write().expect("RwLock poisoned").next_check_at =
+            Some(unix_secs(SystemTime::now() + delay));
+        let Some(watchdog) = self.watchdog else {
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {}
+                () = self.trigger.notified() => {}
+            }
+            return;
+        };
+
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        let triggered = self.trigger.notified();
+        tokio::pin!(triggered);
+        loop {
+            systemd::notify("WATCHDOG=1");
+            tokio::select! {
+                () = &mut sleep => return,
+                () = &mut triggered => return,
+                () = tokio::time::sleep(watchdog) => {}
+            }
+        }
+    }
+
+    /// Record the outcome of a check.
+    ///
+    /// `backoff` is the delay before retrying after a failure.
+    pub fn record(
+        &self,
+        result: CheckResult,
+        error: Option<String>,
+        consecutive_failures: u32,
+        backoff: Option<Duration>,
+    ) {
+        let mut status = self.status.write().expect("RwLock poisoned");
+        status.last_check_at = Some(unix_secs(SystemTime::now()));
+        status.last_result = Some(result);
+        status.last_error = error;
+        status.consecutive_failures = consecutive_failures;
+        status.backoff_secs = backoff.map(|backoff| backoff.as_secs());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, time::Instant};
+
+    #[test]
+    fn test_run_now() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let scheduler = Arc::new(Scheduler::new(Duration::from_hours(1)));
+
+        let started = Instant::now();
+        let waiting = scheduler.clone();
+        rt.block_on(async {
+            let wait = tokio::spawn(async move { waiting.wait(Duration::from_hours(1)).await });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(scheduler.status().next_check_at.is_some());
+            scheduler.run_now();
+            wait.await.unwrap();
+        });
+        assert!(started.elapsed() < Duration::from_mins(1));
+
+        scheduler.record(
+            CheckResult::Failed,
+            Some("boom".to_string()),
+            2,
+            Some(Duration::from_secs(10)),
+        );
+        let status = scheduler.status();
+        assert_eq!(status.interval_secs, 3600);
+        assert_eq!(status.last_result, Some(CheckResult::Failed));
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.backoff_secs, Some(10));
+    }
+
+    #[test]
+    fn test_cron() {
+        let cron: CronSchedule = "0 */2 * * *".parse().unwrap();
+        assert_eq!(cron.to_string(), "0 */2 * * *");
+        // 2026-01-01T01:30:07Z, then 02:00 and 04:00
+        let at = UNIX_EPOCH + Duration::from_secs(1_767_231_007);
+        let next = cron.next_after(at).unwrap();
+        assert_eq!(unix_secs(next), 1_767_232_800);
+        assert_eq!(unix_secs(cron.next_after(next).unwrap()), 1_767_240_000);
+
+        let seconds: CronSchedule = "30 0 3 * * *".parse().unwrap();
+        assert_eq!(
+            unix_secs(seconds.next_after(at).unwrap()),
+            1_767_236_400 + 30
+        );
+        let e = "0 25 * * *".parse::<CronSchedule>().unwrap_err();
+        assert!(
+            e.starts_with("invalid cron expression \"0 25 * * *\""),
+            "{e}"
+        );
+
+        let scheduler = Scheduler::new(Duration::from_hours(1)).with_cron(cron);
+        let status = scheduler.status();
+        assert_eq!(status.interval_secs, 0);
+        assert_eq!(status.cron.as_deref(), Some("0 */2 * * *"));
+        assert!(scheduler.next_delay() <= Duration::from_hours(2));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let secs =
+            |backoff: Backoff| -> Vec<_> { (1..=8).map(|n| backoff.delay(n).as_secs()).collect() };
+        assert_eq!(secs(Backoff::default()), [5, 10, 20, 40, 80, 160, 300, 300]);
+        assert_eq!(Backoff::default().delay(u32::MAX), Duration::from_mins(5));
+
+        let linear = Backoff {
+            initial: Duration::from_secs(30),
+            max: Duration::from_mins(2),
+            curve: BackoffCurve::Linear,
+            ..Backoff::default()
+        };
+        assert_eq!(secs(linear), [30, 60, 90, 120, 120, 120, 120, 120]);
+
+        let gentle = Backoff {
+            multiplier: 1.5,
+            max: Duration::from_hours(1),
+            ..Backoff::default()
+        };
+        assert_eq!(gentle.delay(3), Duration::from_secs_f64(11.25));
+        assert_eq!(secs(gentle)[7], 85);
+    }
+
+    #[test]
+    fn test_windows() {
+        let window: ReloadWindow = "02:00-05:00".parse().unwrap();
+        assert_eq!(window.to_string(), "02:00-05:00");
+        let overnight: ReloadWindow = "22:30-24:00".parse().unwrap();
+        assert_eq!(overnight.to_string(), "22:30-00:00");
+        assert_eq!(overnight.len(), Duration::from_mins(90));
+        for invalid in ["02:00", "2-5", "02:00-25:00", "02:60-05:00", "03:00-03:00"] {
+            assert!(invalid.parse::<ReloadWindow>().is_err(), "{invalid}");
+        }
+
+        // 2026-01-01T01:30:07Z
+        let at = UNIX_EPOCH + Duration::from_secs(1_767_231_007);
+        let hour = Duration::from_hours(1);
+        assert_eq!(next_window(&[], at), None);
+        assert_eq!(next_window(&["01:00-02:00".parse().unwrap()], at), None);
+        assert_eq!(next_window(&["23:00-01:45".parse().unwrap()], at), None);
+        let (opens, len) = next_window(&[overnight, window], at).unwrap();
+        assert_eq!(unix_secs(opens), 1_767_232_800);
+        assert_eq!(len, hour * 3);
+        let (opens, _) = next_window(&["01:00-01:30".parse().unwrap()], at).unwrap();
+        assert_eq!(unix_secs(opens), 1_767_232_800 - hour.as_secs() + 86_400);
+
+        let scheduler = Scheduler::new(hour).with_windows(vec![window]);
+        assert_eq!(scheduler.status().windows, ["02:00-05:00"]);
+        assert_eq!(scheduler.deferred(at, hour), hour);
+        assert_eq!(
+            scheduler.deferred(at, Duration::from_mins(10)),
+            Duration::from_secs(1_767_232_800 - 1_767_231_007)
+        );
+        // Deferred checks are spread over the window's first 30 minutes
+        let scheduler = scheduler.with_jitter(0.5 / 3.0);
+        let deferred = scheduler.deferred(at, Duration::from_mins(10));
+        assert!(deferred >= Duration::from_secs(1_767_232_800 - 1_767_231_007));
+        assert!(deferred <= Duration::from_secs(1_767_232_800 + 1800 - 1_767_231_007));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let hour = Duration::from_hours(1);
+        let scheduler = Scheduler::new(hour);
+        assert_eq!(scheduler.next_delay(), hour);
+        assert_eq!(scheduler.jittered(hour), hour);
+
+        let scheduler = Scheduler::new(hour).with_jitter(0.1);
+        let delays: Vec<_> = (0..200).map(|_| scheduler.next_delay()).collect();
+        let (min, max) = (hour.mul_f64(0.9), hour.mul_f64(1.1));
+        assert!(delays.iter().all(|delay| (min..=max).contains(delay)));
+        assert!(delays.iter().any(|&delay| delay < hour));
+        assert!(delays.iter().any(|&delay| delay > hour));
+
+        // Cron checks are spread over up to 10% of the 2 hours between matches, never early
+        let cron: CronSchedule = "0 */2 * * *".parse().unwrap();
+        let scheduler = Scheduler::new(hour)
+            .with_cron(cron.clone())
+            .with_jitter(0.1);
+        let now = SystemTime::now();
+        let on_time = cron.next_after(now).unwrap().duration_since(now).unwrap();
+        let delay = scheduler.next_delay();
+        assert!(delay + Duration::from_secs(1) >= on_time);
+        assert!(delay <= on_time + Duration::from_mins(12));
+    }
+}
@@ -0,0 +1,84 @@
+//! Last-good snapshots of loaded stores, used to boot while the source is unreachable.
+//!
+//! The file format is [`occlusion::snapshot`]; this module names and writes the files.
+
+use crate::error::{LoadError, Result};
+pub use occlusion::snapshot::Snapshot;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Snapshot file name for the default store.
+const DEFAULT_FILE: &str = "default.snapshot";
+
+/// Snapshot path in `dir` for a namespace, or the default store when `None`.
+pub fn path_in(dir: &Path, namespace: Option<&str>) -> PathBuf {
+    match namespace {
+        Some(name) => dir.join(format!("namespace-{name}.snapshot")),
+        None => dir.join(DEFAULT_FILE),
+    }
+}
+
+/// Deserialize a snapshot, verifying its checksum.
+pub fn decode(bytes: &[u8]) -> Result<Snapshot> {
+    Snapshot::decode(bytes).map_err(|e| LoadError::SnapshotError(e.to_string()))
+}
+
+/// Read and verify a snapshot file.
+pub fn read(path: &Path) -> Result<Snapshot> {
+    decode(&std::fs::read(path)?)
+}
+
+/// Write `snapshot` to `path`, creating its directory if needed.
+///
+/// The file is written next to `path` and renamed into place, so a crash never
+/// leaves a partial snapshot behind.
+pub fn write(snapshot: &Snapshot, path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(&snapshot.encode())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            source: "https://example.com/data.csv".to_string(),
+            created: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            entries: vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 200)],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path_in(&dir.path().join("cache"), Some("tenant-a"));
+        assert!(path.ends_with("cache/namespace-tenant-a.snapshot"));
+
+        write(&snapshot(), &path).unwrap();
+        assert_eq!(read(&path).unwrap(), snapshot());
+    }
+
+    #[test]
+    fn test_corrupt_snapshot() {
+        let bytes = snapshot().encode();
+        let err = decode(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(matches!(err, LoadError::SnapshotError(_)), "{err}");
+    }
+}
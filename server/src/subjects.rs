@@ -0,0 +1,121 @@
+//! Visibility masks of subjects (users, service accounts), for checks by subject.
+//!
+//! A CSV file of `subject,visibility_mask` rows (with a header) is loaded at startup and
+//! re-read at every reload of the default source, so callers can ask what a subject may see
+//! and leave deciding which mask it deserves to the server.
+
+use crate::error::{LoadError, Result};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+use tracing::{debug, warn};
+
+/// Masks of subjects, managed (as an `Arc`) when checks by subject are enabled.
+pub struct SubjectMasks {
+    path: PathBuf,
+    masks: RwLock<Arc<HashMap<String, u8>>>,
+}
+
+impl SubjectMasks {
+    /// Read the mapping file at `path`.
+    pub fn read(path: PathBuf) -> Result<Self> {
+        let masks = parse(&std::fs::read(&path)?)?;
+        Ok(Self {
+            path,
+            masks: RwLock::new(Arc::new(masks)),
+        })
+    }
+
+    /// Re-read the mapping file, keeping the current masks if it cannot be read.
+    pub async fn reload(self: &Arc<Self>) {
+        let this = self.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let masks = parse(&std::fs::read(&this.path)?)?;
+            let count = masks.len();
+            *this.masks.write().expect("RwLock poisoned") = Arc::new(masks);
+            Ok::<_, LoadError>(count)
+        })
+        .await;
+        match result {
+            Ok(Ok(count)) => debug!(subjects = count, "Subject masks reloaded"),
+            Ok(Err(e)) => {
+                warn!(path = %self.path.display(), error = %e, "Failed to reload subject masks");
+            }
+            Err(e) => warn!(error = %e, "Subject masks reload task failed"),
+        }
+    }
+
+    /// The mask of `subject`, if mapped.
+    pub fn get(&self, subject: &str) -> Option<u8> {
+        self.masks
+            .read()
+            .expect("RwLock poisoned")
+            .get(subject)
+            .copied()
+    }
+
+    /// Returns the number of subjects mapped.
+    pub fn len(&self) -> usize {
+        self.masks.read().expect("RwLock poisoned").len()
+    }
+
+    /// Returns true if no subjects are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Parse `subject,visibility_mask` rows, after a header.
+fn parse(content: &[u8]) -> Result<HashMap<String, u8>> {
+    let invalid = |line: u64, reason: String| {
+        LoadError::InvalidFormat(format!("Invalid subject masks, line {line}: {reason}"))
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content);
+    let mut masks = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| LoadError::InvalidFormat(e.to_string()))?;
+        let line = record.position().map_or(0, csv::Position::line);
+        let (Some(subject), Some(mask), None) = (record.get(0), record.get(1), record.get(2))
+        else {
+            return Err(invalid(
+                line,
+                "expected subject,visibility_mask".to_string(),
+            ));
+        };
+        if subject.is_empty() {
+            return Err(invalid(line, "empty subject".to_string()));
+        }
+        let mask = mask
+            .parse()
+            .map_err(|_| invalid(line, format!("invalid visibility mask {mask:?}")))?;
+        if masks.insert(subject.to_string(), mask).is_some() {
+            return Err(invalid(line, format!("duplicate subject {subject:?}")));
+        }
+    }
+    Ok(masks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let masks = parse(b"subject,visibility_mask\nalice,10\n bob , 3\n").unwrap();
+        assert_eq!(masks.len(), 2);
+        assert_eq!(masks["alice"], 10);
+        assert_eq!(masks["bob"], 3);
+
+        let e = parse(b"subject,visibility_mask\nalice,10\nalice,3\n").unwrap_err();
+        assert!(e.to_string().contains("line 3: duplicate subject"), "{e}");
+        assert!(parse(b"subject,visibility_mask\nalice,256\n").is_err());
+        assert!(parse(b"subject,visibility_mask\n,3\n").is_err());
+        assert!(parse(b"subject,visibility_mask\nalice\n").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! systemd integration: readiness and watchdog notifications (`sd_notify`).
+//!
+//! When run as a `Type=notify` service, the server reports `READY=1` once its listener is up
+//! and the data of the default source and of every namespace is loaded, so units ordered after
+//! it start against a serving instance. With `--allow-empty-start`, a source that failed to
+//! load at startup holds `READY=1` back until it loads. With `WatchdogSec=` set, the reload
+//! scheduler pings the watchdog while it waits between checks, so a reload loop stuck for
+//! longer than the watchdog timeout gets the service restarted. Outside systemd (no
+//! `NOTIFY_SOCKET`), notifications are no-ops.
+
+use crate::{ReloadState, namespace::Namespaces};
+use occlusion::{Store, SwappableStore};
+use rocket::{
+    Orbit, Rocket,
+    fairing::{Fairing, Info, Kind},
+};
+use std::{
+    ffi::OsStr,
+    io,
+    os::unix::{ffi::OsStrExt, net::UnixDatagram},
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+use tracing::warn;
+
+/// Send `state` (e.g. `READY=1`) to the service manager, if there is one.
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&socket, state) {
+        warn!(error = %e, "Failed to notify systemd");
+    }
+}
+
+/// Send `state` to the notification socket at `path`; a leading `@` names an abstract socket.
+fn send(path: &OsStr, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+/// How often to ping the watchdog: half its timeout, if systemd enabled it for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Number of sockets systemd passed this process by socket activation (`LISTEN_FDS`).
+///
+/// The server does not serve on them yet: Rocket 0.5 only serves on a listener it binds
+/// itself.
+pub fn passed_sockets() -> usize {
+    parse_listen_fds(
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_listen_fds(fds: Option<&str>, pid: Option<&str>, own_pid: u32) -> usize {
+    // Unlike the watchdog's, sockets are only meant for the process LISTEN_PID names
+    if pid.is_none_or(|pid| pid.parse() != Ok(own_pid)) {
+        return 0;
+    }
+    fds.and_then(|fds| fds.parse().ok()).unwrap_or(0)
+}
+
+/// Ping the watchdog every `interval`, for when no reload scheduler runs.
+///
+/// Must be called within a Tokio runtime.
+pub fn spawn_watchdog(interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            notify("WATCHDOG=1");
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Wait until every one of `states` has loaded its data.
+async fn all_loaded(states: &[Arc<ReloadState>]) {
+    for state in states {
+        state.wait_loaded().await;
+    }
+}
+
+fn notify_ready(store: Option<&SwappableStore>) {
+    let count = store.map_or(0, Store::len);
+    notify(&format!("READY=1\nSTATUS=Serving {count} UUIDs"));
+}
+
+/// Fairing reporting readiness to systemd once Rocket is serving and every source is loaded.
+pub struct SystemdNotify;
+
+#[rocket::async_trait]
+impl Fairing for SystemdNotify {
+    fn info(&self) -> Info {
+        Info {
+            name: "systemd Notify",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let store = rocket.state::<SwappableStore>().cloned();
+        let states: Vec<Arc<ReloadState>> = rocket
+            .state::<Arc<ReloadState>>()
+            .cloned()
+            .into_iter()
+            .chain(
+                rocket
+                    .state::<Namespaces>()
+                    .into_iter()
+                    .flat_map(Namespaces::iter)
+                    .map(|(_, namespace)| namespace.reload_state.clone()),
+            )
+            .collect();
+        if states
+            .iter()
+            .all(|state| state.loaded.load(Ordering::Acquire))
+        {
+            notify_ready(store.as_ref());
+            return;
+        }
+        notify("STATUS=Waiting for the initial load");
+        tokio::spawn(async move {
+            all_loaded(&states).await;
+            notify_ready(store.as_ref());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        send(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+
+    #[tokio::test]
+    async fn test_all_loaded() {
+        use crate::{loader::LoadOptions, source::DataSource, source::SourceMetadata};
+
+        let state = || {
+            Arc::new(ReloadState::new(
+                DataSource::parse("data.csv"),
+                LoadOptions::default(),
+                SourceMetadata::new(),
+            ))
+        };
+        let (loaded, empty) = (state(), state());
+        empty.loaded.store(false, Ordering::Relaxed);
+        let states = vec![loaded, empty.clone()];
+        let waiting = tokio::spawn(async move { all_loaded(&states).await });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished(), "ready before every source loaded");
+        empty.mark_loaded();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("ready once loaded")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(
+            parse_watchdog(Some("30000000"), None, 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("7"), 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(parse_watchdog(Some("30000000"), Some("8"), 7), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 7), None);
+        assert_eq!(parse_watchdog(None, None, 7), None);
+    }
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(parse_listen_fds(Some("2"), Some("7"), 7), 2);
+        assert_eq!(parse_listen_fds(Some("2"), Some("8"), 7), 0);
+        assert_eq!(parse_listen_fds(Some("2"), None, 7), 0);
+        assert_eq!(parse_listen_fds(None, Some("7"), 7), 0);
+    }
+}
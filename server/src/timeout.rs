@@ -0,0 +1,144 @@
+//! Per-request processing timeouts.
+//!
+//! With a [`RequestTimeout`] managed, every data-plane request gets a [`Deadline`] when it is
+//! admitted. A body still arriving at the deadline is answered with 408, and handlers working
+//! through large batches, filters and streams check it as they go, abandoning the request with a
+//! 503 once it has passed, so oversized requests cannot hold a worker indefinitely.
+
+use crate::catchers::ApiError;
+use rocket::{Request, http::Status};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+/// Objects processed between two checks of a deadline.
+const CHECK_INTERVAL: usize = 1024;
+
+/// Longest time a data-plane request may take, managed when timeouts are enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimeout(pub Duration);
+
+/// When a request must be done by; never, without a [`RequestTimeout`].
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Option<(Instant, Duration)>);
+
+impl Deadline {
+    /// The deadline of `request`, counted from the first call for the request.
+    pub fn of(request: &Request<'_>) -> Self {
+        *request.local_cache(|| {
+            let timeout = request.rocket().state::<RequestTimeout>();
+            Deadline(timeout.map(|&RequestTimeout(timeout)| (Instant::now() + timeout, timeout)))
+        })
+    }
+
+    /// Fail with a 503 if the deadline has passed.
+    pub fn check(&self) -> Result<(), ApiError> {
+        match self.0 {
+            Some((at, timeout)) if Instant::now() >= at => Err(ApiError::new(
+                Status::ServiceUnavailable,
+                format!(
+                    "request not processed within the {} timeout",
+                    humantime::format_duration(timeout)
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// [`check`](Self::check), every so many objects `done`.
+    pub fn check_every(&self, done: usize) -> Result<(), ApiError> {
+        if done.is_multiple_of(CHECK_INTERVAL) {
+            self.check()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wait for `future` (e.g. reading the body) until the deadline, failing with a 408 after.
+    pub async fn within<F: Future>(&self, future: F) -> Result<F::Output, ApiError> {
+        let Some((at, timeout)) = self.0 else {
+            return Ok(future.await);
+        };
+        tokio::time::timeout_at(at.into(), future)
+            .await
+            .map_err(|_| {
+                ApiError::new(
+                    Status::RequestTimeout,
+                    format!(
+                        "request body not received within the {} timeout",
+                        humantime::format_duration(timeout)
+                    ),
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{http::ContentType, local::blocking::Client};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_timeout() {
+        let build = |timeout: Option<Duration>| {
+            let entries = vec![(Uuid::from_u128(1), 0)];
+            let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+            let rocket = rocket::build()
+                .manage(store)
+                .register("/", crate::catchers::catchers())
+                .mount(
+                    "/",
+                    rocket::routes![crate::routes::check, crate::routes::check_batch],
+                );
+            let rocket = match timeout {
+                Some(timeout) => rocket.manage(RequestTimeout(timeout)),
+                None => rocket,
+            };
+            Client::tracked(rocket).unwrap()
+        };
+        let object = Uuid::from_u128(1);
+        let batch = format!(r#"{{"objects": ["{object}"], "visibility_mask": 5}}"#);
+
+        let client = build(Some(Duration::ZERO));
+        let response = client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .body(&batch)
+            .dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: crate::models::ErrorResponse = response.into_json().unwrap();
+        assert_eq!(
+            body.error.message,
+            "request not processed within the 0s timeout"
+        );
+        // Single checks are not worth abandoning
+        let response = client
+            .post("/api/v1/check")
+            .header(ContentType::JSON)
+            .body(format!(r#"{{"object": "{object}", "visibility_mask": 5}}"#))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let client = build(None);
+        let response = client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .body(&batch)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_within() {
+        let deadline = Deadline(Some((Instant::now(), Duration::ZERO)));
+        assert_eq!(deadline.within(async { 1 }).await, Ok(1));
+        let e = deadline
+            .within(std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert_eq!(e.status, Status::RequestTimeout);
+        assert_eq!(Deadline(None).within(async { 1 }).await, Ok(1));
+    }
+}
@@ -0,0 +1,224 @@
+//! API v2: checks that tell denied objects from unknown ones.
+//!
+//! Each response carries the store generation it was answered from, and every lookup of a
+//! request is made against the same data. The v1 routes are unchanged.
+
+use crate::{
+    admission::DataPlane,
+    audit::Decisions,
+    cache::Cached,
+    catchers::ApiError,
+    guards::{Entitlement, JsonBody, MaybeState},
+    ids::{IdNamespace, ObjectId},
+    models::{
+        BatchCheckRequestV2, BatchCheckResponseV2, BatchObject, CheckRequest, CheckResponseV2,
+        Decision, ObjectDecision,
+    },
+    routes::{MaxBatchSize, check_batch_size, resolve},
+};
+use occlusion::SwappableStore;
+use rocket::{State, serde::json::Json};
+
+fn decide(
+    store: &SwappableStore,
+    object: ObjectId,
+    mask: u8,
+    ids: &MaybeState<'_, IdNamespace>,
+    decisions: &Decisions<'_>,
+) -> Result<Json<CheckResponseV2>, ApiError> {
+    let object = resolve(&object, ids)?;
+    let (generation, levels) = store.get_visibilities(&[object]);
+    let decision = Decision::new(levels[0], mask);
+    decisions.record(object, mask, decision, generation);
+    decisions.outcome(decision == Decision::Visible);
+    Ok(Json(CheckResponseV2 {
+        object,
+        decision,
+        generation,
+    }))
+}
+
+/// Check a single object.
+#[post("/api/v2/check", data = "<request>")]
+pub fn check(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<CheckRequest>,
+) -> Result<Json<CheckResponseV2>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    let CheckRequest {
+        object,
+        visibility_mask,
+    } = request.into_inner();
+    decide(
+        store,
+        object,
+        entitlement.mask(visibility_mask)?,
+        &ids,
+        &decisions,
+    )
+}
+
+/// Check a single object given in the URL.
+#[get("/api/v2/check/<object>?<mask>")]
+pub fn check_get(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    object: &str,
+    mask: u8,
+) -> Result<Cached<Json<CheckResponseV2>>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    let mask = entitlement.mask(mask)?;
+    let response = decide(store, ObjectId::from(object), mask, &ids, &decisions)?;
+    Ok(Cached::new(response.generation, response).private(entitlement.is_personal()))
+}
+
+/// Check multiple objects, against the request's visibility mask or each against its own,
+/// with a decision (and optionally the level) per object.
+#[post("/api/v2/check/batch", data = "<request>")]
+pub fn check_batch(
+    plane: DataPlane,
+    store: &State<SwappableStore>,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    entitlement: Entitlement,
+    decisions: Decisions<'_>,
+    request: JsonBody<BatchCheckRequestV2>,
+) -> Result<Json<BatchCheckResponseV2>, ApiError> {
+    let store = &plane.pin.store(store)?;
+    check_batch_size(request.objects.len(), &max)?;
+    let (objects, masks): (Vec<_>, Vec<_>) = request
+        .objects
+        .iter()
+        .map(|entry| match entry {
+            BatchObject::Object(id) => {
+                let mask = entitlement.shared_mask(request.visibility_mask)?;
+                Ok((resolve(id, &ids)?, mask))
+            }
+            BatchObject::Masked(check) => Ok((
+                resolve(&check.object, &ids)?,
+                entitlement.mask(check.visibility_mask)?,
+            )),
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?
+        .into_iter()
+        .unzip();
+    plane.deadline.check()?;
+
+    let (generation, levels) = store.get_visibilities(&objects);
+    let results: Vec<ObjectDecision> = objects
+        .into_iter()
+        .zip(masks)
+        .zip(levels)
+        .map(|((object, mask), level)| {
+            let decision = Decision::new(level, mask);
+            decisions.record(object, mask, decision, generation);
+            ObjectDecision {
+                object,
+                decision,
+                level: level.filter(|_| request.include_levels),
+            }
+        })
+        .collect();
+    let all_visible = results
+        .iter()
+        .all(|result| result.decision == Decision::Visible);
+    decisions.outcome(all_visible);
+    Ok(Json(BatchCheckResponseV2 {
+        all_visible,
+        generation,
+        results,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ErrorResponse;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::Client;
+    use uuid::Uuid;
+
+    fn create_test_client() -> Client {
+        let entries = vec![(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 10)];
+        let store = SwappableStore::new(occlusion::build_store(entries).unwrap());
+        let rocket = rocket::build()
+            .manage(store)
+            .register("/", crate::catchers::catchers())
+            .mount("/", routes![check, check_get, check_batch]);
+        Client::tracked(rocket).expect("valid rocket instance")
+    }
+
+    #[test]
+    fn test_check_decisions() {
+        let client = create_test_client();
+        let decision = |n: u128, mask: u8| {
+            let response = client
+                .get(format!("/api/v2/check/{}?mask={mask}", Uuid::from_u128(n)))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<CheckResponseV2>().unwrap().decision
+        };
+        assert_eq!(decision(1, 0), Decision::Visible);
+        assert_eq!(decision(2, 5), Decision::Denied);
+        assert_eq!(decision(2, 10), Decision::Visible);
+        assert_eq!(decision(3, 255), Decision::Unknown);
+
+        let response = client
+            .post("/api/v2/check")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"object": "{}", "visibility_mask": 5}}"#,
+                Uuid::from_u128(2)
+            ))
+            .dispatch();
+        let body: CheckResponseV2 = response.into_json().unwrap();
+        assert_eq!(body.decision, Decision::Denied);
+        assert_eq!(body.generation, 0);
+    }
+
+    #[test]
+    fn test_check_batch() {
+        let client = create_test_client();
+        let batch = |body: serde_json::Value| {
+            client
+                .post("/api/v2/check/batch")
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch()
+        };
+        let objects = [1, 2, 3].map(|n| Uuid::from_u128(n).to_string());
+
+        let response = batch(serde_json::json!({ "objects": objects, "visibility_mask": 5 }));
+        assert_eq!(response.status(), Status::Ok);
+        let body: BatchCheckResponseV2 = response.into_json().unwrap();
+        assert!(!body.all_visible);
+        let decisions: Vec<_> = body.results.iter().map(|r| r.decision).collect();
+        assert_eq!(
+            decisions,
+            [Decision::Visible, Decision::Denied, Decision::Unknown]
+        );
+        assert!(body.results.iter().all(|r| r.level.is_none()));
+
+        let response = batch(serde_json::json!({
+            "objects": [objects[0], { "object": objects[1], "visibility_mask": 10 }],
+            "visibility_mask": 0,
+            "include_levels": true,
+        }));
+        let body: BatchCheckResponseV2 = response.into_json().unwrap();
+        assert!(body.all_visible);
+        let levels: Vec<_> = body.results.iter().map(|r| r.level).collect();
+        assert_eq!(levels, [Some(0), Some(10)]);
+
+        let response = batch(serde_json::json!({ "objects": objects }));
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let body: ErrorResponse = response.into_json().unwrap();
+        assert!(body.error.message.contains("visibility_mask"));
+    }
+}
@@ -0,0 +1,614 @@
+//! Sanity gates checked before a freshly loaded store replaces the current one.
+
+use crate::{
+    error::{LoadError, Result},
+    format::{ErrorBudget, InputFormat, RejectedRow},
+    loader::{LoadOptions, load_with_options, parse_bytes},
+    progress::ProgressReporter,
+    source::DataSource,
+};
+use occlusion::Store;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use uuid::Uuid;
+
+/// Maximum number of duplicated UUIDs listed in a [`FileReport`].
+const MAX_DUPLICATE_SAMPLES: usize = 20;
+
+/// Limits a candidate store must satisfy to be swapped in.
+///
+/// Every gate is optional; the default accepts any store.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidationGates {
+    /// Minimum number of entries
+    pub min_entries: Option<usize>,
+    /// Maximum percentage of entries added, removed or changed versus the current store
+    pub max_churn: Option<f64>,
+    /// Maximum change of the level-0 share, in percentage points
+    pub max_level0_shift: Option<f64>,
+    /// Maximum estimated heap memory of the store, in bytes
+    pub max_memory: Option<usize>,
+}
+
+/// Differences between the current store and a candidate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diff {
+    /// UUIDs only in the candidate
+    pub added: usize,
+    /// UUIDs only in the current store
+    pub removed: usize,
+    /// UUIDs whose level differs
+    pub changed: usize,
+}
+
+impl Diff {
+    /// Compare `candidate` against `current`.
+    pub fn between(current: &impl Store, candidate: &impl Store) -> Self {
+        let mut diff = Diff::default();
+        let mut kept = 0;
+        for (uuid, level) in candidate.entries() {
+            match current.get_visibility(&uuid) {
+                None => diff.added += 1,
+                Some(old) => {
+                    kept += 1;
+                    if old != level {
+                        diff.changed += 1;
+                    }
+                }
+            }
+        }
+        diff.removed = current.len() - kept;
+        diff
+    }
+
+    /// Entries added, removed or changed as a percentage of `base` entries.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn churn(&self, base: usize) -> f64 {
+        if base == 0 {
+            return 0.0;
+        }
+        (self.added + self.removed + self.changed) as f64 * 100.0 / base as f64
+    }
+}
+
+/// Number of UUIDs whose level changed from `from` to `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelShift {
+    pub from: u8,
+    pub to: u8,
+    pub count: usize,
+}
+
+/// Changes of level between `current` and `candidate`, most common first (then by the
+/// largest jump).
+pub fn level_shifts(current: &impl Store, candidate: &impl Store) -> Vec<LevelShift> {
+    let mut counts = BTreeMap::<(u8, u8), usize>::new();
+    for (uuid, level) in candidate.entries() {
+        match current.get_visibility(&uuid) {
+            Some(old) if old != level => *counts.entry((old, level)).or_default() += 1,
+            _ => {}
+        }
+    }
+    let mut shifts: Vec<_> = counts
+        .into_iter()
+        .map(|((from, to), count)| LevelShift { from, to, count })
+        .collect();
+    shifts.sort_by_key(|shift| {
+        (
+            std::cmp::Reverse(shift.count),
+            std::cmp::Reverse(shift.from.abs_diff(shift.to)),
+        )
+    });
+    shifts
+}
+
+/// Percentage of a store's entries at level 0.
+#[allow(clippy::cast_precision_loss)]
+pub fn level0_share(store: &impl Store) -> f64 {
+    if store.is_empty() {
+        return 0.0;
+    }
+    let level0 = store
+        .visibility_distribution()
+        .get(&0)
+        .copied()
+        .unwrap_or(0);
+    level0 as f64 * 100.0 / store.len() as f64
+}
+
+/// `bytes` in MiB, for messages.
+#[allow(clippy::cast_precision_loss)]
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / f64::from(1 << 20)
+}
+
+impl ValidationGates {
+    /// Check the gates that need no baseline (the minimum entry count and the memory budget).
+    pub fn check_standalone(&self, candidate: &impl Store) -> Result<()> {
+        if let Some(min) = self.min_entries
+            && candidate.len() < min
+        {
+            return Err(LoadError::ValidationError(format!(
+                "{} entries, expected at least {min}",
+                candidate.len()
+            )));
+        }
+        if let Some(max) = self.max_memory {
+            let bytes = candidate.memory_usage();
+            if bytes > max {
+                return Err(LoadError::ValidationError(format!(
+                    "store of {} entries would use {:.1} MiB, memory budget is {:.1} MiB",
+                    candidate.len(),
+                    mib(bytes),
+                    mib(max)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check `candidate` against every gate, comparing with the `current` store.
+    ///
+    /// The churn and level-0 gates are skipped when the current store is empty, as
+    /// there is nothing meaningful to compare against.
+    pub fn check(&self, current: &impl Store, candidate: &impl Store) -> Result<()> {
+        self.check_standalone(candidate)?;
+        if current.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(max) = self.max_churn {
+            let diff = Diff::between(current, candidate);
+            let churn = diff.churn(current.len());
+            if churn > max {
+                return Err(LoadError::ValidationError(format!(
+                    "{churn:.1}% of entries changed ({} added, {} removed, {} changed), \
+                     limit is {max}%",
+                    diff.added, diff.removed, diff.changed
+                )));
+            }
+        }
+
+        if let Some(max) = self.max_level0_shift {
+            let (old, new) = (level0_share(current), level0_share(candidate));
+            if (new - old).abs() > max {
+                return Err(LoadError::ValidationError(format!(
+                    "level-0 share moved from {old:.1}% to {new:.1}%, limit is {max} points"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Result of validating a source without serving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationStatus {
+    /// Loaded and passed every gate
+    Valid,
+    /// Could not be fetched or parsed
+    LoadFailed,
+    /// Loaded but rejected by a validation gate
+    Rejected,
+}
+
+impl ValidationStatus {
+    /// Process exit code for `--validate-and-exit`.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Valid => 0,
+            Self::LoadFailed => 1,
+            // 2 is taken by command-line usage errors
+            Self::Rejected => 3,
+        }
+    }
+}
+
+/// Summary of validating one source.
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceReport {
+    /// Data source that was loaded
+    pub source: String,
+    /// Outcome
+    pub status: ValidationStatus,
+    /// Number of entries loaded
+    pub entries: usize,
+    /// Number of entries per visibility level
+    pub distribution: BTreeMap<u8, usize>,
+    /// Malformed rows skipped under the error budget
+    pub rows_rejected: usize,
+    /// The first malformed rows skipped
+    pub rejected: Vec<RejectedRow>,
+    /// Differences from the baseline store, if one was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Diff>,
+    /// Why the source failed to load or was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Load `source` and check it against `gates`, comparing with `baseline` if given.
+///
+/// Never fails: load errors are reported in the returned summary.
+pub async fn validate_source(
+    source: &DataSource,
+    options: &LoadOptions,
+    gates: &ValidationGates,
+    baseline: Option<&impl Store>,
+) -> SourceReport {
+    let (progress, rx) = ProgressReporter::channel();
+    let options = LoadOptions {
+        progress: Some(progress),
+        ..options.clone()
+    };
+
+    let mut report = SourceReport {
+        source: source.to_string(),
+        status: ValidationStatus::Valid,
+        entries: 0,
+        distribution: BTreeMap::new(),
+        rows_rejected: 0,
+        rejected: Vec::new(),
+        changes: None,
+        error: None,
+    };
+
+    let result = load_with_options(source, None, &options).await;
+    let progress = rx.borrow().clone();
+    report.rows_rejected = progress.rows_rejected;
+    report.rejected = progress.rejected;
+
+    let store = match result {
+        Ok(Some((store, _))) => store,
+        Ok(None) => unreachable!("unconditional load always returns data"),
+        Err(e) => {
+            report.status = ValidationStatus::LoadFailed;
+            report.error = Some(e.to_string());
+            return report;
+        }
+    };
+
+    report.entries = store.len();
+    report.distribution = store.visibility_distribution().into_iter().collect();
+    report.changes = baseline.map(|baseline| Diff::between(baseline, &store));
+
+    let checked = match baseline {
+        Some(baseline) => gates.check(baseline, &store),
+        None => gates.check_standalone(&store),
+    };
+    if let Err(e) = checked {
+        report.status = ValidationStatus::Rejected;
+        report.error = Some(e.to_string());
+    }
+    report
+}
+
+/// Contents of a data file, checked without building a store.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    /// File that was parsed
+    pub path: String,
+    /// Format it was parsed as
+    pub format: String,
+    /// Outcome: valid, or would fail to load
+    pub status: ValidationStatus,
+    /// Rows parsed, including duplicates
+    pub rows: usize,
+    /// Malformed rows
+    pub rows_rejected: usize,
+    /// The first malformed rows
+    pub rejected: Vec<RejectedRow>,
+    /// Rows repeating a UUID of an earlier row of the same store
+    pub duplicates: usize,
+    /// The first UUIDs repeated
+    pub duplicated: Vec<Uuid>,
+    /// Number of rows per visibility level
+    pub distribution: BTreeMap<u8, usize>,
+    /// Number of rows per namespace, for rows routed by the namespace column
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, usize>,
+    /// Estimated heap memory of the stores with each implementation, in bytes
+    pub memory: BTreeMap<&'static str, usize>,
+    /// Why the file would fail to load
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Parse the file at `path` as the server would load it (blocking, CPU-intensive).
+///
+/// Unlike a load, every malformed row and duplicate is counted before the file is judged:
+/// it is valid when the malformed rows fit `options.error_budget` and no UUID repeats.
+/// Never fails: errors are reported in the returned summary.
+pub fn inspect_file(path: &Path, options: &LoadOptions) -> FileReport {
+    let format = options
+        .format
+        .or_else(|| InputFormat::from_path(path))
+        .unwrap_or_default();
+    let mut report = FileReport {
+        path: path.display().to_string(),
+        format: format.to_string(),
+        status: ValidationStatus::Valid,
+        rows: 0,
+        rows_rejected: 0,
+        rejected: Vec::new(),
+        duplicates: 0,
+        duplicated: Vec::new(),
+        distribution: BTreeMap::new(),
+        namespaces: BTreeMap::new(),
+        memory: BTreeMap::new(),
+        error: None,
+    };
+
+    let (progress, rx) = ProgressReporter::channel();
+    let lenient = LoadOptions {
+        error_budget: Some(ErrorBudget::Percent(100.0)),
+        progress: Some(progress),
+        ..options.clone()
+    };
+    let rows = std::fs::read(path)
+        .map_err(LoadError::from)
+        .and_then(|content| parse_bytes(content, format, &lenient));
+    let progress = rx.borrow().clone();
+    report.rows_rejected = progress.rows_rejected;
+    report.rejected = progress.rejected;
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            report.status = ValidationStatus::LoadFailed;
+            report.error = Some(e.to_string());
+            return report;
+        }
+    };
+
+    report.rows = rows.len();
+    report.namespaces = rows
+        .namespaced
+        .iter()
+        .map(|(name, entries)| (name.clone(), entries.len()))
+        .collect();
+    for entries in std::iter::once(&rows.entries).chain(rows.namespaced.values()) {
+        let mut counts = [0; 256];
+        for &(_, level) in entries {
+            counts[usize::from(level)] += 1;
+        }
+        for (level, &count) in counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+            let level = u8::try_from(level).expect("256 levels");
+            *report.distribution.entry(level).or_default() += count;
+        }
+        for (algorithm, bytes) in occlusion::estimate_memory(&counts) {
+            *report.memory.entry(algorithm).or_default() += bytes;
+        }
+
+        let mut uuids: Vec<Uuid> = entries.iter().map(|(uuid, _)| *uuid).collect();
+        uuids.sort_unstable();
+        for pair in uuids.windows(2).filter(|pair| pair[0] == pair[1]) {
+            report.duplicates += 1;
+            if report.duplicated.len() < MAX_DUPLICATE_SAMPLES
+                && report.duplicated.last() != Some(&pair[0])
+            {
+                report.duplicated.push(pair[0]);
+            }
+        }
+    }
+
+    let total = report.rows + report.rows_rejected;
+    let within_budget = options
+        .error_budget
+        .map_or(report.rows_rejected == 0, |budget| {
+            budget.allows(report.rows_rejected, total)
+        });
+    if !within_budget {
+        report.status = ValidationStatus::LoadFailed;
+        report.error = Some(match options.error_budget {
+            Some(budget) => format!(
+                "{} of {total} rows malformed, error budget is {budget}",
+                report.rows_rejected
+            ),
+            None => format!("{} of {total} rows malformed", report.rows_rejected),
+        });
+    } else if report.duplicates > 0 {
+        report.status = ValidationStatus::LoadFailed;
+        report.error = Some(format!("{} duplicate UUIDs", report.duplicates));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use occlusion::build_store;
+    use uuid::Uuid;
+
+    fn store(entries: &[(u128, u8)]) -> occlusion::ActiveStore {
+        build_store(
+            entries
+                .iter()
+                .map(|&(id, level)| (Uuid::from_u128(id), level))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let current = store(&[(1, 0), (2, 0), (3, 5), (4, 5)]);
+        let candidate = store(&[(1, 0), (2, 7), (3, 5), (9, 1)]);
+
+        let diff = Diff::between(&current, &candidate);
+        assert_eq!(
+            diff,
+            Diff {
+                added: 1,
+                removed: 1,
+                changed: 1
+            }
+        );
+        assert!((diff.churn(current.len()) - 75.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_level_shifts() {
+        let current = store(&[(1, 0), (2, 0), (3, 5), (4, 5), (5, 1)]);
+        let candidate = store(&[(1, 3), (2, 3), (3, 0), (4, 5), (5, 9), (6, 3)]);
+
+        let shift = |from, to, count| LevelShift { from, to, count };
+        assert_eq!(
+            level_shifts(&current, &candidate),
+            vec![shift(0, 3, 2), shift(1, 9, 1), shift(5, 0, 1)]
+        );
+        assert!(level_shifts(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_gates() {
+        let current = store(&[(1, 0), (2, 0), (3, 5), (4, 5)]);
+        let truncated = store(&[(1, 0)]);
+        let relabeled = store(&[(1, 0), (2, 0), (3, 0), (4, 0)]);
+
+        let min_entries = ValidationGates {
+            min_entries: Some(2),
+            ..Default::default()
+        };
+        assert!(min_entries.check_standalone(&truncated).is_err());
+        assert!(min_entries.check(&current, &relabeled).is_ok());
+
+        let max_memory = ValidationGates {
+            max_memory: Some(current.memory_usage()),
+            ..Default::default()
+        };
+        assert!(max_memory.check(&current, &current).is_ok());
+        let grown = store(&(0..1000).map(|n| (n, 0)).collect::<Vec<_>>());
+        let err = max_memory.check(&current, &grown).unwrap_err();
+        assert!(
+            err.to_string().contains("store of 1000 entries would use"),
+            "{err}"
+        );
+
+        let max_churn = ValidationGates {
+            max_churn: Some(50.0),
+            ..Default::default()
+        };
+        let err = max_churn.check(&current, &truncated).unwrap_err();
+        assert!(err.to_string().contains("3 removed"), "{err}");
+        assert!(max_churn.check(&current, &relabeled).is_ok());
+
+        let max_shift = ValidationGates {
+            max_level0_shift: Some(25.0),
+            ..Default::default()
+        };
+        assert!(max_shift.check(&current, &relabeled).is_err());
+        // Nothing to compare against
+        assert!(max_shift.check(&store(&[]), &relabeled).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        std::fs::write(
+            &path,
+            "uuid,visibility_level\n\
+             00000000-0000-0000-0000-000000000001,0\n\
+             not-a-uuid,1\n\
+             00000000-0000-0000-0000-000000000002,5\n",
+        )
+        .unwrap();
+        let source = DataSource::File(path);
+        let options = LoadOptions {
+            error_budget: Some(crate::format::ErrorBudget::Count(1)),
+            ..LoadOptions::default()
+        };
+        let baseline = store(&[(1, 0)]);
+
+        let report = validate_source(
+            &source,
+            &options,
+            &ValidationGates::default(),
+            Some(&baseline),
+        )
+        .await;
+        assert_eq!(report.status, ValidationStatus::Valid);
+        assert_eq!(report.entries, 2);
+        assert_eq!(report.distribution, BTreeMap::from([(0, 1), (5, 1)]));
+        assert_eq!(report.rows_rejected, 1);
+        assert_eq!(report.rejected[0].line, 3);
+        assert_eq!(report.changes.unwrap().added, 1);
+
+        let gates = ValidationGates {
+            min_entries: Some(3),
+            ..Default::default()
+        };
+        let report =
+            validate_source(&source, &options, &gates, None::<&occlusion::ActiveStore>).await;
+        assert_eq!(report.status, ValidationStatus::Rejected);
+        assert_eq!(report.status.exit_code(), 3);
+
+        let report = validate_source(
+            &source,
+            &LoadOptions::default(),
+            &gates,
+            None::<&occlusion::ActiveStore>,
+        )
+        .await;
+        assert_eq!(report.status, ValidationStatus::LoadFailed);
+    }
+
+    #[test]
+    fn test_inspect_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        std::fs::write(
+            &path,
+            "uuid,visibility_level,namespace\n\
+             00000000-0000-0000-0000-000000000001,0,\n\
+             not-a-uuid,1,\n\
+             00000000-0000-0000-0000-000000000002,5,\n\
+             00000000-0000-0000-0000-000000000001,3,\n\
+             00000000-0000-0000-0000-000000000001,3,eu\n\
+             00000000-0000-0000-0000-000000000001,4,\n",
+        )
+        .unwrap();
+
+        let report = inspect_file(&path, &LoadOptions::default());
+        assert_eq!(report.status, ValidationStatus::LoadFailed);
+        assert_eq!(report.format, "csv");
+        assert_eq!(report.rows, 5);
+        assert_eq!(report.rows_rejected, 1);
+        assert_eq!(report.rejected[0].line, 3);
+        // Repeated twice in the default store, but not across namespaces
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.duplicated, vec![Uuid::from_u128(1)]);
+        assert_eq!(
+            report.distribution,
+            BTreeMap::from([(0, 1), (3, 2), (4, 1), (5, 1)])
+        );
+        assert_eq!(report.namespaces, BTreeMap::from([("eu".to_string(), 1)]));
+        assert_eq!(
+            report.memory.keys().copied().collect::<Vec<_>>(),
+            ["fullhash", "hashmap", "hybrid", "vec"]
+        );
+        assert_eq!(report.memory["vec"], 5 * 17);
+        assert_eq!(report.error.as_deref(), Some("1 of 6 rows malformed"));
+
+        let budget = LoadOptions {
+            error_budget: Some(ErrorBudget::Count(1)),
+            ..LoadOptions::default()
+        };
+        let report = inspect_file(&path, &budget);
+        assert_eq!(report.error.as_deref(), Some("2 duplicate UUIDs"));
+
+        std::fs::write(
+            &path,
+            "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,0\n",
+        )
+        .unwrap();
+        let report = inspect_file(&path, &budget);
+        assert_eq!(report.status, ValidationStatus::Valid);
+        assert_eq!(report.error, None);
+
+        let report = inspect_file(&dir.path().join("missing.csv"), &budget);
+        assert_eq!(report.status, ValidationStatus::LoadFailed);
+    }
+}
@@ -0,0 +1,194 @@
+//! File-watch based reloads for file, directory and glob sources.
+
+use crate::{
+    ReloadState,
+    scheduler::{ReloadWindow, next_window},
+    source::DataSource,
+};
+use notify::{Event, RecursiveMode, Watcher};
+use occlusion::SwappableStore;
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tracing::{debug, error, info, warn};
+
+/// Directory to watch for changes to `source`.
+///
+/// The parent directory of a file is watched rather than the file itself, so that atomic
+/// replacements (rename over, `ConfigMap` symlink swaps) are seen.
+fn watch_target(source: &DataSource) -> Option<(PathBuf, RecursiveMode)> {
+    match source {
+        DataSource::File(path) if path.is_dir() => {
+            Some((path.clone(), RecursiveMode::NonRecursive))
+        }
+        DataSource::File(path) => Some((parent(path), RecursiveMode::NonRecursive)),
+        DataSource::Glob(pattern) => {
+            // Watch the longest literal prefix; recurse if a directory component is a pattern
+            let pattern = Path::new(pattern);
+            let base: PathBuf = pattern
+                .components()
+                .take_while(|component| !is_pattern(*component))
+                .collect();
+            let base = if base.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                base
+            };
+            let mode = if base == parent(pattern) {
+                RecursiveMode::NonRecursive
+            } else {
+                RecursiveMode::Recursive
+            };
+            Some((base, mode))
+        }
+        _ => None,
+    }
+}
+
+fn is_pattern(component: Component<'_>) -> bool {
+    matches!(component, Component::Normal(c) if c.to_string_lossy().contains(['*', '?', '[']))
+}
+
+/// Parent directory of `path` (`.` for a bare file name).
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Watch a local source and reload `store` shortly after it changes.
+///
+/// Events are debounced: the reload runs once no event has arrived for `debounce`. The
+/// reload is conditional, so events for unrelated files in the same directory only cost a
+/// metadata check. Reloads are deferred until the next of `windows` opens, if any.
+pub fn spawn_watcher(
+    store: SwappableStore,
+    reload_state: Arc<ReloadState>,
+    debounce: Duration,
+    windows: Vec<ReloadWindow>,
+) -> notify::Result<()> {
+    let source = reload_state.source();
+    let Some((path, mode)) = watch_target(&source) else {
+        return Err(notify::Error::generic(&format!(
+            "{source} is not a local source"
+        )));
+    };
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if !event.kind.is_access() => {
+                let _ = tx.send(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "File watch error"),
+        })?;
+    watcher.watch(&path, mode)?;
+    info!(path = %path.display(), "Watching data source for changes");
+
+    tokio::spawn(async move {
+        // Dropping the watcher stops it, so keep it alive for the task's lifetime
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            loop {
+                match tokio::time::timeout(debounce, rx.recv()).await {
+                    Ok(Some(())) => {}
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+
+            let now = SystemTime::now();
+            if let Some((opens, _)) = next_window(&windows, now) {
+                let delay = opens.duration_since(now).unwrap_or_default();
+                info!(
+                    delay_secs = delay.as_secs(),
+                    "Deferring reload to the next reload window"
+                );
+                tokio::time::sleep(delay).await;
+            }
+
+            match reload_state.reload(&store, true).await {
+                Ok(true) => {}
+                Ok(false) => debug!("Source unchanged after file event"),
+                Err(e) => error!(error = %e, "Failed to reload store, keeping existing data"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader, source::SourceMetadata};
+    use occlusion::Store;
+
+    #[test]
+    fn test_watch_target() {
+        let target = |s| watch_target(&DataSource::parse(s));
+
+        assert_eq!(
+            target("/data/visibility.csv"),
+            Some(("/data".into(), RecursiveMode::NonRecursive))
+        );
+        assert_eq!(
+            target("visibility.csv"),
+            Some((".".into(), RecursiveMode::NonRecursive))
+        );
+        assert_eq!(
+            target("/data/part-*.csv"),
+            Some(("/data".into(), RecursiveMode::NonRecursive))
+        );
+        assert_eq!(
+            target("/data/*/part-*.csv"),
+            Some(("/data".into(), RecursiveMode::Recursive))
+        );
+        assert_eq!(target("https://example.com/data.csv"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reload_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        std::fs::write(
+            &path,
+            "uuid,visibility_level\n00000000-0000-0000-0000-000000000001,1\n",
+        )
+        .unwrap();
+
+        let source = DataSource::File(path.clone());
+        let (store, metadata) = loader::load(&source, None).await.unwrap().unwrap();
+        let store = SwappableStore::new(store);
+        let state = Arc::new(ReloadState::new(
+            source,
+            loader::LoadOptions::default(),
+            SourceMetadata {
+                // Any later mtime counts as a change
+                mtime: Some(std::time::SystemTime::UNIX_EPOCH),
+                ..metadata
+            },
+        ));
+        spawn_watcher(store.clone(), state, Duration::from_millis(50), Vec::new()).unwrap();
+
+        std::fs::write(
+            &path,
+            "uuid,visibility_level\n\
+             00000000-0000-0000-0000-000000000001,1\n\
+             00000000-0000-0000-0000-000000000002,1\n",
+        )
+        .unwrap();
+
+        for _ in 0..100 {
+            if store.len() == 2 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("store was not reloaded after the file changed");
+    }
+}
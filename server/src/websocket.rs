@@ -0,0 +1,495 @@
+//! Store events over a WebSocket, optionally narrowed down to changes of chosen objects.
+//!
+//! Clients of `GET /api/v1/events/ws` get the same events as the SSE stream of
+//! [`events`](crate::events). After sending `{"watch": [<object>, ...]}`, they instead get a
+//! `changed` message whenever a swap changes the level of one of those objects, so caches can
+//! invalidate exactly the decisions that went stale. `{"watch": null}` goes back to all events.
+
+use crate::{
+    catchers::{ApiError, set_error_message},
+    events::{Events, StoreEvent},
+    guards::MaybeState,
+    ids::{IdNamespace, ObjectId},
+    namespace::Namespaces,
+    namespaced,
+    routes::{DEFAULT_MAX_BATCH_SIZE, MaxBatchSize},
+};
+use futures_util::{SinkExt, StreamExt};
+use occlusion::SwappableStore;
+use rocket::{
+    Request, Response, Shutdown, State,
+    data::{IoHandler, IoStream},
+    http::Status,
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, io, pin::Pin};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::broadcast::error::RecvError,
+};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{self, Message, handshake::derive_accept_key, protocol::Role},
+};
+use uuid::Uuid;
+
+/// Request guard accepting WebSocket handshakes, failing others with a 400.
+pub struct Handshake {
+    /// The `Sec-WebSocket-Accept` answer to the client's key
+    accept: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Handshake {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        let has = |name, value: &str| {
+            headers
+                .get(name)
+                .flat_map(|h| h.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case(value))
+        };
+        let key = headers.get_one("Sec-WebSocket-Key");
+        match key {
+            Some(key)
+                if has("Connection", "upgrade")
+                    && has("Upgrade", "websocket")
+                    && headers.get_one("Sec-WebSocket-Version") == Some("13") =>
+            {
+                Outcome::Success(Handshake {
+                    accept: derive_accept_key(key.as_bytes()),
+                })
+            }
+            _ => {
+                set_error_message(request, "expected a WebSocket (version 13) handshake");
+                Outcome::Error((Status::BadRequest, "not a WebSocket handshake"))
+            }
+        }
+    }
+}
+
+/// A message to a client, besides forwarded [`StoreEvent`]s.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    /// The watch list was replaced
+    Watching { generation: u64, objects: usize },
+    /// Watched objects changed level
+    Changed {
+        generation: u64,
+        changes: Vec<ObjectChange>,
+    },
+    /// Events were missed by a client too slow to keep up
+    Lagged { missed: u64 },
+    /// A message from the client was rejected
+    Error { message: String },
+}
+
+/// A change of the level of a watched object (`None` when it is not in the store).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ObjectChange {
+    pub object: Uuid,
+    pub previous: Option<u8>,
+    pub level: Option<u8>,
+}
+
+/// A message from a client.
+#[derive(Debug, Deserialize)]
+struct Subscribe {
+    /// Objects to watch, or `None` to get all events
+    watch: Option<Vec<ObjectId>>,
+}
+
+/// Watched objects, with the levels the client was last told about.
+struct Watch {
+    objects: Vec<Uuid>,
+    levels: Vec<Option<u8>>,
+}
+
+impl Watch {
+    fn new(mut objects: Vec<Uuid>, store: &SwappableStore) -> (Self, u64) {
+        objects.sort_unstable();
+        objects.dedup();
+        let (generation, levels) = store.get_visibilities(&objects);
+        (Self { objects, levels }, generation)
+    }
+
+    /// Changes since the levels last reported, which are then taken as reported.
+    fn changes(&mut self, store: &SwappableStore) -> Option<Notification> {
+        let (generation, levels) = store.get_visibilities(&self.objects);
+        let changes: Vec<_> = self
+            .objects
+            .iter()
+            .zip(self.levels.iter().zip(&levels))
+            .filter(|(_, (previous, level))| previous != level)
+            .map(|(&object, (&previous, &level))| ObjectChange {
+                object,
+                previous,
+                level,
+            })
+            .collect();
+        self.levels = levels;
+        (!changes.is_empty()).then_some(Notification::Changed {
+            generation,
+            changes,
+        })
+    }
+}
+
+/// State of one client's connection.
+pub struct Session<'r> {
+    store: &'r SwappableStore,
+    events: &'r Events,
+    /// Namespace of the store, to pick its events out
+    namespace: Option<String>,
+    ids: Option<IdNamespace>,
+    /// Longest watch list accepted (0 = unlimited)
+    max_objects: usize,
+    watch: Option<Watch>,
+}
+
+impl<'r> Session<'r> {
+    pub fn new(
+        store: &'r SwappableStore,
+        events: &'r Events,
+        namespace: Option<String>,
+        ids: Option<IdNamespace>,
+        max_objects: usize,
+    ) -> Self {
+        Self {
+            store,
+            events,
+            namespace,
+            ids,
+            max_objects,
+            watch: None,
+        }
+    }
+
+    /// Serve the client on `socket` until it leaves or `shutdown` completes.
+    pub async fn run<S>(
+        mut self,
+        mut socket: WebSocketStream<S>,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), tungstenite::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut subscription = self.events.subscribe();
+        tokio::pin!(shutdown);
+        loop {
+            let reply = tokio::select! {
+                message = socket.next() => match message {
+                    Some(Ok(message)) => self.receive(message),
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                },
+                received = subscription.recv() => match received {
+                    Ok(event) => self.forward(event),
+                    Err(RecvError::Lagged(missed)) => match &mut self.watch {
+                        // Nothing is missed by looking again
+                        Some(watch) => watch.changes(self.store).map(|n| text(&n)),
+                        None => Some(text(&Notification::Lagged { missed })),
+                    },
+                    Err(RecvError::Closed) => break,
+                },
+                () = &mut shutdown => break,
+            };
+            if let Some(reply) = reply {
+                socket.send(reply).await?;
+            }
+        }
+        socket.close(None).await
+    }
+
+    /// The reply to a message from the client, if any.
+    fn receive(&mut self, message: Message) -> Option<Message> {
+        let reply = match message {
+            Message::Text(message) => match self.subscribe(&message) {
+                Ok(reply) => reply,
+                Err(message) => Notification::Error { message },
+            },
+            Message::Binary(_) => Notification::Error {
+                message: "expected a JSON text message".to_string(),
+            },
+            // Pings and closes are answered by the socket itself
+            _ => return None,
+        };
+        Some(text(&reply))
+    }
+
+    fn subscribe(&mut self, message: &str) -> Result<Notification, String> {
+        let subscribe: Subscribe =
+            serde_json::from_str(message).map_err(|e| format!("invalid message: {e}"))?;
+        let Some(objects) = subscribe.watch else {
+            self.watch = None;
+            return Ok(Notification::Watching {
+                generation: self.store.generation(),
+                objects: 0,
+            });
+        };
+        if self.max_objects != 0 && objects.len() > self.max_objects {
+            return Err(format!(
+                "watch list of {} objects exceeds the limit of {}",
+                objects.len(),
+                self.max_objects
+            ));
+        }
+        let objects = objects
+            .iter()
+            .map(|id| {
+                id.resolve(self.ids.as_ref())
+                    .ok_or_else(|| format!("invalid object ID {:?}: expected a UUID", id.as_text()))
+            })
+            .collect::<Result<_, _>>()?;
+        let (watch, generation) = Watch::new(objects, self.store);
+        let objects = watch.objects.len();
+        self.watch = Some(watch);
+        Ok(Notification::Watching {
+            generation,
+            objects,
+        })
+    }
+
+    /// The message for `event`, if it is about this session's store and of interest.
+    fn forward(&mut self, event: StoreEvent) -> Option<Message> {
+        let (StoreEvent::Swapped { namespace, .. } | StoreEvent::ReloadFailed { namespace, .. }) =
+            &event;
+        if *namespace != self.namespace {
+            return None;
+        }
+        match (&mut self.watch, &event) {
+            (Some(watch), StoreEvent::Swapped { .. }) => {
+                watch.changes(self.store).map(|n| text(&n))
+            }
+            _ => Some(text(&event)),
+        }
+    }
+}
+
+fn text(message: &impl Serialize) -> Message {
+    Message::text(serde_json::to_string(message).expect("messages serialize to JSON"))
+}
+
+/// A session, waiting for the connection to be upgraded.
+pub struct Upgrade<'r> {
+    handshake: Handshake,
+    session: Session<'r>,
+    shutdown: Shutdown,
+}
+
+impl<'r> Responder<'r, 'r> for Upgrade<'r> {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'r> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", self.handshake.accept.clone())
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Upgrade<'_> {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let Upgrade {
+            session, shutdown, ..
+        } = *Pin::into_inner(self);
+        let socket = WebSocketStream::from_raw_socket(io, Role::Server, None).await;
+        match session.run(socket, shutdown).await {
+            Ok(())
+            | Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                Ok(())
+            }
+            Err(tungstenite::Error::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+/// Subscribe to store events over a WebSocket, those of `namespace`'s store if given.
+#[get("/api/v1/events/ws?<namespace>")]
+#[allow(clippy::too_many_arguments)]
+pub fn events_ws<'r>(
+    handshake: Handshake,
+    store: &'r State<SwappableStore>,
+    namespaces: MaybeState<'r, Namespaces>,
+    events: &'r State<Events>,
+    ids: MaybeState<'_, IdNamespace>,
+    max: MaybeState<'_, MaxBatchSize>,
+    namespace: Option<&str>,
+    shutdown: Shutdown,
+) -> Result<Upgrade<'r>, ApiError> {
+    let store = match (namespace, namespaces.0) {
+        (Some(name), Some(namespaces)) => &namespaced::lookup(namespaces, name)?.store,
+        (Some(name), None) => {
+            return Err(ApiError::new(
+                Status::NotFound,
+                format!("unknown namespace {name:?}"),
+            ));
+        }
+        (None, _) => store.inner(),
+    };
+    let max_objects = max.0.map_or(DEFAULT_MAX_BATCH_SIZE, |max| max.0);
+    let session = Session::new(
+        store,
+        events,
+        namespace.map(str::to_string),
+        ids.0.copied(),
+        max_objects,
+    );
+    Ok(Upgrade {
+        handshake,
+        session,
+        shutdown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    fn build(entries: Vec<(Uuid, u8)>) -> SwappableStore {
+        SwappableStore::new(occlusion::build_store(entries).unwrap())
+    }
+
+    async fn receive<S: AsyncRead + AsyncWrite + Unpin>(
+        client: &mut WebSocketStream<S>,
+    ) -> serde_json::Value {
+        match client.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("unexpected {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let (a, b) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let store = build(vec![(a, 1), (b, 2)]);
+        let events = Events::new();
+        let (client, server) = tokio::io::duplex(4096);
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let session = Session::new(&store, &events, None, None, 3);
+        let server = async {
+            let socket = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+            session
+                .run(socket, async {
+                    let _ = stopped.await;
+                })
+                .await
+                .unwrap();
+        };
+        let client = async {
+            let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+            let send = |message: String| Message::text(message);
+
+            // All events until a watch list is set
+            while !events.has_subscribers() {
+                tokio::task::yield_now().await;
+            }
+            events.send(StoreEvent::swapped(None, &store, None));
+            assert_eq!(receive(&mut client).await["type"], "swapped");
+
+            client
+                .send(send(format!(r#"{{"watch": ["{a}", "{b}", "{a}"]}}"#)))
+                .await
+                .unwrap();
+            let reply = receive(&mut client).await;
+            assert_eq!(reply["type"], "watching");
+            assert_eq!(reply["objects"], 2);
+
+            // Only changes of watched objects, and only for this session's store
+            store.swap(occlusion::build_store(vec![(a, 1), (Uuid::from_u128(3), 0)]).unwrap());
+            events.send(StoreEvent::swapped(
+                Some("tenant-a".to_string()),
+                &store,
+                None,
+            ));
+            events.send(StoreEvent::swapped(None, &store, None));
+            let changed: Notification = serde_json::from_value(receive(&mut client).await).unwrap();
+            assert_eq!(
+                changed,
+                Notification::Changed {
+                    generation: store.generation(),
+                    changes: vec![ObjectChange {
+                        object: b,
+                        previous: Some(2),
+                        level: None,
+                    }],
+                }
+            );
+            // Nothing changed for the watched objects: no message
+            store.swap(occlusion::build_store(vec![(a, 1)]).unwrap());
+            events.send(StoreEvent::swapped(None, &store, None));
+            events.send(StoreEvent::ReloadFailed {
+                namespace: None,
+                error: "HTTP 503".to_string(),
+                consecutive_failures: 1,
+            });
+            assert_eq!(receive(&mut client).await["type"], "reload_failed");
+
+            client
+                .send(send(r#"{"watch": ["not-a-uuid"]}"#.to_string()))
+                .await
+                .unwrap();
+            let reply = receive(&mut client).await;
+            assert_eq!(reply["type"], "error");
+            assert_eq!(
+                reply["message"],
+                r#"invalid object ID "not-a-uuid": expected a UUID"#
+            );
+            let watch = format!(r#"{{"watch": ["{a}", "{b}", "{a}", "{b}"]}}"#);
+            client.send(send(watch)).await.unwrap();
+            let reply = receive(&mut client).await;
+            assert_eq!(
+                reply["message"],
+                "watch list of 4 objects exceeds the limit of 3"
+            );
+
+            stop.send(()).unwrap();
+            assert!(matches!(
+                client.next().await.unwrap().unwrap(),
+                Message::Close(_)
+            ));
+        };
+        tokio::join!(server, client);
+    }
+
+    #[test]
+    fn test_handshake() {
+        let rocket = rocket::build()
+            .manage(build(vec![]))
+            .manage(Events::new())
+            .register("/", crate::catchers::catchers())
+            .mount("/", rocket::routes![events_ws]);
+        let client = Client::tracked(rocket).unwrap();
+        let response = client.get("/api/v1/events/ws").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let handshake = |uri: &str| {
+            client
+                .get(uri.to_string())
+                .header(rocket::http::Header::new(
+                    "Connection",
+                    "keep-alive, Upgrade",
+                ))
+                .header(rocket::http::Header::new("Upgrade", "websocket"))
+                .header(rocket::http::Header::new("Sec-WebSocket-Version", "13"))
+                .header(rocket::http::Header::new(
+                    "Sec-WebSocket-Key",
+                    "dGhlIHNhbXBsZSBub25jZQ==",
+                ))
+                .dispatch()
+        };
+        let response = handshake("/api/v1/events/ws");
+        assert_eq!(
+            response.headers().get_one("Sec-WebSocket-Accept"),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+        let response = handshake("/api/v1/events/ws?namespace=tenant-a");
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
@@ -15,6 +15,8 @@ use uuid::Uuid;
 struct HealthResponse {
     status: String,
     uuid_count: usize,
+    #[serde(default)]
+    namespaces: HashMap<String, usize>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,6 +54,24 @@ fn create_test_csv(entries: &[(Uuid, u8)]) -> NamedTempFile {
     file
 }
 
+/// Create a test JSON Lines file with the given entries.
+fn create_test_jsonl(entries: &[(Uuid, u8)]) -> NamedTempFile {
+    let mut file = tempfile::Builder::new()
+        .suffix(".jsonl")
+        .tempfile()
+        .expect("Failed to create temp file");
+    for (uuid, level) in entries {
+        writeln!(
+            file,
+            r#"{{"uuid": "{}", "visibility_level": {}}}"#,
+            uuid, level
+        )
+        .expect("Failed to write entry");
+    }
+    file.flush().expect("Failed to flush file");
+    file
+}
+
 /// Build a test rocket instance from a CSV file path.
 fn build_test_rocket(csv_path: &str) -> rocket::Rocket<rocket::Build> {
     use occlusion::SwappableStore;
@@ -267,3 +287,1028 @@ fn test_visibility_boundaries() {
     let body: CheckResponse = response.into_json().unwrap();
     assert!(body.is_visible); // 128 <= 255
 }
+
+#[test]
+fn test_server_with_jsonl_file() {
+    let uuid1 = Uuid::from_u128(1);
+    let uuid2 = Uuid::from_u128(2);
+
+    let entries = vec![(uuid1, 0), (uuid2, 7)];
+    let jsonl_file = create_test_jsonl(&entries);
+
+    let rocket = build_test_rocket(jsonl_file.path().to_str().unwrap());
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    let response = client.get("/health").dispatch();
+    let body: HealthResponse = response.into_json().unwrap();
+    assert_eq!(body.uuid_count, 2);
+
+    let response = client
+        .post("/api/v1/check")
+        .header(ContentType::JSON)
+        .body(format!(
+            r#"{{"object": "{}", "visibility_mask": 6}}"#,
+            uuid2
+        ))
+        .dispatch();
+    let body: CheckResponse = response.into_json().unwrap();
+    assert!(!body.is_visible); // 7 > 6
+}
+
+#[test]
+fn test_server_with_gzipped_csv_file() {
+    use flate2::{Compression, write::GzEncoder};
+
+    let uuid = Uuid::from_u128(5);
+    let mut file = tempfile::Builder::new()
+        .suffix(".csv.gz")
+        .tempfile()
+        .expect("Failed to create temp file");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    writeln!(encoder, "uuid,visibility_level").unwrap();
+    writeln!(encoder, "{},3", uuid).unwrap();
+    file.write_all(&encoder.finish().unwrap()).unwrap();
+    file.flush().unwrap();
+
+    let rocket = build_test_rocket(file.path().to_str().unwrap());
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    let response = client.get("/health").dispatch();
+    let body: HealthResponse = response.into_json().unwrap();
+    assert_eq!(body.uuid_count, 1);
+
+    let response = client
+        .post("/api/v1/check")
+        .header(ContentType::JSON)
+        .body(format!(r#"{{"object": "{}", "visibility_mask": 3}}"#, uuid))
+        .dispatch();
+    let body: CheckResponse = response.into_json().unwrap();
+    assert!(body.is_visible);
+}
+
+#[test]
+fn test_checksum_sidecar() {
+    use server::{
+        loader::{LoadOptions, load_with_options},
+        source::DataSource,
+    };
+    use sha2::{Digest, Sha256};
+
+    let csv_file = create_test_csv(&[(Uuid::from_u128(1), 1)]);
+    let source = DataSource::parse(csv_file.path().to_str().unwrap());
+    let sidecar = NamedTempFile::new().unwrap();
+    let options = LoadOptions {
+        checksum: Some(DataSource::parse(sidecar.path().to_str().unwrap())),
+        ..LoadOptions::default()
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    // Digest of the full file, as written by sha256sum
+    let content = std::fs::read(csv_file.path()).unwrap();
+    let digest = format!("{:x}  data.csv\n", Sha256::digest(&content));
+    std::fs::write(sidecar.path(), &digest).unwrap();
+    let loaded = rt
+        .block_on(load_with_options(&source, None, &options))
+        .expect("matching checksum should load");
+    assert!(loaded.is_some());
+
+    std::fs::write(sidecar.path(), "0".repeat(64)).unwrap();
+    let err = rt
+        .block_on(load_with_options(&source, None, &options))
+        .unwrap_err();
+    assert!(err.to_string().contains("SHA-256 mismatch"), "{err}");
+}
+
+#[test]
+fn test_server_with_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    for part in 0..4u128 {
+        let mut content = String::from("uuid,visibility_level\n");
+        for i in 0..10 {
+            content.push_str(&Uuid::from_u128(part * 10 + i).to_string());
+            content.push_str(",1\n");
+        }
+        std::fs::write(dir.path().join(format!("part-{part}.csv")), content).unwrap();
+    }
+    // Hidden files are ignored
+    std::fs::write(dir.path().join(".part-4.csv"), "not,a,csv\n").unwrap();
+
+    let rocket = build_test_rocket(dir.path().to_str().unwrap());
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let response = client.get("/health").dispatch();
+    let body: HealthResponse = response.into_json().unwrap();
+    assert_eq!(body.uuid_count, 40);
+
+    let pattern = dir.path().join("part-[01].csv");
+    let rocket = build_test_rocket(pattern.to_str().unwrap());
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let response = client.get("/health").dispatch();
+    let body: HealthResponse = response.into_json().unwrap();
+    assert_eq!(body.uuid_count, 20);
+}
+
+#[test]
+fn test_health_reports_namespaces() {
+    use server::{
+        ReloadState,
+        loader::LoadOptions,
+        namespace::{Namespace, Namespaces},
+        source::DataSource,
+    };
+    use std::sync::Arc;
+
+    let csv_file = create_test_csv(&[(Uuid::from_u128(1), 0)]);
+    let tenant_file = create_test_csv(&[(Uuid::from_u128(2), 0), (Uuid::from_u128(3), 0)]);
+
+    let source = DataSource::parse(tenant_file.path().to_str().unwrap());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, metadata) = rt
+        .block_on(server::loader::load(&source, None))
+        .unwrap()
+        .unwrap();
+    let mut namespaces = Namespaces::new();
+    namespaces.insert(
+        "tenant-a".to_string(),
+        Namespace {
+            store: occlusion::SwappableStore::new(store),
+            reload_state: Arc::new(ReloadState::new(source, LoadOptions::default(), metadata)),
+        },
+    );
+
+    let rocket = build_test_rocket(csv_file.path().to_str().unwrap()).manage(namespaces);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let response = client.get("/health").dispatch();
+    let body: HealthResponse = response.into_json().unwrap();
+    assert_eq!(body.uuid_count, 1);
+    assert_eq!(body.namespaces.get("tenant-a"), Some(&2));
+
+    // Without configured namespaces the field is omitted
+    let rocket = build_test_rocket(csv_file.path().to_str().unwrap());
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let body = client.get("/health").dispatch().into_string().unwrap();
+    assert!(!body.contains("namespaces"), "{body}");
+}
+
+#[test]
+fn test_stats_report_last_load() {
+    use server::{
+        ReloadState, loader::LoadOptions, progress::ProgressReporter, source::DataSource,
+    };
+    use std::sync::Arc;
+
+    let csv_file = create_test_csv(&[(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)]);
+    let source = DataSource::parse(csv_file.path().to_str().unwrap());
+    let (progress, _rx) = ProgressReporter::channel();
+    let options = LoadOptions {
+        progress: Some(progress),
+        ..LoadOptions::default()
+    };
+    let state = Arc::new(ReloadState::new(
+        source.clone(),
+        options,
+        server::source::SourceMetadata::new(),
+    ));
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, _) = rt
+        .block_on(server::loader::load(&source, None))
+        .unwrap()
+        .unwrap();
+    let store = occlusion::SwappableStore::new(store);
+    assert!(rt.block_on(state.reload(&store, false)).unwrap());
+
+    let rocket = rocket::build()
+        .manage(store)
+        .manage(state)
+        .mount("/", rocket::routes![server::routes::stats]);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let body: serde_json::Value = client.get("/api/v1/stats").dispatch().into_json().unwrap();
+    let last_load = &body["last_load"];
+    assert_eq!(last_load["rows_parsed"], 2);
+    assert_eq!(last_load["rows_rejected"], 0);
+    assert_eq!(
+        last_load["bytes_fetched"],
+        std::fs::metadata(csv_file.path()).unwrap().len()
+    );
+    assert_eq!(body["generation"], 1);
+    assert_eq!(body["source"], source.to_string());
+    assert!(body["last_reload_at"].is_u64());
+    assert_eq!(body["consecutive_failures"], 0);
+
+    // A failed reload is counted until the next success
+    let state = client.rocket().state::<Arc<ReloadState>>().unwrap();
+    let store = client
+        .rocket()
+        .state::<occlusion::SwappableStore>()
+        .unwrap();
+    std::fs::write(csv_file.path(), "not,a,valid\nfile\n").unwrap();
+    assert!(rt.block_on(state.reload(store, false)).is_err());
+    let body: serde_json::Value = client.get("/api/v1/stats").dispatch().into_json().unwrap();
+    assert_eq!(body["consecutive_failures"], 1);
+    assert_eq!(body["generation"], 1);
+}
+
+#[test]
+fn test_namespace_column_routes_rows() {
+    use occlusion::{Store, SwappableStore};
+    use server::{
+        ReloadState,
+        loader::{LoadOptions, load_routed},
+        source::DataSource,
+    };
+    use std::collections::BTreeMap;
+
+    let mut file = NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "uuid,visibility_level,namespace\n{},0,\n{},3,tenant-a\n{},5,tenant-b\n",
+        Uuid::from_u128(1),
+        Uuid::from_u128(2),
+        Uuid::from_u128(3)
+    )
+    .unwrap();
+    file.flush().unwrap();
+    let source = DataSource::parse(file.path().to_str().unwrap());
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (loaded, metadata) = rt
+        .block_on(load_routed(&source, None, &LoadOptions::default()))
+        .unwrap()
+        .unwrap();
+    assert_eq!(loaded.store.len(), 1);
+    assert_eq!(
+        loaded.namespaces["tenant-a"].get_visibility(&Uuid::from_u128(2)),
+        Some(3)
+    );
+
+    let store = SwappableStore::new(loaded.store);
+    let routes: BTreeMap<_, _> = loaded
+        .namespaces
+        .into_iter()
+        .map(|(name, store)| (name, SwappableStore::new(store)))
+        .collect();
+    let state =
+        ReloadState::new(source, LoadOptions::default(), metadata).with_routes(routes.clone());
+
+    // tenant-b disappears, tenant-a changes and tenant-c is new
+    std::fs::write(
+        file.path(),
+        format!(
+            "uuid,visibility_level,namespace\n{},0,\n{},7,tenant-a\n{},1,tenant-c\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2),
+            Uuid::from_u128(4)
+        ),
+    )
+    .unwrap();
+    assert!(rt.block_on(state.reload(&store, false)).unwrap());
+    assert_eq!(
+        routes["tenant-a"].get_visibility(&Uuid::from_u128(2)),
+        Some(7)
+    );
+    assert!(routes["tenant-b"].is_empty());
+    assert!(!routes.contains_key("tenant-c"));
+}
+
+#[test]
+fn test_admin_reload() {
+    use rocket::http::Header;
+    use server::{
+        ReloadState, guards::AdminToken, loader::LoadOptions, models::ReloadResponse,
+        source::DataSource,
+    };
+    use std::sync::Arc;
+
+    let csv_file = create_test_csv(&[(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)]);
+    let source = DataSource::parse(csv_file.path().to_str().unwrap());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, metadata) = rt
+        .block_on(server::loader::load(&source, None))
+        .unwrap()
+        .unwrap();
+    let state = Arc::new(ReloadState::new(source, LoadOptions::default(), metadata));
+
+    let rocket = rocket::build()
+        .manage(occlusion::SwappableStore::new(store))
+        .manage(state)
+        .manage(AdminToken("secret".to_string()))
+        .mount("/", rocket::routes![server::admin::reload]);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let auth = Header::new("Authorization", "Bearer secret");
+
+    let response = client.post("/api/v1/admin/reload").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // Unchanged source
+    let response = client
+        .post("/api/v1/admin/reload")
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: ReloadResponse = response.into_json().unwrap();
+    assert!(!body.reloaded);
+    assert_eq!(body.generation, 0);
+
+    let mut file = std::fs::File::create(csv_file.path()).unwrap();
+    writeln!(file, "uuid,visibility_level").unwrap();
+    writeln!(file, "{},3", Uuid::from_u128(2)).unwrap();
+    writeln!(file, "{},0", Uuid::from_u128(3)).unwrap();
+    writeln!(file, "{},0", Uuid::from_u128(4)).unwrap();
+    drop(file);
+    let response = client
+        .post("/api/v1/admin/reload?force=true")
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: ReloadResponse = response.into_json().unwrap();
+    assert!(body.reloaded);
+    assert_eq!(body.generation, 1);
+    let diff = body.diff.unwrap();
+    assert_eq!((diff.added, diff.removed, diff.changed), (2, 1, 1));
+
+    std::fs::write(csv_file.path(), "not,a,valid\nfile\n").unwrap();
+    let response = client
+        .post("/api/v1/admin/reload?force=true")
+        .header(auth)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadGateway);
+}
+
+#[test]
+fn test_admin_reload_dry_run() {
+    use occlusion::Store;
+    use rocket::http::Header;
+    use server::{ReloadState, guards::AdminToken, loader::LoadOptions, source::DataSource};
+    use std::sync::Arc;
+
+    let csv_file = create_test_csv(&[(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)]);
+    let source = DataSource::parse(csv_file.path().to_str().unwrap());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, metadata) = rt
+        .block_on(server::loader::load(&source, None))
+        .unwrap()
+        .unwrap();
+    let gates = server::validation::ValidationGates {
+        max_churn: Some(50.0),
+        ..Default::default()
+    };
+    let state = ReloadState::new(source, LoadOptions::default(), metadata).with_gates(gates);
+    let store = occlusion::SwappableStore::new(store);
+
+    let rocket = rocket::build()
+        .manage(store.clone())
+        .manage(Arc::new(state))
+        .manage(AdminToken("secret".to_string()))
+        .mount(
+            "/",
+            rocket::routes![server::admin::reload, server::admin::reload_dry_run],
+        );
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let dry_run = || {
+        let response = client
+            .post("/api/v1/admin/reload?dry_run=true")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<serde_json::Value>().unwrap()
+    };
+
+    let report = dry_run();
+    assert_eq!(report["status"], "valid");
+    assert_eq!(report["entries"], 2);
+    assert_eq!(report["changes"]["added"], 0);
+
+    // Rejected by the churn gate, but the report still shows the changes
+    std::fs::write(
+        csv_file.path(),
+        format!(
+            "uuid,visibility_level\n{},3\n{},0\n",
+            Uuid::from_u128(2),
+            Uuid::from_u128(3)
+        ),
+    )
+    .unwrap();
+    let report = dry_run();
+    assert_eq!(report["status"], "rejected");
+    assert_eq!(report["changes"]["added"], 1);
+    assert_eq!(report["changes"]["removed"], 1);
+    assert_eq!(report["changes"]["changed"], 1);
+    assert!(report["error"].as_str().unwrap().contains("limit is 50%"));
+
+    // Nothing was swapped in
+    assert_eq!(store.generation(), 0);
+    assert_eq!(store.get_visibility(&Uuid::from_u128(1)), Some(0));
+}
+
+#[test]
+fn test_admin_diff_source() {
+    use rocket::http::{ContentType, Header};
+    use server::{guards::AdminToken, models::DiffResponse, validation::LevelShift};
+
+    let current = create_test_csv(&[(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)]);
+    let candidate = create_test_csv(&[
+        (Uuid::from_u128(1), 3),
+        (Uuid::from_u128(2), 0),
+        (Uuid::from_u128(3), 0),
+    ]);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, _) = rt
+        .block_on(server::loader::load(
+            &server::source::DataSource::parse(current.path().to_str().unwrap()),
+            None,
+        ))
+        .unwrap()
+        .unwrap();
+
+    let rocket = rocket::build()
+        .manage(occlusion::SwappableStore::new(store))
+        .manage(AdminToken("secret".to_string()))
+        .register("/", server::catchers::catchers())
+        .mount("/", rocket::routes![server::admin::diff_source]);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let diff = |body: String| {
+        client
+            .post("/api/v1/admin/diff")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", "Bearer secret"))
+            .body(body)
+            .dispatch()
+    };
+
+    let path = candidate.path().to_str().unwrap();
+    let response = diff(format!(r#"{{"source": "{path}", "top": 1}}"#));
+    assert_eq!(response.status(), Status::Ok);
+    let body: DiffResponse = response.into_json().unwrap();
+    assert_eq!(body.entries, 3);
+    assert_eq!(
+        (body.diff.added, body.diff.removed, body.diff.changed),
+        (1, 0, 2)
+    );
+    // Both shifts are as common, the largest comes first
+    assert_eq!(
+        body.level_shifts,
+        vec![LevelShift {
+            from: 5,
+            to: 0,
+            count: 1
+        }]
+    );
+
+    let response = diff(r#"{"source": "/nonexistent/data.csv"}"#.to_string());
+    assert_eq!(response.status(), Status::BadGateway);
+}
+
+#[test]
+fn test_admin_sample() {
+    use rocket::http::Header;
+    use server::{guards::AdminToken, models::SampleResponse};
+
+    let entries: Vec<_> = (0..100u8)
+        .map(|i| (Uuid::from_u128(u128::from(i)), i % 4))
+        .collect();
+    let store = occlusion::build_store(entries.clone()).unwrap();
+    let rocket = rocket::build()
+        .manage(occlusion::SwappableStore::new(store))
+        .manage(AdminToken("secret".to_string()))
+        .mount("/", rocket::routes![server::admin::sample]);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let sample = |uri: &str| {
+        let response = client
+            .get(uri.to_string())
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<SampleResponse>().unwrap()
+    };
+
+    let body = sample("/api/v1/admin/sample?n=10&level=3");
+    assert_eq!(body.population, 25);
+    assert_eq!(body.entries.len(), 10);
+    for entry in &body.entries {
+        assert_eq!(entry.level, 3);
+        assert!(entries.contains(&(entry.object, entry.level)));
+    }
+
+    let body = sample("/api/v1/admin/sample");
+    assert_eq!(body.population, 100);
+    assert_eq!(body.entries.len(), server::admin::DEFAULT_SAMPLE_SIZE);
+    let body = sample("/api/v1/admin/sample?n=1000&level=7");
+    assert_eq!((body.population, body.entries.len()), (0, 0));
+
+    let response = client.get("/api/v1/admin/sample").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_admin_export() {
+    use rocket::http::{ContentType, Header};
+    use server::guards::AdminToken;
+
+    let entries = vec![(Uuid::from_u128(2), 7), (Uuid::from_u128(1), 0)];
+    let store = occlusion::build_store(entries).unwrap();
+    let rocket = rocket::build()
+        .manage(occlusion::SwappableStore::new(store))
+        .manage(AdminToken("secret".to_string()))
+        .register("/", server::catchers::catchers())
+        .mount("/", rocket::routes![server::admin::export]);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let export = |uri: &str| {
+        client
+            .get(uri.to_string())
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch()
+    };
+
+    let response = export("/api/v1/admin/export");
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some(r#"attachment; filename="occlusion-0.csv""#)
+    );
+    assert_eq!(
+        response.headers().get_one("X-Occlusion-Generation"),
+        Some("0")
+    );
+    assert_eq!(
+        response.into_string().unwrap(),
+        format!(
+            "uuid,visibility_level\n{},0\n{},7\n",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        )
+    );
+
+    let response = export("/api/v1/admin/export?format=snapshot");
+    assert_eq!(response.content_type(), Some(ContentType::Binary));
+    let snapshot = occlusion::snapshot::Snapshot::decode(&response.into_bytes().unwrap()).unwrap();
+    assert_eq!(snapshot.source, "export");
+    assert_eq!(snapshot.entries.len(), 2);
+
+    let response = export("/api/v1/admin/export?namespace=tenant-a");
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_generation_pinning() {
+    use rocket::http::{ContentType, Header};
+    use server::{
+        cache::GenerationHeader,
+        guards::AdminToken,
+        models::{CheckResponse, UuidPage},
+    };
+
+    let entries: Vec<_> = (1..=3).map(|i| (Uuid::from_u128(i), 0)).collect();
+    let store = occlusion::SwappableStore::new(occlusion::build_store(entries).unwrap());
+    store.set_retention(1);
+    let rocket = rocket::build()
+        .manage(store.clone())
+        .manage(AdminToken("secret".to_string()))
+        .attach(GenerationHeader)
+        .register("/", server::catchers::catchers())
+        .mount(
+            "/",
+            rocket::routes![server::routes::list_uuids, server::routes::check],
+        );
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let list = |cursor: Option<Uuid>, generation: &str| {
+        let cursor = cursor.map(|c| format!("&cursor={c}")).unwrap_or_default();
+        client
+            .get(format!("/api/v1/uuids?level=0&limit=2{cursor}"))
+            .header(Header::new("Authorization", "Bearer secret"))
+            .header(Header::new(
+                "X-Occlusion-Generation",
+                generation.to_string(),
+            ))
+            .dispatch()
+    };
+    let check = |generation: &str| {
+        client
+            .post("/api/v1/check")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "X-Occlusion-Generation",
+                generation.to_string(),
+            ))
+            .body(format!(
+                r#"{{"object": "{}", "visibility_mask": 0}}"#,
+                Uuid::from_u128(3)
+            ))
+            .dispatch()
+    };
+
+    let first: UuidPage = list(None, "0").into_json().unwrap();
+    assert_eq!(first.uuids.len(), 2);
+
+    // A reload in the middle of the listing does not affect the remaining pages
+    store.swap(occlusion::build_store(vec![(Uuid::from_u128(1), 0)]).unwrap());
+    let second: UuidPage = list(first.next_cursor, "0").into_json().unwrap();
+    assert_eq!(second.uuids, vec![Uuid::from_u128(3)]);
+
+    let response = check("0");
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("X-Occlusion-Generation"),
+        Some("0")
+    );
+    assert!(response.into_json::<CheckResponse>().unwrap().is_visible);
+    assert!(!check("1").into_json::<CheckResponse>().unwrap().is_visible);
+
+    // Only one replaced generation is retained
+    store.swap(occlusion::build_store(vec![]).unwrap());
+    let response = check("0");
+    assert_eq!(response.status(), Status::Conflict);
+    assert!(
+        response
+            .into_string()
+            .unwrap()
+            .contains("generation 0 is not available (current generation is 2)")
+    );
+    assert_eq!(list(None, "0").status(), Status::Conflict);
+    assert_eq!(list(None, "1").status(), Status::Ok);
+    assert_eq!(check("latest").status(), Status::BadRequest);
+}
+
+#[test]
+fn test_admin_clear_and_replace_store() {
+    use rocket::http::Header;
+    use server::{guards::AdminToken, models::StoreResponse};
+
+    let csv_file = create_test_csv(&[(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)]);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, _) = rt
+        .block_on(server::loader::load(
+            &server::source::DataSource::parse(csv_file.path().to_str().unwrap()),
+            None,
+        ))
+        .unwrap()
+        .unwrap();
+
+    let rocket = rocket::build()
+        .manage(occlusion::SwappableStore::new(store))
+        .manage(AdminToken("secret".to_string()))
+        .mount(
+            "/",
+            rocket::routes![
+                server::routes::health,
+                server::admin::clear,
+                server::admin::replace_store
+            ],
+        );
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let auth = Header::new("Authorization", "Bearer secret");
+    let uuid_count = |client: &Client| {
+        let body: HealthResponse = client.get("/health").dispatch().into_json().unwrap();
+        body.uuid_count
+    };
+
+    let response = client
+        .put("/api/v1/admin/store")
+        .body("uuid,visibility_level\n")
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let body = format!(
+        "{{\"uuid\": \"{}\", \"visibility_level\": 1}}\n",
+        Uuid::from_u128(9)
+    );
+    let response = client
+        .put("/api/v1/admin/store")
+        .header(auth.clone())
+        .header(ContentType::new("application", "x-ndjson"))
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: StoreResponse = response.into_json().unwrap();
+    assert_eq!(body.uuid_count, 1);
+    assert_eq!(body.generation, 1);
+    assert_eq!(uuid_count(&client), 1);
+
+    // Malformed data leaves the store alone
+    let response = client
+        .put("/api/v1/admin/store")
+        .header(auth.clone())
+        .body("uuid,visibility_level\nnope,1\n")
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(uuid_count(&client), 1);
+
+    let response = client.post("/api/v1/admin/clear").header(auth).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(uuid_count(&client), 0);
+}
+
+#[test]
+fn test_admin_clear_ends_canary() {
+    use rocket::http::Header;
+    use server::{
+        canary::{Canary, CanaryOptions},
+        guards::AdminToken,
+    };
+    use std::{sync::Arc, time::Duration};
+
+    let build = |level| occlusion::build_store(vec![(Uuid::from_u128(1), level)]).unwrap();
+    let store = occlusion::SwappableStore::new(build(0));
+    let canary = Arc::new(Canary::new(
+        store.clone(),
+        CanaryOptions {
+            sample: 1.0,
+            window: Duration::from_mins(1),
+            max_divergence: 0.0,
+            min_samples: 1,
+        },
+    ));
+    // A reload that changes every decision
+    let previous = store.at_generation(0).unwrap();
+    store.swap(build(9));
+    canary.start(previous);
+
+    let rocket = rocket::build()
+        .manage(store)
+        .manage(canary.clone())
+        .manage(AdminToken("secret".to_string()))
+        .mount(
+            "/",
+            rocket::routes![
+                server::routes::check,
+                server::routes::health,
+                server::admin::clear
+            ],
+        );
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let response = client
+        .post("/api/v1/admin/clear")
+        .header(Header::new("Authorization", "Bearer secret"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Diverging decisions made during the window no longer roll back to the cleared data
+    let response = client
+        .post("/api/v1/check")
+        .header(ContentType::JSON)
+        .body(format!(
+            r#"{{"object": "{}", "visibility_mask": 5}}"#,
+            Uuid::from_u128(1)
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: HealthResponse = client.get("/health").dispatch().into_json().unwrap();
+    assert_eq!(body.uuid_count, 0);
+    assert_eq!(canary.status().rollbacks, 0);
+}
+
+#[test]
+fn test_remote_authority() {
+    use occlusion::TieredStore;
+    use server::remote::HttpAuthority;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    // A central instance holding object 2 at level 3, counting lookups
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let counter = lookups.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            let known = Uuid::from_u128(2);
+            let (status, level) = if request_line.contains(&format!("/api/v1/level/{known} ")) {
+                ("200 OK", "3")
+            } else {
+                ("404 Not Found", "null")
+            };
+            let body = format!(r#"{{"object":"{known}","level":{level}}}"#);
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let store = occlusion::SwappableStore::new(
+        occlusion::build_store(vec![(Uuid::from_u128(1), 0)]).unwrap(),
+    );
+    let remote = Arc::new(TieredStore::new(
+        store.clone(),
+        HttpAuthority::new(url.parse().unwrap()),
+        Duration::from_mins(1),
+    ));
+    let rocket = rocket::build().manage(store).manage(remote).mount(
+        "/",
+        rocket::routes![server::routes::check, server::routes::check_batch],
+    );
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let check = |object: u128, mask: u8| -> CheckResponse {
+        client
+            .post("/api/v1/check")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"object": "{}", "visibility_mask": {mask}}}"#,
+                Uuid::from_u128(object)
+            ))
+            .dispatch()
+            .into_json()
+            .unwrap()
+    };
+
+    // Local objects never reach the remote
+    assert!(check(1, 0).is_visible);
+    assert_eq!(lookups.load(Ordering::SeqCst), 0);
+
+    assert!(check(2, 3).is_visible);
+    assert!(!check(2, 2).is_visible);
+    assert!(!check(3, 255).is_visible);
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    // Answers are cached
+    assert!(check(2, 3).is_visible);
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+
+    let batch = |mask: u8| -> BatchCheckResponse {
+        client
+            .post("/api/v1/check/batch")
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"objects": ["{}", "{}"], "visibility_mask": {mask}}}"#,
+                Uuid::from_u128(1),
+                Uuid::from_u128(2)
+            ))
+            .dispatch()
+            .into_json()
+            .unwrap()
+    };
+    assert!(batch(3).all_visible);
+    assert!(!batch(2).all_visible);
+}
+
+#[test]
+fn test_admin_change_source() {
+    use rocket::http::Header;
+    use server::{
+        ReloadState, guards::AdminToken, loader::LoadOptions, models::SourceResponse,
+        source::DataSource,
+    };
+    use std::sync::Arc;
+
+    let old_file = create_test_csv(&[(Uuid::from_u128(1), 0), (Uuid::from_u128(2), 5)]);
+    let new_file = create_test_csv(&[(Uuid::from_u128(2), 5), (Uuid::from_u128(3), 0)]);
+    let source = DataSource::parse(old_file.path().to_str().unwrap());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (store, metadata) = rt
+        .block_on(server::loader::load(&source, None))
+        .unwrap()
+        .unwrap();
+    let state = Arc::new(ReloadState::new(source, LoadOptions::default(), metadata));
+
+    let rocket = rocket::build()
+        .manage(occlusion::SwappableStore::new(store))
+        .manage(state.clone())
+        .manage(AdminToken("secret".to_string()))
+        .mount("/", rocket::routes![server::admin::change_source]);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let auth = Header::new("Authorization", "Bearer secret");
+
+    let response = client
+        .put("/api/v1/admin/source")
+        .header(auth.clone())
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "source": new_file.path() }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: SourceResponse = response.into_json().unwrap();
+    assert_eq!(body.source, new_file.path().to_str().unwrap());
+    assert_eq!((body.diff.added, body.diff.removed), (1, 1));
+    assert_eq!(
+        state.source(),
+        DataSource::parse(new_file.path().to_str().unwrap())
+    );
+
+    // A broken source is rejected and the current one kept
+    let response = client
+        .put("/api/v1/admin/source")
+        .header(auth)
+        .header(ContentType::JSON)
+        .body(r#"{"source": "/nonexistent/data.csv"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadGateway);
+    assert_eq!(
+        state.source(),
+        DataSource::parse(new_file.path().to_str().unwrap())
+    );
+}
+
+#[test]
+fn test_admin_scheduler() {
+    use rocket::http::Header;
+    use server::{
+        guards::AdminToken,
+        scheduler::{
+            CheckResult, NamespaceSchedulers, Scheduler, SchedulerStatus, SchedulersStatus,
+        },
+    };
+    use std::{sync::Arc, time::Duration};
+
+    let scheduler = Arc::new(Scheduler::new(Duration::from_secs(300)));
+    scheduler.record(CheckResult::Unchanged, None, 0, None);
+    // A namespace with its own source fails without affecting the default source
+    let tenant = Arc::new(Scheduler::new(Duration::from_secs(300)));
+    tenant.record(
+        CheckResult::Failed,
+        Some("HTTP 503".to_string()),
+        3,
+        Some(Duration::from_secs(20)),
+    );
+    let namespaces = NamespaceSchedulers([("tenant-a".to_string(), tenant)].into());
+    let routes = rocket::routes![
+        server::admin::scheduler_status,
+        server::admin::run_scheduler
+    ];
+    let rocket = rocket::build()
+        .manage(scheduler)
+        .manage(namespaces)
+        .manage(AdminToken("secret".to_string()))
+        .mount("/", routes.clone());
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let auth = Header::new("Authorization", "Bearer secret");
+
+    let response = client
+        .get("/api/v1/admin/scheduler")
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let status: SchedulerStatus = response.into_json().unwrap();
+    assert_eq!(status.interval_secs, 300);
+    assert_eq!(status.last_result, Some(CheckResult::Unchanged));
+    assert_eq!(status.consecutive_failures, 0);
+
+    let response = client
+        .get("/api/v1/admin/scheduler")
+        .header(auth.clone())
+        .dispatch();
+    let status: SchedulersStatus = response.into_json().unwrap();
+    assert_eq!(status.status.last_result, Some(CheckResult::Unchanged));
+    let tenant = &status.namespaces["tenant-a"];
+    assert_eq!(tenant.last_result, Some(CheckResult::Failed));
+    assert_eq!(tenant.consecutive_failures, 3);
+    assert_eq!(tenant.backoff_secs, Some(20));
+
+    let response = client
+        .get("/api/v1/admin/scheduler?namespace=tenant-a")
+        .header(auth.clone())
+        .dispatch();
+    let status: SchedulersStatus = response.into_json().unwrap();
+    assert_eq!(status.status.consecutive_failures, 3);
+    assert!(status.namespaces.is_empty());
+    let response = client
+        .get("/api/v1/admin/scheduler?namespace=tenant-b")
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .post("/api/v1/admin/scheduler/run")
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    let response = client
+        .post("/api/v1/admin/scheduler/run?namespace=tenant-a")
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    let status: SchedulerStatus = response.into_json().unwrap();
+    assert_eq!(status.consecutive_failures, 3);
+
+    let response = client.post("/api/v1/admin/scheduler/run").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // Without scheduled reloads there is nothing to report or run
+    let rocket = rocket::build()
+        .manage(AdminToken("secret".to_string()))
+        .mount("/", routes);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    let response = client
+        .get("/api/v1/admin/scheduler")
+        .header(auth)
+        .dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+}